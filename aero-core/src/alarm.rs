@@ -0,0 +1,239 @@
+//! alarm
+//!
+//! This module contains the over and under temperature alarm of a channel: the thresholds with
+//! their hysteresis and the state machine going from cleared to active and acknowledged
+//!
+
+use crate::Temp;
+
+/// Which threshold has been crossed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Kind {
+    Low,
+    High,
+}
+
+/// The alarm raises when the temperature goes below `low` or above `high`, and clears only when it
+/// comes back by at least `hysteresis`, so that a temperature oscillating around a threshold
+/// doesn't raise it again and again. `None` disables the threshold.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Thresholds {
+    pub low: Option<Temp>,
+    pub high: Option<Temp>,
+    pub hysteresis: Temp,
+}
+
+impl Thresholds {
+    pub const DISABLED: Thresholds = Thresholds {
+        low: None,
+        high: None,
+        hysteresis: Temp(0),
+    };
+
+    /// The threshold crossed by `temp`, if any
+    fn crossed(&self, temp: Temp) -> Option<Kind> {
+        match (self.low, self.high) {
+            (Some(low), _) if temp < low => Some(Kind::Low),
+            (_, Some(high)) if temp > high => Some(Kind::High),
+            _ => None,
+        }
+    }
+
+    /// `temp` is back from the threshold of `kind` by at least the hysteresis
+    fn recovered(&self, kind: Kind, temp: Temp) -> bool {
+        let hysteresis = self.hysteresis.0 as i32;
+        match (kind, self.low, self.high) {
+            (Kind::Low, Some(low), _) => temp.0 as i32 >= low.0 as i32 + hysteresis,
+            (Kind::High, _, Some(high)) => temp.0 as i32 <= high.0 as i32 - hysteresis,
+            // the threshold has been disabled
+            _ => true,
+        }
+    }
+}
+
+/// Thresholds of the gauges until others are configured, OAT first then CAT
+pub const DEFAULT_THRESHOLDS: [Thresholds; 2] = [
+    Thresholds {
+        low: Some(Temp(-2000)),
+        high: Some(Temp(4500)),
+        hysteresis: Temp(100),
+    },
+    Thresholds {
+        low: Some(Temp(-1000)),
+        high: Some(Temp(6000)),
+        hysteresis: Temp(100),
+    },
+];
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds::DISABLED
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum State {
+    /// The temperature is within the thresholds
+    #[default]
+    Cleared,
+    /// A threshold has been crossed and the pilot didn't acknowledge it yet
+    Active(Kind),
+    /// A threshold has been crossed and the pilot acknowledged it, the temperature is still beyond
+    Acknowledged(Kind),
+}
+
+/// The alarm of a channel
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Alarm {
+    thresholds: Thresholds,
+    state: State,
+}
+
+impl Alarm {
+    pub const fn new(thresholds: Thresholds) -> Self {
+        Alarm {
+            thresholds,
+            state: State::Cleared,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn thresholds(&self) -> Thresholds {
+        self.thresholds
+    }
+
+    /// Change the thresholds, the state is re-evaluated at the next `update`
+    pub fn set_thresholds(&mut self, thresholds: Thresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Update the state with a new temperature, return true if the state changed
+    pub fn update(&mut self, temp: Temp) -> bool {
+        let new = match self.state {
+            State::Cleared => match self.thresholds.crossed(temp) {
+                Some(kind) => State::Active(kind),
+                None => State::Cleared,
+            },
+            State::Active(kind) | State::Acknowledged(kind) => {
+                match self.thresholds.crossed(temp) {
+                    // jumping to the opposite threshold is a new alarm
+                    Some(crossed) if crossed != kind => State::Active(crossed),
+                    _ if self.thresholds.recovered(kind, temp) => State::Cleared,
+                    _ => self.state,
+                }
+            }
+        };
+        let changed = new != self.state;
+        self.state = new;
+        changed
+    }
+
+    /// Acknowledge an active alarm, return true if the state changed
+    pub fn acknowledge(&mut self) -> bool {
+        match self.state {
+            State::Active(kind) => {
+                self.state = State::Acknowledged(kind);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The alarm is active and not acknowledged
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, State::Active(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alarm() -> Alarm {
+        Alarm::new(Thresholds {
+            low: Some(Temp(-1000)),
+            high: Some(Temp(4000)),
+            hysteresis: Temp(200),
+        })
+    }
+
+    #[test]
+    fn test_cleared() {
+        let mut alarm = alarm();
+        for t in [-1000, 0, 2000, 4000] {
+            assert!(!alarm.update(Temp(t)));
+            assert_eq!(alarm.state(), State::Cleared);
+        }
+        assert!(!alarm.acknowledge());
+        assert_eq!(alarm.state(), State::Cleared);
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let mut alarm = alarm();
+        assert!(alarm.update(Temp(4001)));
+        assert_eq!(alarm.state(), State::Active(Kind::High));
+        assert!(!alarm.update(Temp(3900)));
+        assert!(!alarm.update(Temp(4100)));
+        assert!(!alarm.update(Temp(3801)));
+        assert_eq!(alarm.state(), State::Active(Kind::High));
+        assert!(alarm.update(Temp(3800)));
+        assert_eq!(alarm.state(), State::Cleared);
+
+        assert!(alarm.update(Temp(-1001)));
+        assert_eq!(alarm.state(), State::Active(Kind::Low));
+        assert!(!alarm.update(Temp(-801)));
+        assert!(alarm.update(Temp(-800)));
+        assert_eq!(alarm.state(), State::Cleared);
+    }
+
+    #[test]
+    fn test_acknowledge() {
+        let mut alarm = alarm();
+        alarm.update(Temp(-2000));
+        assert!(alarm.is_active());
+        assert!(alarm.acknowledge());
+        assert_eq!(alarm.state(), State::Acknowledged(Kind::Low));
+        assert!(!alarm.is_active());
+        assert!(!alarm.acknowledge());
+
+        // still beyond the threshold, stays acknowledged
+        assert!(!alarm.update(Temp(-1500)));
+        assert_eq!(alarm.state(), State::Acknowledged(Kind::Low));
+
+        // the opposite threshold raises a new alarm
+        assert!(alarm.update(Temp(5000)));
+        assert_eq!(alarm.state(), State::Active(Kind::High));
+        alarm.acknowledge();
+        assert!(alarm.update(Temp(0)));
+        assert_eq!(alarm.state(), State::Cleared);
+
+        // after clearing the alarm raises again
+        assert!(alarm.update(Temp(5000)));
+        assert!(alarm.is_active());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut alarm = Alarm::default();
+        for t in [i16::MIN, 0, i16::MAX] {
+            assert!(!alarm.update(Temp(t)));
+        }
+        let mut alarm = Alarm::new(Thresholds {
+            high: Some(Temp(i16::MAX - 1)),
+            hysteresis: Temp(i16::MAX),
+            ..Thresholds::DISABLED
+        });
+        assert!(alarm.update(Temp(i16::MAX)));
+        assert!(!alarm.update(Temp(0)));
+        alarm.set_thresholds(Thresholds::DISABLED);
+        assert!(alarm.update(Temp(i16::MIN)));
+        assert_eq!(alarm.state(), State::Cleared);
+    }
+}
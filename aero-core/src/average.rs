@@ -0,0 +1,51 @@
+//! average
+//!
+//! Averages of temperatures over a fixed number of samples
+//!
+
+use crate::temp::Temp;
+
+/// Accumulates `N` temperatures and returns their average once the period is complete
+#[derive(Debug, Clone, Copy)]
+pub struct Average<const N: usize> {
+    sum: i32,
+    count: usize,
+}
+
+impl<const N: usize> Default for Average<N> {
+    fn default() -> Self {
+        Average { sum: 0, count: 0 }
+    }
+}
+
+impl<const N: usize> Average<N> {
+    /// Add `temp` to the period, return the average if it is the `N`-th sample, in this case a new
+    /// period starts
+    pub fn push(&mut self, temp: Temp) -> Option<Temp> {
+        self.sum += temp.0 as i32;
+        self.count += 1;
+        if self.count == N {
+            let average = self.sum / N as i32;
+            *self = Average::default();
+            Some(Temp(average as i16))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average() {
+        let mut average = Average::<3>::default();
+        assert_eq!(average.push(Temp(100)), None);
+        assert_eq!(average.push(Temp(200)), None);
+        assert_eq!(average.push(Temp(600)), Some(Temp(300)));
+        assert_eq!(average.push(Temp(i16::MIN)), None);
+        assert_eq!(average.push(Temp(i16::MIN)), None);
+        assert_eq!(average.push(Temp(i16::MIN)), Some(Temp(i16::MIN)));
+    }
+}
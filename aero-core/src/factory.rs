@@ -0,0 +1,379 @@
+//! factory
+//!
+//! This module sequences the factory test of the front panel: the display patterns and the led
+//! checked by the operator, and the buttons pressed when prompted, with the time they're held. It's
+//! fed with the debounced edges of the buttons and the time in milliseconds, the firmware draws the
+//! steps and reports the outcomes
+//!
+
+use core::fmt;
+
+/// The buttons of the front panel
+pub const BUTTONS: usize = 2;
+
+/// The button confirming a visual check, the other one rejects it
+pub const PASS_BUTTON: usize = 0;
+
+/// Milliseconds the led is on and off during `Step::Led`
+pub const LED_BLINK: u32 = 250;
+
+/// A step of the test
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Step {
+    /// Vertical bars of the primary and secondary colors, white and black
+    ColorBars,
+    /// Ramps of red, green, blue and white, for stuck bits of the color depth
+    Gradients,
+    /// A line walking across the columns then the rows, for dead lines
+    PixelWalk,
+    /// A checkerboard inverted periodically, for dead or stuck pixels
+    Checkerboard,
+    /// The operator is asked to press and release the button
+    Button(usize),
+    /// The led blinks
+    Led,
+}
+
+/// The steps in the order they're done
+pub const STEPS: [Step; 7] = [
+    Step::ColorBars,
+    Step::Gradients,
+    Step::PixelWalk,
+    Step::Checkerboard,
+    Step::Button(0),
+    Step::Button(1),
+    Step::Led,
+];
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::ColorBars => f.pad("color bars"),
+            Step::Gradients => f.pad("gradients"),
+            Step::PixelWalk => f.pad("pixel walk"),
+            Step::Checkerboard => f.pad("checkerboard"),
+            Step::Button(i) => write!(f, "button {}", i + 1),
+            Step::Led => f.pad("led"),
+        }
+    }
+}
+
+/// How a step ended
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Outcome {
+    /// Confirmed by the operator
+    Pass,
+    /// The button has been held this many milliseconds, within the limits
+    Held(u32),
+    /// Rejected by the operator
+    Rejected,
+    /// No answer or no press within the limit
+    Timeout,
+    /// Another button has been pressed, the buttons may be swapped or shorted
+    WrongButton(usize),
+    /// Released after these milliseconds, less than `Limits::min_hold`
+    TooShort(u32),
+    /// Held for these milliseconds, more than `Limits::max_hold`
+    TooLong(u32),
+}
+
+impl Outcome {
+    pub fn is_pass(&self) -> bool {
+        matches!(self, Outcome::Pass | Outcome::Held(_))
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "ok"),
+            Outcome::Held(ms) => write!(f, "ok {}ms", ms),
+            Outcome::Rejected => write!(f, "rejected"),
+            Outcome::Timeout => write!(f, "timeout"),
+            Outcome::WrongButton(i) => write!(f, "wrong button {}", i + 1),
+            Outcome::TooShort(ms) => write!(f, "short {}ms", ms),
+            Outcome::TooLong(ms) => write!(f, "long {}ms", ms),
+        }
+    }
+}
+
+/// Limits of the steps in milliseconds
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// To answer a visual check
+    pub answer: u32,
+    /// To press the button prompted
+    pub press: u32,
+    /// Minimum time a button is held, a shorter press is a bounce of a faulty switch
+    pub min_hold: u32,
+    /// Maximum time a button is held, a longer press is a stuck switch
+    pub max_hold: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            answer: 30_000,
+            press: 10_000,
+            min_hold: 50,
+            max_hold: 2_000,
+        }
+    }
+}
+
+/// The state of the test, a step after the other until all of them are done
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Factory {
+    limits: Limits,
+    /// Index of the current step in `STEPS`, its length when done
+    current: usize,
+    /// When the current step started
+    started: u32,
+    /// When the buttons have been pressed in the current step, if they're down
+    pressed: [Option<u32>; BUTTONS],
+    outcomes: [Option<Outcome>; STEPS.len()],
+}
+
+impl Factory {
+    pub fn new(limits: Limits, now: u32) -> Self {
+        Factory {
+            limits,
+            current: 0,
+            started: now,
+            pressed: [None; BUTTONS],
+            outcomes: [None; STEPS.len()],
+        }
+    }
+
+    /// The current step, `None` when the test is done
+    pub fn step(&self) -> Option<Step> {
+        STEPS.get(self.current).copied()
+    }
+
+    /// Milliseconds since the current step started, for its animations
+    pub fn elapsed(&self, now: u32) -> u32 {
+        now.wrapping_sub(self.started)
+    }
+
+    /// Whether the led is on at `now`, it blinks only during `Step::Led`
+    pub fn led(&self, now: u32) -> bool {
+        self.step() == Some(Step::Led) && (self.elapsed(now) / LED_BLINK).is_multiple_of(2)
+    }
+
+    /// `button` has been pressed, or released if not `down`, return the step ended if any. A
+    /// visual check is answered on the release, so the button isn't still down in the next step,
+    /// and only a press started in the step counts
+    pub fn button(&mut self, button: usize, down: bool, now: u32) -> Option<(Step, Outcome)> {
+        let step = self.step()?;
+        if down {
+            self.pressed[button] = Some(now);
+        }
+        let outcome = match step {
+            Step::Button(prompted) if button != prompted && down => Outcome::WrongButton(button),
+            _ if down => return None,
+            Step::Button(prompted) if button != prompted => return None,
+            Step::Button(_) => {
+                let held = now.wrapping_sub(self.pressed[button].take()?);
+                if held < self.limits.min_hold {
+                    Outcome::TooShort(held)
+                } else if held > self.limits.max_hold {
+                    Outcome::TooLong(held)
+                } else {
+                    Outcome::Held(held)
+                }
+            }
+            _ => {
+                self.pressed[button].take()?;
+                match button {
+                    PASS_BUTTON => Outcome::Pass,
+                    _ => Outcome::Rejected,
+                }
+            }
+        };
+        Some(self.finish(outcome, now))
+    }
+
+    /// End the current step at `now` if it ran out of time, return it with the outcome
+    pub fn update(&mut self, now: u32) -> Option<(Step, Outcome)> {
+        let step = self.step()?;
+        let outcome = match step {
+            Step::Button(prompted) => match self.pressed[prompted] {
+                Some(pressed) => {
+                    let held = now.wrapping_sub(pressed);
+                    (held > self.limits.max_hold).then_some(Outcome::TooLong(held))
+                }
+                None => (self.elapsed(now) >= self.limits.press).then_some(Outcome::Timeout),
+            },
+            _ => (self.elapsed(now) >= self.limits.answer).then_some(Outcome::Timeout),
+        }?;
+        Some(self.finish(outcome, now))
+    }
+
+    fn finish(&mut self, outcome: Outcome, now: u32) -> (Step, Outcome) {
+        let step = STEPS[self.current];
+        self.outcomes[self.current] = Some(outcome);
+        self.current += 1;
+        self.started = now;
+        self.pressed = [None; BUTTONS];
+        (step, outcome)
+    }
+
+    /// The outcomes of the steps done, in order
+    pub fn outcomes(&self) -> impl Iterator<Item = (Step, Outcome)> + '_ {
+        STEPS
+            .iter()
+            .zip(self.outcomes.iter())
+            .filter_map(|(step, outcome)| Some((*step, (*outcome)?)))
+    }
+
+    /// Whether all the steps are done
+    pub fn is_done(&self) -> bool {
+        self.step().is_none()
+    }
+
+    /// Whether all the steps are done and passed
+    pub fn passed(&self) -> bool {
+        self.is_done() && self.outcomes().all(|(_, outcome)| outcome.is_pass())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factory() -> Factory {
+        Factory::new(Limits::default(), 1_000)
+    }
+
+    /// Click `button` from `now` holding it `held` milliseconds, return the step ended
+    fn click(factory: &mut Factory, button: usize, now: u32, held: u32) -> Option<(Step, Outcome)> {
+        assert_eq!(factory.button(button, true, now), None);
+        factory.button(button, false, now + held)
+    }
+
+    #[test]
+    fn test_pass() {
+        let mut factory = factory();
+        let mut now = 1_000;
+        for step in &STEPS[..4] {
+            assert_eq!(factory.step(), Some(*step));
+            assert!(!factory.led(now));
+            now += 2_000;
+            assert_eq!(factory.update(now), None);
+            assert_eq!(
+                click(&mut factory, PASS_BUTTON, now, 100),
+                Some((*step, Outcome::Pass))
+            );
+            now += 100;
+        }
+        assert_eq!(factory.step(), Some(Step::Button(0)));
+        assert_eq!(
+            click(&mut factory, 0, now + 500, 120),
+            Some((Step::Button(0), Outcome::Held(120)))
+        );
+        assert_eq!(factory.step(), Some(Step::Button(1)));
+        assert_eq!(
+            click(&mut factory, 1, now + 1_000, 300),
+            Some((Step::Button(1), Outcome::Held(300)))
+        );
+        now += 1_300;
+
+        assert_eq!(factory.step(), Some(Step::Led));
+        assert_eq!(factory.elapsed(now + 10), 10);
+        let led: Vec<_> = (0..4).map(|i| factory.led(now + i * LED_BLINK)).collect();
+        assert_eq!(led, [true, false, true, false]);
+        assert!(!factory.passed());
+        assert_eq!(
+            click(&mut factory, PASS_BUTTON, now + 3_000, 100),
+            Some((Step::Led, Outcome::Pass))
+        );
+
+        assert!(factory.is_done());
+        assert!(factory.passed());
+        assert_eq!(factory.outcomes().count(), STEPS.len());
+        assert!(!factory.led(now + 4_000));
+        assert_eq!(factory.update(now + 100_000), None);
+        assert_eq!(factory.button(0, true, now + 100_000), None);
+    }
+
+    #[test]
+    fn test_visual_fail() {
+        let mut factory = factory();
+        // the press doesn't answer, the release does
+        assert_eq!(factory.button(1, true, 2_000), None);
+        assert_eq!(
+            factory.button(1, false, 2_100),
+            Some((Step::ColorBars, Outcome::Rejected))
+        );
+        assert_eq!(factory.update(2_100 + 29_999), None);
+        assert_eq!(
+            factory.update(2_100 + 30_000),
+            Some((Step::Gradients, Outcome::Timeout))
+        );
+        assert_eq!(factory.step(), Some(Step::PixelWalk));
+        assert_eq!(
+            factory.outcomes().collect::<Vec<_>>(),
+            [
+                (Step::ColorBars, Outcome::Rejected),
+                (Step::Gradients, Outcome::Timeout)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_buttons_fail() {
+        let mut factory = factory();
+        for _ in 0..4 {
+            click(&mut factory, PASS_BUTTON, 1_000, 100);
+        }
+        // the release of a press started in the previous step and the wrong button
+        assert_eq!(factory.button(PASS_BUTTON, false, 2_000), None);
+        assert_eq!(
+            factory.button(1, true, 2_000),
+            Some((Step::Button(0), Outcome::WrongButton(1)))
+        );
+        // its release is ignored, then a bounce
+        assert_eq!(factory.button(1, false, 2_100), None);
+        assert_eq!(
+            click(&mut factory, 1, 2_200, 10),
+            Some((Step::Button(1), Outcome::TooShort(10)))
+        );
+        let mut factory = factory.clone();
+        factory.current = 4;
+        factory.started = 3_000;
+        assert_eq!(factory.update(12_999), None);
+        assert_eq!(
+            factory.update(13_000),
+            Some((Step::Button(0), Outcome::Timeout))
+        );
+        // stuck down
+        assert_eq!(factory.button(1, true, 14_000), None);
+        assert_eq!(factory.update(16_000), None);
+        assert_eq!(
+            factory.update(16_001),
+            Some((Step::Button(1), Outcome::TooLong(2_001)))
+        );
+        assert_eq!(factory.button(1, false, 17_000), None);
+        assert_eq!(factory.step(), Some(Step::Led));
+        assert_eq!(factory.update(17_000), None);
+    }
+
+    #[test]
+    fn test_display() {
+        use core::fmt::Write;
+        let mut buffer = heapless::String::<96>::new();
+        for (step, outcome) in [
+            (Step::Button(0), Outcome::Held(120)),
+            (Step::ColorBars, Outcome::WrongButton(1)),
+            (Step::Led, Outcome::TooLong(2_500)),
+        ] {
+            write!(buffer, "{} {};", step, outcome).unwrap();
+        }
+        assert_eq!(
+            buffer,
+            "button 1 ok 120ms;color bars wrong button 2;led long 2500ms;"
+        );
+    }
+}
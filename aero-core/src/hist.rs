@@ -0,0 +1,119 @@
+//! hist
+//!
+//! This module provides the geometry of histograms, the firmwares draw the resulting lines on
+//! their display
+//!
+
+use heapless::Vec;
+
+use crate::minmax::MinMax;
+use crate::temp::Temp;
+
+/// A point on the display, `x` grows to the right and `y` grows down
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Point { x, y }
+    }
+}
+
+/// A struct containing three points
+pub type ThreePoints = [Point; 3];
+
+/// Represent a histogram with values rescaled to fit in the window defined by the `upper_left`
+/// point, `width` and `height`
+#[derive(Debug)]
+pub struct Hist {
+    upper_left: Point,
+    width: u32,
+    height: u32,
+}
+
+impl Hist {
+    pub fn new(upper_left: Point, width: u32, height: u32) -> Hist {
+        Hist {
+            upper_left,
+            width,
+            height,
+        }
+    }
+
+    /// Return a tuple of 3 points (A,B,C) for every value, values are aligned to the right and only
+    /// the last `width` are considered.
+    /// A->B should be foreground colored while B-C should be background colored
+    pub fn lines<I, const N: usize>(&self, values: I) -> Vec<ThreePoints, N>
+    where
+        I: Iterator<Item = Temp> + Clone,
+    {
+        let mut result = Vec::new();
+
+        let len = values.clone().count();
+        let skip = len.saturating_sub((self.width as usize).min(N));
+        let len = len - skip;
+        if len == 0 {
+            return result;
+        }
+
+        let mut min_max = MinMax::default();
+        for val in values.clone().skip(skip) {
+            min_max.update(val);
+        }
+        let (Temp(min), Temp(max)) = min_max.extremes().unwrap_or_default();
+        let delta = max as i32 - min as i32;
+        let height = self.height as i32;
+        let baseline_y = self.upper_left.y + height;
+        let baseline_x = self.upper_left.x + self.width as i32 - len as i32;
+
+        for (i, val) in values.skip(skip).enumerate() {
+            let x = baseline_x + i as i32;
+            let rescaled = if delta == 0 {
+                height / 2
+            } else {
+                (val.0 as i32 - min as i32) * height / delta
+            };
+
+            let a = Point::new(x, baseline_y);
+            let b = Point::new(x, baseline_y - rescaled);
+            let c = Point::new(x, baseline_y - height + 1);
+            // can't fail since `len <= N`
+            let _ = result.push([a, b, c]);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let hist = Hist::new(Point::new(0, 10), 4, 10);
+        let values = [Temp(0), Temp(50), Temp(100)];
+        let lines: Vec<ThreePoints, 4> = hist.lines(values.iter().cloned());
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            [Point::new(1, 20), Point::new(1, 20), Point::new(1, 11)]
+        );
+        assert_eq!(lines[1][1], Point::new(2, 15));
+        assert_eq!(lines[2][1], Point::new(3, 10));
+    }
+
+    #[test]
+    fn test_lines_flat_and_empty() {
+        let hist = Hist::new(Point::new(0, 0), 2, 10);
+        let lines: Vec<ThreePoints, 2> = hist.lines([Temp(7); 5].iter().cloned());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][1], Point::new(0, 5));
+        assert_eq!(lines[1][1], Point::new(1, 5));
+
+        let lines: Vec<ThreePoints, 2> = hist.lines([].iter().cloned());
+        assert!(lines.is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+//! icing
+//!
+//! This module classifies the carburettor icing risk from the outside air temperature (OAT), the
+//! carburettor air temperature (CAT) and, when available, the humidity of the outside air
+//!
+//! The humidity bands approximate the carburettor icing chart published by the UK CAA in the
+//! Safety Sense leaflet 14, the CAT arc is the yellow one of the usual carb temperature gauges.
+//!
+
+use crate::Temp;
+use core::fmt;
+
+/// Icing risk bands, ordered from the lowest to the highest
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Risk {
+    #[default]
+    Low,
+    /// Light icing at cruise or descent power
+    Light,
+    /// Moderate icing at cruise power, serious icing at descent power
+    Moderate,
+    /// Serious icing at any power
+    Serious,
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `pad` so that the width can be set, eg `{:8}`
+        f.pad(match self {
+            Risk::Low => "LOW",
+            Risk::Light => "LIGHT",
+            Risk::Moderate => "MODERATE",
+            Risk::Serious => "SERIOUS",
+        })
+    }
+}
+
+impl Risk {
+    fn raise(self) -> Risk {
+        match self {
+            Risk::Low => Risk::Light,
+            Risk::Light => Risk::Moderate,
+            Risk::Moderate | Risk::Serious => Risk::Serious,
+        }
+    }
+}
+
+/// Humidity of the outside air, as measured by a DHT22 for example
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Humidity {
+    DewPoint(Temp),
+    /// Relative humidity in percent, 0 to 100
+    Relative(u8),
+}
+
+impl Humidity {
+    /// The difference between the OAT and the dew point, in hundredths of degree.
+    ///
+    /// The relative humidity is converted with the `(100 - RH) / 5` rule of thumb, which is
+    /// accurate within a degree above 50% where the icing bands are
+    fn spread(&self, oat: Temp) -> i32 {
+        match *self {
+            Humidity::DewPoint(dew_point) => (oat.0 as i32 - dew_point.0 as i32).max(0),
+            Humidity::Relative(rh) => (100 - rh.min(100) as i32) * 20,
+        }
+    }
+}
+
+/// Outside air temperatures (inclusive, hundredths of degree) and maximum dew point spreads of the
+/// chart bands, from the highest risk
+const BANDS: [(Risk, i32, i32, i32); 3] = [
+    (Risk::Serious, -500, 2500, 300),
+    (Risk::Moderate, -800, 3000, 700),
+    (Risk::Light, -1000, 3500, 1500),
+];
+
+/// Carburettor air temperatures where ice forms in the venturi
+const CAT_ARC: (i16, i16) = (-1500, 500);
+
+/// Carburettor air temperatures near the arc, where ice may still form
+const CAT_NEAR_ARC: (i16, i16) = (-2000, 1000);
+
+/// The icing risk of the current conditions.
+///
+/// With the humidity the chart band of the OAT is raised by one when the CAT is in the arc, and
+/// lowered to at most `Risk::Light` when the CAT is far from it, eg. carb heat is on. Without the
+/// humidity only the CAT is used, assuming moist air when it's in the arc.
+pub fn risk(oat: Temp, cat: Temp, humidity: Option<Humidity>) -> Risk {
+    let in_arc = (CAT_ARC.0..=CAT_ARC.1).contains(&cat.0);
+    let near_arc = (CAT_NEAR_ARC.0..=CAT_NEAR_ARC.1).contains(&cat.0);
+    match humidity {
+        Some(humidity) => {
+            let spread = humidity.spread(oat);
+            let oat = oat.0 as i32;
+            let chart = BANDS
+                .iter()
+                .find(|(_, min, max, spread_max)| {
+                    (*min..=*max).contains(&oat) && spread <= *spread_max
+                })
+                .map(|(risk, _, _, _)| *risk)
+                .unwrap_or_default();
+            if chart == Risk::Low {
+                Risk::Low
+            } else if in_arc {
+                chart.raise()
+            } else if near_arc {
+                chart
+            } else {
+                chart.min(Risk::Light)
+            }
+        }
+        None if in_arc => Risk::Moderate,
+        None if near_arc => Risk::Light,
+        None => Risk::Low,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chart() {
+        let cat = Temp(2000); // outside of the arc influence
+        let dew = |oat: i16, spread: i16| Some(Humidity::DewPoint(Temp(oat - spread)));
+        let near = Temp(800);
+
+        assert_eq!(risk(Temp(1500), near, dew(1500, 0)), Risk::Serious);
+        assert_eq!(risk(Temp(1500), near, dew(1500, 300)), Risk::Serious);
+        assert_eq!(risk(Temp(1500), near, dew(1500, 301)), Risk::Moderate);
+        assert_eq!(risk(Temp(1500), near, dew(1500, 700)), Risk::Moderate);
+        assert_eq!(risk(Temp(1500), near, dew(1500, 1500)), Risk::Light);
+        assert_eq!(risk(Temp(1500), near, dew(1500, 1501)), Risk::Low);
+
+        // hot and cold days are out of the serious band
+        assert_eq!(risk(Temp(2800), near, dew(2800, 0)), Risk::Moderate);
+        assert_eq!(risk(Temp(3300), near, dew(3300, 0)), Risk::Light);
+        assert_eq!(risk(Temp(3600), near, dew(3600, 0)), Risk::Low);
+        assert_eq!(risk(Temp(-900), near, dew(-900, 0)), Risk::Light);
+        assert_eq!(risk(Temp(-1100), near, dew(-1100, 0)), Risk::Low);
+
+        // carb heat on
+        assert_eq!(risk(Temp(1500), cat, dew(1500, 0)), Risk::Light);
+        assert_eq!(risk(Temp(1500), cat, dew(1500, 1501)), Risk::Low);
+    }
+
+    #[test]
+    fn test_cat_arc() {
+        let humid = Some(Humidity::Relative(90));
+        assert_eq!(risk(Temp(1500), Temp(0), humid), Risk::Serious);
+        assert_eq!(risk(Temp(1500), Temp(-1500), humid), Risk::Serious);
+        assert_eq!(risk(Temp(2800), Temp(0), humid), Risk::Serious);
+        assert_eq!(risk(Temp(3300), Temp(0), humid), Risk::Moderate);
+        assert_eq!(risk(Temp(3600), Temp(0), humid), Risk::Low);
+
+        assert_eq!(risk(Temp(1500), Temp(0), None), Risk::Moderate);
+        assert_eq!(risk(Temp(1500), Temp(500), None), Risk::Moderate);
+        assert_eq!(risk(Temp(1500), Temp(501), None), Risk::Light);
+        assert_eq!(risk(Temp(1500), Temp(-2000), None), Risk::Light);
+        assert_eq!(risk(Temp(1500), Temp(-2001), None), Risk::Low);
+        assert_eq!(risk(Temp(1500), Temp(1001), None), Risk::Low);
+    }
+
+    #[test]
+    fn test_relative_humidity() {
+        let near = Temp(800);
+        let rh = |rh| Some(Humidity::Relative(rh));
+        assert_eq!(risk(Temp(1000), near, rh(100)), Risk::Serious);
+        assert_eq!(risk(Temp(1000), near, rh(255)), Risk::Serious);
+        assert_eq!(risk(Temp(1000), near, rh(85)), Risk::Serious);
+        assert_eq!(risk(Temp(1000), near, rh(84)), Risk::Moderate);
+        assert_eq!(risk(Temp(1000), near, rh(65)), Risk::Moderate);
+        assert_eq!(risk(Temp(1000), near, rh(25)), Risk::Light);
+        assert_eq!(risk(Temp(1000), near, rh(24)), Risk::Low);
+        assert_eq!(risk(Temp(1000), near, rh(0)), Risk::Low);
+
+        // a dew point above the OAT is a measurement error, it's treated as saturated air
+        let dew_point = Some(Humidity::DewPoint(Temp(1200)));
+        assert_eq!(risk(Temp(1000), near, dew_point), Risk::Serious);
+    }
+
+    #[test]
+    fn test_extremes() {
+        for oat in [i16::MIN, 0, i16::MAX] {
+            for cat in [i16::MIN, 0, i16::MAX] {
+                for humidity in [
+                    None,
+                    Some(Humidity::DewPoint(Temp(i16::MIN))),
+                    Some(Humidity::DewPoint(Temp(i16::MAX))),
+                    Some(Humidity::Relative(u8::MAX)),
+                ] {
+                    risk(Temp(oat), Temp(cat), humidity);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Risk::Moderate.to_string(), "MODERATE");
+        assert_eq!(format!("{:8}|", Risk::Low), "LOW     |");
+        assert!(Risk::Serious > Risk::Low);
+    }
+}
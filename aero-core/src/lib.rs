@@ -0,0 +1,19 @@
+//! aero-core
+//!
+//! Hardware independent logic of the aerotemp gauges, shared by the firmwares and testable on the
+//! host
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod alarm;
+pub mod average;
+pub mod factory;
+pub mod hist;
+pub mod icing;
+pub mod minmax;
+pub mod temp;
+pub mod unit;
+
+pub use temp::{Level, Temp};
+pub use unit::Unit;
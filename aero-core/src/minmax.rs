@@ -0,0 +1,70 @@
+//! minmax
+//!
+//! Tracks the extremes reached by a temperature
+//!
+
+use crate::temp::Temp;
+
+/// Minimum and maximum of the temperatures seen, `None` until the first update
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MinMax(Option<(Temp, Temp)>);
+
+impl MinMax {
+    /// Extremes already known, eg. restored after a reset
+    pub fn new(min: Temp, max: Temp) -> Self {
+        MinMax(Some((min.min(max), min.max(max))))
+    }
+
+    /// Update the extremes with `temp`, return true if one of them changed
+    pub fn update(&mut self, temp: Temp) -> bool {
+        let new = match self.0 {
+            None => (temp, temp),
+            Some((min, max)) => (min.min(temp), max.max(temp)),
+        };
+        let changed = self.0 != Some(new);
+        self.0 = Some(new);
+        changed
+    }
+
+    pub fn min(&self) -> Option<Temp> {
+        self.0.map(|(min, _)| min)
+    }
+
+    pub fn max(&self) -> Option<Temp> {
+        self.0.map(|(_, max)| max)
+    }
+
+    /// Both the extremes, `(min, max)`
+    pub fn extremes(&self) -> Option<(Temp, Temp)> {
+        self.0
+    }
+
+    /// Forget the extremes seen
+    pub fn reset(&mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_max() {
+        let mut min_max = MinMax::default();
+        assert_eq!(min_max.min(), None);
+        assert!(min_max.update(Temp(100)));
+        assert_eq!(min_max.min(), Some(Temp(100)));
+        assert_eq!(min_max.max(), Some(Temp(100)));
+        assert!(min_max.update(Temp(-100)));
+        assert!(!min_max.update(Temp(0)));
+        assert!(min_max.update(Temp(200)));
+        assert_eq!(min_max.min(), Some(Temp(-100)));
+        assert_eq!(min_max.max(), Some(Temp(200)));
+        assert_eq!(min_max.extremes(), Some((Temp(-100), Temp(200))));
+        assert_eq!(MinMax::new(Temp(200), Temp(-100)), min_max);
+        min_max.reset();
+        assert_eq!(min_max.max(), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! temp
+//!
+//! Temperatures in hundredths of a degree Celsius, their colour levels and formatting
+//!
+
+use core::fmt::Write;
+use core::ops::Deref;
+
+use crate::unit::Unit;
+
+/// A temperature stored in celsius multiplied by 100 (eg. `Temp(100i16) = 1.0°C` )
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Temp(pub i16);
+
+impl From<i16> for Temp {
+    fn from(t: i16) -> Self {
+        Temp(t)
+    }
+}
+
+impl Deref for Temp {
+    type Target = i16;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Below this temperature the level is `Level::Freezing`
+pub const FREEZING: Temp = Temp(0);
+
+/// Below this temperature (and above `FREEZING`) the level is `Level::Cold`
+pub const COLD: Temp = Temp(1500);
+
+/// The range in which a temperature is, used for example to choose the color of the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Level {
+    Freezing,
+    Cold,
+    Warm,
+}
+
+/// Characters used by a temperature without the unit, eg `-12.3`
+pub const WIDTH: usize = 5;
+
+/// Characters used by a temperature with the unit, eg `-12.3°C`
+pub const WIDTH_WITH_UNIT: usize = WIDTH + 2;
+
+/// SPACES to add before the temperature to align right, indexed by the characters used
+const SPACES: [&str; WIDTH + 1] = ["", "", "", "  ", " ", ""];
+
+impl Temp {
+    /// format into `buf` this temperature in the given unit for example: `-12.3°C`
+    /// 7 characters are always used with unit and 5 character without
+    pub fn write_buffer<W: Write>(&self, unit: Unit, show_unit: bool, buf: &mut W) {
+        let val = match unit {
+            Unit::Fahrenheit => fahrenheit(self.0),
+            Unit::Celsius => self.0 as i32,
+        };
+        let abs_val = val.unsigned_abs();
+        let mut before_comma = abs_val / 100;
+        let after_comma = (abs_val % 100) / 10;
+
+        let mut char_used = 0usize; // character used by the temperature excluded the units
+        if val < 0 {
+            char_used += 1;
+        }
+        if before_comma < 10 {
+            char_used += 3; // 1 before, 1 for comma, 1 after
+        } else if before_comma < 100 {
+            char_used += 4; // 2 before, 1 for comma, 1 after
+        } else if before_comma < 1000 {
+            char_used += 3; // 3 before
+        } else {
+            char_used += 3; // 3 before
+            before_comma = 999; // overflow
+        }
+
+        let need_comma = before_comma < 100;
+
+        let spaces = SPACES[char_used];
+        write!(buf, "{}", spaces).unwrap();
+
+        if val < 0 {
+            write!(buf, "-").unwrap();
+        }
+        if need_comma {
+            write!(buf, "{}.{}", before_comma, after_comma).unwrap();
+        } else {
+            write!(buf, "{}", before_comma).unwrap();
+        }
+        if show_unit {
+            write!(buf, "{}", unit).unwrap();
+        }
+    }
+
+    /// The range in which this temperature is, see `FREEZING` and `COLD`
+    pub fn level(&self) -> Level {
+        if *self < FREEZING {
+            Level::Freezing
+        } else if *self < COLD {
+            Level::Cold
+        } else {
+            Level::Warm
+        }
+    }
+}
+
+/// `degrees` is degrees multiplied by 100, eg 3.31 °C is 331
+/// returned value is fahrenheit multiplied by 100, eg 22.41 °F is 2241
+/// the result is an `i32` because the fahrenheit of the `i16` extremes doesn't fit in an `i16`
+pub fn fahrenheit(degrees: i16) -> i32 {
+    let f = degrees as i32;
+    f * 9 / 5 + 3200
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::String;
+
+    const UNITS: [Unit; 2] = [Unit::Celsius, Unit::Fahrenheit];
+
+    fn format(temp: i16, unit: Unit, show_unit: bool) -> String<16> {
+        let mut buf = String::new();
+        Temp(temp).write_buffer(unit, show_unit, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_write_buffer() {
+        assert_eq!(format(1234, Unit::Celsius, true), " 12.3°C");
+        assert_eq!(format(-1234, Unit::Celsius, false), "-12.3");
+        assert_eq!(format(0, Unit::Celsius, false), "  0.0");
+        assert_eq!(format(-99, Unit::Celsius, false), " -0.9");
+        assert_eq!(format(0, Unit::Fahrenheit, true), " 32.0°F");
+        assert_eq!(format(12345, Unit::Celsius, false), "  123");
+        assert_eq!(format(-12345, Unit::Celsius, false), " -123");
+        assert_eq!(format(i16::MAX, Unit::Celsius, false), "  327");
+        assert_eq!(format(i16::MAX, Unit::Fahrenheit, true), "  621°F");
+        assert_eq!(format(i16::MIN, Unit::Celsius, true), " -327°C");
+        assert_eq!(format(i16::MIN, Unit::Fahrenheit, true), " -557°F");
+    }
+
+    #[test]
+    fn test_write_buffer_width_all_values() {
+        for unit in UNITS {
+            for temp in i16::MIN..=i16::MAX {
+                let with_unit = format(temp, unit, true);
+                assert_eq!(with_unit.chars().count(), WIDTH_WITH_UNIT, "{}", with_unit);
+                assert!(with_unit.ends_with(unit_str(unit)));
+
+                let without_unit = format(temp, unit, false);
+                assert_eq!(without_unit.chars().count(), WIDTH, "{}", without_unit);
+                assert_eq!(
+                    with_unit.strip_suffix(unit_str(unit)),
+                    Some(&without_unit[..])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_buffer_value_all_values() {
+        for unit in UNITS {
+            for temp in i16::MIN..=i16::MAX {
+                let val = match unit {
+                    Unit::Celsius => temp as i32,
+                    Unit::Fahrenheit => fahrenheit(temp),
+                };
+                let formatted = format(temp, unit, false);
+                let parsed: f64 = formatted.trim_start().parse().unwrap();
+                // one decimal truncated below 100, no decimal above, never more than 3 digits
+                let expected = if val.abs() < 10_000 {
+                    (val / 10) as f64 / 10.0
+                } else {
+                    (val / 100).clamp(-999, 999) as f64
+                };
+                assert_eq!(parsed, expected, "{} {:?} {}", temp, unit, formatted);
+            }
+        }
+    }
+
+    fn unit_str(unit: Unit) -> &'static str {
+        match unit {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+        }
+    }
+
+    #[test]
+    fn test_level() {
+        assert_eq!(Temp(-1).level(), Level::Freezing);
+        assert_eq!(Temp(0).level(), Level::Cold);
+        assert_eq!(Temp(1499).level(), Level::Cold);
+        assert_eq!(Temp(1500).level(), Level::Warm);
+    }
+
+    #[test]
+    fn test_fahrenheit() {
+        assert_eq!(fahrenheit(0), 3200);
+        assert_eq!(fahrenheit(10000), 21200);
+        assert_eq!(fahrenheit(-4000), -4000);
+        assert_eq!(fahrenheit(i16::MAX), 62180);
+        assert_eq!(fahrenheit(i16::MIN), -55782);
+        assert!(fahrenheit(i16::MAX) > i16::MAX as i32);
+    }
+}
@@ -1,8 +1,14 @@
+//! unit
+//!
+//! The unit a temperature is shown in, and the conversion to it
+//!
+
 use core::fmt;
-use defmt::Format;
 
-#[derive(Copy, Clone, Format, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Unit {
+    #[default]
     Celsius,
     Fahrenheit,
 }
@@ -16,13 +22,8 @@ impl fmt::Display for Unit {
     }
 }
 
-impl Default for Unit {
-    fn default() -> Self {
-        Unit::Celsius
-    }
-}
-
 impl Unit {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Self {
         *self = match self {
             Unit::Celsius => Unit::Fahrenheit,
@@ -1,6 +1,13 @@
-//! Uses the timer interrupt to blink a led with different frequencies.
+//! Factory test of the aero front panel: display patterns, buttons and led.
 //!
-//! This assumes that a LED is connected to pc13 as is the case on the blue pill board.
+//! The test goes through the steps of `aero_core::factory`: color bars, gradients, a pixel walk
+//! and a checkerboard on the display, confirmed with button 1 or rejected with button 2, then the
+//! press and release of each button when prompted, then the led on PC13 blinking. Every outcome is
+//! printed over RTT, and the summary is shown on the display at the end so that assembly can sign
+//! off the unit.
+//!
+//! The buttons on PA0 and PA1 are sampled every millisecond by TIM2 and debounced by
+//! `e_button::Debouncer`, the same timer is the clock of the test.
 //!
 //! Note: Without additional hardware, PC13 should not be used to drive an LED, see page 5.1.2 of
 //! the reference manual for an explanation. This is not an issue on the blue pill.
@@ -13,30 +20,32 @@ use panic_halt as _;
 
 use rtic::app;
 
+use aero_core::factory::{Factory, Limits, Step, BUTTONS};
+use core::fmt::Write;
+use e_button::Debouncer;
+use embedded_graphics::drawable::Drawable;
+use embedded_graphics::fonts::{Font12x16, Font6x8, Text};
+use embedded_graphics::geometry::Point;
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::prelude::Primitive;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::style::{PrimitiveStyleBuilder, TextStyleBuilder};
 use embedded_hal::digital::v2::{InputPin, OutputPin};
-use stm32f1xx_hal::gpio::gpioa::{PA0, PA1, PA5, PA6, PA7, PA3};
-use stm32f1xx_hal::gpio::gpioc::PC13;
-use stm32f1xx_hal::gpio::{Edge, Alternate, State};
-use stm32f1xx_hal::gpio::{ExtiPin, Floating, Input, Output, PushPull};
-use stm32f1xx_hal::prelude::*;
-use ssd1351::prelude::SSD1351_SPI_MODE;
-use ssd1351::mode::GraphicsMode;
+use heapless::String;
+use rtt_target::{rprintln, rtt_init_print};
 use ssd1351::builder::Builder;
+use ssd1351::interface::SpiInterface;
+use ssd1351::mode::GraphicsMode;
+use ssd1351::prelude::SSD1351_SPI_MODE;
 use stm32f1xx_hal::delay::Delay;
+use stm32f1xx_hal::gpio::gpioa::{PA0, PA1, PA3, PA5, PA6, PA7};
+use stm32f1xx_hal::gpio::gpioc::PC13;
+use stm32f1xx_hal::gpio::{Alternate, State};
+use stm32f1xx_hal::gpio::{Floating, Input, Output, PushPull};
+use stm32f1xx_hal::pac;
+use stm32f1xx_hal::prelude::*;
 use stm32f1xx_hal::spi::{Spi, Spi1NoRemap};
-use ssd1351::interface::SpiInterface;
-use embedded_graphics::pixelcolor::{RgbColor};
-use embedded_graphics::style::{PrimitiveStyleBuilder};
-use embedded_graphics::geometry::Point;
-use embedded_graphics::drawable::Drawable;
-use embedded_graphics::primitives::Rectangle;
-use embedded_graphics::prelude::Primitive;
-use stm32f1xx_hal::time::{MonoTimer, Instant};
-
-pub struct Button<T> {
-    pin: T,
-    pressed: bool,
-}
+use stm32f1xx_hal::timer::{CountDownTimer, Event, Timer};
 
 #[cfg(feature = "semihosting")]
 macro_rules! hprintln {
@@ -49,31 +58,68 @@ macro_rules! hprintln {
     ($s:expr, $($tt:tt)*) => {};
 }
 
-type Display = GraphicsMode<SpiInterface<Spi<stm32f1xx_hal::pac::SPI1, Spi1NoRemap, (PA5<Alternate<PushPull>>, PA6<Input<Floating>>, PA7<Alternate<PushPull>>), u8>, PA3<Output<PushPull>>>>;
-
-pub enum Last {
-    None,
-    Left,
-    Right,
-}
+type Display = GraphicsMode<
+    SpiInterface<
+        Spi<
+            stm32f1xx_hal::pac::SPI1,
+            Spi1NoRemap,
+            (
+                PA5<Alternate<PushPull>>,
+                PA6<Input<Floating>>,
+                PA7<Alternate<PushPull>>,
+            ),
+            u8,
+        >,
+        PA3<Output<PushPull>>,
+    >,
+>;
+
+/// Pixels of a side of the display
+const SIZE: i32 = 128;
+
+/// The buttons are down or up for 5 more samples than the opposite, 5ms
+const DEBOUNCE_SAMPLES: u8 = 5;
+
+/// Milliseconds between the redraws of the display
+const FRAME_MS: u32 = 20;
+
+/// Milliseconds the instructions of a visual check are shown before its pattern
+const INTRO_MS: u32 = 1_500;
+
+/// Milliseconds a line of the pixel walk is lit
+const WALK_MS: u32 = 20;
+
+/// Milliseconds between the inversions of the checkerboard, and pixels of its squares
+const CHECKER_MS: u32 = 1_000;
+const CHECKER_SIZE: i32 = 4;
 
 #[app(device = stm32f1xx_hal::pac, peripherals = true)]
 const APP: () = {
     struct Resources {
         display: Display,
 
-        pa0: Button<PA0<Input<Floating>>>,
-        pa1: Button<PA1<Input<Floating>>>,
+        pa0: PA0<Input<Floating>>,
+        pa1: PA1<Input<Floating>>,
+        debouncers: [Debouncer; BUTTONS],
 
         led: PC13<Output<PushPull>>,
 
-        start: Instant,
+        timer: CountDownTimer<pac::TIM2>,
+        /// Milliseconds counted by `timer`, the clock of `factory`
+        #[init(0)]
+        millis: u32,
 
-        last: Last,
+        factory: Factory,
+        /// The step and the frame on the display
+        #[init(None)]
+        shown: Option<(Option<Step>, u32)>,
     }
 
     #[init]
     fn init(cx: init::Context) -> init::LateResources {
+        rtt_init_print!();
+        rprintln!("aero front panel factory test");
+
         let mut rcc = cx.device.RCC.constrain();
         let mut afio = cx.device.AFIO.constrain(&mut rcc.apb2);
         let mut flash = cx.device.FLASH.constrain();
@@ -86,9 +132,6 @@ const APP: () = {
             .freeze(&mut flash.acr);
         hprintln!("sysclk: {:?}", clocks.sysclk());
 
-        let timer = MonoTimer::new(cx.core.DWT, cx.core.DCB, clocks);
-        let start = timer.now();
-
         let mut gpioc = cx.device.GPIOC.split(&mut rcc.apb2);
 
         let led = gpioc
@@ -122,36 +165,23 @@ const APP: () = {
         display.reset(&mut rst, &mut delay).unwrap();
         display.init().unwrap();
 
-        draw_square(&mut display, 0, 128, 0, 0);
-        draw_square(&mut display, 10, 128, 0, 0);
-
-        // Setup Buttons
-        let mut pa0 = gpioa.pa0.into_floating_input(&mut gpioa.crl);
-        pa0.make_interrupt_source(&mut afio);
-        pa0.trigger_on_edge(&cx.device.EXTI, Edge::RISING_FALLING);
-        pa0.enable_interrupt(&cx.device.EXTI);
-        let pa0 = Button {
-            pin: pa0,
-            pressed: false,
-        };
+        // Setup Buttons, sampled by the timer
+        let pa0 = gpioa.pa0.into_floating_input(&mut gpioa.crl);
+        let pa1 = gpioa.pa1.into_floating_input(&mut gpioa.crl);
+        let debouncers = [Debouncer::integrator(DEBOUNCE_SAMPLES); BUTTONS];
 
-        let mut pa1 = gpioa.pa1.into_floating_input(&mut gpioa.crl);
-        pa1.make_interrupt_source(&mut afio);
-        pa1.trigger_on_edge(&cx.device.EXTI, Edge::RISING_FALLING);
-        pa1.enable_interrupt(&cx.device.EXTI);
-        let pa1 = Button {
-            pin: pa1,
-            pressed: false,
-        };
-        let last = Last::None;
+        let mut timer =
+            Timer::tim2(cx.device.TIM2, &clocks, &mut rcc.apb1).start_count_down(1_000.hz());
+        timer.listen(Event::Update);
 
         init::LateResources {
             display,
             pa0,
             pa1,
+            debouncers,
             led,
-            start,
-            last,
+            timer,
+            factory: Factory::new(Limits::default(), 0),
         }
     }
 
@@ -162,70 +192,241 @@ const APP: () = {
         }
     }
 
-    #[task(binds = EXTI0, priority = 1, spawn = [buttons], resources = [pa0, led])]
-    fn exti0(cx: exti0::Context) {
-        hprintln!("exti0, p0 is low? {:?}", cx.resources.pa0.pin.is_low());
-        cx.resources.pa0.pressed = cx.resources.pa0.pin.is_low().unwrap();
-        cx.resources.pa0.pin.clear_interrupt_pending_bit();
-        cx.spawn.buttons().unwrap();
-        cx.resources.led.toggle().unwrap();
-    }
+    #[task(binds = TIM2, priority = 2, spawn = [draw], resources = [timer, pa0, pa1, debouncers, led, millis, factory])]
+    fn tick(cx: tick::Context) {
+        let millis = cx.resources.millis;
+        *millis = millis.wrapping_add(1);
+        let now = *millis;
+        let factory = cx.resources.factory;
+
+        // the buttons pull the pins low when pressed
+        let levels = [
+            cx.resources.pa0.is_low().unwrap(),
+            cx.resources.pa1.is_low().unwrap(),
+        ];
+        for (i, debouncer) in cx.resources.debouncers.iter_mut().enumerate() {
+            if let Some(down) = debouncer.sample(levels[i]) {
+                if let Some(ended) = factory.button(i, down, now) {
+                    report(factory, ended);
+                }
+            }
+        }
+        if let Some(ended) = factory.update(now) {
+            report(factory, ended);
+        }
 
-    #[task(binds = EXTI1, priority = 1, spawn = [buttons], resources = [pa1, led])]
-    fn exti1(cx: exti1::Context) {
-        hprintln!("exti1, p1 is low? {:?}", cx.resources.pa1.pin.is_low());
-        cx.resources.pa1.pressed = cx.resources.pa1.pin.is_low().unwrap();
-        cx.resources.pa1.pin.clear_interrupt_pending_bit();
-        cx.spawn.buttons().unwrap();
-        cx.resources.led.toggle().unwrap();
-    }
+        // the led on PC13 lights up when the pin is low
+        if factory.led(now) {
+            cx.resources.led.set_low().unwrap();
+        } else {
+            cx.resources.led.set_high().unwrap();
+        }
 
-    #[task(resources = [display, pa0, pa1, last, start])]
-    fn buttons(cx: buttons::Context) {
-        let elapsed = cx.resources.start.elapsed();
-        let x = (elapsed % 118) as i32;
-        let y = ((elapsed / 118) % 118) as i32;
+        if now % FRAME_MS == 0 {
+            // still drawing the previous frame, skip this one
+            let _ = cx.spawn.draw();
+        }
+        cx.resources.timer.clear_update_interrupt_flag();
+    }
 
+    #[task(priority = 1, resources = [display, factory, millis, shown])]
+    fn draw(mut cx: draw::Context) {
+        let factory = cx.resources.factory.lock(|factory| factory.clone());
+        let now = cx.resources.millis.lock(|millis| *millis);
+        let step = factory.step();
+        let frame = frame(step, factory.elapsed(now));
+        let shown = cx.resources.shown;
+        if *shown == Some((step, frame)) {
+            return;
+        }
+        // the frame shown before of the same step, if any
+        let previous = match *shown {
+            Some((shown_step, shown_frame)) if shown_step == step => Some(shown_frame),
+            _ => None,
+        };
         let display = cx.resources.display;
-        match (cx.resources.pa0.pressed, cx.resources.pa1.pressed) {
-            (false, false) => {
-                match cx.resources.last {
-                    Last::Left => draw_square(display, 1, 10,x,y),
-                    Last::Right => draw_square(display, 2, 10,x,y),
-                    Last::None => (),
-                }
-                *cx.resources.last = Last::None;
-            },
-            (true, false) => *cx.resources.last = Last::Left,
-            (false, true) => *cx.resources.last = Last::Right,
-            (true, true) => {
-                draw_square(display, 3, 10,x,y);
-                *cx.resources.last = Last::None;
-                cx.resources.pa0.pressed = false;
-                cx.resources.pa1.pressed = false;
-            },
+        match (step, frame) {
+            (None, _) => draw_summary(display, &factory),
+            (Some(Step::Button(i)), _) => {
+                let mut buffer: String<16> = String::new();
+                write!(buffer, "button {}", i + 1).unwrap();
+                draw_prompt(display, "press and release", &buffer);
+            }
+            (Some(Step::Led), _) => draw_prompt(display, "led blinking?", "1 yes, 2 no"),
+            (Some(step), 0) => {
+                let mut buffer: String<16> = String::new();
+                write!(buffer, "{}", step).unwrap();
+                draw_prompt(display, &buffer, "1 ok, 2 fail");
+            }
+            (Some(Step::ColorBars), _) => draw_color_bars(display),
+            (Some(Step::Gradients), _) => draw_gradients(display),
+            (Some(Step::PixelWalk), _) => draw_walk(display, frame, previous),
+            (Some(Step::Checkerboard), _) => draw_checkerboard(display, frame % 2 == 0),
         }
+        *shown = Some((step, frame));
     }
 
     extern "C" {
         fn TAMPER();
     }
-
 };
 
-fn draw_square(display: &mut Display, color: u8, size: i32, x: i32, y: i32 ) {
-    let color = match color {
-        0u8 => RgbColor::WHITE,
-        1 => RgbColor::RED,
-        2 => RgbColor::GREEN,
-        3 => RgbColor::BLUE,
-        _ => RgbColor::BLACK,
+/// Print the outcome of the step `ended` over RTT, and the result when the test is done
+fn report(factory: &Factory, ended: (Step, aero_core::factory::Outcome)) {
+    let (step, outcome) = ended;
+    rprintln!("{}: {}", step, outcome);
+    if factory.is_done() {
+        rprintln!("{}", if factory.passed() { "PASS" } else { "FAIL" });
+    }
+}
+
+/// The frame of the display for `step` at `elapsed` milliseconds since it started: 0 for the
+/// instructions, then the frames of the animation of the pattern, if any
+fn frame(step: Option<Step>, elapsed: u32) -> u32 {
+    let pattern = elapsed.saturating_sub(INTRO_MS);
+    match step {
+        None | Some(Step::Button(_)) | Some(Step::Led) => 0,
+        Some(_) if elapsed < INTRO_MS => 0,
+        Some(Step::PixelWalk) => 1 + pattern / WALK_MS % (2 * SIZE as u32),
+        Some(Step::Checkerboard) => 1 + pattern / CHECKER_MS % 2,
+        Some(_) => 1,
+    }
+}
+
+fn fill(display: &mut Display, top_left: (i32, i32), bottom_right: (i32, i32), color: Rgb565) {
+    let style = PrimitiveStyleBuilder::new().fill_color(color).build();
+    Rectangle::new(
+        Point::new(top_left.0, top_left.1),
+        Point::new(bottom_right.0, bottom_right.1),
+    )
+    .into_styled(style)
+    .draw(display)
+    .unwrap();
+}
+
+fn clear(display: &mut Display) {
+    fill(display, (0, 0), (SIZE - 1, SIZE - 1), Rgb565::BLACK);
+}
+
+fn draw_text(display: &mut Display, text: &str, position: Point, color: Rgb565) {
+    let style = TextStyleBuilder::new(Font6x8).text_color(color).build();
+    Text::new(text, position)
+        .into_styled(style)
+        .draw(display)
+        .unwrap();
+}
+
+/// Two lines of instructions on a black screen
+fn draw_prompt(display: &mut Display, first: &str, second: &str) {
+    clear(display);
+    draw_text(display, first, Point::new(4, 50), Rgb565::WHITE);
+    draw_text(display, second, Point::new(4, 66), Rgb565::YELLOW);
+}
+
+/// Vertical bars: white, yellow, cyan, green, magenta, red, blue and black
+fn draw_color_bars(display: &mut Display) {
+    let colors = [
+        Rgb565::WHITE,
+        Rgb565::YELLOW,
+        Rgb565::CYAN,
+        Rgb565::GREEN,
+        Rgb565::MAGENTA,
+        Rgb565::RED,
+        Rgb565::BLUE,
+        Rgb565::BLACK,
+    ];
+    let width = SIZE / colors.len() as i32;
+    for (i, color) in colors.iter().enumerate() {
+        let x = i as i32 * width;
+        fill(display, (x, 0), (x + width - 1, SIZE - 1), *color);
+    }
+}
+
+/// Horizontal bands of red, green, blue and white going from black to the full color, in the 32
+/// levels of red and blue, the 6 bits of green are scaled
+fn draw_gradients(display: &mut Display) {
+    let height = SIZE / 4;
+    let width = SIZE / 32;
+    for level in 0..32u8 {
+        let green = level << 1 | level >> 4;
+        let colors = [
+            Rgb565::new(level, 0, 0),
+            Rgb565::new(0, green, 0),
+            Rgb565::new(0, 0, level),
+            Rgb565::new(level, green, level),
+        ];
+        let x = level as i32 * width;
+        for (i, color) in colors.iter().enumerate() {
+            let y = i as i32 * height;
+            fill(display, (x, y), (x + width - 1, y + height - 1), *color);
+        }
+    }
+}
+
+/// A white line on black walking across the columns, then across the rows
+fn draw_walk(display: &mut Display, frame: u32, previous: Option<u32>) {
+    let line = |frame: u32| {
+        let i = frame as i32 - 1;
+        match i < SIZE {
+            true => ((i, 0), (i, SIZE - 1)),
+            false => ((0, i - SIZE), (SIZE - 1, i - SIZE)),
+        }
+    };
+    match previous {
+        Some(previous) if previous > 0 => {
+            let (top_left, bottom_right) = line(previous);
+            fill(display, top_left, bottom_right, Rgb565::BLACK);
+        }
+        _ => clear(display),
+    }
+    let (top_left, bottom_right) = line(frame);
+    fill(display, top_left, bottom_right, Rgb565::WHITE);
+}
+
+/// White and black squares, the white one in the top left corner if `even`
+fn draw_checkerboard(display: &mut Display, even: bool) {
+    let squares = SIZE / CHECKER_SIZE;
+    for row in 0..squares {
+        for column in 0..squares {
+            let white = ((row + column) % 2 == 0) == even;
+            let color = if white { Rgb565::WHITE } else { Rgb565::BLACK };
+            let (x, y) = (column * CHECKER_SIZE, row * CHECKER_SIZE);
+            fill(
+                display,
+                (x, y),
+                (x + CHECKER_SIZE - 1, y + CHECKER_SIZE - 1),
+                color,
+            );
+        }
+    }
+}
 
+/// PASS on green or FAIL on red, then the outcome of every step, the details are over RTT
+fn draw_summary(display: &mut Display, factory: &Factory) {
+    clear(display);
+    let (result, background) = match factory.passed() {
+        true => ("PASS", Rgb565::GREEN),
+        false => ("FAIL", Rgb565::RED),
     };
-    let style = PrimitiveStyleBuilder::new()
-        .fill_color(color)
+    fill(display, (0, 0), (SIZE - 1, 23), background);
+    let style = TextStyleBuilder::new(Font12x16)
+        .text_color(Rgb565::BLACK)
         .build();
-    Rectangle::new(Point::new(x, y), Point::new(x+size, y+size))
+    Text::new(result, Point::new(40, 4))
         .into_styled(style)
-        .draw(display).unwrap();
-}
\ No newline at end of file
+        .draw(display)
+        .unwrap();
+
+    let mut buffer: String<16> = String::new();
+    for (i, (step, outcome)) in factory.outcomes().enumerate() {
+        let y = 30 + 12 * i as i32;
+        write!(buffer, "{}", step).unwrap();
+        draw_text(display, &buffer, Point::new(4, y), Rgb565::WHITE);
+        buffer.clear();
+        let (text, color) = match outcome.is_pass() {
+            true => ("ok", Rgb565::GREEN),
+            false => ("FAIL", Rgb565::RED),
+        };
+        draw_text(display, text, Point::new(96, y), color);
+    }
+}
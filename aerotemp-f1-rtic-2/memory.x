@@ -1,6 +1,6 @@
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  FLASH : ORIGIN = 0x08000000, LENGTH = 62K /* the last 2 pages are used by `storage` */
   RAM : ORIGIN = 0x20000000, LENGTH = 20K
 }
 
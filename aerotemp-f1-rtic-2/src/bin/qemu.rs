@@ -0,0 +1,164 @@
+//! Runs the `Model`/`ModelChange`/`Hist` pipeline of the every_second and draw tasks on QEMU, with
+//! stubbed sensors and display, reporting the result through semihosting.
+//!
+//! Run with `cargo run --features qemu --bin qemu`, see Readme.md for the QEMU runner.
+
+#![no_main]
+#![no_std]
+
+use panic_semihosting as _;
+
+use aero_core::{Temp, Unit};
+use aerotemp_f1_rtic_2::hist::Hist;
+use aerotemp_f1_rtic_2::model::{
+    Model, ModelChange, Sampler, ScreenType, Temps, PERIOD, SCREEN_WIDTH,
+};
+use core::convert::Infallible;
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use embedded_graphics::geometry::{OriginDimensions, Point, Size};
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::prelude::{DrawTarget, Pixel};
+
+/// Seconds simulated, enough to fill and scroll the histories
+const SECONDS: usize = 300;
+
+/// Stub of the MAX31865 sensors: OAT goes down 0.1°C per second, CAT goes up 0.25°C per second
+fn read_sensors(second: usize) -> Temps {
+    let second = second as i16;
+    [Temp(1500 - 10 * second), Temp(-500 + 25 * second)]
+}
+
+/// Stub of the SSD1351, counting the pixels drawn
+#[derive(Default)]
+struct CountingDisplay {
+    foreground: usize,
+    background: usize,
+    outside: usize,
+}
+
+impl DrawTarget for CountingDisplay {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let size = self.size();
+            if point.x < 0
+                || point.y < 0
+                || point.x >= size.width as i32
+                || point.y >= size.height as i32
+            {
+                self.outside += 1;
+            } else if color == Rgb565::GREEN {
+                self.foreground += 1;
+            } else {
+                self.background += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for CountingDisplay {
+    fn size(&self) -> Size {
+        Size::new(SCREEN_WIDTH as u32, SCREEN_WIDTH as u32)
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    checks: usize,
+    failed: usize,
+}
+
+impl Report {
+    fn check(&mut self, ok: bool, what: &str) {
+        self.checks += 1;
+        if !ok {
+            self.failed += 1;
+            hprintln!("FAIL: {}", what).unwrap();
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let mut report = Report::default();
+    let mut model = Model::default();
+    let mut sampler = Sampler::default();
+
+    model.apply(ModelChange::Clear);
+    report.check(model.changed && model.clear, "clear redraws everything");
+
+    for second in 0..SECONDS {
+        let temps = read_sensors(second);
+        // the seconds of the RTC, from the midnight of its epoch
+        let change = sampler.sample(second as u32, temps);
+        let average_expected = second > 0 && second % PERIOD == 0;
+        report.check(
+            matches!(change, ModelChange::LastAndAverage(..)) == average_expected,
+            "average when a window of PERIOD seconds ends",
+        );
+        model.apply(change);
+        report.check(model.changed && !model.clear, "new temperatures are drawn");
+        report.check(model.last == temps, "last temperatures");
+
+        // the draw task draws both histories on the `ScreenType::Both` screen
+        let mut display = CountingDisplay::default();
+        for i in 0..2 {
+            let hist = Hist::new(
+                Point::new(0, 30 + i as i32 * 64),
+                Size::new(SCREEN_WIDTH as u32, 30),
+            );
+            hist.draw(&model.history[i], &mut display, Rgb565::GREEN, Rgb565::BLACK)
+                .unwrap();
+        }
+        let columns = model.history[0].len() + model.history[1].len();
+        report.check(display.outside == 0, "histograms inside the screen");
+        report.check(display.foreground >= columns, "a bar for every average");
+    }
+
+    let expected_len = ((SECONDS - 1) / PERIOD).min(SCREEN_WIDTH);
+    report.check(model.history[0].len() == expected_len, "history is full");
+    report.check(
+        model.history_starts.iter().last()
+            == Some(&((SECONDS - 1) / PERIOD * PERIOD - PERIOD) as u32),
+        "newest window start",
+    );
+    let first = read_sensors(0);
+    let last = read_sensors(SECONDS - 1);
+    report.check(model.min_or_max(false, 0) == last[0], "OAT min");
+    report.check(model.min_or_max(true, 0) == first[0], "OAT max");
+    report.check(model.min_or_max(false, 1) == first[1], "CAT min");
+    report.check(model.min_or_max(true, 1) == last[1], "CAT max");
+    let newest = model.history[1].iter().last().copied();
+    // the window of the last second is still being filled
+    let expected = Temp((*read_sensors(SECONDS - 4)[1] + *read_sensors(SECONDS - 3)[1]) / 2);
+    report.check(newest == Some(expected), "newest CAT average");
+
+    model.apply(ModelChange::Last(last));
+    report.check(!model.changed, "same temperatures are not redrawn");
+
+    model.apply(ModelChange::Unit(Unit::Fahrenheit));
+    report.check(model.clear && model.unit == Unit::Fahrenheit, "unit change");
+
+    let mut screen = ScreenType::default();
+    model.apply(ModelChange::ScreenType(screen.next()));
+    report.check(
+        model.clear && matches!(model.screen_type, ScreenType::Single(false)),
+        "screen change",
+    );
+
+    hprintln!("{} checks, {} failed", report.checks, report.failed).unwrap();
+    if report.failed == 0 {
+        debug::exit(debug::EXIT_SUCCESS);
+    } else {
+        debug::exit(debug::EXIT_FAILURE);
+    }
+
+    loop {}
+}
@@ -1,24 +1,64 @@
-use crate::types::{Instant, ENOUGH_TIME_BUTTON_PRESSED};
+use crate::types::{Instant, ENOUGH_TIME_BUTTON_PRESSED, LONG_PRESS};
+use e_button::Debouncer;
+use embedded_hal::digital::v2::InputPin;
 use stm32f1xx_hal::gpio::{ExtiPin, PinExt};
 
-pub struct Button<T: ExtiPin + PinExt> {
+/// How long the button has been kept pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Press {
+    Short,
+    /// Pressed for at least `LONG_PRESS`
+    Long,
+}
+
+pub struct Button<T: ExtiPin + PinExt + InputPin> {
     pub pin: T,
     pub last: Instant,
+    /// when the button has been pressed, if it's still down
+    pub down: Option<Instant>,
+    /// if some the pin is sampled when its interrupt is pended by a timer, instead of interrupting
+    /// on its edges
+    pub debouncer: Option<Debouncer>,
 }
 
-impl<T: ExtiPin + PinExt> Button<T> {
-    /// update last time is pressed, return if it is passed enough time from last time
-    pub fn pressed(&mut self, instant: Instant) -> bool {
+impl<T: ExtiPin + PinExt + InputPin> Button<T> {
+    /// to be called on both edges, or on every sample with a debouncer, return the press when the
+    /// button is released. Edges closer than `ENOUGH_TIME_BUTTON_PRESSED` to the last accepted one
+    /// are bounces and ignored
+    pub fn pressed(&mut self, instant: Instant) -> Option<Press> {
+        let is_down = self.pin.is_low().unwrap_or(false);
+        self.pin.clear_interrupt_pending_bit();
+        if let Some(debouncer) = self.debouncer.as_mut() {
+            let is_down = debouncer.sample(is_down)?;
+            return self.changed(is_down, instant);
+        }
         let enough_time_passed = (instant - self.last) > ENOUGH_TIME_BUTTON_PRESSED;
         defmt::debug!(
-            "pin{=u8} pressed at {=u64} last {=u64} enough time passed:{=bool}",
+            "pin{=u8} changed at {=u64} last {=u64} enough time passed:{=bool} down:{=bool}",
             self.pin.pin_id(),
             instant.ticks(),
             self.last.ticks(),
-            enough_time_passed
+            enough_time_passed,
+            is_down
         );
+        if !enough_time_passed {
+            return None;
+        }
         self.last = instant;
-        self.pin.clear_interrupt_pending_bit();
-        enough_time_passed
+        self.changed(is_down, instant)
+    }
+
+    fn changed(&mut self, is_down: bool, instant: Instant) -> Option<Press> {
+        if is_down {
+            self.down = Some(instant);
+            None
+        } else {
+            let down = self.down.take()?;
+            if (instant - down) >= LONG_PRESS {
+                Some(Press::Long)
+            } else {
+                Some(Press::Short)
+            }
+        }
     }
 }
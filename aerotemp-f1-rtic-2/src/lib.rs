@@ -0,0 +1,8 @@
+//! Hardware independent part of the firmware, used by the board binary and by the QEMU one
+//! (see `src/bin/qemu.rs`)
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod hist;
+pub mod model;
+pub mod node;
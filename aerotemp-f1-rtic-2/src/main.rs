@@ -1,35 +1,54 @@
 #![deny(unsafe_code)]
 // #![deny(warnings)]
-#![no_main]
-#![no_std]
+#![cfg_attr(not(test), no_main)]
+#![cfg_attr(not(test), no_std)]
 
 mod button;
-mod hist;
 mod screen;
-mod temp;
+mod sensor;
+mod storage;
 mod types;
-mod unit;
 
+#[cfg(not(test))]
 use defmt_rtt as _;
+#[cfg(not(test))]
 use panic_rtt_target as _;
+#[cfg(not(test))]
 use rtic::app;
 
-#[app(device = stm32f1xx_hal::pac, peripherals = true, dispatchers = [TAMPER])]
+#[cfg(not(test))]
+#[app(device = stm32f1xx_hal::pac, peripherals = true, dispatchers = [TAMPER, PVD])]
 mod app {
 
+    use bxcan::filter::Mask32;
+    use bxcan::{Data, Id, Interrupts, StandardId};
+    use core::fmt::Write;
     use ssd1351::builder::Builder;
+    use stm32f1xx_hal::can::Can;
     use stm32f1xx_hal::gpio::{Edge, ExtiPin};
+    use stm32f1xx_hal::pac::Interrupt;
     use stm32f1xx_hal::prelude::*;
+    use stm32f1xx_hal::rtc::Rtc;
     use stm32f1xx_hal::spi::Spi;
     use systick_monotonic::Systick;
 
-    use crate::button::Button;
-    use crate::hist::Hist;
+    use crate::button::{Button, Press};
     use crate::screen::{
-        draw_titles, text_small_white, text_temperature, Model, ModelChange, ScreenType,
+        draw_alarms, draw_icing, draw_icing_badge, draw_titles, text_small_white, text_temperature,
     };
+    use crate::sensor::Sensors;
+    use crate::storage::Storage;
     use crate::types::*;
-    use crate::unit::Unit;
+    use aero_core::alarm::Thresholds;
+    use aero_core::Unit;
+    use aerotemp_f1_rtic_2::hist::Hist;
+    use aerotemp_f1_rtic_2::model::{Model, ModelChange, Sampler, ScreenType};
+    use aerotemp_f1_rtic_2::node::{Params, NODE};
+    use can_core::protocol::server::Server;
+    use can_core::protocol::REQUEST;
+    use can_core::telemetry::{Readings, Telemetry, DEFAULT_BASE_ID};
+    use can_core::Frame;
+    use e_store::Store;
     use embedded_graphics::geometry::{Point, Size};
     use embedded_graphics::image::Image;
     use embedded_graphics::prelude::RgbColor;
@@ -42,20 +61,28 @@ mod app {
     use tinytga::DynamicTga;
 
     #[shared]
-    struct Shared {}
+    struct Shared {
+        can: Can1,
+    }
 
     #[local]
     struct Local {
         seconds: usize,
+        rtc: Rtc,
         pa0: Button<PA0>,
         pa1: Button<PA1>,
         display: Display,
-        latest_period: [Temps; PERIOD],
+        sensors: TempSensors,
+        sampler: Sampler,
 
         unit: Unit,
         screen: ScreenType,
 
         model: Model,
+        store: Store<Storage>,
+        server: Server,
+        /// The alarm thresholds of the model, answered to the reads of `node::THRESHOLDS`
+        thresholds: [Thresholds; 2],
     }
 
     #[monotonic(binds = SysTick, default = true)]
@@ -77,18 +104,37 @@ mod app {
             .pclk1(36.MHz())
             .freeze(&mut flash.acr);
 
+        // The RTC in the backup domain keeps the time across resets
+        let mut pwr = cx.device.PWR;
+        let mut backup_domain = rcc.bkp.constrain(cx.device.BKP, &mut pwr);
+        let mut rtc = Rtc::new(cx.device.RTC, &mut backup_domain);
+        rtc_core::set_clock_if_lost!(rtc, backup_domain);
+
+        // Restore the settings and the extremes saved in the last pages of the flash
+        let mut store = Store::new(Storage::new(flash)).unwrap();
+        let mut model = Model::default();
+        if model.load(&mut store).is_err() {
+            defmt::warn!("error loading the model from flash");
+        }
+
         // Setup Buttons
         let mut gpioa = cx.device.GPIOA.split();
+        let mut gpiob = cx.device.GPIOB.split();
 
+        // the interrupts of the sampled buttons are pended by `sample_buttons`, not by their edges
         let mut pa0 = gpioa.pa0.into_pull_up_input(&mut gpioa.crl);
         pa0.make_interrupt_source(&mut afio);
-        pa0.trigger_on_edge(&cx.device.EXTI, Edge::Rising);
-        pa0.enable_interrupt(&cx.device.EXTI);
+        pa0.trigger_on_edge(&cx.device.EXTI, Edge::RisingFalling);
+        if PA0_DEBOUNCER.is_none() {
+            pa0.enable_interrupt(&cx.device.EXTI);
+        }
 
         let mut pa1 = gpioa.pa1.into_pull_up_input(&mut gpioa.crl);
         pa1.make_interrupt_source(&mut afio);
-        pa1.trigger_on_edge(&cx.device.EXTI, Edge::Rising);
-        pa1.enable_interrupt(&cx.device.EXTI);
+        pa1.trigger_on_edge(&cx.device.EXTI, Edge::RisingFalling);
+        if PA1_DEBOUNCER.is_none() {
+            pa1.enable_interrupt(&cx.device.EXTI);
+        }
 
         // Setup display
         defmt::debug!("Setup display");
@@ -121,6 +167,57 @@ mod app {
         defmt::debug!("display init");
         display.set_rotation(DisplayRotation::Rotate180).unwrap();
 
+        // Setup temperature sensors, the MAX31865 share SPI2 with their own chip select and ready pins
+        defmt::debug!("Setup sensors");
+        let pins = (
+            gpiob.pb13.into_alternate_push_pull(&mut gpiob.crh), // sck
+            gpiob.pb14.into_floating_input(&mut gpiob.crh),      // miso
+            gpiob.pb15.into_alternate_push_pull(&mut gpiob.crh), // mosi
+        );
+        let spi = Spi::spi2(cx.device.SPI2, pins, max31865::MODE, 2_000_000.Hz(), clocks);
+        let manager = shared_bus_rtic::new!(spi, SPI2);
+
+        let nss_1 = gpiob.pb0.into_push_pull_output(&mut gpiob.crl);
+        let rdy_1 = gpiob.pb1.into_floating_input(&mut gpiob.crl);
+        let nss_2 = gpiob.pb11.into_push_pull_output(&mut gpiob.crh);
+        let rdy_2 = gpiob.pb10.into_floating_input(&mut gpiob.crh);
+
+        let sensors = Sensors::new(
+            manager.acquire(),
+            nss_1,
+            rdy_1,
+            manager.acquire(),
+            nss_2,
+            rdy_2,
+        )
+        .unwrap();
+
+        // Setup CAN1 to broadcast the telemetry and answer the parameter requests, a transceiver is
+        // connected to PA11 and PA12
+        defmt::debug!("Setup CAN");
+        let can = Can::new(cx.device.CAN1, cx.device.USB);
+        let rx = gpioa.pa11.into_floating_input(&mut gpioa.crh);
+        let tx = gpioa.pa12.into_alternate_push_pull(&mut gpioa.crh);
+        can.assign_pins((tx, rx), &mut afio.mapr);
+
+        let mut can = bxcan::Can::builder(can)
+            .set_bit_timing(CAN_BTR)
+            .leave_disabled();
+        // only the requests to this node are received, the filters are applied when `filters` is
+        // dropped
+        {
+            let request = StandardId::new(REQUEST + NODE as u16).unwrap();
+            let mut filters = can.modify_filters();
+            filters.clear();
+            filters.enable_bank(
+                0,
+                bxcan::Fifo::Fifo0,
+                Mask32::frames_with_std_id(request, StandardId::MAX),
+            );
+        }
+        can.enable_interrupts(Interrupts::FIFO0_MESSAGE_PENDING);
+        nb::block!(can.enable_non_blocking()).unwrap();
+
         let image_data = include_bytes!("../assets/logo_groppo_aviazione_128x128.tga");
         let tga = DynamicTga::from_slice(image_data).unwrap();
         defmt::debug!("loading dynamic image");
@@ -131,62 +228,71 @@ mod app {
 
         // Schedule the every_second task
         every_second::spawn_after(ONE_SEC).unwrap();
+        if PA0_DEBOUNCER.is_some() || PA1_DEBOUNCER.is_some() {
+            sample_buttons::spawn_after(SAMPLE_PERIOD).unwrap();
+        }
+
+        let thresholds = model.alarms.0.map(|alarm| alarm.thresholds());
 
         (
-            Shared {},
+            Shared { can },
             Local {
                 seconds: 0,
-                latest_period: [[0.into(), 0.into()]; PERIOD],
+                rtc,
+                sampler: Sampler::default(),
                 pa0: Button {
                     pin: pa0,
                     last: ZERO_INSTANT,
+                    down: None,
+                    debouncer: PA0_DEBOUNCER,
                 },
                 pa1: Button {
                     pin: pa1,
                     last: ZERO_INSTANT,
+                    down: None,
+                    debouncer: PA1_DEBOUNCER,
                 },
                 display,
-
-                unit: Unit::Celsius,
-                screen: ScreenType::Both,
-                model: Model::default(),
+                sensors,
+
+                unit: model.unit,
+                screen: model.screen_type,
+                model,
+                store,
+                server: Server::new(NODE),
+                thresholds,
             },
             init::Monotonics(mono),
         )
     }
 
-    #[task(local = [seconds, latest_period])]
+    #[task(local = [seconds, rtc, sensors, sampler])]
     fn every_second(cx: every_second::Context) {
         every_second::spawn_after(ONE_SEC).unwrap();
 
         let current = *cx.local.seconds;
         defmt::debug!("every_second {=usize}", current);
         if current == 0 {
-            draw::spawn(ModelChange::Clear).unwrap();
+            redraw(ModelChange::Clear);
         }
 
-        //TODO read from sensors
-        let temps = [(current as i16).into(), (-(current as i16)).into()];
-
-        cx.local.latest_period[current % PERIOD] = temps.clone();
-        let change = if ((current + 1) % PERIOD) == 0 {
-            let acc = cx.local.latest_period.iter().fold([0i32; 2], |acc, x| {
-                [acc[0] + x[0].0 as i32, acc[1] + x[1].0 as i32]
-            });
-            let average = [
-                ((acc[0] / PERIOD as i32) as i16).into(),
-                ((acc[1] / PERIOD as i32) as i16).into(),
-            ];
-            ModelChange::LastAndAverage(temps, average)
-        } else {
-            ModelChange::Last(temps)
+        let temps = match cx.local.sensors.read() {
+            Ok(temps) => temps,
+            Err(_) => {
+                defmt::warn!("error reading sensors");
+                redraw(ModelChange::SensorError);
+                *cx.local.seconds += 1;
+                return;
+            }
         };
 
-        draw::spawn(change).unwrap();
+        let change = cx.local.sampler.sample(cx.local.rtc.current_time(), temps);
+
+        redraw(change);
         *cx.local.seconds += 1;
     }
 
-    #[task(capacity = 3, local = [display, model, buffer: String<32> = String::new()])]
+    #[task(capacity = 3, local = [display, model, store, last_save: Instant = ZERO_INSTANT, last_telemetry: Instant = ZERO_INSTANT, buffer: String<32> = String::new()])]
     fn draw(cx: draw::Context, changes: ModelChange) {
         defmt::debug!("draw {}", changes);
 
@@ -196,6 +302,26 @@ mod app {
 
         model.apply(changes);
 
+        // settings change on user input so they are saved immediately, while extremes may change
+        // every second and are saved at most every `SAVE_EXTREMES_EVERY` to spare the flash
+        let store = cx.local.store;
+        if model.settings_changed && model.save_settings(store).is_err() {
+            defmt::warn!("error saving settings");
+        }
+        let now = monotonics::now();
+        if model.extremes_changed && (now - *cx.local.last_save) > SAVE_EXTREMES_EVERY {
+            *cx.local.last_save = now;
+            if model.save_extremes(store).is_err() {
+                defmt::warn!("error saving extremes");
+            }
+        }
+        if (now - *cx.local.last_telemetry) >= TELEMETRY_PERIOD {
+            *cx.local.last_telemetry = now;
+            if telemetry::spawn(model.readings()).is_err() {
+                defmt::warn!("telemetry still transmitting the previous readings");
+            }
+        }
+
         if model.changed {
             if model.clear {
                 display.clear();
@@ -209,7 +335,7 @@ mod app {
                         text_temperature(
                             display,
                             &mut buffer,
-                            32,
+                            44, // `WIDTH_WITH_UNIT` characters of 12 pixels, right aligned
                             6 + i as i32 * 64,
                             false,
                             last[i],
@@ -227,6 +353,7 @@ mod app {
                         )
                         .unwrap();
                     }
+                    draw_icing_badge(display, &mut buffer, model.icing);
                 }
                 ScreenType::Single(i) => {
                     let i = i as usize;
@@ -234,6 +361,11 @@ mod app {
                     let hist = Hist::new(Point::new(0, 53), Size::new(SCREEN_WIDTH as u32, 45));
                     hist.draw(&model.history[i], display, RgbColor::GREEN, RgbColor::BLACK)
                         .unwrap();
+                    // the clock time of the history, below it
+                    if let Some(span) = model.history_span() {
+                        write!(buffer, "{}", span).unwrap();
+                        text_small_white(display, buffer, 0, 99);
+                    }
                     for b in 0..2 {
                         buffer.push_str(MIN_OR_MAX[b]).unwrap();
                         model
@@ -242,25 +374,130 @@ mod app {
                         text_small_white(display, buffer, b as i32 * 68, 110);
                     }
                 }
+                ScreenType::Icing => {
+                    draw_icing(display, &mut buffer, last, model.icing, model.unit)
+                }
+            }
+            draw_alarms(
+                display,
+                &mut buffer,
+                model.screen_type,
+                &model.alarms,
+                model.blink,
+            );
+        }
+    }
+
+    #[task(shared = [can], local = [telemetry: Telemetry = Telemetry::new(DEFAULT_BASE_ID)])]
+    fn telemetry(mut cx: telemetry::Context, readings: Readings) {
+        let frames = cx.local.telemetry.encode(&readings);
+        cx.shared.can.lock(|can| {
+            for frame in frames {
+                // without other nodes acknowledging, the mailboxes stay full and frames are dropped
+                if can.transmit(&to_bxcan(&frame)).is_err() {
+                    defmt::warn!("no free mailbox, telemetry frame dropped");
+                }
             }
+        });
+    }
+
+    /// The parameter requests to `NODE`, a write of the thresholds is applied to the model by
+    /// `draw`, which saves them in flash
+    #[task(binds = USB_LP_CAN_RX0, shared = [can], local = [server, thresholds])]
+    fn can_rx0(mut cx: can_rx0::Context) {
+        let server = cx.local.server;
+        let mut params = Params {
+            thresholds: cx.local.thresholds,
+            uptime: (monotonics::now().ticks() / 1_000) as u32,
+            apply: redraw,
+        };
+        cx.shared.can.lock(|can| loop {
+            let request = match can.rx0().receive() {
+                Ok(frame) => frame,
+                Err(nb::Error::Other(_overrun)) => {
+                    defmt::warn!("CAN receive overrun, request dropped");
+                    continue;
+                }
+                Err(nb::Error::WouldBlock) => break,
+            };
+            let request = match (request.id(), request.data()) {
+                (Id::Standard(id), Some(data)) => Frame::new(id.as_raw(), data),
+                _ => None,
+            };
+            let response = request.and_then(|request| server.handle(&request, &mut params));
+            if let Some(response) = response {
+                if can.transmit(&to_bxcan(&response)).is_err() {
+                    defmt::warn!("no free mailbox, response dropped");
+                }
+            }
+        });
+    }
+
+    fn to_bxcan(frame: &Frame) -> bxcan::Frame {
+        let id = StandardId::new(frame.id()).unwrap();
+        bxcan::Frame::new_data(id, Data::new(frame.data()).unwrap())
+    }
+
+    /// Pends the interrupts of the sampled buttons, their tasks feed the samples to the debouncers.
+    /// At the priority of the buttons, so that drawing doesn't delay the samples
+    #[task(priority = 2)]
+    fn sample_buttons(_: sample_buttons::Context) {
+        sample_buttons::spawn_after(SAMPLE_PERIOD).unwrap();
+        if PA0_DEBOUNCER.is_some() {
+            rtic::pend(Interrupt::EXTI0);
+        }
+        if PA1_DEBOUNCER.is_some() {
+            rtic::pend(Interrupt::EXTI1);
         }
     }
 
     #[task(binds = EXTI0, priority = 2, local = [pa0, screen])]
     fn exti0(cx: exti0::Context) {
-        if cx.local.pa0.pressed(monotonics::now()) {
-            let new = cx.local.screen.next();
-            defmt::debug!("screen {}", new);
-            draw::spawn(ModelChange::ScreenType(new)).unwrap();
+        match cx.local.pa0.pressed(monotonics::now()) {
+            Some(Press::Short) => {
+                let shown = *cx.local.screen;
+                let new = cx.local.screen.next();
+                defmt::debug!("screen {}", new);
+                if !redraw(ModelChange::ScreenType(new)) {
+                    // the press is dropped, the next one starts from the screen shown
+                    *cx.local.screen = shown;
+                }
+            }
+            Some(Press::Long) => {
+                redraw(ModelChange::Acknowledge);
+            }
+            None => (),
         }
     }
 
     #[task(binds = EXTI1, priority = 2, local = [pa1, unit])]
     fn exti1(cx: exti1::Context) {
-        if cx.local.pa1.pressed(monotonics::now()) {
-            let new = cx.local.unit.next();
-            defmt::debug!("unit {}", new);
-            draw::spawn(ModelChange::Unit(new)).unwrap();
+        match cx.local.pa1.pressed(monotonics::now()) {
+            Some(Press::Short) => {
+                let shown = *cx.local.unit;
+                let new = cx.local.unit.next();
+                defmt::debug!("unit {}", new);
+                if !redraw(ModelChange::Unit(new)) {
+                    // the press is dropped, the next one starts from the unit shown
+                    *cx.local.unit = shown;
+                }
+            }
+            Some(Press::Long) => {
+                redraw(ModelChange::Acknowledge);
+            }
+            None => (),
+        }
+    }
+
+    /// Spawn `draw` with `change`, false if its queue is full: the display is still busy with the
+    /// previous redraws, eg. a burst of presses during a slow SPI transfer, and `change` is dropped
+    fn redraw(change: ModelChange) -> bool {
+        match draw::spawn(change) {
+            Ok(()) => true,
+            Err(change) => {
+                defmt::warn!("draw queue full, {} dropped", change);
+                false
+            }
         }
     }
 }
@@ -0,0 +1,598 @@
+//! model
+//!
+//! This module contains what is shown on screen and how it changes, it doesn't depend on the
+//! hardware so it runs also on QEMU
+//!
+
+use aero_core::alarm::{Alarm, Thresholds, DEFAULT_THRESHOLDS};
+use aero_core::icing::{self, Risk};
+use aero_core::minmax::MinMax;
+use aero_core::{Temp, Unit};
+use can_core::telemetry::Readings;
+use defmt::Format;
+use e_series::{Reducer, Window};
+use e_store::{Error, Flash, Store};
+use heapless::spsc::Queue;
+use rtc_core::Span;
+
+pub type Temps = [Temp; 2];
+
+pub const PERIOD: usize = 2;
+pub const SCREEN_WIDTH: usize = 128;
+pub const SCREEN_WIDTH_PLUS_1: usize = SCREEN_WIDTH + 1;
+
+/// Key of the record with unit and screen type in the flash store
+const KEY_SETTINGS: u8 = 0;
+/// Key of the record with the extremes of both temperatures in the flash store
+const KEY_EXTREMES: u8 = 1;
+/// Key of the record with the alarm thresholds of both temperatures in the flash store
+const KEY_THRESHOLDS: u8 = 2;
+/// Version of the records layout, increase it when the encoding changes
+const RECORDS_VERSION: u8 = 0;
+
+#[derive(Copy, Clone, Format, Debug)]
+pub enum ScreenType {
+    Both,
+    Single(bool),
+    /// The carburettor icing risk
+    Icing,
+}
+
+impl ScreenType {
+    pub fn next(&mut self) -> Self {
+        *self = match self {
+            ScreenType::Both => ScreenType::Single(false),
+            ScreenType::Single(false) => ScreenType::Single(true),
+            ScreenType::Single(true) => ScreenType::Icing,
+            ScreenType::Icing => ScreenType::Both,
+        };
+        *self
+    }
+}
+
+impl Default for ScreenType {
+    fn default() -> Self {
+        ScreenType::Both
+    }
+}
+
+#[derive(Debug, Format)]
+pub enum ModelChange {
+    Last(Temps),
+    /// The last temperatures and the average of the window of `PERIOD` seconds starting at the
+    /// seconds of the RTC
+    LastAndAverage(Temps, Temps, u32),
+    Unit(Unit),
+    ScreenType(ScreenType),
+    Clear,
+    /// Acknowledge the active alarms
+    Acknowledge,
+    /// Set the alarm thresholds of both temperatures
+    Thresholds([Thresholds; 2]),
+    /// Reading the temperatures failed
+    SensorError,
+}
+
+/// The alarms of both temperatures
+#[derive(Debug)]
+pub struct Alarms(pub [Alarm; 2]);
+
+impl Default for Alarms {
+    fn default() -> Self {
+        Alarms(DEFAULT_THRESHOLDS.map(Alarm::new))
+    }
+}
+
+impl Alarms {
+    /// Update the alarms with the last temperatures, return true if any changed state
+    fn update(&mut self, temps: Temps) -> bool {
+        let mut changed = false;
+        for (alarm, temp) in self.0.iter_mut().zip(temps) {
+            changed |= alarm.update(temp);
+        }
+        changed
+    }
+
+    /// Acknowledge the active alarms, return true if any changed state
+    fn acknowledge(&mut self) -> bool {
+        let mut changed = false;
+        for alarm in self.0.iter_mut() {
+            changed |= alarm.acknowledge();
+        }
+        changed
+    }
+
+    /// Any alarm is active and not acknowledged
+    pub fn any_active(&self) -> bool {
+        self.0.iter().any(Alarm::is_active)
+    }
+}
+
+#[derive(Default)]
+pub struct Model {
+    pub last: Temps,
+    pub min_max: [MinMax; 2],
+    pub history: [Queue<Temp, SCREEN_WIDTH_PLUS_1>; 2],
+    /// Seconds of the RTC of the start of the windows averaged in `history`
+    pub history_starts: Queue<u32, SCREEN_WIDTH_PLUS_1>,
+    pub unit: Unit,
+    pub screen_type: ScreenType,
+    pub changed: bool,
+    pub clear: bool,
+    /// unit or screen type changed since they were saved
+    pub settings_changed: bool,
+    /// extremes changed since they were saved
+    pub extremes_changed: bool,
+    pub alarms: Alarms,
+    /// toggled at every sample, active alarms are shown only when true so that they flash
+    pub blink: bool,
+    /// carburettor icing risk of the last temperatures
+    pub icing: Risk,
+    /// consecutive failed reads of the temperatures, `last` are the last good ones
+    pub read_errors: u8,
+}
+
+impl Model {
+    fn update_min_max(&mut self, temps: Temps) {
+        for i in 0..2 {
+            self.extremes_changed |= self.min_max[i].update(temps[i]);
+        }
+    }
+    fn update_icing(&mut self) {
+        // no humidity sensor is wired, the risk comes only from the temperatures
+        let icing = icing::risk(self.last[0], self.last[1], None);
+        if icing != self.icing {
+            self.changed = true;
+            self.icing = icing;
+        }
+    }
+    fn update_alarms(&mut self, temps: Temps) {
+        let changed = self.alarms.update(temps);
+        self.blink = !self.blink;
+        // active alarms must be redrawn to flash even if temperatures didn't change
+        self.changed |= changed || self.alarms.any_active();
+    }
+    pub fn apply(&mut self, changes: ModelChange) {
+        match changes {
+            ModelChange::Last(last) => {
+                self.clear = false;
+                self.read_errors = 0;
+                if last != self.last {
+                    self.changed = true;
+                    self.last = last;
+                    self.update_min_max(last);
+                } else {
+                    self.changed = false;
+                }
+                self.update_alarms(last);
+                self.update_icing();
+            }
+            ModelChange::LastAndAverage(last, average, start) => {
+                self.changed = true;
+                self.clear = false;
+                self.read_errors = 0;
+                self.last = last;
+                self.update_min_max(last);
+                for i in 0..2 {
+                    if self.history[i].len() == SCREEN_WIDTH {
+                        self.history[i].dequeue();
+                    }
+                    self.history[i].enqueue(average[i]).unwrap();
+                }
+                if self.history_starts.len() == SCREEN_WIDTH {
+                    self.history_starts.dequeue();
+                }
+                self.history_starts.enqueue(start).unwrap();
+                self.update_alarms(last);
+                self.update_icing();
+            }
+            ModelChange::Unit(unit) => {
+                self.changed = true;
+                self.clear = true;
+                self.settings_changed = true;
+                self.unit = unit;
+            }
+            ModelChange::ScreenType(screen_type) => {
+                self.changed = true;
+                self.clear = true;
+                self.settings_changed = true;
+                self.screen_type = screen_type;
+            }
+            ModelChange::Clear => {
+                self.changed = true;
+                self.clear = true;
+            }
+            ModelChange::Acknowledge => {
+                self.clear = false;
+                self.changed = self.alarms.acknowledge();
+            }
+            ModelChange::SensorError => {
+                self.changed = false;
+                self.clear = false;
+                self.read_errors = self.read_errors.saturating_add(1);
+            }
+            ModelChange::Thresholds(thresholds) => {
+                self.changed = false;
+                self.clear = false;
+                self.settings_changed = true;
+                for (alarm, thresholds) in self.alarms.0.iter_mut().zip(thresholds) {
+                    alarm.set_thresholds(thresholds);
+                }
+            }
+        }
+    }
+
+    pub fn min_or_max(&self, max: bool, index: usize) -> Temp {
+        let min_max = &self.min_max[index];
+        if max {
+            min_max.max().unwrap_or_default()
+        } else {
+            min_max.min().unwrap_or_default()
+        }
+    }
+
+    /// The clock time covered by `history`, from the start of the oldest average to the end of the
+    /// newest. `None` if the clock went backwards in the meantime, eg. set again after the backup
+    /// domain lost its power
+    pub fn history_span(&self) -> Option<Span> {
+        let oldest = *self.history_starts.iter().next()?;
+        let newest = *self.history_starts.iter().last()?;
+        Some(Span {
+            start: oldest,
+            interval: newest.checked_sub(oldest)?.saturating_add(PERIOD as u32),
+        })
+    }
+
+    /// What is broadcast on the CAN bus
+    pub fn readings(&self) -> Readings {
+        Readings {
+            temps: self.last,
+            extremes: [self.min_max[0].extremes(), self.min_max[1].extremes()],
+            alarms: [self.alarms.0[0].state(), self.alarms.0[1].state()],
+            icing: self.icing,
+            read_errors: self.read_errors,
+        }
+    }
+
+    /// Restore unit, screen type and extremes from the flash `store`, records missing or written
+    /// with another layout are ignored
+    pub fn load<F: Flash>(&mut self, store: &mut Store<F>) -> Result<(), Error<F::Error>> {
+        let mut buf = [0u8; 10];
+        if store.read(KEY_SETTINGS, RECORDS_VERSION, &mut buf)? == Some(2) {
+            if let (Some(unit), Some(screen_type)) = (decode_unit(buf[0]), decode_screen(buf[1])) {
+                self.unit = unit;
+                self.screen_type = screen_type;
+            }
+        }
+        let mut thresholds = [0u8; 14];
+        if store.read(KEY_THRESHOLDS, RECORDS_VERSION, &mut thresholds)? == Some(14) {
+            for (alarm, bytes) in self.alarms.0.iter_mut().zip(thresholds.chunks(7)) {
+                alarm.set_thresholds(decode_thresholds(bytes));
+            }
+        }
+        if store.read(KEY_EXTREMES, RECORDS_VERSION, &mut buf)? == Some(10) {
+            for (min_max, bytes) in self.min_max.iter_mut().zip(buf.chunks(5)) {
+                if bytes[0] == 1 {
+                    let min = i16::from_le_bytes([bytes[1], bytes[2]]);
+                    let max = i16::from_le_bytes([bytes[3], bytes[4]]);
+                    *min_max = MinMax::new(min.into(), max.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Save unit, screen type and alarm thresholds in the flash `store`
+    pub fn save_settings<F: Flash>(&mut self, store: &mut Store<F>) -> Result<(), Error<F::Error>> {
+        let data = [encode_unit(self.unit), encode_screen(self.screen_type)];
+        store.write(KEY_SETTINGS, RECORDS_VERSION, &data)?;
+        let mut data = [0u8; 14];
+        for (alarm, bytes) in self.alarms.0.iter().zip(data.chunks_mut(7)) {
+            encode_thresholds(alarm.thresholds(), bytes);
+        }
+        store.write(KEY_THRESHOLDS, RECORDS_VERSION, &data)?;
+        self.settings_changed = false;
+        Ok(())
+    }
+
+    /// Save the extremes of both temperatures in the flash `store`
+    pub fn save_extremes<F: Flash>(&mut self, store: &mut Store<F>) -> Result<(), Error<F::Error>> {
+        let mut data = [0u8; 10];
+        for (min_max, bytes) in self.min_max.iter().zip(data.chunks_mut(5)) {
+            if let Some((min, max)) = min_max.extremes() {
+                bytes[0] = 1;
+                bytes[1..3].copy_from_slice(&min.0.to_le_bytes());
+                bytes[3..5].copy_from_slice(&max.0.to_le_bytes());
+            }
+        }
+        store.write(KEY_EXTREMES, RECORDS_VERSION, &data)?;
+        self.extremes_changed = false;
+        Ok(())
+    }
+}
+
+fn encode_unit(unit: Unit) -> u8 {
+    match unit {
+        Unit::Celsius => 0,
+        Unit::Fahrenheit => 1,
+    }
+}
+
+fn decode_unit(byte: u8) -> Option<Unit> {
+    match byte {
+        0 => Some(Unit::Celsius),
+        1 => Some(Unit::Fahrenheit),
+        _ => None,
+    }
+}
+
+/// Encode `thresholds` in 7 bytes: a flag byte with bit 0 set if `low` is present and bit 1 if
+/// `high` is, followed by low, high and hysteresis as little endian
+pub(crate) fn encode_thresholds(thresholds: Thresholds, bytes: &mut [u8]) {
+    let low = thresholds.low.unwrap_or_default();
+    let high = thresholds.high.unwrap_or_default();
+    bytes[0] = thresholds.low.is_some() as u8 | (thresholds.high.is_some() as u8) << 1;
+    bytes[1..3].copy_from_slice(&low.0.to_le_bytes());
+    bytes[3..5].copy_from_slice(&high.0.to_le_bytes());
+    bytes[5..7].copy_from_slice(&thresholds.hysteresis.0.to_le_bytes());
+}
+
+pub(crate) fn decode_thresholds(bytes: &[u8]) -> Thresholds {
+    let temp = |i: usize| Temp(i16::from_le_bytes([bytes[i], bytes[i + 1]]));
+    Thresholds {
+        low: (bytes[0] & 1 != 0).then(|| temp(1)),
+        high: (bytes[0] & 2 != 0).then(|| temp(3)),
+        hysteresis: temp(5),
+    }
+}
+
+fn encode_screen(screen_type: ScreenType) -> u8 {
+    match screen_type {
+        ScreenType::Both => 0,
+        ScreenType::Single(false) => 1,
+        ScreenType::Single(true) => 2,
+        ScreenType::Icing => 3,
+    }
+}
+
+fn decode_screen(byte: u8) -> Option<ScreenType> {
+    match byte {
+        0 => Some(ScreenType::Both),
+        1 => Some(ScreenType::Single(false)),
+        2 => Some(ScreenType::Single(true)),
+        3 => Some(ScreenType::Icing),
+        _ => None,
+    }
+}
+
+/// Turns the temperatures read every second into the `ModelChange` to apply, adding the average
+/// of the previous window when a window of `PERIOD` seconds of the RTC ends. The windows are
+/// aligned to the clock, a window without readings is skipped
+#[derive(Default)]
+pub struct Sampler {
+    /// `seconds / PERIOD` of the window being filled
+    index: u32,
+    windows: [Window; 2],
+}
+
+impl Sampler {
+    /// Sample `temps` read at `seconds` of the RTC
+    pub fn sample(&mut self, seconds: u32, temps: Temps) -> ModelChange {
+        let index = seconds / PERIOD as u32;
+        let mut average = None;
+        if index != self.index {
+            if let (Some(oat), Some(cat)) = (
+                self.windows[0].reduce(Reducer::Mean),
+                self.windows[1].reduce(Reducer::Mean),
+            ) {
+                average = Some(([Temp(oat), Temp(cat)], self.index * PERIOD as u32));
+            }
+            self.index = index;
+            self.windows = [Window::new(); 2];
+        }
+        for (window, temp) in self.windows.iter_mut().zip(temps) {
+            window.add(temp.0);
+        }
+        match average {
+            Some((average, start)) => ModelChange::LastAndAverage(temps, average, start),
+            None => ModelChange::Last(temps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use e_store::RamFlash;
+
+    #[test]
+    fn test_save_load() {
+        let mut store = Store::new(RamFlash::<1024, 2>::default()).unwrap();
+        let mut model = Model::default();
+        model.load(&mut store).unwrap();
+        assert_eq!(model.unit, Unit::Celsius);
+        assert_eq!(model.min_max[0].extremes(), None);
+
+        model.apply(ModelChange::Unit(Unit::Fahrenheit));
+        model.apply(ModelChange::ScreenType(ScreenType::Single(true)));
+        assert!(model.settings_changed);
+        model.save_settings(&mut store).unwrap();
+        assert!(!model.settings_changed);
+
+        model.apply(ModelChange::Last([Temp(-500), Temp(100)]));
+        model.apply(ModelChange::Last([Temp(1200), Temp(100)]));
+        assert!(model.extremes_changed);
+        model.save_extremes(&mut store).unwrap();
+
+        let mut store = Store::new(store.release()).unwrap();
+        let mut restored = Model::default();
+        restored.load(&mut store).unwrap();
+        assert_eq!(restored.unit, Unit::Fahrenheit);
+        assert!(matches!(restored.screen_type, ScreenType::Single(true)));
+        assert_eq!(restored.min_max, model.min_max);
+    }
+
+    #[test]
+    fn test_alarms() {
+        use aero_core::alarm::{Kind, State};
+
+        let mut model = Model::default();
+        model.apply(ModelChange::Last([Temp(1000), Temp(1000)]));
+        assert!(!model.alarms.any_active());
+
+        // the same temperatures don't need a redraw, unless an alarm is flashing
+        model.apply(ModelChange::Last([Temp(1000), Temp(1000)]));
+        assert!(!model.changed);
+        model.apply(ModelChange::Last([Temp(4600), Temp(1000)]));
+        assert_eq!(model.alarms.0[0].state(), State::Active(Kind::High));
+        assert_eq!(model.alarms.0[1].state(), State::Cleared);
+        let blink = model.blink;
+        model.apply(ModelChange::Last([Temp(4600), Temp(1000)]));
+        assert!(model.changed);
+        assert_ne!(blink, model.blink);
+
+        model.apply(ModelChange::Acknowledge);
+        assert!(model.changed);
+        assert!(!model.alarms.any_active());
+        assert_eq!(model.alarms.0[0].state(), State::Acknowledged(Kind::High));
+        model.apply(ModelChange::Acknowledge);
+        assert!(!model.changed);
+        model.apply(ModelChange::Last([Temp(4600), Temp(1000)]));
+        assert!(!model.changed);
+
+        // within the hysteresis
+        model.apply(ModelChange::LastAndAverage(
+            [Temp(4450), Temp(1000)],
+            [Temp(4450), Temp(1000)],
+            0,
+        ));
+        assert_eq!(model.alarms.0[0].state(), State::Acknowledged(Kind::High));
+        model.apply(ModelChange::Last([Temp(4400), Temp(-1100)]));
+        assert!(model.changed);
+        assert_eq!(model.alarms.0[0].state(), State::Cleared);
+        assert_eq!(model.alarms.0[1].state(), State::Active(Kind::Low));
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut store = Store::new(RamFlash::<1024, 2>::default()).unwrap();
+        let mut model = Model::default();
+        let thresholds = [
+            Thresholds {
+                low: None,
+                high: Some(Temp(3000)),
+                hysteresis: Temp(50),
+            },
+            Thresholds::DISABLED,
+        ];
+        model.apply(ModelChange::Thresholds(thresholds));
+        assert!(model.settings_changed);
+        model.apply(ModelChange::Last([Temp(3001), Temp(i16::MIN)]));
+        assert!(model.alarms.0[0].is_active());
+        assert!(!model.alarms.0[1].is_active());
+        model.save_settings(&mut store).unwrap();
+
+        let mut restored = Model::default();
+        assert_eq!(restored.alarms.0[0].thresholds(), DEFAULT_THRESHOLDS[0]);
+        restored.load(&mut store).unwrap();
+        assert_eq!(restored.alarms.0[0].thresholds(), thresholds[0]);
+        assert_eq!(restored.alarms.0[1].thresholds(), thresholds[1]);
+    }
+
+    #[test]
+    fn test_icing() {
+        let mut model = Model::default();
+        assert_eq!(model.icing, Risk::Low);
+
+        // CAT in the arc of the carb temperature gauge
+        model.apply(ModelChange::Last([Temp(1500), Temp(0)]));
+        assert_eq!(model.icing, Risk::Moderate);
+
+        // partial carb heat, CAT just above the arc
+        model.apply(ModelChange::Last([Temp(1500), Temp(800)]));
+        assert_eq!(model.icing, Risk::Light);
+
+        // carb heat on
+        model.apply(ModelChange::Last([Temp(1500), Temp(2500)]));
+        assert_eq!(model.icing, Risk::Low);
+    }
+
+    #[test]
+    fn test_screen_type() {
+        let mut screen = ScreenType::default();
+        for expected in 1..=4 {
+            let code = encode_screen(screen.next());
+            assert_eq!(code, expected % 4);
+            assert!(matches!(decode_screen(code), Some(s) if encode_screen(s) == code));
+        }
+        assert!(matches!(screen, ScreenType::Both));
+        assert!(decode_screen(4).is_none());
+    }
+
+    #[test]
+    fn test_sampler() {
+        // the RTC at 03:59:56, the windows start at even seconds of the clock
+        let three = 3 * 3_600;
+        let mut sampler = Sampler::default();
+        let mut model = Model::default();
+        let mut starts = vec![];
+        for seconds in three + 3_596..three + 3_610 {
+            let temps = [Temp(seconds as i16 % 100), Temp(0)];
+            let change = sampler.sample(seconds, temps);
+            if let ModelChange::LastAndAverage(_, average, start) = change {
+                starts.push(start);
+                // the mean of the two readings of the window, truncated
+                assert_eq!(average[0], Temp((start as i16 % 100 * 2 + 1) / 2));
+            }
+            model.apply(change);
+        }
+        let expected: Vec<u32> = (three + 3_596..three + 3_608).step_by(PERIOD).collect();
+        assert_eq!(starts, expected);
+        assert!(model.history_starts.iter().eq(expected.iter()));
+        assert_eq!(model.history[0].len(), expected.len());
+        let span = model.history_span().unwrap();
+        assert_eq!(span.to_string(), "03:59:56-04:00:08");
+
+        // the clock set backwards
+        let mut backwards = Model::default();
+        backwards.apply(ModelChange::LastAndAverage(
+            [Temp(0); 2],
+            [Temp(0); 2],
+            three,
+        ));
+        assert!(backwards.history_span().is_some());
+        backwards.apply(ModelChange::LastAndAverage([Temp(0); 2], [Temp(0); 2], 10));
+        assert_eq!(backwards.history_span(), None);
+
+        // a missing reading leaves a gap in the starts
+        let mut sampler = Sampler::default();
+        sampler.sample(10, [Temp(0); 2]);
+        assert!(matches!(
+            sampler.sample(14, [Temp(0); 2]),
+            ModelChange::LastAndAverage(_, _, 10)
+        ));
+    }
+
+    #[test]
+    fn test_readings() {
+        use aero_core::alarm::{Kind, State};
+
+        let mut model = Model::default();
+        assert_eq!(model.readings(), Readings::default());
+
+        model.apply(ModelChange::Last([Temp(-2100), Temp(0)]));
+        model.apply(ModelChange::Last([Temp(-1950), Temp(100)]));
+        for _ in 0..300 {
+            model.apply(ModelChange::SensorError);
+        }
+        let readings = model.readings();
+        assert_eq!(readings.temps, [Temp(-1950), Temp(100)]);
+        assert_eq!(readings.extremes[0], Some((Temp(-2100), Temp(-1950))));
+        assert_eq!(readings.alarms[0], State::Active(Kind::Low));
+        assert_eq!(readings.icing, Risk::Moderate);
+        assert_eq!(readings.read_errors, u8::MAX);
+
+        model.apply(ModelChange::Last([Temp(-1950), Temp(100)]));
+        assert_eq!(model.readings().read_errors, 0);
+    }
+}
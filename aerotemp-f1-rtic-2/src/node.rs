@@ -0,0 +1,148 @@
+//! node
+//!
+//! The parameters of the gauge on the bus, read and written through `can_core::protocol`
+//!
+
+use crate::model::{decode_thresholds, encode_thresholds, ModelChange};
+use aero_core::alarm::Thresholds;
+use can_core::protocol::server::Parameters;
+use can_core::protocol::{index, Abort, Value};
+
+/// Node id of the gauge, the can nodes are 1 and 2
+pub const NODE: u8 = 3;
+
+/// The alarm thresholds of OAT then CAT, 7 bytes each as in the flash record (see
+/// `encode_thresholds`), writable
+pub const THRESHOLDS: u16 = 0x0100;
+
+const NAME: &[u8] = b"aerotemp";
+
+/// The parameters at the time of a request
+pub struct Params<'a> {
+    /// The thresholds applied to the model
+    pub thresholds: &'a mut [Thresholds; 2],
+    /// Seconds since the gauge started
+    pub uptime: u32,
+    /// Pass a change to the model, false if it can't take it now
+    pub apply: fn(ModelChange) -> bool,
+}
+
+impl Parameters for Params<'_> {
+    fn read(&mut self, index: u16) -> Result<Value, Abort> {
+        let value = match index {
+            index::NAME => Value::from_slice(NAME),
+            index::UPTIME => Value::from_slice(&self.uptime.to_le_bytes()),
+            THRESHOLDS => {
+                let mut bytes = [0u8; 14];
+                for (thresholds, bytes) in self.thresholds.iter().zip(bytes.chunks_mut(7)) {
+                    encode_thresholds(*thresholds, bytes);
+                }
+                Value::from_slice(&bytes)
+            }
+            _ => return Err(Abort::UnknownParameter),
+        };
+        // all the values fit
+        Ok(value.unwrap())
+    }
+
+    fn write(&mut self, index: u16, value: &[u8]) -> Result<(), Abort> {
+        match index {
+            THRESHOLDS => {
+                let thresholds = parse_thresholds(value)?;
+                if !(self.apply)(ModelChange::Thresholds(thresholds)) {
+                    return Err(Abort::Busy);
+                }
+                *self.thresholds = thresholds;
+                Ok(())
+            }
+            index::NAME | index::UPTIME => Err(Abort::ReadOnly),
+            _ => Err(Abort::UnknownParameter),
+        }
+    }
+}
+
+/// The thresholds of both temperatures in `value`, refused if a flag byte has unknown bits, the
+/// hysteresis is negative or low is not below high
+fn parse_thresholds(value: &[u8]) -> Result<[Thresholds; 2], Abort> {
+    match value.len() {
+        14 => (),
+        len if len > 14 => return Err(Abort::TooLong),
+        _ => return Err(Abort::InvalidValue),
+    }
+    let mut thresholds = [Thresholds::DISABLED; 2];
+    for (thresholds, bytes) in thresholds.iter_mut().zip(value.chunks(7)) {
+        *thresholds = decode_thresholds(bytes);
+        let crossed = matches!((thresholds.low, thresholds.high), (Some(l), Some(h)) if l >= h);
+        if bytes[0] > 3 || thresholds.hysteresis.0 < 0 || crossed {
+            return Err(Abort::InvalidValue);
+        }
+    }
+    Ok(thresholds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aero_core::alarm::DEFAULT_THRESHOLDS;
+    use aero_core::Temp;
+
+    fn accept(_: ModelChange) -> bool {
+        true
+    }
+
+    fn refuse(_: ModelChange) -> bool {
+        false
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut thresholds = DEFAULT_THRESHOLDS;
+        let mut params = Params {
+            thresholds: &mut thresholds,
+            uptime: 7,
+            apply: accept,
+        };
+        let value = params.read(THRESHOLDS).unwrap();
+        assert_eq!(value.len(), 14);
+        assert_eq!(parse_thresholds(&value), Ok(DEFAULT_THRESHOLDS));
+        assert_eq!(
+            &params.read(index::UPTIME).unwrap()[..],
+            &7u32.to_le_bytes()
+        );
+
+        let mut value = value;
+        // OAT high at 40.00°C
+        value[3..5].copy_from_slice(&4000i16.to_le_bytes());
+        params.write(THRESHOLDS, &value).unwrap();
+        assert_eq!(params.thresholds[0].high, Some(Temp(4000)));
+        assert_eq!(params.read(THRESHOLDS).unwrap(), value);
+
+        // the draw queue is full, the thresholds are left as they were
+        params.apply = refuse;
+        let mut busy = value.clone();
+        busy[3..5].copy_from_slice(&3000i16.to_le_bytes());
+        assert_eq!(params.write(THRESHOLDS, &busy), Err(Abort::Busy));
+        assert_eq!(params.thresholds[0].high, Some(Temp(4000)));
+
+        assert_eq!(params.write(index::NAME, b"x"), Err(Abort::ReadOnly));
+        assert_eq!(params.write(0x0200, &[]), Err(Abort::UnknownParameter));
+    }
+
+    #[test]
+    fn test_parse_thresholds() {
+        let mut value = [0u8; 14];
+        assert_eq!(parse_thresholds(&value), Ok([Thresholds::DISABLED; 2]));
+        assert_eq!(parse_thresholds(&value[..13]), Err(Abort::InvalidValue));
+        assert_eq!(parse_thresholds(&[0; 15]), Err(Abort::TooLong));
+
+        value[0] = 4;
+        assert_eq!(parse_thresholds(&value), Err(Abort::InvalidValue));
+        // low and high both at 0
+        value[0] = 3;
+        assert_eq!(parse_thresholds(&value), Err(Abort::InvalidValue));
+        value[3..5].copy_from_slice(&100i16.to_le_bytes());
+        assert!(parse_thresholds(&value).is_ok());
+        value[5..7].copy_from_slice(&(-1i16).to_le_bytes());
+        assert_eq!(parse_thresholds(&value), Err(Abort::InvalidValue));
+    }
+}
@@ -1,121 +1,22 @@
+use aero_core::alarm::{Kind, State};
+use aero_core::icing::Risk;
+use aero_core::{Level, Temp, Unit};
+use core::fmt::Write;
 use core::str::FromStr;
-use defmt::Format;
 use embedded_graphics::mono_font::iso_8859_13::FONT_6X10;
 use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::pixelcolor::Rgb565;
-use embedded_graphics::prelude::{Point, RgbColor};
+use embedded_graphics::prelude::{Point, Primitive, RgbColor, Size};
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
 use embedded_graphics::text::renderer::{CharacterStyle, TextRenderer};
 use embedded_graphics::text::{Baseline, Text};
 use embedded_graphics::Drawable;
-use heapless::spsc::Queue;
 use heapless::String;
 use profont::{PROFONT_18_POINT, PROFONT_24_POINT};
 
-use crate::temp::Temp;
-use crate::types::{Display, Temps, SCREEN_WIDTH, SCREEN_WIDTH_PLUS_1, TITLES};
+use aerotemp_f1_rtic_2::model::{Alarms, ScreenType};
 
-use crate::unit::Unit;
-
-#[derive(Copy, Clone, Format, Debug)]
-pub enum ScreenType {
-    Both,
-    Single(bool),
-}
-
-impl ScreenType {
-    pub fn next(&mut self) -> Self {
-        *self = match self {
-            ScreenType::Both => ScreenType::Single(false),
-            ScreenType::Single(false) => ScreenType::Single(true),
-            ScreenType::Single(true) => ScreenType::Both,
-        };
-        *self
-    }
-}
-
-impl Default for ScreenType {
-    fn default() -> Self {
-        ScreenType::Both
-    }
-}
-
-#[derive(Debug, Format)]
-pub enum ModelChange {
-    Last(Temps),
-    LastAndAverage(Temps, Temps),
-    Unit(Unit),
-    ScreenType(ScreenType),
-    Clear,
-}
-
-#[derive(Default)]
-pub struct Model {
-    pub last: Temps,
-    pub mins: Temps,
-    pub maxs: Temps,
-    pub history: [Queue<Temp, SCREEN_WIDTH_PLUS_1>; 2],
-    pub unit: Unit,
-    pub screen_type: ScreenType,
-    pub changed: bool,
-    pub clear: bool,
-}
-
-impl Model {
-    fn update_min_max(&mut self, temps: Temps) {
-        for i in 0..2 {
-            self.mins[i] = self.mins[i].min(*temps[i]).into();
-            self.maxs[i] = self.maxs[i].max(*temps[i]).into();
-        }
-    }
-    pub fn apply(&mut self, changes: ModelChange) {
-        match changes {
-            ModelChange::Last(last) => {
-                self.clear = false;
-                if last != self.last {
-                    self.changed = true;
-                    self.last = last;
-                    self.update_min_max(last);
-                } else {
-                    self.changed = false;
-                }
-            }
-            ModelChange::LastAndAverage(last, average) => {
-                self.changed = true;
-                self.clear = false;
-                self.last = last;
-                self.update_min_max(last);
-                for i in 0..2 {
-                    if self.history[i].len() == SCREEN_WIDTH {
-                        self.history[i].dequeue();
-                    }
-                    self.history[i].enqueue(average[i]).unwrap();
-                }
-            }
-            ModelChange::Unit(unit) => {
-                self.changed = true;
-                self.clear = true;
-                self.unit = unit;
-            }
-            ModelChange::ScreenType(screen_type) => {
-                self.changed = true;
-                self.clear = true;
-                self.screen_type = screen_type;
-            }
-            ModelChange::Clear => {
-                self.changed = true;
-                self.clear = true;
-            }
-        }
-    }
-
-    pub fn min_or_max(&self, max: bool, index: usize) -> Temp {
-        if max {
-            self.maxs[index]
-        } else {
-            self.mins[index]
-        }
-    }
-}
+use crate::types::{Display, SCREEN_WIDTH, TITLES};
 
 /// Draw the texts that needs only to be re-drawn only on reset
 pub fn draw_titles(display: &mut Display, screen_type: ScreenType) {
@@ -130,6 +31,10 @@ pub fn draw_titles(display: &mut Display, screen_type: ScreenType) {
             let mut title = String::<10>::from_str(TITLES[i as usize]).unwrap();
             text_titles(display, &mut title, 0, 0);
         }
+        ScreenType::Icing => {
+            let mut title = String::<10>::from_str("ICING").unwrap();
+            text_titles(display, &mut title, 0, 0);
+        }
     }
 }
 
@@ -162,12 +67,10 @@ pub fn text_temperature<const N: usize>(
     temp: Temp,
     unit: Unit,
 ) {
-    let color = if temp < 0.into() {
-        RgbColor::RED
-    } else if temp < 1500.into() {
-        RgbColor::YELLOW
-    } else {
-        RgbColor::GREEN
+    let color = match temp.level() {
+        Level::Freezing => RgbColor::RED,
+        Level::Cold => RgbColor::YELLOW,
+        Level::Warm => RgbColor::GREEN,
     };
     let font = if single {
         PROFONT_24_POINT
@@ -192,3 +95,87 @@ pub fn text_small_white<const N: usize>(
     style.set_background_color(Some(Rgb565::BLACK));
     text(display, buffer, x, y, style)
 }
+
+/// Draw a frame around the temperatures with an alarm and the crossed threshold on the title line.
+/// Active alarms are drawn only when `blink` is true so that they flash, acknowledged ones are steady
+pub fn draw_alarms<const N: usize>(
+    display: &mut Display,
+    buffer: &mut String<N>,
+    screen_type: ScreenType,
+    alarms: &Alarms,
+    blink: bool,
+) {
+    let (channels, height) = match screen_type {
+        ScreenType::Both => (0..2, 64),
+        ScreenType::Single(i) => (i as usize..i as usize + 1, 128),
+        ScreenType::Icing => (0..0, 128),
+    };
+    for (row, i) in channels.enumerate() {
+        let (color, kind) = match alarms.0[i].state() {
+            State::Active(kind) if blink => (Rgb565::RED, Some(kind)),
+            State::Acknowledged(kind) => (Rgb565::YELLOW, Some(kind)),
+            _ => (Rgb565::BLACK, None),
+        };
+        let y = row as i32 * height;
+        Rectangle::new(
+            Point::new(0, y),
+            Size::new(SCREEN_WIDTH as u32, height as u32),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+        .draw(display)
+        .unwrap();
+
+        buffer
+            .push_str(match kind {
+                Some(Kind::Low) => " LOW",
+                Some(Kind::High) => "HIGH",
+                None => "    ",
+            })
+            .unwrap();
+        let mut style = MonoTextStyle::new(&FONT_6X10, Rgb565::BLACK);
+        style.set_background_color(Some(color));
+        text(display, buffer, 100, y + 4, style)
+    }
+}
+
+fn risk_color(risk: Risk) -> Rgb565 {
+    match risk {
+        Risk::Low => Rgb565::GREEN,
+        Risk::Light => Rgb565::CYAN,
+        Risk::Moderate => Rgb565::YELLOW,
+        Risk::Serious => Rgb565::RED,
+    }
+}
+
+/// Draw the `ScreenType::Icing` page: the risk and the temperatures it is computed from
+pub fn draw_icing<const N: usize>(
+    display: &mut Display,
+    buffer: &mut String<N>,
+    temps: [Temp; 2],
+    risk: Risk,
+    unit: Unit,
+) {
+    // padded to the longest risk to overwrite the previous one
+    write!(buffer, "{:8}", risk).unwrap();
+    let mut style = MonoTextStyle::new(&PROFONT_18_POINT, risk_color(risk));
+    style.set_background_color(Some(Rgb565::BLACK));
+    text(display, buffer, 0, 30, style);
+
+    for (i, temp) in temps.iter().enumerate() {
+        write!(buffer, "{} ", TITLES[i]).unwrap();
+        temp.write_buffer(unit, true, buffer);
+        text_small_white(display, buffer, 0, 70 + i as i32 * 15);
+    }
+}
+
+/// Draw a badge on the CAT title of the `ScreenType::Both` screen when there is an icing risk
+pub fn draw_icing_badge<const N: usize>(display: &mut Display, buffer: &mut String<N>, risk: Risk) {
+    let color = match risk {
+        Risk::Low => Rgb565::BLACK,
+        risk => risk_color(risk),
+    };
+    buffer.push_str("ICE").unwrap();
+    let mut style = MonoTextStyle::new(&FONT_6X10, Rgb565::BLACK);
+    style.set_background_color(Some(color));
+    text(display, buffer, 60, 64 + 4, style)
+}
@@ -0,0 +1,185 @@
+//! sensor
+//!
+//! This module reads the OAT and CAT PT1000 probes through two MAX31865 sharing the same SPI bus
+//!
+
+use aero_core::Temp;
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use max31865::FilterMode::Filter50Hz;
+use max31865::SensorType::TwoOrFourWire;
+use max31865::{temp_conversion, Max31865};
+
+use crate::types::Temps;
+
+/// Reference resistor mounted on the MAX31865 boards, in hundredths of ohm
+pub const CALIBRATION: u32 = 430_000;
+
+/// The two temperature sensors, the first is the OAT, the second is the CAT (see `TITLES`)
+pub struct Sensors<SPI, NCS1, RDY1, NCS2, RDY2> {
+    oat: Max31865<SPI, NCS1, RDY1>,
+    cat: Max31865<SPI, NCS2, RDY2>,
+}
+
+impl<E, SPI, NCS1, RDY1, NCS2, RDY2> Sensors<SPI, NCS1, RDY1, NCS2, RDY2>
+where
+    SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    NCS1: OutputPin,
+    RDY1: InputPin,
+    NCS2: OutputPin,
+    RDY2: InputPin,
+{
+    /// Create and configure both sensors, `spi1` and `spi2` are usually handles to the same bus
+    pub fn new(
+        spi1: SPI,
+        nss1: NCS1,
+        rdy1: RDY1,
+        spi2: SPI,
+        nss2: NCS2,
+        rdy2: RDY2,
+    ) -> Result<Self, E> {
+        Ok(Sensors {
+            oat: configure(Max31865::new(spi1, nss1, rdy1)?)?,
+            cat: configure(Max31865::new(spi2, nss2, rdy2)?)?,
+        })
+    }
+
+    /// Read both sensors, returning the temperatures in the same order of `TITLES`
+    pub fn read(&mut self) -> Result<Temps, E> {
+        let oat = self.oat.read_ohms()?;
+        let cat = self.cat.read_ohms()?;
+        Ok([ohms_to_temp(oat), ohms_to_temp(cat)])
+    }
+}
+
+fn configure<E, SPI, NCS, RDY>(
+    mut sensor: Max31865<SPI, NCS, RDY>,
+) -> Result<Max31865<SPI, NCS, RDY>, E>
+where
+    SPI: Write<u8, Error = E> + Transfer<u8, Error = E>,
+    NCS: OutputPin,
+    RDY: InputPin,
+{
+    sensor.configure(true, true, false, TwoOrFourWire, Filter50Hz)?;
+    sensor.set_calibration(CALIBRATION);
+    Ok(sensor)
+}
+
+/// `ohms` is the PT1000 resistance multiplied by 100, eg 1000.00 Ω is 100_000
+pub fn ohms_to_temp(ohms: u32) -> Temp {
+    let temp = temp_conversion::LOOKUP_VEC_PT1000.lookup_temperature(ohms as i32);
+    Temp(temp.clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    /// RTD MSB register, the LSB follows and is returned in the same transfer
+    const RTD_MSB: u8 = 0x01;
+
+    /// A fake SPI bus answering RTD reads with the raw value of `ohms`
+    struct MockSpi {
+        ohms: u32,
+    }
+
+    impl MockSpi {
+        fn raw(&self) -> u16 {
+            // the MAX31865 returns a 15 bits ratio with the reference resistor, bit 0 is the fault bit
+            let ratio = ((self.ohms as u64) << 15) / CALIBRATION as u64;
+            (ratio as u16) << 1
+        }
+    }
+
+    impl Transfer<u8> for MockSpi {
+        type Error = Infallible;
+
+        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+            if words[0] == RTD_MSB && words.len() == 3 {
+                let raw = self.raw();
+                words[1] = (raw >> 8) as u8;
+                words[2] = raw as u8;
+            }
+            Ok(words)
+        }
+    }
+
+    impl Write<u8> for MockSpi {
+        type Error = Infallible;
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct MockPin;
+
+    impl OutputPin for MockPin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl InputPin for MockPin {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    fn read(oat: u32, cat: u32) -> Temps {
+        let mut sensors = Sensors::new(
+            MockSpi { ohms: oat },
+            MockPin,
+            MockPin,
+            MockSpi { ohms: cat },
+            MockPin,
+            MockPin,
+        )
+        .unwrap();
+        sensors.read().unwrap()
+    }
+
+    fn assert_near(value: Temp, expected: i16) {
+        // the 15 bits ADC has a resolution of about 0.03°C with a PT1000 and a 4300Ω reference
+        assert!(
+            (value.0 - expected).abs() <= 10,
+            "{:?} is not near {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_read() {
+        // PT1000 resistances from the IEC 60751 table
+        let [oat, cat] = read(100_000, 138_506);
+        assert_near(oat, 0);
+        assert_near(cat, 10_000);
+
+        let [oat, cat] = read(84_271, 107_794);
+        assert_near(oat, -4_000);
+        assert_near(cat, 2_000);
+
+        let [oat, cat] = read(92_160, 111_673);
+        assert_near(oat, -2_000);
+        assert_near(cat, 3_000);
+    }
+
+    #[test]
+    fn test_ohms_to_temp() {
+        assert_near(ohms_to_temp(100_000), 0);
+        assert_near(ohms_to_temp(103_903), 1_000);
+        assert_near(ohms_to_temp(96_086), -1_000);
+    }
+}
@@ -0,0 +1,7 @@
+//! storage
+//!
+//! This module gives the last pages of the STM32F103 flash to the `e_store::Store`, through the
+//! backend shared with the other firmwares in `e_store::stm32f1`
+//!
+
+e_store::stm32f1_storage!();
@@ -1,8 +1,11 @@
-use crate::temp::Temp;
+use crate::sensor::Sensors;
+use can_core::timing::{bit_timing, BITRATE, SAMPLE_POINT};
+use e_button::Debouncer;
+use shared_bus_rtic::SharedBus;
 use ssd1351::{interface::SpiInterface, mode::GraphicsMode};
 use stm32f1xx_hal::{
-    gpio::{Alternate, Floating, Input, Output, Pin, PullUp, PushPull, CRL},
-    spi::Spi1NoRemap,
+    gpio::{Alternate, Floating, Input, Output, Pin, PullUp, PushPull, CRH, CRL},
+    spi::{Spi1NoRemap, Spi2NoRemap},
 };
 use stm32f1xx_hal::{pac, spi};
 use systick_monotonic::fugit;
@@ -17,18 +20,43 @@ pub type PA5 = Pin<Alternate<PushPull>, CRL, 'A', 5_u8>;
 pub type PA6 = Pin<Input<Floating>, CRL, 'A', 6_u8>;
 pub type PA7 = Pin<Alternate<PushPull>, CRL, 'A', 7_u8>;
 
+pub type PB0 = Pin<Output<PushPull>, CRL, 'B', 0_u8>;
+pub type PB1 = Pin<Input<Floating>, CRL, 'B', 1_u8>;
+pub type PB10 = Pin<Input<Floating>, CRH, 'B', 10_u8>;
+pub type PB11 = Pin<Output<PushPull>, CRH, 'B', 11_u8>;
+pub type PB13 = Pin<Alternate<PushPull>, CRH, 'B', 13_u8>;
+pub type PB14 = Pin<Input<Floating>, CRH, 'B', 14_u8>;
+pub type PB15 = Pin<Alternate<PushPull>, CRH, 'B', 15_u8>;
+
 pub type SPI1 = spi::Spi<pac::SPI1, Spi1NoRemap, (PA5, PA6, PA7), u8>;
+pub type SPI2 = spi::Spi<pac::SPI2, Spi2NoRemap, (PB13, PB14, PB15), u8>;
+
+pub type Can1 = bxcan::Can<stm32f1xx_hal::can::Can<pac::CAN1>>;
 
 pub type Display = GraphicsMode<SpiInterface<SPI1, PA3>>;
+pub type TempSensors = Sensors<SharedBus<SPI2>, PB0, PB1, PB11, PB10>;
 
-pub type Temps = [Temp; 2];
+pub use aerotemp_f1_rtic_2::model::{Temps, SCREEN_WIDTH};
 
-pub const PERIOD: usize = 2;
-pub const SCREEN_WIDTH: usize = 128;
-pub const SCREEN_WIDTH_PLUS_1: usize = SCREEN_WIDTH + 1;
-pub const ENOUGH_TIME_BUTTON_PRESSED: Duration = Duration::from_ticks(200);
+pub const ENOUGH_TIME_BUTTON_PRESSED: Duration = Duration::from_ticks(50);
+/// How the buttons are debounced: `None` on their edges, or sampled every `SAMPLE_PERIOD` for
+/// noisy switches that would interrupt on every bounce, here down or up for 10 more samples than
+/// the opposite
+pub const PA0_DEBOUNCER: Option<Debouncer> = None;
+pub const PA1_DEBOUNCER: Option<Debouncer> = Some(Debouncer::integrator(10));
+pub const SAMPLE_PERIOD: Duration = Duration::from_ticks(1);
+/// A press at least this long acknowledges the alarms
+pub const LONG_PRESS: Duration = Duration::from_ticks(1_000);
 
 pub const ONE_SEC: Duration = Duration::from_ticks(1_000);
+pub const SAVE_EXTREMES_EVERY: Duration = Duration::from_ticks(60_000);
+/// How often the readings are broadcast on the CAN bus
+pub const TELEMETRY_PERIOD: Duration = Duration::from_ticks(1_000);
+/// APB1 (PCLK1): 36MHz, the bitrate and the sample point of the bus
+pub const CAN_BTR: u32 = match bit_timing(36_000_000, BITRATE, SAMPLE_POINT) {
+    Ok(timing) => timing.btr(),
+    Err(_) => panic!("no bit timing for the bus bitrate"),
+};
 pub const ZERO_INSTANT: Instant = Instant::from_ticks(0);
 pub const TITLES: [&'static str; 2] = ["OAT", "CAT"];
 pub const MIN_OR_MAX: [&'static str; 2] = ["min:", "max:"];
@@ -0,0 +1,50 @@
+//! hist
+//!
+//! This module draws the histograms of the stored temperatures on the display, the geometry is
+//! computed by `aero_core::hist`
+//!
+
+use aero_core::hist::{self, ThreePoints};
+use aero_core::Temp;
+use e_ring::Ring;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::prelude::{DrawTarget, PixelColor, Primitive};
+use embedded_graphics::primitives::{Line, PrimitiveStyle};
+use embedded_graphics::Drawable;
+use heapless::Vec;
+
+/// A histogram of the values of a `Ring` rescaled to fit in the window defined by the
+/// `upper_left` point and the `size`
+pub struct Hist(hist::Hist);
+
+impl Hist {
+    pub fn new(upper_left: Point, size: Size) -> Hist {
+        Hist(hist::Hist::new(
+            hist::Point::new(upper_left.x, upper_left.y),
+            size.width,
+            size.height,
+        ))
+    }
+
+    /// Draw the histogram of the values in `ring` on a display
+    pub fn draw<C: PixelColor, D: DrawTarget<Color = C>, const N: usize>(
+        &self,
+        ring: &Ring<i16, N>,
+        display: &mut D,
+        foreground: C,
+        background: C,
+    ) -> Result<(), D::Error> {
+        let values: Vec<Temp, N> = ring.iter().map(Temp).collect();
+        let lines: Vec<ThreePoints, N> = self.0.lines(values.iter().cloned());
+        for points in lines.iter() {
+            let [a, b, c] = points.map(|p| Point::new(p.x, p.y));
+            Line::new(a, b)
+                .into_styled(PrimitiveStyle::with_stroke(foreground, 1))
+                .draw(display)?;
+            Line::new(b, c)
+                .into_styled(PrimitiveStyle::with_stroke(background, 1))
+                .draw(display)?;
+        }
+        Ok(())
+    }
+}
@@ -1,26 +1,31 @@
 #![no_std]
 #![no_main]
 
+mod hist;
 mod temps;
 mod types;
 use panic_halt as _;
 
 use rtic::app;
 
+use crate::hist::Hist;
 use crate::temps::TempsValues;
-use crate::types::{BusType, Button, Display, Scale, SharedBusResources, Unit};
+use crate::types::{BusType, Display, Scale, SharedBusResources};
+use aero_core::alarm::{Alarm, Kind, State, DEFAULT_THRESHOLDS};
+use aero_core::{Level, Temp, Unit};
 use core::fmt::Write;
-use e_ring::hist::Hist;
+use e_button::{Buttons, Debouncer, Event, Timings};
 use e_write_buffer::WriteBuffer;
 use embedded_graphics::geometry::{Point, Size};
 use embedded_graphics::image::Image;
 use embedded_graphics::mono_font::ascii::{FONT_6X9, FONT_8X13};
 use embedded_graphics::mono_font::{iso_8859_1, MonoTextStyle};
 use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use embedded_graphics::primitives::{Primitive, PrimitiveStyle, Rectangle};
 use embedded_graphics::text::renderer::CharacterStyle;
 use embedded_graphics::text::{Alignment, Baseline, Text, TextStyleBuilder};
 use embedded_graphics::Drawable;
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
 use max31865::FilterMode::Filter50Hz;
 use max31865::SensorType::TwoOrFourWire;
 use max31865::{temp_conversion, Max31865};
@@ -34,32 +39,56 @@ use stm32f1xx_hal::gpio::Edge;
 use stm32f1xx_hal::gpio::{ExtiPin, Floating, Input};
 use stm32f1xx_hal::pac;
 use stm32f1xx_hal::prelude::*;
+use stm32f1xx_hal::rtc::Rtc;
 use stm32f1xx_hal::spi::Spi;
-use stm32f1xx_hal::time::MonoTimer;
-use stm32f1xx_hal::timer::{CountDownTimer, Event, Timer};
+use stm32f1xx_hal::time::{Instant, MonoTimer};
+use stm32f1xx_hal::timer::{CountDownTimer, Event as TimerEvent, Timer};
 use tinytga::DynamicTga;
 
-const RECENTLY: u32 = 2_000_000;
+/// Index of the buttons in `Buttons`
+const PA0_BUTTON: usize = 0;
+const PA1_BUTTON: usize = 1;
+
+/// How the buttons are debounced: `None` on their edges, or sampled by TIM2 for noisy switches
+/// that would interrupt on every bounce, here down or up for 10 more milliseconds than the opposite
+const DEBOUNCERS: [Option<Debouncer>; 2] = [None, Some(Debouncer::integrator(10))];
+
+/// Edges within 50ms are bounces, a press of 1s acknowledges the alarms, without double clicks
+/// so a click is reported on the release
+const TIMINGS: Timings = Timings {
+    debounce: 50,
+    long: 1_000,
+    repeat: 0,
+    double: 0,
+};
+
+/// Frequency of the samples of the sampled buttons and of the polls of the gestures, the
+/// debouncers count milliseconds
+const SAMPLE_HZ: u32 = 1_000;
 
 #[app(device = stm32f1xx_hal::pac, peripherals = true)]
 const APP: () = {
     struct Resources {
-        #[init(1)]
-        seconds: u32,
+        rtc: Rtc,
         #[init(true)]
         reset_display: bool,
-        #[init(Unit::Degrees)]
+        #[init(Unit::Celsius)]
         unit: Unit,
         #[init(Scale::Seconds)]
         scale: Scale,
+        #[init([Alarm::new(DEFAULT_THRESHOLDS[0]), Alarm::new(DEFAULT_THRESHOLDS[1])])]
+        alarms: [Alarm; 2],
 
         timer_handler: CountDownTimer<pac::TIM1>,
-        mono_timer: MonoTimer,
+        sample_timer: CountDownTimer<pac::TIM2>,
 
         display: Display,
 
-        pa0: Button<PA0<Input<Floating>>>,
-        pa1: Button<PA1<Input<Floating>>>,
+        pa0: PA0<Input<Floating>>,
+        pa1: PA1<Input<Floating>>,
+        buttons: Buttons<2>,
+        /// The ticks of `buttons` are the cycles elapsed since `start`
+        start: Instant,
 
         temps: SharedBusResources<BusType>,
         temps_values: TempsValues,
@@ -104,30 +133,43 @@ const APP: () = {
         display.set_rotation(DisplayRotation::Rotate180).unwrap();
 
         let mono_timer = MonoTimer::new(cx.core.DWT, cx.core.DCB, clocks);
+        let start = mono_timer.now();
+        let ticks_per_ms = mono_timer.frequency().0 / 1_000;
+        let mut buttons = Buttons::new(TIMINGS.scaled(ticks_per_ms));
 
-        // Setup Buttons
+        // Setup Buttons, the EXTI interrupt is enabled only for the ones debounced on their edges
         let mut pa0 = gpioa.pa0.into_floating_input(&mut gpioa.crl);
         pa0.make_interrupt_source(&mut afio);
-        pa0.trigger_on_edge(&cx.device.EXTI, Edge::RISING);
-        pa0.enable_interrupt(&cx.device.EXTI);
-        let pa0 = Button {
-            pin: pa0,
-            last: mono_timer.now(),
-        };
+        pa0.trigger_on_edge(&cx.device.EXTI, Edge::RISING_FALLING);
+        match DEBOUNCERS[PA0_BUTTON] {
+            Some(debouncer) => buttons.set_debouncer(PA0_BUTTON, debouncer),
+            None => pa0.enable_interrupt(&cx.device.EXTI),
+        }
 
         let mut pa1 = gpioa.pa1.into_floating_input(&mut gpioa.crl);
         pa1.make_interrupt_source(&mut afio);
-        pa1.trigger_on_edge(&cx.device.EXTI, Edge::RISING);
-        pa1.enable_interrupt(&cx.device.EXTI);
-        let pa1 = Button {
-            pin: pa1,
-            last: mono_timer.now(),
-        };
+        pa1.trigger_on_edge(&cx.device.EXTI, Edge::RISING_FALLING);
+        match DEBOUNCERS[PA1_BUTTON] {
+            Some(debouncer) => buttons.set_debouncer(PA1_BUTTON, debouncer),
+            None => pa1.enable_interrupt(&cx.device.EXTI),
+        }
 
         // Configure the syst timer to trigger an update every second and enables interrupt
         let mut timer_handler =
             Timer::tim1(cx.device.TIM1, &clocks, &mut rcc.apb2).start_count_down(1.hz());
-        timer_handler.listen(Event::Update);
+        timer_handler.listen(TimerEvent::Update);
+
+        // Samples the buttons not debounced on their edges and polls the gestures depending only
+        // on time, like the long press
+        let mut sample_timer =
+            Timer::tim2(cx.device.TIM2, &clocks, &mut rcc.apb1).start_count_down(SAMPLE_HZ.hz());
+        sample_timer.listen(TimerEvent::Update);
+
+        // The RTC in the backup domain keeps the time across resets, the samples are on its seconds
+        let mut pwr = cx.device.PWR;
+        let mut backup_domain = rcc.bkp.constrain(cx.device.BKP, &mut rcc.apb1, &mut pwr);
+        let mut rtc = Rtc::rtc(cx.device.RTC, &mut backup_domain);
+        rtc_core::set_clock_if_lost!(rtc, backup_domain);
 
         let mut gpiob = cx.device.GPIOB.split(&mut rcc.apb2);
 
@@ -177,12 +219,15 @@ const APP: () = {
         delay.delay_ms(2000u16);
 
         init::LateResources {
+            rtc,
             temps_values: TempsValues::default(),
             timer_handler,
-            mono_timer,
+            sample_timer,
             display,
             pa0,
             pa1,
+            buttons,
+            start,
             temps,
         }
     }
@@ -194,9 +239,11 @@ const APP: () = {
         }
     }
 
-    #[task(binds = TIM1_UP, priority = 1, resources = [timer_handler, seconds, temps, temps_values, scale, unit, display, reset_display])]
+    #[task(binds = TIM1_UP, priority = 1, resources = [timer_handler, rtc, temps, temps_values, scale, unit, alarms, display, reset_display])]
     fn tick(mut cx: tick::Context) {
-        let seconds = cx.resources.seconds;
+        // cleared first, the task may return early
+        cx.resources.timer_handler.clear_update_interrupt_flag();
+        let seconds = cx.resources.rtc.current_time();
         let display = cx.resources.display;
         let temps_values = cx.resources.temps_values;
         let reset_display = cx.resources.reset_display.lock(|reset_display| {
@@ -211,42 +258,47 @@ const APP: () = {
 
         let ohms1 = cx.resources.temps.t1.read_ohms().unwrap();
         let t1 = temp_conversion::LOOKUP_VEC_PT1000.lookup_temperature(ohms1 as i32);
-        temps_values.store(t1 as i16, *seconds, 0);
+        temps_values.store(t1 as i16, seconds, 0);
 
         let ohms2 = cx.resources.temps.t2.read_ohms().unwrap();
         let t2 = temp_conversion::LOOKUP_VEC_PT1000.lookup_temperature(ohms2 as i32);
-        temps_values.store(t2 as i16, *seconds, 1);
+        temps_values.store(t2 as i16, seconds, 1);
 
         if reset_display {
             display.clear();
             draw_titles(display, scale, &mut buffer);
         }
 
-        let last_degrees = match (temps_values.last(0), temps_values.last(1)) {
-            (Some(t1), Some(t2)) => [t1, t2],
+        let last = match (temps_values.last(0), temps_values.last(1)) {
+            (Some(t1), Some(t2)) => [Temp(t1), Temp(t2)],
             _ => return,
         };
 
-        let last = match unit {
-            Unit::Degrees => last_degrees,
-            Unit::Fahrenheit => [fahrenheit(last_degrees[0]), fahrenheit(last_degrees[1])],
-        };
-        let color = [color(last_degrees[0]), color(last_degrees[1])];
+        let states = cx.resources.alarms.lock(|alarms| {
+            alarms[0].update(last[0]);
+            alarms[1].update(last[1]);
+            [alarms[0].state(), alarms[1].state()]
+        });
+        // active alarms flash every second
+        let blink = seconds % 2 == 0;
+        let color = [
+            color(last[0], states[0], blink),
+            color(last[1], states[1], blink),
+        ];
         let temp_position = [Point::new(136, 0), Point::new(136, 60)];
         let hist_position = [Point::new(0, 25), Point::new(0, 85)];
         let hist_size = Size::new(128, 30);
 
         for i in 0..=1usize {
-            let mut font = MonoTextStyle::new(&iso_8859_1::FONT_10X20, color[i]);
-            font.set_background_color(Some(Rgb565::BLACK));
+            let (foreground, background) = color[i];
+            let mut font = MonoTextStyle::new(&iso_8859_1::FONT_10X20, foreground);
+            font.set_background_color(Some(background));
             let style = TextStyleBuilder::new()
                 .alignment(Alignment::Right)
                 .baseline(Baseline::Top)
                 .build();
 
-            write!(buffer, "  ").unwrap();
-            format_100(last[i], &mut buffer);
-            write!(buffer, "{}", unit).unwrap();
+            last[i].write_buffer(unit, true, &mut buffer);
             Text::with_text_style(buffer.as_str().unwrap(), temp_position[i], font, style)
                 .draw(display)
                 .unwrap();
@@ -260,32 +312,84 @@ const APP: () = {
                 RgbColor::BLACK,
             )
             .unwrap();
+
+            draw_alarm(display, &mut buffer, i, states[i], blink);
         }
-        *seconds += 1;
 
-        // Clears the update flag
-        cx.resources.timer_handler.clear_update_interrupt_flag();
+        // the clock time of the newest values of the scale, at the right of its name
+        if let Some(span) = temps_values.newest(0, scale) {
+            let start = span.start();
+            let font = MonoTextStyle::new(&FONT_6X9, Rgb565::WHITE);
+            let style = TextStyleBuilder::new()
+                .alignment(Alignment::Right)
+                .baseline(Baseline::Top)
+                .build();
+            write!(
+                buffer,
+                "{:02}:{:02}:{:02}",
+                start.hour(),
+                start.minute(),
+                start.second()
+            )
+            .unwrap();
+            Text::with_text_style(buffer.as_str().unwrap(), Point::new(128, 119), font, style)
+                .draw(display)
+                .unwrap();
+            buffer.reset();
+        }
     }
 
-    #[task(binds = EXTI0, priority = 2, resources = [pa0, unit, mono_timer])]
+    #[task(binds = EXTI0, priority = 2, resources = [pa0, buttons, start, unit, scale, alarms, reset_display])]
     fn exti0(cx: exti0::Context) {
-        let button = cx.resources.pa0;
-        if button.last.elapsed() > RECENTLY {
-            button.last = cx.resources.mono_timer.now();
-            cx.resources.unit.next();
-        }
-        button.pin.clear_interrupt_pending_bit();
+        let now = cx.resources.start.elapsed();
+        let mut settings = Settings {
+            unit: cx.resources.unit,
+            scale: cx.resources.scale,
+            reset_display: cx.resources.reset_display,
+            alarms: cx.resources.alarms,
+        };
+        let pa0 = cx.resources.pa0;
+        let down = pa0.is_high().unwrap_or(false);
+        cx.resources
+            .buttons
+            .edge(PA0_BUTTON, down, now, |e| settings.pressed(e));
+        pa0.clear_interrupt_pending_bit();
     }
 
-    #[task(binds = EXTI1, priority = 2, resources = [pa1, scale, reset_display, mono_timer])]
+    #[task(binds = EXTI1, priority = 2, resources = [pa1, buttons, start, unit, scale, alarms, reset_display])]
     fn exti1(cx: exti1::Context) {
-        let button = cx.resources.pa1;
-        if button.last.elapsed() > RECENTLY {
-            button.last = cx.resources.mono_timer.now();
-            cx.resources.scale.next();
-            *cx.resources.reset_display = true;
-        }
-        button.pin.clear_interrupt_pending_bit();
+        let now = cx.resources.start.elapsed();
+        let mut settings = Settings {
+            unit: cx.resources.unit,
+            scale: cx.resources.scale,
+            reset_display: cx.resources.reset_display,
+            alarms: cx.resources.alarms,
+        };
+        let pa1 = cx.resources.pa1;
+        let down = pa1.is_high().unwrap_or(false);
+        cx.resources
+            .buttons
+            .edge(PA1_BUTTON, down, now, |e| settings.pressed(e));
+        pa1.clear_interrupt_pending_bit();
+    }
+
+    #[task(binds = TIM2, priority = 2, resources = [sample_timer, pa0, pa1, buttons, start, unit, scale, alarms, reset_display])]
+    fn sample(cx: sample::Context) {
+        let now = cx.resources.start.elapsed();
+        let mut settings = Settings {
+            unit: cx.resources.unit,
+            scale: cx.resources.scale,
+            reset_display: cx.resources.reset_display,
+            alarms: cx.resources.alarms,
+        };
+        let buttons = cx.resources.buttons;
+        // ignored by the buttons debounced on their edges
+        let down = cx.resources.pa0.is_high().unwrap_or(false);
+        buttons.sample(PA0_BUTTON, down, now, |e| settings.pressed(e));
+        let down = cx.resources.pa1.is_high().unwrap_or(false);
+        buttons.sample(PA1_BUTTON, down, now, |e| settings.pressed(e));
+        buttons.poll(now, |e| settings.pressed(e));
+        cx.resources.sample_timer.clear_update_interrupt_flag();
     }
 
     extern "C" {
@@ -293,31 +397,91 @@ const APP: () = {
     }
 };
 
-/// `degrees` is degrees multiplied by 100, eg 3.31 °C is 331
-/// returned value is fahrenheit multiplied by 100, eg 22.41 °F is 2241
-fn fahrenheit(degrees: i16) -> i16 {
-    let f = degrees as i32;
-    (f * 9 / 5 + 3200) as i16
+/// foreground and background colors of the text printing degrees, the background is red when the
+/// alarm is active and `blink` is true, yellow when the alarm has been acknowledged
+fn color(temp: Temp, alarm: State, blink: bool) -> (Rgb565, Rgb565) {
+    match alarm {
+        State::Active(_) if blink => (RgbColor::WHITE, RgbColor::RED),
+        State::Acknowledged(_) => (RgbColor::BLACK, RgbColor::YELLOW),
+        _ => {
+            let foreground = match temp.level() {
+                Level::Freezing => RgbColor::RED,
+                Level::Cold => RgbColor::YELLOW,
+                Level::Warm => RgbColor::GREEN,
+            };
+            (foreground, RgbColor::BLACK)
+        }
+    }
 }
 
-/// color of the text printing degrees
-fn color(degrees: i16) -> Rgb565 {
-    if degrees < 0 {
-        RgbColor::RED
-    } else if degrees < 1500 {
-        RgbColor::YELLOW
-    } else {
-        RgbColor::GREEN
-    }
+/// Draw the alarm overlay of channel `i` over its temperature and histogram: a red frame with
+/// LOW/HIGH next to the title when the alarm is active and `blink` is true, yellow when it has
+/// been acknowledged, erased otherwise
+fn draw_alarm<const N: usize>(
+    display: &mut Display,
+    buffer: &mut WriteBuffer<N>,
+    i: usize,
+    alarm: State,
+    blink: bool,
+) {
+    let (color, kind) = match alarm {
+        State::Active(kind) if blink => (Rgb565::RED, Some(kind)),
+        State::Acknowledged(kind) => (Rgb565::YELLOW, Some(kind)),
+        _ => (Rgb565::BLACK, None),
+    };
+    let y = i as i32 * 60;
+    Rectangle::new(Point::new(0, y), Size::new(128, 60))
+        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+        .draw(display)
+        .unwrap();
+
+    buffer
+        .write_str(match kind {
+            Some(Kind::Low) => " LOW",
+            Some(Kind::High) => "HIGH",
+            None => "    ",
+        })
+        .unwrap();
+    let mut font = MonoTextStyle::new(&FONT_6X9, Rgb565::BLACK);
+    font.set_background_color(Some(color));
+    // at the right of the title
+    Text::with_baseline(
+        buffer.as_str().unwrap(),
+        Point::new(28, y + 2),
+        font,
+        Baseline::Top,
+    )
+    .draw(display)
+    .unwrap();
+    buffer.reset();
+}
+
+/// The resources changed by the buttons
+struct Settings<'a> {
+    unit: &'a mut Unit,
+    scale: &'a mut Scale,
+    reset_display: &'a mut bool,
+    alarms: &'a mut [Alarm; 2],
 }
 
-/// format a value multiplied by 100 into a decimal number with 1 digit after the dot
-fn format_100<const N: usize>(val: i16, buf: &mut WriteBuffer<N>) {
-    let sign = if val > 0 { "" } else { "-" };
-    let abs_val = val.abs();
-    let before_comma = abs_val / 100;
-    let after_comma = (abs_val % 100) / 10;
-    write!(buf, "{}{}.{}", sign, before_comma, after_comma).unwrap();
+impl Settings<'_> {
+    /// A click of PA0 changes the unit, of PA1 the scale of the histograms, a long press of either
+    /// acknowledges the alarms
+    fn pressed(&mut self, event: Event) {
+        match event {
+            Event::Click(PA0_BUTTON) => self.unit.next(),
+            Event::Click(PA1_BUTTON) => {
+                self.scale.next();
+                *self.reset_display = true;
+            }
+            Event::Long(_) => {
+                for alarm in self.alarms.iter_mut() {
+                    alarm.acknowledge();
+                }
+            }
+            _ => (),
+        }
+    }
 }
 
 /// Draw the texts that needs only to be re-drawn only on reset
@@ -1,42 +1,68 @@
 use crate::types::Scale;
 use e_ring::Ring;
+use e_series::{Reducer, Series, Store, Timeline};
+use rtc_core::Span;
 
-pub struct TempsValues([[Ring<i16, 128>; 3]; 2]);
+/// The values of a scale, drawn on the screen, and the wall-clock starts of their windows
+pub struct History {
+    values: Ring<i16, 128>,
+    starts: Timeline<128>,
+}
+
+impl History {
+    fn new(interval: u32) -> Self {
+        History {
+            values: Ring::new(),
+            starts: Timeline::new(interval),
+        }
+    }
+}
+
+impl Store for History {
+    fn append(&mut self, start: u32, value: i16) {
+        self.values.append(value);
+        self.starts.push(start);
+    }
+}
+
+/// The temperatures of the two channels averaged by `Scale`, on the seconds of the RTC so that the
+/// minutes are the ones of the clock
+pub struct TempsValues([Series<History, 3>; 2]);
 impl TempsValues {
     pub fn series(&self, t: usize, scale: Scale) -> &Ring<i16, 128> {
-        &self.0[t][scale as usize]
+        &self.0[t].level(scale as usize).values
     }
 
+    /// The clock time of the newest value of `series`
+    pub fn newest(&self, t: usize, scale: Scale) -> Option<Span> {
+        let starts = &self.0[t].level(scale as usize).starts;
+        starts.newest().map(|start| Span {
+            start,
+            interval: starts.interval(),
+        })
+    }
+
+    /// The latest temperature of channel `t`, in the window of the current second until it's
+    /// closed by the next one
     pub fn last(&self, t: usize) -> Option<i16> {
-        self.0[t][0].iter().last()
+        self.0[t].partial(0)
     }
 
     pub fn store(&mut self, value: i16, seconds: u32, t: usize) {
-        let current = &mut self.0[t];
-        current[0].append(value);
-        for (i, interval) in [Scale::TenSeconds.seconds(), Scale::Minute.seconds()]
-            .iter()
-            .enumerate()
-        {
-            if seconds % interval == 0 {
-                let len = current[0].len();
-                let value = current[0]
-                    .iter()
-                    .skip(len - *interval as usize)
-                    .map(|e| e as f32)
-                    .sum::<f32>()
-                    / *interval as f32;
-                current[i + 1].append(value as i16);
-            }
-        }
+        self.0[t].push(seconds, Some(value));
     }
 }
 
 impl Default for TempsValues {
     fn default() -> Self {
-        TempsValues([
-            [Ring::new(), Ring::new(), Ring::new()],
-            [Ring::new(), Ring::new(), Ring::new()],
-        ])
+        let series = || {
+            let intervals = [
+                Scale::Seconds.seconds(),
+                Scale::TenSeconds.seconds(),
+                Scale::Minute.seconds(),
+            ];
+            Series::new(intervals.map(History::new), intervals, Reducer::Mean)
+        };
+        TempsValues([series(), series()])
     }
 }
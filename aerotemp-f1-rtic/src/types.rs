@@ -7,7 +7,6 @@ use stm32f1xx_hal::gpio::gpioa::{PA3, PA5, PA6, PA7};
 use stm32f1xx_hal::gpio::gpiob::{PB0, PB1, PB10, PB11, PB13, PB14, PB15};
 use stm32f1xx_hal::gpio::{Alternate, Floating, Input, Output, PushPull};
 use stm32f1xx_hal::spi::{Spi, Spi1NoRemap, Spi2NoRemap};
-use stm32f1xx_hal::time::Instant;
 
 #[rustfmt::skip]
 pub type BusType = Spi<stm32f1xx_hal::pac::SPI2, Spi2NoRemap, (PB13<Alternate<PushPull>>, PB14<Input<Floating>>, PB15<Alternate<PushPull>>), u8>;
@@ -19,30 +18,6 @@ pub struct SharedBusResources<T: 'static> {
     pub t2: Max31865<SharedBus<T>, PB11<Output<PushPull>>, PB10<Input<Floating>>>,
 }
 
-#[derive(Copy, Clone)]
-pub enum Unit {
-    Degrees,
-    Fahrenheit,
-}
-
-impl fmt::Display for Unit {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> core::fmt::Result {
-        match self {
-            Unit::Degrees => write!(f, "°C"),
-            Unit::Fahrenheit => write!(f, "°F"),
-        }
-    }
-}
-
-impl Unit {
-    pub fn next(&mut self) {
-        *self = match self {
-            Unit::Degrees => Unit::Fahrenheit,
-            Unit::Fahrenheit => Unit::Degrees,
-        }
-    }
-}
-
 #[derive(Copy, Clone)]
 pub enum Scale {
     Seconds,
@@ -76,8 +51,3 @@ impl Scale {
         }
     }
 }
-
-pub struct Button<T> {
-    pub pin: T,
-    pub last: Instant,
-}
@@ -1,4 +1,9 @@
-//! Uses the timer interrupt to blink a led with different frequencies.
+//! Tells gestures of the buttons on PA5 and PA6 apart and shows them on the leds.
+//!
+//! A click of PA5 toggles the led on PA1, of PA6 the led on PA2, pressing both toggles the led on
+//! PA0 and a double click turns them off. Holding a button blinks the led on PC13. The gestures are
+//! recognized by `e_button::Buttons` from the edges of the pins, timestamped with the cycle counter,
+//! and a 100Hz timer.
 //!
 //! This assumes that a LED is connected to pc13 as is the case on the blue pill board.
 //!
@@ -13,33 +18,47 @@ use panic_halt as _;
 
 use rtic::app;
 
+use e_button::{Buttons, Event, Timings};
 use embedded_hal::digital::v2::{InputPin, OutputPin};
-use stm32f1xx_hal::gpio::gpioa::{PA0, PA1, PA2, PA5, PA6, PA7};
+use stm32f1xx_hal::gpio::gpioa::{PA0, PA1, PA2, PA5, PA6};
 use stm32f1xx_hal::gpio::{gpioc::PC13, Edge};
 use stm32f1xx_hal::gpio::{ExtiPin, Floating, Input, Output, PushPull, State};
+use stm32f1xx_hal::pac;
 use stm32f1xx_hal::prelude::*;
+use stm32f1xx_hal::time::{Instant, MonoTimer};
+use stm32f1xx_hal::timer::{CountDownTimer, Event as TimerEvent, Timer};
+
+/// Index of the buttons in `Buttons`
+const PA5_BUTTON: usize = 0;
+const PA6_BUTTON: usize = 1;
+
+pub struct Leds {
+    led: PC13<Output<PushPull>>,
 
-pub struct Button<T> {
-    pin: T,
-    pressed: bool,
+    led0: PA0<Output<PushPull>>,
+    led1: PA1<Output<PushPull>>,
+    led2: PA2<Output<PushPull>>,
 }
 
 #[app(device = stm32f1xx_hal::pac, peripherals = true)]
 const APP: () = {
     struct Resources {
-        led: PC13<Output<PushPull>>,
+        leds: Leds,
 
-        led0: PA0<Output<PushPull>>,
-        led1: PA1<Output<PushPull>>,
-        led2: PA2<Output<PushPull>>,
+        pa5: PA5<Input<Floating>>,
+        pa6: PA6<Input<Floating>>,
 
-        pa5: Button<PA5<Input<Floating>>>,
-        pa6: Button<PA6<Input<Floating>>>,
+        buttons: Buttons<2>,
+        /// The ticks of `buttons` are the cycles elapsed since `start`
+        start: Instant,
+        timer: CountDownTimer<pac::TIM2>,
     }
 
     #[init]
     fn init(cx: init::Context) -> init::LateResources {
+        let mut flash = cx.device.FLASH.constrain();
         let mut rcc = cx.device.RCC.constrain();
+        let clocks = rcc.cfgr.freeze(&mut flash.acr);
         let mut afio = cx.device.AFIO.constrain(&mut rcc.apb2);
 
         let mut gpioc = cx.device.GPIOC.split(&mut rcc.apb2);
@@ -60,35 +79,41 @@ const APP: () = {
         let led2 = gpioa
             .pa2
             .into_push_pull_output_with_state(&mut gpioa.crl, State::Low);
+        let leds = Leds {
+            led,
+            led0,
+            led1,
+            led2,
+        };
 
         // Setup Buttons
         let mut pa5 = gpioa.pa5.into_floating_input(&mut gpioa.crl);
         pa5.make_interrupt_source(&mut afio);
         pa5.trigger_on_edge(&cx.device.EXTI, Edge::RISING_FALLING);
         pa5.enable_interrupt(&cx.device.EXTI);
-        let pa5 = Button {
-            pin: pa5,
-            pressed: false,
-        };
 
         let mut pa6 = gpioa.pa6.into_floating_input(&mut gpioa.crl);
         pa6.make_interrupt_source(&mut afio);
         pa6.trigger_on_edge(&cx.device.EXTI, Edge::RISING_FALLING);
         pa6.enable_interrupt(&cx.device.EXTI);
-        let pa6 = Button {
-            pin: pa6,
-            pressed: false,
-        };
 
-        // Setup Leds
+        let mono_timer = MonoTimer::new(cx.core.DWT, cx.core.DCB, clocks);
+        let start = mono_timer.now();
+        let ticks_per_ms = mono_timer.frequency().0 / 1_000;
+        let buttons = Buttons::new(Timings::default().scaled(ticks_per_ms));
+
+        // Polls the buttons for the gestures depending only on time, like the long press
+        let mut timer =
+            Timer::tim2(cx.device.TIM2, &clocks, &mut rcc.apb1).start_count_down(100.hz());
+        timer.listen(TimerEvent::Update);
 
         init::LateResources {
-            led,
-            led0,
-            led1,
-            led2,
+            leds,
             pa5,
             pa6,
+            buttons,
+            start,
+            timer,
         }
     }
 
@@ -99,37 +124,43 @@ const APP: () = {
         }
     }
 
-    #[task(binds = EXTI9_5, priority = 1, resources = [led, led0, led1, led2, pa5, pa6])]
+    #[task(binds = EXTI9_5, priority = 1, resources = [leds, pa5, pa6, buttons, start])]
     fn pin(cx: pin::Context) {
-        if !cx.resources.pa5.pressed && cx.resources.pa5.pin.is_low().unwrap() {
-            // PA5 RISING
-            cx.resources.pa5.pressed = true;
-        } else if cx.resources.pa5.pressed && cx.resources.pa5.pin.is_high().unwrap() {
-            // PA5 FALLING
-            cx.resources.pa5.pressed = false;
-            if cx.resources.pa6.pressed {
-                // BOTH pressed
-                cx.resources.pa6.pressed = false;
-                cx.resources.led0.toggle().unwrap();
-            } else {
-                cx.resources.led1.toggle().unwrap();
-            }
-        } else if !cx.resources.pa6.pressed && cx.resources.pa6.pin.is_low().unwrap() {
-            // PA6 RISING
-            cx.resources.pa6.pressed = true;
-        } else if cx.resources.pa6.pressed && cx.resources.pa6.pin.is_high().unwrap() {
-            // PA6 FALLING
-            cx.resources.pa6.pressed = false;
-            if cx.resources.pa5.pressed {
-                // BOTH pressed
-                cx.resources.pa5.pressed = false;
-                cx.resources.led0.toggle().unwrap();
-            } else {
-                cx.resources.led2.toggle().unwrap();
-            }
+        let now = cx.resources.start.elapsed();
+        let leds = cx.resources.leds;
+        let buttons = cx.resources.buttons;
+        // the buttons pull the pins low when pressed
+        let pa5 = cx.resources.pa5;
+        if pa5.check_interrupt() {
+            buttons.edge(PA5_BUTTON, pa5.is_low().unwrap(), now, |e| show(e, leds));
+            pa5.clear_interrupt_pending_bit();
+        }
+        let pa6 = cx.resources.pa6;
+        if pa6.check_interrupt() {
+            buttons.edge(PA6_BUTTON, pa6.is_low().unwrap(), now, |e| show(e, leds));
+            pa6.clear_interrupt_pending_bit();
         }
+    }
 
-        cx.resources.pa5.pin.clear_interrupt_pending_bit();
-        cx.resources.pa6.pin.clear_interrupt_pending_bit();
+    #[task(binds = TIM2, priority = 1, resources = [leds, buttons, start, timer])]
+    fn tick(cx: tick::Context) {
+        let now = cx.resources.start.elapsed();
+        let leds = cx.resources.leds;
+        cx.resources.buttons.poll(now, |e| show(e, leds));
+        cx.resources.timer.clear_update_interrupt_flag();
     }
 };
+
+fn show(event: Event, leds: &mut Leds) {
+    match event {
+        Event::Click(PA5_BUTTON) => leds.led1.toggle().unwrap(),
+        Event::Click(_) => leds.led2.toggle().unwrap(),
+        Event::Chord(_) => leds.led0.toggle().unwrap(),
+        Event::DoubleClick(_) => {
+            leds.led0.set_low().unwrap();
+            leds.led1.set_low().unwrap();
+            leds.led2.set_low().unwrap();
+        }
+        Event::Long(_) | Event::Repeat(_) => leds.led.toggle().unwrap(),
+    }
+}
@@ -0,0 +1,173 @@
+//! describe
+//!
+//! This module turns the frames of our boards into human readable lines: the telemetry of the
+//! gauges, the heartbeats and the requests and responses of the protocol
+//!
+
+use aero_core::alarm::{Kind, State};
+use aero_core::{Temp, Unit};
+use can_core::protocol::{self, Heartbeat, Request, Response, MAX_NODE};
+use can_core::telemetry::{self, Message};
+use can_core::Frame;
+use std::fmt::Write;
+
+/// Describe `frame`, the telemetry is expected from the gauge with `base_id`
+pub fn describe(frame: &Frame, base_id: u16) -> String {
+    if let Ok((message, counter)) = telemetry::decode(base_id, frame) {
+        return format!("{} #{}", telemetry_message(&message), counter);
+    }
+    if let Ok(heartbeat) = Heartbeat::decode(frame) {
+        return format!(
+            "heartbeat node {} {:?} uptime {}s",
+            heartbeat.node, heartbeat.state, heartbeat.uptime
+        );
+    }
+    if let Some(node) = node_of(frame.id(), protocol::REQUEST) {
+        if let Ok(request) = Request::decode(node, frame) {
+            return format!("request to node {} {:?}", node, request);
+        }
+    }
+    if let Some(node) = node_of(frame.id(), protocol::RESPONSE) {
+        if let Ok(response) = Response::decode(node, frame) {
+            return format!("response of node {} {:?}", node, response);
+        }
+    }
+    format!("{:03X} {}", frame.id(), hex(frame.data()))
+}
+
+fn telemetry_message(message: &Message) -> String {
+    let mut s = String::new();
+    match message {
+        Message::Temps {
+            temps,
+            alarms,
+            icing,
+            read_errors,
+        } => {
+            s.push_str("temps");
+            for (i, name) in ["OAT", "CAT"].iter().enumerate() {
+                write!(s, " {} {} {}", name, temp(temps[i]), alarm(alarms[i])).unwrap();
+            }
+            write!(s, " icing {} read errors {}", icing, read_errors).unwrap();
+        }
+        Message::Extremes { channel, min_max } => {
+            let name = if *channel == 0 { "OAT" } else { "CAT" };
+            match min_max {
+                Some((min, max)) => {
+                    write!(s, "{} min {} max {}", name, temp(*min), temp(*max)).unwrap()
+                }
+                None => write!(s, "{} no extremes yet", name).unwrap(),
+            }
+        }
+    }
+    s
+}
+
+fn temp(temp: Temp) -> String {
+    let mut s = String::new();
+    temp.write_buffer(Unit::Celsius, true, &mut s);
+    s.trim_start().to_string()
+}
+
+fn alarm(state: State) -> &'static str {
+    match state {
+        State::Cleared => "ok",
+        State::Active(Kind::Low) => "LOW",
+        State::Active(Kind::High) => "HIGH",
+        State::Acknowledged(Kind::Low) => "low (ack)",
+        State::Acknowledged(Kind::High) => "high (ack)",
+    }
+}
+
+/// The node of `id` if it is `function` plus a valid node id
+fn node_of(id: u16, function: u16) -> Option<u8> {
+    let node = id.checked_sub(function)?;
+    (1..=MAX_NODE as u16).contains(&node).then_some(node as u8)
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// The value of a parameter as hex, followed by the text or the number it could be
+pub fn value(value: &[u8]) -> String {
+    let mut s = hex(value);
+    match value.len() {
+        _ if !value.is_empty() && value.iter().all(|b| b.is_ascii_graphic() || *b == b' ') => {
+            write!(s, " \"{}\"", String::from_utf8_lossy(value)).unwrap()
+        }
+        2 => write!(s, " {}", u16::from_le_bytes([value[0], value[1]])).unwrap(),
+        4 => write!(s, " {}", u32::from_le_bytes(value.try_into().unwrap())).unwrap(),
+        _ => (),
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aero_core::icing::Risk;
+    use can_core::protocol::{Abort, NodeState};
+    use can_core::telemetry::{Readings, Telemetry, DEFAULT_BASE_ID};
+
+    #[test]
+    fn test_telemetry() {
+        let mut telemetry = Telemetry::new(DEFAULT_BASE_ID);
+        let readings = Readings {
+            temps: [Temp(-1234), Temp(567)],
+            extremes: [Some((Temp(-2000), Temp(100))), None],
+            alarms: [State::Active(Kind::Low), State::Cleared],
+            icing: Risk::Moderate,
+            read_errors: 0,
+        };
+        let [temps, oat, cat] = telemetry.encode(&readings);
+        assert_eq!(
+            describe(&temps, DEFAULT_BASE_ID),
+            "temps OAT -12.3°C LOW CAT 5.6°C ok icing MODERATE read errors 0 #0"
+        );
+        assert_eq!(
+            describe(&oat, DEFAULT_BASE_ID),
+            "OAT min -20.0°C max 1.0°C #0"
+        );
+        assert_eq!(describe(&cat, DEFAULT_BASE_ID), "CAT no extremes yet #0");
+        // another gauge
+        assert_eq!(describe(&cat, 0x200), "102 000000000000");
+    }
+
+    #[test]
+    fn test_protocol() {
+        let heartbeat = Heartbeat {
+            node: 1,
+            state: NodeState::Operational,
+            uptime: 60,
+        };
+        assert_eq!(
+            describe(&heartbeat.encode(), 0x100),
+            "heartbeat node 1 Operational uptime 60s"
+        );
+        let request = Request::Read { index: 2 }.encode(3);
+        assert_eq!(
+            describe(&request, 0x100),
+            "request to node 3 Read { index: 2 }"
+        );
+        let response = Response::Abort {
+            index: 2,
+            code: Abort::ReadOnly,
+        };
+        assert_eq!(
+            describe(&response.encode(3), 0x100),
+            "response of node 3 Abort { index: 2, code: ReadOnly }"
+        );
+        let frame = Frame::new(0x600, &[0x40, 0, 0]).unwrap();
+        assert_eq!(describe(&frame, 0x100), "600 400000");
+    }
+
+    #[test]
+    fn test_value() {
+        assert_eq!(value(b"can node"), "63616E206E6F6465 \"can node\"");
+        assert_eq!(value(&[0xE8, 0x03]), "E803 1000");
+        assert_eq!(value(&[1, 0, 0, 0]), "01000000 1");
+        assert_eq!(value(&[]), "");
+        assert_eq!(value(&[0]), "00");
+    }
+}
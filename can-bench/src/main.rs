@@ -0,0 +1,242 @@
+//! can-bench
+//!
+//! Bench tool talking to our boards through a SocketCAN interface of the PC, a USB adapter or the
+//! `vcan0` virtual interface
+//!
+
+mod describe;
+mod socket;
+mod trace;
+
+use can_core::protocol::client::{Client, Outcome, Step};
+use can_core::protocol::{index, is_valid_node};
+use can_core::stats::Counters;
+use can_core::telemetry::DEFAULT_BASE_ID;
+use socket::Socket;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use trace::Record;
+
+/// How long to wait for a response of a node
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+const USAGE: &str = "usage: can-bench <interface> <command>
+
+commands:
+  dump [base id]                 print the frames, the telemetry of the gauge with base id
+                                 (default 0x100) decoded
+  read <node> <index>            read a parameter of a node
+  write <node> <index> <value>   write a parameter of a node, the value is one of
+                                 u8:<n> u16:<n> u32:<n> hex:<bytes> str:<text>
+  record <file>                  save the frames in candump log format
+  replay <file>                  send the frames of a candump log keeping their timing
+
+numbers are decimal or hex with 0x, eg. `can-bench vcan0 read 1 0x0002`";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[&str]) -> Result<(), String> {
+    let (interface, command, args) = match args {
+        [interface, command, args @ ..] => (*interface, *command, args),
+        _ => return Err(USAGE.to_string()),
+    };
+    let socket = Socket::open(interface).map_err(|e| format!("opening {}: {}", interface, e))?;
+    match (command, args) {
+        ("dump", []) => dump(&socket, DEFAULT_BASE_ID),
+        ("dump", [base_id]) => dump(&socket, parse_number(base_id)?),
+        ("read", [node, index]) => read(&socket, parse_node(node)?, parse_number(index)?),
+        ("write", [node, index, value]) => write(
+            &socket,
+            parse_node(node)?,
+            parse_number(index)?,
+            &parse_value(value)?,
+        ),
+        ("record", [file]) => record(&socket, interface, file),
+        ("replay", [file]) => replay(&socket, file),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn dump(socket: &Socket, base_id: u16) -> Result<(), String> {
+    loop {
+        let frame = socket.receive().map_err(|e| e.to_string())?;
+        println!("{}", describe::describe(&frame, base_id));
+    }
+}
+
+fn read(socket: &Socket, node: u8, index: u16) -> Result<(), String> {
+    let mut client = Client::new(node);
+    let request = client.read(index).map_err(|e| format!("{:?}", e))?;
+    match transfer(socket, &mut client, request)? {
+        Outcome::Read(value) => {
+            println!("{}", describe::value(&value));
+            if index == index::COUNTERS {
+                if let Some(counters) = Counters::from_le_bytes(&value) {
+                    println!("{:#?}", counters);
+                }
+            }
+            Ok(())
+        }
+        outcome => Err(format!("unexpected {:?}", outcome)),
+    }
+}
+
+fn write(socket: &Socket, node: u8, index: u16, value: &[u8]) -> Result<(), String> {
+    let mut client = Client::new(node);
+    let request = client.write(index, value).map_err(|e| format!("{:?}", e))?;
+    match transfer(socket, &mut client, request)? {
+        Outcome::Written => Ok(()),
+        outcome => Err(format!("unexpected {:?}", outcome)),
+    }
+}
+
+/// Run the transfer started by `request` until it's done, aborting it if the node doesn't answer
+/// within `TIMEOUT`
+fn transfer(
+    socket: &Socket,
+    client: &mut Client,
+    request: can_core::Frame,
+) -> Result<Outcome, String> {
+    let io = |e: io::Error| e.to_string();
+    socket.send(&request).map_err(io)?;
+    let mut deadline = Instant::now() + TIMEOUT;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let received = match left {
+            Duration::ZERO => Err(io::ErrorKind::WouldBlock.into()),
+            left => socket
+                .set_timeout(Some(left))
+                .and_then(|_| socket.receive()),
+        };
+        let frame = match received {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if let Some(abort) = client.cancel() {
+                    socket.send(&abort).map_err(io)?;
+                }
+                return Err(format!("no response from node {}", client.server()));
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        match client.handle(&frame) {
+            Some(Step::Send(request)) => {
+                socket.send(&request).map_err(io)?;
+                deadline = Instant::now() + TIMEOUT;
+            }
+            Some(Step::Done(Outcome::Aborted(code))) => {
+                return Err(format!("node {} aborted: {:?}", client.server(), code))
+            }
+            Some(Step::Done(outcome)) => return Ok(outcome),
+            None => (),
+        }
+    }
+}
+
+fn record(socket: &Socket, interface: &str, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("creating {}: {}", path, e))?;
+    let mut file = BufWriter::new(file);
+    loop {
+        let frame = socket.receive().map_err(|e| e.to_string())?;
+        let record = Record {
+            time: SystemTime::now().duration_since(UNIX_EPOCH).unwrap(),
+            interface: interface.to_string(),
+            frame,
+        };
+        // flushed at every frame, recording is stopped with ctrl-c
+        writeln!(file, "{}", record)
+            .and_then(|_| file.flush())
+            .map_err(|e| e.to_string())?;
+        println!("{}", describe::describe(&frame, DEFAULT_BASE_ID));
+    }
+}
+
+fn replay(socket: &Socket, path: &str) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("opening {}: {}", path, e))?;
+    let mut first: Option<Duration> = None;
+    let start = Instant::now();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = line
+            .parse()
+            .map_err(|e| format!("{}:{}: {}", path, i + 1, e))?;
+        let first = *first.get_or_insert(record.time);
+        let at = record.time.saturating_sub(first);
+        if let Some(wait) = at.checked_sub(start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        socket.send(&record.frame).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn parse_number(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number `{}`", s))
+}
+
+fn parse_node(s: &str) -> Result<u8, String> {
+    match parse_number(s)? {
+        node if node <= u8::MAX as u16 && is_valid_node(node as u8) => Ok(node as u8),
+        _ => Err(format!("invalid node `{}`, from 1 to 127", s)),
+    }
+}
+
+/// A value as `type:value`, the numbers are written little endian
+fn parse_value(s: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid value `{}`", s);
+    let (kind, value) = s.split_once(':').ok_or_else(invalid)?;
+    let value = match kind {
+        "u8" => vec![value.parse::<u8>().map_err(|_| invalid())?],
+        "u16" => value
+            .parse::<u16>()
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        "u32" => value
+            .parse::<u32>()
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        "hex" => trace::parse_hex(value).ok_or_else(invalid)?,
+        "str" => value.as_bytes().to_vec(),
+        _ => return Err(invalid()),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_number("10"), Ok(10));
+        assert_eq!(parse_number("0x10"), Ok(16));
+        assert!(parse_number("x").is_err());
+        assert_eq!(parse_node("127"), Ok(127));
+        assert!(parse_node("0").is_err());
+        assert!(parse_node("128").is_err());
+        assert!(parse_node("256").is_err());
+
+        assert_eq!(parse_value("u16:1000"), Ok(vec![0xE8, 0x03]));
+        assert_eq!(parse_value("u32:1"), Ok(vec![1, 0, 0, 0]));
+        assert_eq!(parse_value("hex:0aff"), Ok(vec![0x0A, 0xFF]));
+        assert_eq!(parse_value("str:oat: left"), Ok(b"oat: left".to_vec()));
+        assert!(parse_value("u8:256").is_err());
+        assert!(parse_value("1000").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! socket
+//!
+//! This module contains a raw SocketCAN socket, bound to an interface like `can0` or `vcan0`.
+//! The few functions of the C library needed are declared here instead of depending on the `libc`
+//! crate.
+//!
+
+use can_core::Frame;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_void};
+use std::time::Duration;
+
+const AF_CAN: c_int = 29;
+const SOCK_RAW: c_int = 3;
+const CAN_RAW: c_int = 1;
+const SOL_SOCKET: c_int = 1;
+const SO_RCVTIMEO: c_int = 20;
+
+/// Flags in the identifier of frames we don't use: extended, remote and error frames
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+
+/// `struct sockaddr_can`, the union of the addresses of the other protocols is not used
+#[repr(C)]
+struct SockaddrCan {
+    can_family: u16,
+    can_ifindex: c_int,
+    addr: [u64; 2],
+}
+
+/// `struct can_frame`
+#[repr(C, align(8))]
+#[derive(Default)]
+struct CanFrame {
+    can_id: u32,
+    len: u8,
+    pad: u8,
+    res0: u8,
+    len8_dlc: u8,
+    data: [u8; 8],
+}
+
+#[repr(C)]
+struct Timeval {
+    tv_sec: c_long,
+    tv_usec: c_long,
+}
+
+extern "C" {
+    fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+    fn bind(fd: c_int, addr: *const SockaddrCan, len: c_uint) -> c_int;
+    fn setsockopt(fd: c_int, level: c_int, name: c_int, value: *const c_void, len: c_uint)
+        -> c_int;
+    fn if_nametoindex(name: *const c_char) -> c_uint;
+    fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+    fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+}
+
+pub struct Socket {
+    fd: OwnedFd,
+}
+
+impl Socket {
+    /// Open a socket receiving all the frames of `interface`
+    pub fn open(interface: &str) -> io::Result<Socket> {
+        let name = CString::new(interface)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+        let index = unsafe { if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { socket(AF_CAN, SOCK_RAW, CAN_RAW) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // closed on drop from now on
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let addr = SockaddrCan {
+            can_family: AF_CAN as u16,
+            can_ifindex: index as c_int,
+            addr: [0; 2],
+        };
+        let len = size_of::<SockaddrCan>() as c_uint;
+        if unsafe { bind(fd.as_raw_fd(), &addr, len) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Socket { fd })
+    }
+
+    /// How long `receive` waits for a frame, `None` waits forever
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        // a zero timeval waits forever
+        let timeout = match timeout {
+            Some(timeout) => timeout.max(Duration::from_micros(1)),
+            None => Duration::ZERO,
+        };
+        let timeval = Timeval {
+            tv_sec: timeout.as_secs() as c_long,
+            tv_usec: timeout.subsec_micros() as c_long,
+        };
+        let value = &timeval as *const Timeval as *const c_void;
+        let len = size_of::<Timeval>() as c_uint;
+        if unsafe { setsockopt(self.fd.as_raw_fd(), SOL_SOCKET, SO_RCVTIMEO, value, len) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// The next standard data frame on the bus, the others are skipped. Returns an error of kind
+    /// `WouldBlock` when the timeout expires
+    pub fn receive(&self) -> io::Result<Frame> {
+        loop {
+            let mut frame = CanFrame::default();
+            let buf = &mut frame as *mut CanFrame as *mut c_void;
+            let n = unsafe { read(self.fd.as_raw_fd(), buf, size_of::<CanFrame>()) };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if n as usize != size_of::<CanFrame>() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short frame"));
+            }
+            if frame.can_id & (CAN_EFF_FLAG | CAN_RTR_FLAG | CAN_ERR_FLAG) != 0 {
+                continue;
+            }
+            let len = (frame.len as usize).min(8);
+            // the identifier is 11 bits without the flags
+            return Ok(Frame::new(frame.can_id as u16, &frame.data[..len]).unwrap());
+        }
+    }
+
+    pub fn send(&self, frame: &Frame) -> io::Result<()> {
+        let mut raw = CanFrame {
+            can_id: frame.id() as u32,
+            len: frame.data().len() as u8,
+            ..Default::default()
+        };
+        raw.data[..frame.data().len()].copy_from_slice(frame.data());
+        let buf = &raw as *const CanFrame as *const c_void;
+        let n = unsafe { write(self.fd.as_raw_fd(), buf, size_of::<CanFrame>()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
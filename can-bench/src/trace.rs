@@ -0,0 +1,157 @@
+//! trace
+//!
+//! This module reads and writes traces in the log format of `candump -l`, one frame per line:
+//! `(1436509052.249713) vcan0 123#DEADBEEF`, so the traces can be used with the can-utils too
+//!
+
+use can_core::Frame;
+use std::fmt;
+use std::time::Duration;
+
+/// A frame seen on the bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Since the unix epoch
+    pub time: Duration,
+    pub interface: String,
+    pub frame: Frame,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The line is not `(time) interface id#data`
+    Format,
+    InvalidTime,
+    /// The identifier is not a standard one or the data is not hex
+    InvalidFrame,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Format => write!(f, "expected `(time) interface id#data`"),
+            Error::InvalidTime => write!(f, "invalid time"),
+            Error::InvalidFrame => {
+                write!(f, "invalid frame, only standard data frames are supported")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}.{:06}) {} {:03X}#",
+            self.time.as_secs(),
+            self.time.subsec_micros(),
+            self.interface,
+            self.frame.id()
+        )?;
+        for byte in self.frame.data() {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Record {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Record, Error> {
+        let mut parts = line.split_whitespace();
+        let (time, interface, frame) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(time), Some(interface), Some(frame)) => (time, interface, frame),
+            _ => return Err(Error::Format),
+        };
+        let time = time
+            .strip_prefix('(')
+            .and_then(|t| t.strip_suffix(')'))
+            .ok_or(Error::Format)?;
+        let (secs, micros) = time.split_once('.').ok_or(Error::InvalidTime)?;
+        if micros.len() != 6 {
+            return Err(Error::InvalidTime);
+        }
+        let secs: u64 = secs.parse().map_err(|_| Error::InvalidTime)?;
+        let micros: u32 = micros.parse().map_err(|_| Error::InvalidTime)?;
+
+        let (id, data) = frame.split_once('#').ok_or(Error::Format)?;
+        if id.len() != 3 {
+            return Err(Error::InvalidFrame);
+        }
+        let id = u16::from_str_radix(id, 16).map_err(|_| Error::InvalidFrame)?;
+        let data = parse_hex(data).ok_or(Error::InvalidFrame)?;
+        let frame = Frame::new(id, &data).ok_or(Error::InvalidFrame)?;
+
+        Ok(Record {
+            time: Duration::new(secs, micros * 1_000),
+            interface: interface.to_string(),
+            frame,
+        })
+    }
+}
+
+/// The bytes of an hex string like `DEADBEEF`, `None` if not valid
+pub fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let record = Record {
+            time: Duration::new(1436509052, 249_713_000),
+            interface: "vcan0".to_string(),
+            frame: Frame::new(0x123, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap(),
+        };
+        let line = "(1436509052.249713) vcan0 123#DEADBEEF";
+        assert_eq!(record.to_string(), line);
+        assert_eq!(line.parse(), Ok(record));
+
+        let record: Record = "(0.000001) can0 7FF#".parse().unwrap();
+        assert_eq!(record.time, Duration::from_micros(1));
+        assert_eq!(record.frame, Frame::new(0x7FF, &[]).unwrap());
+        assert_eq!(record.to_string(), "(0.000001) can0 7FF#");
+        // lowercase as written by other tools
+        let record: Record = "(1.000000) can0 10a#0aff".parse().unwrap();
+        assert_eq!(record.frame, Frame::new(0x10A, &[0x0A, 0xFF]).unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        let parse = |line: &str| line.parse::<Record>();
+        assert_eq!(parse(""), Err(Error::Format));
+        assert_eq!(parse("(1.000000) vcan0"), Err(Error::Format));
+        assert_eq!(parse("1.000000 vcan0 123#00"), Err(Error::Format));
+        assert_eq!(parse("(1.0) vcan0 123#00"), Err(Error::InvalidTime));
+        assert_eq!(parse("(x.000000) vcan0 123#00"), Err(Error::InvalidTime));
+        assert_eq!(
+            parse("(1.000000) vcan0 12345678#00"),
+            Err(Error::InvalidFrame)
+        );
+        assert_eq!(parse("(1.000000) vcan0 800#00"), Err(Error::InvalidFrame));
+        assert_eq!(parse("(1.000000) vcan0 123#0"), Err(Error::InvalidFrame));
+        assert_eq!(parse("(1.000000) vcan0 123#zz"), Err(Error::InvalidFrame));
+        assert_eq!(
+            parse("(1.000000) vcan0 123#000000000000000000"),
+            Err(Error::InvalidFrame)
+        );
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert_eq!(parse_hex("00ff10"), Some(vec![0, 255, 16]));
+        assert_eq!(parse_hex("0"), None);
+        assert_eq!(parse_hex("é0"), None);
+    }
+}
@@ -0,0 +1,349 @@
+//! filter
+//!
+//! This module turns the table of identifiers a node cares about into the configuration of the
+//! bxCAN filter banks, so that the other frames are dropped by the peripheral without waking up
+//! the node
+//!
+//! Our boards use standard identifiers only: a bank in the 16-bit modes holds 4 identifiers (list)
+//! or 2 identifier/mask pairs, twice the 32-bit modes, so the 32-bit modes are never used.
+//!
+
+use crate::frame::MAX_ID;
+use core::ops::Deref;
+
+/// Filter banks of the STM32F103 with a single CAN peripheral
+pub const BANKS: usize = 14;
+
+/// All the bits of a standard identifier, the mask of an exact match
+const EXACT: u16 = MAX_ID;
+
+/// The receive FIFO of the frames accepted by a bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Fifo {
+    Fifo0,
+    Fifo1,
+}
+
+/// An identifier or an inclusive range of identifiers to receive in `fifo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Filter {
+    first: u16,
+    last: u16,
+    fifo: Fifo,
+}
+
+impl Filter {
+    pub const fn id(id: u16, fifo: Fifo) -> Self {
+        Filter {
+            first: id,
+            last: id,
+            fifo,
+        }
+    }
+
+    pub const fn range(first: u16, last: u16, fifo: Fifo) -> Self {
+        Filter { first, last, fifo }
+    }
+}
+
+/// The configuration of a filter bank in one of the 16-bit modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bank {
+    /// Data frames with one of the identifiers, unused slots repeat an identifier of the bank
+    List16([u16; 4]),
+    /// Frames whose identifier is equal to `id` in the bits set in `mask`, as `(id, mask)`
+    Mask16([(u16, u16); 2]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// An identifier is greater than `MAX_ID`
+    InvalidId,
+    /// The first identifier of a range is greater than the last
+    EmptyRange,
+    /// The filters need `needed` banks while only `available` are
+    OutOfBanks { needed: usize, available: usize },
+}
+
+/// The banks of a table of filters, built by `banks`, dereferences to a slice of `(Fifo, Bank)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Banks<const N: usize> {
+    banks: [(Fifo, Bank); N],
+    /// The banks needed, more than `N` while `banks` finds out that they don't fit
+    len: usize,
+}
+
+impl<const N: usize> Banks<N> {
+    const fn new() -> Self {
+        Banks {
+            banks: [(Fifo::Fifo0, Bank::List16([0; 4])); N],
+            len: 0,
+        }
+    }
+
+    /// Append `bank` if there is room, count it anyway
+    const fn push(&mut self, fifo: Fifo, bank: Bank) {
+        if self.len < N {
+            self.banks[self.len] = (fifo, bank);
+        }
+        self.len += 1;
+    }
+}
+
+impl<const N: usize> Deref for Banks<N> {
+    type Target = [(Fifo, Bank)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.banks[..self.len]
+    }
+}
+
+/// The banks accepting the frames of `filters`, at most `N` of them, eg. `BANKS`. The banks of
+/// `Fifo::Fifo0` come first.
+///
+/// Ranges are split in blocks aligned to a power of two, each one a mask entry, the blocks of a
+/// single identifier are list entries. A single identifier left alone in a list bank goes in the
+/// free slot of a mask bank when there is one.
+///
+/// It is a `const fn` so the firmwares build their banks at build time, and a table that doesn't
+/// fit fails the build.
+pub const fn banks<const N: usize>(filters: &[Filter]) -> Result<Banks<N>, Error> {
+    let mut i = 0;
+    while i < filters.len() {
+        let filter = filters[i];
+        if filter.first > filter.last {
+            return Err(Error::EmptyRange);
+        }
+        if filter.last > MAX_ID {
+            return Err(Error::InvalidId);
+        }
+        i += 1;
+    }
+
+    let mut banks = Banks::new();
+    pack(filters, Fifo::Fifo0, &mut banks);
+    pack(filters, Fifo::Fifo1, &mut banks);
+    if banks.len > N {
+        return Err(Error::OutOfBanks {
+            needed: banks.len,
+            available: N,
+        });
+    }
+    Ok(banks)
+}
+
+/// Push to `banks` the banks of the filters with `fifo`
+const fn pack<const N: usize>(filters: &[Filter], fifo: Fifo, banks: &mut Banks<N>) {
+    let mut ids = [0u16; 4];
+    let mut ids_len = 0;
+    let mut masks = [(0u16, 0u16); 2];
+    let mut masks_len = 0;
+
+    let mut i = 0;
+    while i < filters.len() {
+        let filter = filters[i];
+        i += 1;
+        if filter.fifo as u8 != fifo as u8 {
+            continue;
+        }
+        // the `(id, mask)` blocks covering the range, each one the largest aligned to a power of
+        // two
+        let last = filter.last as u32;
+        let mut start = filter.first as u32;
+        while start <= last {
+            let mut size = 1u32;
+            while start.is_multiple_of(size * 2) && start + size * 2 - 1 <= last {
+                size *= 2;
+            }
+            let (id, mask) = (start as u16, !(size as u16 - 1) & EXACT);
+            start += size;
+
+            if mask == EXACT {
+                ids[ids_len] = id;
+                ids_len += 1;
+                if ids_len == ids.len() {
+                    banks.push(fifo, Bank::List16(ids));
+                    ids_len = 0;
+                }
+            } else {
+                masks[masks_len] = (id, mask);
+                masks_len += 1;
+                if masks_len == masks.len() {
+                    banks.push(fifo, Bank::Mask16(masks));
+                    masks_len = 0;
+                }
+            }
+        }
+    }
+
+    if ids_len == 1 && masks_len == 1 {
+        masks[1] = (ids[0], EXACT);
+        masks_len = 2;
+        ids_len = 0;
+    }
+    if ids_len > 0 {
+        let mut j = ids_len;
+        while j < ids.len() {
+            ids[j] = ids[0];
+            j += 1;
+        }
+        banks.push(fifo, Bank::List16(ids));
+    }
+    if masks_len > 0 {
+        if masks_len == 1 {
+            masks[1] = masks[0];
+        }
+        banks.push(fifo, Bank::Mask16(masks));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepts(banks: &[(Fifo, Bank)], id: u16) -> Option<Fifo> {
+        banks
+            .iter()
+            .find(|(_, bank)| match bank {
+                Bank::List16(ids) => ids.contains(&id),
+                Bank::Mask16(masks) => masks.iter().any(|(i, m)| id & m == i & m),
+            })
+            .map(|(fifo, _)| *fifo)
+    }
+
+    #[test]
+    fn test_list() {
+        let filters = [
+            Filter::id(0x100, Fifo::Fifo0),
+            Filter::id(0x101, Fifo::Fifo0),
+            Filter::id(0x7FF, Fifo::Fifo0),
+            Filter::id(0x000, Fifo::Fifo0),
+            Filter::id(0x200, Fifo::Fifo0),
+        ];
+        let result = banks::<BANKS>(&filters).unwrap();
+        assert_eq!(
+            &result[..],
+            &[
+                (Fifo::Fifo0, Bank::List16([0x100, 0x101, 0x7FF, 0x000])),
+                (Fifo::Fifo0, Bank::List16([0x200, 0x200, 0x200, 0x200])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        let filters = [Filter::range(0x100, 0x1FF, Fifo::Fifo1)];
+        let result = banks::<BANKS>(&filters).unwrap();
+        assert_eq!(
+            &result[..],
+            &[(Fifo::Fifo1, Bank::Mask16([(0x100, 0x700), (0x100, 0x700)]))]
+        );
+
+        // 0x0FF, 0x100-0x13F, 0x140-0x141, 0x142
+        let filters = [Filter::range(0x0FF, 0x142, Fifo::Fifo0)];
+        let result = banks::<BANKS>(&filters).unwrap();
+        assert_eq!(
+            &result[..],
+            &[
+                (Fifo::Fifo0, Bank::Mask16([(0x100, 0x7C0), (0x140, 0x7FE)])),
+                (Fifo::Fifo0, Bank::List16([0x0FF, 0x142, 0x0FF, 0x0FF])),
+            ]
+        );
+        for id in 0..=MAX_ID {
+            let expected = (0x0FF..=0x142).contains(&id).then_some(Fifo::Fifo0);
+            assert_eq!(accepts(&result, id), expected, "{:x}", id);
+        }
+    }
+
+    #[test]
+    fn test_single_id_in_mask_bank() {
+        // the telemetry of a gauge and a ping, it fits in one bank
+        let filters = [
+            Filter::range(0x100, 0x103, Fifo::Fifo0),
+            Filter::id(0x000, Fifo::Fifo0),
+        ];
+        let result = banks::<BANKS>(&filters).unwrap();
+        assert_eq!(
+            &result[..],
+            &[(Fifo::Fifo0, Bank::Mask16([(0x100, 0x7FC), (0x000, 0x7FF)]))]
+        );
+    }
+
+    #[test]
+    fn test_fifos() {
+        let filters = [
+            Filter::id(0x010, Fifo::Fifo1),
+            Filter::id(0x020, Fifo::Fifo0),
+            Filter::range(0x000, 0x7FF, Fifo::Fifo1),
+        ];
+        let result = banks::<BANKS>(&filters).unwrap();
+        assert_eq!(
+            &result[..],
+            &[
+                (Fifo::Fifo0, Bank::List16([0x020; 4])),
+                (Fifo::Fifo1, Bank::Mask16([(0x000, 0x000), (0x010, 0x7FF)])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_accepts_exactly() {
+        let filters = [
+            Filter::range(0x003, 0x00A, Fifo::Fifo0),
+            Filter::range(0x123, 0x456, Fifo::Fifo1),
+            Filter::id(0x500, Fifo::Fifo0),
+            Filter::range(0x7F0, 0x7FF, Fifo::Fifo0),
+        ];
+        let result = banks::<BANKS>(&filters).unwrap();
+        for id in 0..=MAX_ID {
+            let expected = filters
+                .iter()
+                .find(|f| (f.first..=f.last).contains(&id))
+                .map(|f| f.fifo);
+            assert_eq!(accepts(&result, id), expected, "{:x}", id);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let filters = [Filter::range(0x101, 0x100, Fifo::Fifo0)];
+        assert_eq!(banks::<BANKS>(&filters), Err(Error::EmptyRange));
+        let filters = [Filter::range(0x700, 0x800, Fifo::Fifo0)];
+        assert_eq!(banks::<BANKS>(&filters), Err(Error::InvalidId));
+
+        // every odd identifier up to 0x6F needs a list entry, 56 of them in 14 banks
+        let mut filters: std::vec::Vec<_> = (0..56u16)
+            .map(|i| Filter::id(i * 2 + 1, Fifo::Fifo0))
+            .collect();
+        assert_eq!(banks::<BANKS>(&filters).unwrap().len(), 14);
+        filters.push(Filter::id(0x200, Fifo::Fifo1));
+        assert_eq!(
+            banks::<BANKS>(&filters),
+            Err(Error::OutOfBanks {
+                needed: 15,
+                available: 14
+            })
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(banks::<BANKS>(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_const() {
+        const FILTERS: &[Filter] = &[
+            Filter::id(0x601, Fifo::Fifo0),
+            Filter::range(0x701, 0x77F, Fifo::Fifo1),
+        ];
+        const FILTER_BANKS: Banks<BANKS> = match banks(FILTERS) {
+            Ok(banks) => banks,
+            Err(_) => panic!(),
+        };
+        assert_eq!(FILTER_BANKS, banks::<BANKS>(FILTERS).unwrap());
+        assert_eq!(FILTER_BANKS.len(), 5);
+    }
+}
@@ -0,0 +1,55 @@
+//! frame
+//!
+//! This module contains a CAN 2.0A data frame independent of the peripheral driver, the firmwares
+//! convert it to and from `bxcan::Frame`
+//!
+
+/// Highest standard (11 bits) identifier
+pub const MAX_ID: u16 = 0x7FF;
+
+/// A data frame with a standard identifier and up to 8 bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    id: u16,
+    len: u8,
+    data: [u8; 8],
+}
+
+impl Frame {
+    /// Create a frame, `None` if `id` is over `MAX_ID` or `data` is longer than 8 bytes
+    pub fn new(id: u16, data: &[u8]) -> Option<Frame> {
+        if id > MAX_ID || data.len() > 8 {
+            return None;
+        }
+        let mut frame = Frame {
+            id,
+            len: data.len() as u8,
+            data: [0u8; 8],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let frame = Frame::new(MAX_ID, &[1, 2, 3]).unwrap();
+        assert_eq!(frame.id(), MAX_ID);
+        assert_eq!(frame.data(), &[1, 2, 3]);
+        assert_eq!(Frame::new(0, &[]).unwrap().data(), &[] as &[u8]);
+        assert!(Frame::new(MAX_ID + 1, &[]).is_none());
+        assert!(Frame::new(0, &[0u8; 9]).is_none());
+    }
+}
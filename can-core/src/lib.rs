@@ -0,0 +1,20 @@
+//! can-core
+//!
+//! Hardware independent logic of the CAN bus nodes: frames, filters, transmit queue, counters,
+//! bus-off recovery, bit timing, the request/response protocol and the telemetry layout of the
+//! aerotemp gauges, testable on the host
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod filter;
+pub mod frame;
+pub mod protocol;
+pub mod queue;
+pub mod recovery;
+pub mod stats;
+pub mod telemetry;
+pub mod timing;
+
+pub use frame::Frame;
+pub use queue::TxQueue;
@@ -0,0 +1,781 @@
+//! protocol
+//!
+//! This module contains a small request/response protocol, modelled on the CANopen SDO, to read
+//! and write the numbered parameters of a node from the bus, and the heartbeat frames the nodes
+//! broadcast. See README.md for the layout.
+//!
+//! A `client::Client` sends requests to the `server::Server` of a node. Values up to 5 bytes fit
+//! in a single frame, longer ones up to `MAX_VALUE` bytes are split in segments of 7 bytes, each
+//! one acknowledged before the next one is sent.
+//!
+
+pub mod client;
+pub mod server;
+
+use crate::Frame;
+use heapless::Vec;
+
+/// Function code of the requests, the identifier is this plus the node id of the server
+pub const REQUEST: u16 = 0x600;
+/// Function code of the responses, the identifier is this plus the node id of the server
+pub const RESPONSE: u16 = 0x580;
+/// Function code of the heartbeats, the identifier is this plus the node id of the sender
+pub const HEARTBEAT: u16 = 0x700;
+
+/// Highest node id, 0 is not a valid node id
+pub const MAX_NODE: u8 = 0x7F;
+
+/// Longest value of a parameter
+pub const MAX_VALUE: usize = 64;
+
+/// Longest value transferred in a single frame
+pub const MAX_EXPEDITED: usize = 5;
+
+/// Longest data in a segment
+pub const MAX_SEGMENT: usize = 7;
+
+/// The parameters every node has
+pub mod index {
+    /// Name of the node, up to 32 bytes of UTF-8, writable
+    pub const NAME: u16 = 0x0000;
+    /// Seconds since the node started, `u32` little endian
+    pub const UPTIME: u16 = 0x0001;
+    /// The `stats::Counters` of the node, see `Counters::to_le_bytes`
+    pub const COUNTERS: u16 = 0x0002;
+    /// Milliseconds between heartbeats, `u16` little endian, writable, 0 disables them
+    pub const HEARTBEAT_PERIOD: u16 = 0x0003;
+}
+
+pub type Value = Vec<u8, MAX_VALUE>;
+
+/// The data of a single frame request or response, or of a segment
+pub type Chunk = Vec<u8, MAX_SEGMENT>;
+
+/// Why a transfer has been aborted, sent by both sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Abort {
+    /// The node doesn't have the parameter
+    UnknownParameter = 1,
+    /// The parameter can't be written
+    ReadOnly = 2,
+    /// The value is not valid for the parameter
+    InvalidValue = 3,
+    /// The value is longer than `MAX_VALUE` or than the parameter
+    TooLong = 4,
+    /// A frame not expected in the current state of the transfer, or malformed
+    Protocol = 5,
+    /// The node can't take the value now, the client may try again later
+    Busy = 6,
+}
+
+impl Abort {
+    fn from_code(code: u8) -> Result<Abort, Error> {
+        match code {
+            1 => Ok(Abort::UnknownParameter),
+            2 => Ok(Abort::ReadOnly),
+            3 => Ok(Abort::InvalidValue),
+            4 => Ok(Abort::TooLong),
+            5 => Ok(Abort::Protocol),
+            6 => Ok(Abort::Busy),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The identifier is not the one expected
+    UnknownId,
+    /// The command byte is unknown
+    UnknownCommand,
+    /// The frame doesn't have the length of its command
+    WrongLength,
+    /// A field has a value not defined by the layout
+    InvalidValue,
+}
+
+/// Sent by a client to the server of a node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Read {
+        index: u16,
+    },
+    /// Write a value of up to `MAX_EXPEDITED` bytes
+    Write {
+        index: u16,
+        data: Chunk,
+    },
+    /// Start a segmented write of `len` bytes
+    WriteStart {
+        index: u16,
+        len: u16,
+    },
+    WriteSegment {
+        toggle: bool,
+        last: bool,
+        data: Chunk,
+    },
+    /// Ask the next segment of a segmented read
+    ReadSegment {
+        toggle: bool,
+    },
+    Abort {
+        index: u16,
+        code: Abort,
+    },
+}
+
+/// Sent by the server of a node to the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// The value of a parameter of up to `MAX_EXPEDITED` bytes
+    ReadData {
+        index: u16,
+        data: Chunk,
+    },
+    /// The value is `len` bytes, it follows in segments
+    ReadStart {
+        index: u16,
+        len: u16,
+    },
+    ReadSegment {
+        toggle: bool,
+        last: bool,
+        data: Chunk,
+    },
+    /// The value has been written, or the segmented write can start
+    Written {
+        index: u16,
+    },
+    /// The segment of a segmented write has been received
+    WriteSegmentAck {
+        toggle: bool,
+    },
+    Abort {
+        index: u16,
+        code: Abort,
+    },
+}
+
+// command bytes, the direction is given by the identifier so the codes of requests and responses
+// overlap
+const READ: u8 = 0x40;
+const WRITE: u8 = 0x20;
+const WRITE_START: u8 = 0x21;
+const READ_SEGMENT: u8 = 0x60;
+const READ_DATA: u8 = 0x43;
+const READ_START: u8 = 0x41;
+const WRITTEN: u8 = 0x60;
+const WRITE_SEGMENT_ACK: u8 = 0x20;
+const ABORT: u8 = 0x80;
+/// Command of the segments with data, bit 0 is the last flag
+const SEGMENT: u8 = 0x00;
+/// The toggle bit of the segments and their acknowledges, it alternates starting from 0
+const TOGGLE: u8 = 0x10;
+const LAST: u8 = 0x01;
+
+impl Request {
+    /// The frame for the server of `node`
+    pub fn encode(&self, node: u8) -> Frame {
+        let mut buf = Buffer::new();
+        match self {
+            Request::Read { index } => buf.command(READ).u16(*index),
+            Request::Write { index, data } => buf.command(WRITE).u16(*index).bytes(data),
+            Request::WriteStart { index, len } => buf.command(WRITE_START).u16(*index).u16(*len),
+            Request::WriteSegment { toggle, last, data } => {
+                buf.command(segment(*toggle, *last)).bytes(data)
+            }
+            Request::ReadSegment { toggle } => buf.command(READ_SEGMENT | toggle_bit(*toggle)),
+            Request::Abort { index, code } => buf.command(ABORT).u16(*index).u8(*code as u8),
+        };
+        buf.frame(REQUEST + node as u16)
+    }
+
+    /// Decode a frame sent to the server of `node`
+    pub fn decode(node: u8, frame: &Frame) -> Result<Request, Error> {
+        if frame.id() != REQUEST + node as u16 {
+            return Err(Error::UnknownId);
+        }
+        let data = frame.data();
+        let command = *data.first().ok_or(Error::WrongLength)?;
+        let request = match command {
+            READ => Request::Read {
+                index: u16_at(data, 1, 3..=3)?,
+            },
+            WRITE => Request::Write {
+                index: u16_at(data, 1, 3..=3 + MAX_EXPEDITED)?,
+                data: chunk(&data[3..]),
+            },
+            WRITE_START => Request::WriteStart {
+                index: u16_at(data, 1, 5..=5)?,
+                len: u16_at(data, 3, 5..=5)?,
+            },
+            c if c & !(TOGGLE | LAST) == SEGMENT => {
+                let (toggle, last, data) = decode_segment(data)?;
+                Request::WriteSegment { toggle, last, data }
+            }
+            c if c & !TOGGLE == READ_SEGMENT => {
+                length(data, 1..=1)?;
+                Request::ReadSegment {
+                    toggle: c & TOGGLE != 0,
+                }
+            }
+            ABORT => Request::Abort {
+                index: u16_at(data, 1, 4..=4)?,
+                code: Abort::from_code(data[3])?,
+            },
+            _ => return Err(Error::UnknownCommand),
+        };
+        Ok(request)
+    }
+}
+
+impl Response {
+    /// The frame sent by the server of `node`
+    pub fn encode(&self, node: u8) -> Frame {
+        let mut buf = Buffer::new();
+        match self {
+            Response::ReadData { index, data } => buf.command(READ_DATA).u16(*index).bytes(data),
+            Response::ReadStart { index, len } => buf.command(READ_START).u16(*index).u16(*len),
+            Response::ReadSegment { toggle, last, data } => {
+                buf.command(segment(*toggle, *last)).bytes(data)
+            }
+            Response::Written { index } => buf.command(WRITTEN).u16(*index),
+            Response::WriteSegmentAck { toggle } => {
+                buf.command(WRITE_SEGMENT_ACK | toggle_bit(*toggle))
+            }
+            Response::Abort { index, code } => buf.command(ABORT).u16(*index).u8(*code as u8),
+        };
+        buf.frame(RESPONSE + node as u16)
+    }
+
+    /// Decode a frame sent by the server of `node`
+    pub fn decode(node: u8, frame: &Frame) -> Result<Response, Error> {
+        if frame.id() != RESPONSE + node as u16 {
+            return Err(Error::UnknownId);
+        }
+        let data = frame.data();
+        let command = *data.first().ok_or(Error::WrongLength)?;
+        let response = match command {
+            READ_DATA => Response::ReadData {
+                index: u16_at(data, 1, 3..=3 + MAX_EXPEDITED)?,
+                data: chunk(&data[3..]),
+            },
+            READ_START => Response::ReadStart {
+                index: u16_at(data, 1, 5..=5)?,
+                len: u16_at(data, 3, 5..=5)?,
+            },
+            c if c & !(TOGGLE | LAST) == SEGMENT => {
+                let (toggle, last, data) = decode_segment(data)?;
+                Response::ReadSegment { toggle, last, data }
+            }
+            WRITTEN => Response::Written {
+                index: u16_at(data, 1, 3..=3)?,
+            },
+            c if c & !TOGGLE == WRITE_SEGMENT_ACK => {
+                length(data, 1..=1)?;
+                Response::WriteSegmentAck {
+                    toggle: c & TOGGLE != 0,
+                }
+            }
+            ABORT => Response::Abort {
+                index: u16_at(data, 1, 4..=4)?,
+                code: Abort::from_code(data[3])?,
+            },
+            _ => return Err(Error::UnknownCommand),
+        };
+        Ok(response)
+    }
+}
+
+/// The state of a node, in the heartbeat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NodeState {
+    Starting = 0,
+    Operational = 5,
+    /// The node works but something is wrong, eg. the sensors don't answer
+    Fault = 0x7F,
+}
+
+/// Broadcast periodically by every node, so that the others know it is alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Heartbeat {
+    pub node: u8,
+    pub state: NodeState,
+    /// Seconds since the node started
+    pub uptime: u32,
+}
+
+impl Heartbeat {
+    pub fn encode(&self) -> Frame {
+        let mut buf = Buffer::new();
+        buf.u8(self.state as u8).bytes(&self.uptime.to_le_bytes());
+        buf.frame(HEARTBEAT + self.node as u16)
+    }
+
+    pub fn decode(frame: &Frame) -> Result<Heartbeat, Error> {
+        let node = frame.id().checked_sub(HEARTBEAT).ok_or(Error::UnknownId)?;
+        if node == 0 || node > MAX_NODE as u16 {
+            return Err(Error::UnknownId);
+        }
+        let data = frame.data();
+        length(data, 5..=5)?;
+        let state = match data[0] {
+            0 => NodeState::Starting,
+            5 => NodeState::Operational,
+            0x7F => NodeState::Fault,
+            _ => return Err(Error::InvalidValue),
+        };
+        Ok(Heartbeat {
+            node: node as u8,
+            state,
+            uptime: u32::from_le_bytes([data[1], data[2], data[3], data[4]]),
+        })
+    }
+}
+
+/// Whether `node` is a valid node id
+pub const fn is_valid_node(node: u8) -> bool {
+    node >= 1 && node <= MAX_NODE
+}
+
+fn toggle_bit(toggle: bool) -> u8 {
+    if toggle {
+        TOGGLE
+    } else {
+        0
+    }
+}
+
+fn segment(toggle: bool, last: bool) -> u8 {
+    SEGMENT | toggle_bit(toggle) | if last { LAST } else { 0 }
+}
+
+fn decode_segment(data: &[u8]) -> Result<(bool, bool, Chunk), Error> {
+    length(data, 2..=1 + MAX_SEGMENT)?;
+    Ok((
+        data[0] & TOGGLE != 0,
+        data[0] & LAST != 0,
+        chunk(&data[1..]),
+    ))
+}
+
+/// `data` must be at most `MAX_SEGMENT` bytes, the lengths are checked before
+fn chunk(data: &[u8]) -> Chunk {
+    Vec::from_slice(data).unwrap()
+}
+
+fn length(data: &[u8], len: impl core::ops::RangeBounds<usize>) -> Result<(), Error> {
+    if len.contains(&data.len()) {
+        Ok(())
+    } else {
+        Err(Error::WrongLength)
+    }
+}
+
+/// The `u16` at `i` of a frame whose length must be in `len`
+fn u16_at(data: &[u8], i: usize, len: impl core::ops::RangeBounds<usize>) -> Result<u16, Error> {
+    length(data, len)?;
+    Ok(u16::from_le_bytes([data[i], data[i + 1]]))
+}
+
+/// Builds the data of a frame, the callers never go over 8 bytes
+struct Buffer(Vec<u8, 8>);
+
+impl Buffer {
+    fn new() -> Self {
+        Buffer(Vec::new())
+    }
+
+    fn command(&mut self, command: u8) -> &mut Self {
+        self.u8(command)
+    }
+
+    fn u8(&mut self, value: u8) -> &mut Self {
+        self.0.push(value).unwrap();
+        self
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(bytes).unwrap();
+        self
+    }
+
+    fn frame(&self, id: u16) -> Frame {
+        Frame::new(id, &self.0).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client::{Client, Outcome, Step};
+    use super::server::{Parameters, Server};
+    use super::*;
+    use std::collections::VecDeque;
+
+    fn chunk(data: &[u8]) -> Chunk {
+        Vec::from_slice(data).unwrap()
+    }
+
+    struct Params {
+        name: Value,
+        uptime: u32,
+        writes: usize,
+    }
+
+    impl Params {
+        fn new() -> Self {
+            Params {
+                name: Value::from_slice(b"can node").unwrap(),
+                uptime: 1234,
+                writes: 0,
+            }
+        }
+    }
+
+    impl Parameters for Params {
+        fn read(&mut self, index: u16) -> Result<Value, Abort> {
+            match index {
+                index::NAME => Ok(self.name.clone()),
+                index::UPTIME => Ok(Value::from_slice(&self.uptime.to_le_bytes()).unwrap()),
+                _ => Err(Abort::UnknownParameter),
+            }
+        }
+
+        fn write(&mut self, index: u16, value: &[u8]) -> Result<(), Abort> {
+            self.writes += 1;
+            match index {
+                index::NAME if value.len() > 32 => Err(Abort::TooLong),
+                index::NAME => {
+                    self.name = Value::from_slice(value).unwrap();
+                    Ok(())
+                }
+                index::UPTIME => Err(Abort::ReadOnly),
+                _ => Err(Abort::UnknownParameter),
+            }
+        }
+    }
+
+    /// An in-memory bus where every node sees every frame, returns the outcome of the transfer
+    /// started by `first` and the frames exchanged
+    fn transfer(
+        client: &mut Client,
+        servers: &mut [(Server, Params)],
+        first: Frame,
+    ) -> (Outcome, usize) {
+        let mut bus = VecDeque::from([first]);
+        let mut frames = 0;
+        while let Some(frame) = bus.pop_front() {
+            frames += 1;
+            for (server, params) in servers.iter_mut() {
+                if let Some(response) = server.handle(&frame, params) {
+                    bus.push_back(response);
+                }
+            }
+            match client.handle(&frame) {
+                Some(Step::Send(request)) => bus.push_back(request),
+                Some(Step::Done(outcome)) => return (outcome, frames),
+                None => (),
+            }
+        }
+        panic!("the transfer didn't end")
+    }
+
+    fn nodes() -> [(Server, Params); 2] {
+        [
+            (Server::new(1), Params::new()),
+            (Server::new(2), Params::new()),
+        ]
+    }
+
+    #[test]
+    fn test_loopback_expedited() {
+        let mut nodes = nodes();
+        let mut client = Client::new(2);
+
+        let read = client.read(index::UPTIME).unwrap();
+        assert!(client.is_busy());
+        let expected = Outcome::Read(Value::from_slice(&1234u32.to_le_bytes()).unwrap());
+        assert_eq!(transfer(&mut client, &mut nodes, read), (expected, 2));
+        assert!(!client.is_busy());
+
+        let write = client.write(index::NAME, b"oat").unwrap();
+        assert_eq!(
+            transfer(&mut client, &mut nodes, write),
+            (Outcome::Written, 2)
+        );
+        assert_eq!(&nodes[1].1.name[..], b"oat");
+        // the other node ignored the requests
+        assert_eq!(&nodes[0].1.name[..], b"can node");
+        assert_eq!(nodes[0].1.writes, 0);
+    }
+
+    #[test]
+    fn test_loopback_segmented() {
+        let mut nodes = nodes();
+        let mut client = Client::new(1);
+
+        for len in [6, 7, 8, 14, 15, 32] {
+            let name: std::vec::Vec<u8> = (0..len as u8).collect();
+            let write = client.write(index::NAME, &name).unwrap();
+            // start, then request and ack for every segment
+            let segments = usize::div_ceil(len, MAX_SEGMENT);
+            assert_eq!(
+                transfer(&mut client, &mut nodes, write),
+                (Outcome::Written, 2 + 2 * segments)
+            );
+            assert_eq!(&nodes[0].1.name[..], &name[..]);
+
+            let read = client.read(index::NAME).unwrap();
+            let expected = Outcome::Read(Value::from_slice(&name).unwrap());
+            assert_eq!(
+                transfer(&mut client, &mut nodes, read),
+                (expected, 2 + 2 * segments)
+            );
+        }
+    }
+
+    #[test]
+    fn test_loopback_aborts() {
+        let mut nodes = nodes();
+        let mut client = Client::new(1);
+
+        let read = client.read(0x1000).unwrap();
+        let outcome = Outcome::Aborted(Abort::UnknownParameter);
+        assert_eq!(transfer(&mut client, &mut nodes, read), (outcome, 2));
+
+        let write = client.write(index::UPTIME, &[0; 4]).unwrap();
+        let outcome = Outcome::Aborted(Abort::ReadOnly);
+        assert_eq!(transfer(&mut client, &mut nodes, write), (outcome, 2));
+
+        // the error of a segmented write comes after the last segment
+        let write = client.write(index::NAME, &[b'x'; 40]).unwrap();
+        let outcome = Outcome::Aborted(Abort::TooLong);
+        assert_eq!(transfer(&mut client, &mut nodes, write), (outcome, 14));
+        assert_eq!(&nodes[0].1.name[..], b"can node");
+
+        assert_eq!(
+            client.write(index::NAME, &[0; MAX_VALUE + 1]),
+            Err(client::Error::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_busy_and_cancel() {
+        let (mut server, mut params) = (Server::new(1), Params::new());
+        let mut client = Client::new(1);
+
+        let read = client.read(index::NAME).unwrap();
+        assert_eq!(client.read(index::NAME), Err(client::Error::Busy));
+        assert_eq!(client.write(index::NAME, &[]), Err(client::Error::Busy));
+
+        // the node starts the segmented read, the client gives up
+        let start = server.handle(&read, &mut params).unwrap();
+        let Some(Step::Send(segment)) = client.handle(&start) else {
+            panic!("expected a segment request")
+        };
+        let abort = client.cancel().unwrap();
+        assert!(!client.is_busy());
+        assert_eq!(client.cancel(), None);
+        assert_eq!(server.handle(&abort, &mut params), None);
+
+        // the request of the segment is now out of a transfer
+        let response = server.handle(&segment, &mut params).unwrap();
+        assert_eq!(
+            Response::decode(1, &response),
+            Ok(Response::Abort {
+                index: 0,
+                code: Abort::Protocol
+            })
+        );
+        // and the late responses are ignored by the client
+        assert_eq!(client.handle(&response), None);
+    }
+
+    #[test]
+    fn test_wrong_toggle() {
+        let (mut server, mut params) = (Server::new(1), Params::new());
+        let mut client = Client::new(1);
+
+        let read = client.read(index::NAME).unwrap();
+        let start = server.handle(&read, &mut params).unwrap();
+        client.handle(&start).unwrap();
+
+        // the node sends the first segment twice, eg. the client didn't see the first one
+        let request = Request::ReadSegment { toggle: false }.encode(1);
+        let segment = server.handle(&request, &mut params).unwrap();
+        let Some(Step::Send(request)) = client.handle(&segment) else {
+            panic!("expected a segment request")
+        };
+        assert_eq!(
+            client.handle(&segment),
+            Some(Step::Done(Outcome::Aborted(Abort::Protocol)))
+        );
+
+        // the node aborts a repeated segment request
+        let response = server.handle(&request, &mut params).unwrap();
+        assert!(matches!(
+            Response::decode(1, &response),
+            Ok(Response::ReadSegment { toggle: true, .. })
+        ));
+        let response = server.handle(&request, &mut params).unwrap();
+        assert_eq!(
+            Response::decode(1, &response),
+            Ok(Response::Abort {
+                index: index::NAME,
+                code: Abort::Protocol
+            })
+        );
+    }
+
+    #[test]
+    fn test_request_roundtrip() {
+        let requests = [
+            Request::Read { index: 0x1234 },
+            Request::Write {
+                index: 3,
+                data: chunk(&[1, 2, 3, 4, 5]),
+            },
+            Request::Write {
+                index: 3,
+                data: chunk(&[]),
+            },
+            Request::WriteStart { index: 0, len: 64 },
+            Request::WriteSegment {
+                toggle: true,
+                last: false,
+                data: chunk(&[1, 2, 3, 4, 5, 6, 7]),
+            },
+            Request::WriteSegment {
+                toggle: false,
+                last: true,
+                data: chunk(&[1]),
+            },
+            Request::ReadSegment { toggle: true },
+            Request::ReadSegment { toggle: false },
+            Request::Abort {
+                index: 2,
+                code: Abort::Protocol,
+            },
+        ];
+        for request in requests {
+            let frame = request.encode(0x7F);
+            assert_eq!(frame.id(), 0x67F);
+            assert_eq!(Request::decode(0x7F, &frame), Ok(request));
+            assert_eq!(Request::decode(1, &frame), Err(Error::UnknownId));
+        }
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        let responses = [
+            Response::ReadData {
+                index: 0xFFFF,
+                data: chunk(&[0xAA; 5]),
+            },
+            Response::ReadStart { index: 1, len: 6 },
+            Response::ReadSegment {
+                toggle: false,
+                last: true,
+                data: chunk(&[9; 7]),
+            },
+            Response::Written { index: 7 },
+            Response::WriteSegmentAck { toggle: true },
+            Response::Abort {
+                index: 0,
+                code: Abort::UnknownParameter,
+            },
+            Response::Abort {
+                index: 0x0100,
+                code: Abort::Busy,
+            },
+        ];
+        for response in responses {
+            let frame = response.encode(1);
+            assert_eq!(frame.id(), 0x581);
+            assert_eq!(Response::decode(1, &frame), Ok(response));
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let frame = Request::Read { index: 0x0102 }.encode(5);
+        assert_eq!((frame.id(), frame.data()), (0x605, &[0x40, 0x02, 0x01][..]));
+        let frame = Response::ReadData {
+            index: 0x0102,
+            data: chunk(&[7, 8]),
+        }
+        .encode(5);
+        assert_eq!(frame.id(), 0x585);
+        assert_eq!(frame.data(), &[0x43, 0x02, 0x01, 7, 8]);
+        let frame = Response::ReadSegment {
+            toggle: true,
+            last: true,
+            data: chunk(&[1]),
+        }
+        .encode(5);
+        assert_eq!(frame.data(), &[0x11, 1]);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let frame = |data: &[u8]| Frame::new(0x601, data).unwrap();
+        assert_eq!(Request::decode(1, &frame(&[])), Err(Error::WrongLength));
+        assert_eq!(
+            Request::decode(1, &frame(&[0x40, 0])),
+            Err(Error::WrongLength)
+        );
+        assert_eq!(
+            Request::decode(1, &frame(&[0x21, 0, 0, 1])),
+            Err(Error::WrongLength)
+        );
+        assert_eq!(Request::decode(1, &frame(&[0x00])), Err(Error::WrongLength));
+        assert_eq!(
+            Request::decode(1, &frame(&[0xFF])),
+            Err(Error::UnknownCommand)
+        );
+        assert_eq!(
+            Request::decode(1, &frame(&[0x80, 0, 0, 9])),
+            Err(Error::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let heartbeat = Heartbeat {
+            node: 0x10,
+            state: NodeState::Operational,
+            uptime: 0x01020304,
+        };
+        let frame = heartbeat.encode();
+        assert_eq!(frame.id(), 0x710);
+        assert_eq!(frame.data(), &[5, 4, 3, 2, 1]);
+        assert_eq!(Heartbeat::decode(&frame), Ok(heartbeat));
+
+        let frame = |id, data: &[u8]| Frame::new(id, data).unwrap();
+        assert_eq!(
+            Heartbeat::decode(&frame(0x700, &[0; 5])),
+            Err(Error::UnknownId)
+        );
+        assert_eq!(
+            Heartbeat::decode(&frame(0x6FF, &[0; 5])),
+            Err(Error::UnknownId)
+        );
+        assert_eq!(
+            Heartbeat::decode(&frame(0x701, &[0; 4])),
+            Err(Error::WrongLength)
+        );
+        assert_eq!(
+            Heartbeat::decode(&frame(0x701, &[1, 0, 0, 0, 0])),
+            Err(Error::InvalidValue)
+        );
+    }
+}
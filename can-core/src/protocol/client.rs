@@ -0,0 +1,274 @@
+//! client
+//!
+//! This module reads and writes the parameters of a remote node: a request starts a transfer,
+//! then every response of the node is passed to `Client::handle` until the transfer is done
+//!
+
+use super::{
+    is_valid_node, Abort, Chunk, Request, Response, Value, MAX_EXPEDITED, MAX_SEGMENT, MAX_VALUE,
+};
+use crate::Frame;
+
+#[derive(Debug)]
+enum State {
+    Idle,
+    Read {
+        index: u16,
+    },
+    /// Receiving `len` bytes of `index`, the last segment requested has `toggle`
+    Upload {
+        index: u16,
+        value: Value,
+        len: usize,
+        toggle: bool,
+    },
+    Write {
+        index: u16,
+    },
+    /// Sending `value` to `index`, `sent` bytes have been sent, the last one with `toggle`.
+    /// `sent` is 0 until the node acknowledges the start of the transfer
+    Download {
+        index: u16,
+        value: Value,
+        sent: usize,
+        toggle: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A transfer is in progress
+    Busy,
+    /// The value is longer than `MAX_VALUE`
+    TooLong,
+}
+
+/// How a transfer ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Read(Value),
+    Written,
+    /// Aborted by the node, or by the client on an unexpected response
+    Aborted(Abort),
+}
+
+/// What to do after a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Send the frame to continue the transfer
+    Send(Frame),
+    Done(Outcome),
+}
+
+/// Transfers parameters with the node `server`, one transfer at a time. A transfer without
+/// responses is never done: the caller decides the timeout and calls `cancel`
+#[derive(Debug)]
+pub struct Client {
+    server: u8,
+    state: State,
+}
+
+impl Client {
+    pub fn new(server: u8) -> Self {
+        assert!(is_valid_node(server));
+        Client {
+            server,
+            state: State::Idle,
+        }
+    }
+
+    pub fn server(&self) -> u8 {
+        self.server
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !matches!(self.state, State::Idle)
+    }
+
+    /// The frame requesting the value of `index`
+    pub fn read(&mut self, index: u16) -> Result<Frame, Error> {
+        self.idle()?;
+        self.state = State::Read { index };
+        Ok(Request::Read { index }.encode(self.server))
+    }
+
+    /// The frame starting the write of `value` to `index`
+    pub fn write(&mut self, index: u16, value: &[u8]) -> Result<Frame, Error> {
+        self.idle()?;
+        let request = if value.len() <= MAX_EXPEDITED {
+            self.state = State::Write { index };
+            Request::Write {
+                index,
+                data: Chunk::from_slice(value).unwrap(),
+            }
+        } else {
+            let value = Value::from_slice(value).map_err(|_| Error::TooLong)?;
+            let len = value.len() as u16;
+            self.state = State::Download {
+                index,
+                value,
+                sent: 0,
+                toggle: true,
+            };
+            Request::WriteStart { index, len }
+        };
+        Ok(request.encode(self.server))
+    }
+
+    /// Stop the transfer in progress, returns the frame telling the node
+    pub fn cancel(&mut self) -> Option<Frame> {
+        let index = match core::mem::replace(&mut self.state, State::Idle) {
+            State::Idle => return None,
+            State::Read { index }
+            | State::Upload { index, .. }
+            | State::Write { index }
+            | State::Download { index, .. } => index,
+        };
+        let code = Abort::Protocol;
+        Some(Request::Abort { index, code }.encode(self.server))
+    }
+
+    /// Continue the transfer with the response `frame`, `None` if the frame is not a response of
+    /// the node or there is no transfer in progress
+    pub fn handle(&mut self, frame: &Frame) -> Option<Step> {
+        if frame.id() != super::RESPONSE + self.server as u16 || !self.is_busy() {
+            return None;
+        }
+        let response = match Response::decode(self.server, frame) {
+            Ok(response) => response,
+            Err(_) => return Some(self.protocol_error()),
+        };
+        let state = core::mem::replace(&mut self.state, State::Idle);
+        let step = match (response, state) {
+            (Response::Abort { code, .. }, _) => Step::Done(Outcome::Aborted(code)),
+            (Response::ReadData { index, data }, State::Read { index: expected })
+                if index == expected =>
+            {
+                Step::Done(Outcome::Read(Value::from_slice(&data).unwrap()))
+            }
+            (Response::ReadStart { index, len }, State::Read { index: expected })
+                if index == expected && len as usize <= MAX_VALUE =>
+            {
+                self.state = State::Upload {
+                    index,
+                    value: Value::new(),
+                    len: len as usize,
+                    toggle: false,
+                };
+                self.request(Request::ReadSegment { toggle: false })
+            }
+            (
+                Response::ReadSegment { toggle, last, data },
+                State::Upload {
+                    index,
+                    mut value,
+                    len,
+                    toggle: expected,
+                },
+            ) if toggle == expected && value.len() + data.len() <= len => {
+                value.extend_from_slice(&data).unwrap();
+                match (last, value.len() == len) {
+                    (true, true) => Step::Done(Outcome::Read(value)),
+                    (false, false) => {
+                        self.state = State::Upload {
+                            index,
+                            value,
+                            len,
+                            toggle: !toggle,
+                        };
+                        self.request(Request::ReadSegment { toggle: !toggle })
+                    }
+                    _ => self.protocol_error(),
+                }
+            }
+            (Response::Written { index }, State::Write { index: expected })
+                if index == expected =>
+            {
+                Step::Done(Outcome::Written)
+            }
+            (
+                Response::Written { index },
+                State::Download {
+                    index: expected,
+                    value,
+                    sent: 0,
+                    toggle,
+                },
+            ) if index == expected => {
+                self.state = State::Download {
+                    index,
+                    value,
+                    sent: 0,
+                    toggle,
+                };
+                self.next_segment()
+            }
+            (
+                Response::WriteSegmentAck { toggle },
+                State::Download {
+                    index,
+                    value,
+                    sent,
+                    toggle: expected,
+                },
+            ) if sent > 0 && toggle == expected => {
+                if sent == value.len() {
+                    Step::Done(Outcome::Written)
+                } else {
+                    self.state = State::Download {
+                        index,
+                        value,
+                        sent,
+                        toggle,
+                    };
+                    self.next_segment()
+                }
+            }
+            (_, state) => {
+                self.state = state;
+                self.protocol_error()
+            }
+        };
+        Some(step)
+    }
+
+    /// Send the segment after the acknowledged ones
+    fn next_segment(&mut self) -> Step {
+        match &mut self.state {
+            State::Download {
+                value,
+                sent,
+                toggle,
+                ..
+            } => {
+                let end = (*sent + MAX_SEGMENT).min(value.len());
+                let data = Chunk::from_slice(&value[*sent..end]).unwrap();
+                let last = end == value.len();
+                *sent = end;
+                *toggle = !*toggle;
+                let toggle = *toggle;
+                self.request(Request::WriteSegment { toggle, last, data })
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn request(&self, request: Request) -> Step {
+        Step::Send(request.encode(self.server))
+    }
+
+    /// Give up the transfer on an unexpected response, the node drops it at the next request
+    fn protocol_error(&mut self) -> Step {
+        self.state = State::Idle;
+        Step::Done(Outcome::Aborted(Abort::Protocol))
+    }
+
+    fn idle(&self) -> Result<(), Error> {
+        if self.is_busy() {
+            Err(Error::Busy)
+        } else {
+            Ok(())
+        }
+    }
+}
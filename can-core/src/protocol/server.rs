@@ -0,0 +1,191 @@
+//! server
+//!
+//! This module answers the requests sent to a node, reading and writing its parameters through
+//! the `Parameters` trait
+//!
+
+use super::{
+    is_valid_node, Abort, Chunk, Request, Response, Value, MAX_EXPEDITED, MAX_SEGMENT, MAX_VALUE,
+};
+use crate::Frame;
+
+/// The parameters of a node
+pub trait Parameters {
+    /// The value of the parameter `index`
+    fn read(&mut self, index: u16) -> Result<Value, Abort>;
+
+    /// Set the parameter `index` to `value`
+    fn write(&mut self, index: u16, value: &[u8]) -> Result<(), Abort>;
+}
+
+#[derive(Debug)]
+enum State {
+    Idle,
+    /// Sending the value of `index`, `sent` bytes have been sent already
+    Upload {
+        index: u16,
+        value: Value,
+        sent: usize,
+        toggle: bool,
+    },
+    /// Receiving `len` bytes for `index`
+    Download {
+        index: u16,
+        value: Value,
+        len: usize,
+        toggle: bool,
+    },
+}
+
+/// The server of a node, there is a single transfer at a time: a new read or write request
+/// drops the one in progress
+#[derive(Debug)]
+pub struct Server {
+    node: u8,
+    state: State,
+}
+
+impl Server {
+    pub fn new(node: u8) -> Self {
+        assert!(is_valid_node(node));
+        Server {
+            node,
+            state: State::Idle,
+        }
+    }
+
+    pub fn node(&self) -> u8 {
+        self.node
+    }
+
+    /// The response to `frame`, `None` if the frame is not a request for this node or needs no
+    /// response
+    pub fn handle(&mut self, frame: &Frame, params: &mut impl Parameters) -> Option<Frame> {
+        if frame.id() != super::REQUEST + self.node as u16 {
+            return None;
+        }
+        let response = match Request::decode(self.node, frame) {
+            Ok(request) => self.request(request, params)?,
+            Err(_) => self.abort(Abort::Protocol),
+        };
+        Some(response.encode(self.node))
+    }
+
+    fn request(&mut self, request: Request, params: &mut impl Parameters) -> Option<Response> {
+        let state = core::mem::replace(&mut self.state, State::Idle);
+        let response = match (request, state) {
+            (Request::Read { index }, _) => match params.read(index) {
+                Ok(value) if value.len() <= MAX_EXPEDITED => Response::ReadData {
+                    index,
+                    data: Chunk::from_slice(&value).unwrap(),
+                },
+                Ok(value) => {
+                    let len = value.len() as u16;
+                    self.state = State::Upload {
+                        index,
+                        value,
+                        sent: 0,
+                        toggle: false,
+                    };
+                    Response::ReadStart { index, len }
+                }
+                Err(code) => Response::Abort { index, code },
+            },
+            (
+                Request::ReadSegment { toggle },
+                State::Upload {
+                    index,
+                    value,
+                    sent,
+                    toggle: expected,
+                },
+            ) if toggle == expected => {
+                let end = (sent + MAX_SEGMENT).min(value.len());
+                let data = Chunk::from_slice(&value[sent..end]).unwrap();
+                let last = end == value.len();
+                if !last {
+                    self.state = State::Upload {
+                        index,
+                        value,
+                        sent: end,
+                        toggle: !toggle,
+                    };
+                }
+                Response::ReadSegment { toggle, last, data }
+            }
+            (Request::Write { index, data }, _) => match params.write(index, &data) {
+                Ok(()) => Response::Written { index },
+                Err(code) => Response::Abort { index, code },
+            },
+            (Request::WriteStart { index, len }, _) => {
+                if len as usize > MAX_VALUE {
+                    Response::Abort {
+                        index,
+                        code: Abort::TooLong,
+                    }
+                } else {
+                    self.state = State::Download {
+                        index,
+                        value: Value::new(),
+                        len: len as usize,
+                        toggle: false,
+                    };
+                    Response::Written { index }
+                }
+            }
+            (
+                Request::WriteSegment { toggle, last, data },
+                State::Download {
+                    index,
+                    mut value,
+                    len,
+                    toggle: expected,
+                },
+            ) if toggle == expected => {
+                if value.len() + data.len() > len {
+                    return Some(Response::Abort {
+                        index,
+                        code: Abort::TooLong,
+                    });
+                }
+                // there is room, `len` is at most `MAX_VALUE`
+                value.extend_from_slice(&data).unwrap();
+                if !last {
+                    self.state = State::Download {
+                        index,
+                        value,
+                        len,
+                        toggle: !toggle,
+                    };
+                    Response::WriteSegmentAck { toggle }
+                } else if value.len() != len {
+                    Response::Abort {
+                        index,
+                        code: Abort::Protocol,
+                    }
+                } else {
+                    match params.write(index, &value) {
+                        Ok(()) => Response::WriteSegmentAck { toggle },
+                        Err(code) => Response::Abort { index, code },
+                    }
+                }
+            }
+            (Request::Abort { .. }, _) => return None,
+            // a segment out of a transfer or with the wrong toggle bit
+            (_, state) => {
+                self.state = state;
+                self.abort(Abort::Protocol)
+            }
+        };
+        Some(response)
+    }
+
+    /// Abort the transfer in progress
+    fn abort(&mut self, code: Abort) -> Response {
+        let index = match core::mem::replace(&mut self.state, State::Idle) {
+            State::Idle => 0,
+            State::Upload { index, .. } | State::Download { index, .. } => index,
+        };
+        Response::Abort { index, code }
+    }
+}
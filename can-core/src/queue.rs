@@ -0,0 +1,140 @@
+//! queue
+//!
+//! This module contains the transmit queue of a node: frames wait here until a mailbox of the
+//! peripheral is free and leave it in the order they would win the bus arbitration
+//!
+
+use crate::Frame;
+use core::cmp::Ordering;
+use heapless::binary_heap::{BinaryHeap, Min};
+
+/// A frame waiting in the queue, frames with the same identifier keep the order they were pushed
+/// (except across the wrap of `seq`, every 2^32 frames)
+#[derive(Debug)]
+struct Entry {
+    seq: u32,
+    frame: Frame,
+}
+
+impl Entry {
+    fn key(&self) -> (u16, u32) {
+        (self.frame.id(), self.seq)
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Up to `N` frames waiting to be transmitted, the lowest identifier (the highest priority on the
+/// bus) first
+#[derive(Default)]
+pub struct TxQueue<const N: usize> {
+    heap: BinaryHeap<Entry, Min, N>,
+    seq: u32,
+}
+
+impl<const N: usize> TxQueue<N> {
+    pub fn new() -> Self {
+        TxQueue {
+            heap: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    /// Add a frame, giving it back if the queue is full
+    pub fn push(&mut self, frame: Frame) -> Result<(), Frame> {
+        let entry = Entry {
+            seq: self.seq,
+            frame,
+        };
+        self.heap.push(entry).map_err(|e| e.frame)?;
+        self.seq = self.seq.wrapping_add(1);
+        Ok(())
+    }
+
+    /// The next frame to transmit
+    pub fn peek(&self) -> Option<&Frame> {
+        self.heap.peek().map(|e| &e.frame)
+    }
+
+    pub fn pop(&mut self) -> Option<Frame> {
+        self.heap.pop().map(|e| e.frame)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: u16, byte: u8) -> Frame {
+        Frame::new(id, &[byte]).unwrap()
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut queue = TxQueue::<8>::new();
+        assert!(queue.is_empty());
+        for (id, byte) in [(0x300, 0), (0x100, 1), (0x7FF, 2), (0x100, 3), (0x000, 4)] {
+            queue.push(frame(id, byte)).unwrap();
+        }
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.peek(), Some(&frame(0x000, 4)));
+
+        let order: Vec<_> = core::iter::from_fn(|| queue.pop()).collect();
+        let expected = [(0x000, 4), (0x100, 1), (0x100, 3), (0x300, 0), (0x7FF, 2)];
+        assert_eq!(order, expected.map(|(id, byte)| frame(id, byte)));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_full() {
+        let mut queue = TxQueue::<2>::new();
+        queue.push(frame(1, 0)).unwrap();
+        queue.push(frame(1, 1)).unwrap();
+        assert_eq!(queue.push(frame(0, 2)), Err(frame(0, 2)));
+        assert_eq!(queue.pop(), Some(frame(1, 0)));
+        queue.push(frame(1, 3)).unwrap();
+        assert_eq!(queue.pop(), Some(frame(1, 1)));
+        assert_eq!(queue.pop(), Some(frame(1, 3)));
+    }
+
+    #[test]
+    fn test_fifo_same_id() {
+        let mut queue = TxQueue::<4>::new();
+        for byte in 0..=255u8 {
+            queue.push(frame(0x10, byte)).unwrap();
+            assert_eq!(queue.pop(), Some(frame(0x10, byte)));
+        }
+        for byte in 0..4 {
+            queue.push(frame(0x10, byte)).unwrap();
+        }
+        for byte in 0..4 {
+            assert_eq!(queue.pop(), Some(frame(0x10, byte)));
+        }
+    }
+}
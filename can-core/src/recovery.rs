@@ -0,0 +1,59 @@
+//! recovery
+//!
+//! This module decides when a node in bus-off tries to join the bus again: the first attempt is
+//! quick, then the delay doubles so that a node with a broken transceiver doesn't flood the bus
+//! with error frames
+//!
+
+/// Delay before the first attempt, in milliseconds
+pub const FIRST_DELAY_MS: u32 = 100;
+
+/// Maximum delay between attempts, in milliseconds
+pub const MAX_DELAY_MS: u32 = 5_000;
+
+#[derive(Debug, Default)]
+pub struct Recovery {
+    attempts: u32,
+}
+
+impl Recovery {
+    /// The node went bus-off, returns how many milliseconds to wait before the next attempt
+    pub fn bus_off(&mut self) -> u32 {
+        // past 16 doublings the shift would overflow, the delay is capped way before anyway
+        let delay = match self.attempts {
+            0..=16 => (FIRST_DELAY_MS << self.attempts).min(MAX_DELAY_MS),
+            _ => MAX_DELAY_MS,
+        };
+        self.attempts = self.attempts.saturating_add(1);
+        delay
+    }
+
+    /// A frame has been transmitted, the bus works again
+    pub fn recovered(&mut self) {
+        self.attempts = 0;
+    }
+
+    /// Attempts since the last successful transmission
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut recovery = Recovery::default();
+        let delays: Vec<_> = (0..8).map(|_| recovery.bus_off()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1600, 3200, 5000, 5000]);
+        assert_eq!(recovery.attempts(), 8);
+        for _ in 0..100 {
+            assert_eq!(recovery.bus_off(), MAX_DELAY_MS);
+        }
+        recovery.recovered();
+        assert_eq!(recovery.attempts(), 0);
+        assert_eq!(recovery.bus_off(), FIRST_DELAY_MS);
+    }
+}
@@ -0,0 +1,218 @@
+//! stats
+//!
+//! This module contains the counters kept by a node and the interpretation of the error registers
+//! of the bxCAN peripheral
+//!
+
+/// The last error code (LEC) field of the error status register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LastError {
+    Stuff,
+    Form,
+    Acknowledgement,
+    BitRecessive,
+    BitDominant,
+    Crc,
+}
+
+impl LastError {
+    /// `None` for 0 (no error) and 7 (set by software)
+    pub fn from_code(lec: u8) -> Option<LastError> {
+        match lec {
+            1 => Some(LastError::Stuff),
+            2 => Some(LastError::Form),
+            3 => Some(LastError::Acknowledgement),
+            4 => Some(LastError::BitRecessive),
+            5 => Some(LastError::BitDominant),
+            6 => Some(LastError::Crc),
+            _ => None,
+        }
+    }
+}
+
+/// The fault confinement state of the node
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusState {
+    #[default]
+    Active,
+    /// A counter reached the warning limit of 96
+    Warning,
+    /// A counter is over 127, the node sends only passive error flags
+    Passive,
+    /// The transmit counter went over 255, the node is disconnected from the bus
+    Off,
+}
+
+impl BusState {
+    /// State from the transmit and receive error counters and the bus-off flag
+    pub fn new(tec: u8, rec: u8, bus_off: bool) -> BusState {
+        if bus_off {
+            BusState::Off
+        } else if tec > 127 || rec > 127 {
+            BusState::Passive
+        } else if tec >= 96 || rec >= 96 {
+            BusState::Warning
+        } else {
+            BusState::Active
+        }
+    }
+}
+
+/// Counters of a node since reset, they wrap
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Counters {
+    pub rx_frames: u32,
+    pub tx_frames: u32,
+    /// Frames lost because a receive FIFO was full
+    pub rx_overruns: u32,
+    /// Frames not transmitted because the transmit queue was full
+    pub tx_dropped: u32,
+    /// Bus errors, indexed by `LastError`
+    pub errors: [u32; 6],
+    pub bus_off: u32,
+    /// The state at the last error interrupt, not encoded by `to_le_bytes`
+    pub state: BusState,
+}
+
+impl Counters {
+    pub fn error(&mut self, error: LastError) {
+        let counter = &mut self.errors[error as usize];
+        *counter = counter.wrapping_add(1);
+    }
+
+    /// Count an error interrupt with the last error code `lec` of the error status register and
+    /// the bus `state`, return true if the node is bus-off
+    pub fn error_interrupt(&mut self, lec: u8, state: BusState) -> bool {
+        if let Some(error) = LastError::from_code(lec) {
+            self.error(error);
+        }
+        self.state = state;
+        let bus_off = state == BusState::Off;
+        if bus_off {
+            self.bus_off = self.bus_off.wrapping_add(1);
+        }
+        bus_off
+    }
+
+    pub fn total_errors(&self) -> u32 {
+        self.errors.iter().fold(0, |acc, e| acc.wrapping_add(*e))
+    }
+
+    /// The counters in the order of the fields, each one a little endian `u32`
+    pub fn to_le_bytes(&self) -> [u8; COUNTERS_LEN] {
+        let mut bytes = [0u8; COUNTERS_LEN];
+        let fields = [
+            self.rx_frames,
+            self.tx_frames,
+            self.rx_overruns,
+            self.tx_dropped,
+        ]
+        .into_iter()
+        .chain(self.errors)
+        .chain([self.bus_off]);
+        for (chunk, field) in bytes.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// The counters encoded by `to_le_bytes`, `None` if `bytes` is not `COUNTERS_LEN` long
+    pub fn from_le_bytes(bytes: &[u8]) -> Option<Counters> {
+        if bytes.len() != COUNTERS_LEN {
+            return None;
+        }
+        let field = |i: usize| {
+            u32::from_le_bytes([
+                bytes[i * 4],
+                bytes[i * 4 + 1],
+                bytes[i * 4 + 2],
+                bytes[i * 4 + 3],
+            ])
+        };
+        Some(Counters {
+            rx_frames: field(0),
+            tx_frames: field(1),
+            rx_overruns: field(2),
+            tx_dropped: field(3),
+            errors: [field(4), field(5), field(6), field(7), field(8), field(9)],
+            bus_off: field(10),
+            state: BusState::default(),
+        })
+    }
+}
+
+/// Length of the encoded `Counters`
+pub const COUNTERS_LEN: usize = 44;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_error() {
+        assert_eq!(LastError::from_code(0), None);
+        assert_eq!(LastError::from_code(3), Some(LastError::Acknowledgement));
+        assert_eq!(LastError::from_code(6), Some(LastError::Crc));
+        assert_eq!(LastError::from_code(7), None);
+    }
+
+    #[test]
+    fn test_bus_state() {
+        assert_eq!(BusState::new(0, 0, false), BusState::Active);
+        assert_eq!(BusState::new(95, 95, false), BusState::Active);
+        assert_eq!(BusState::new(96, 0, false), BusState::Warning);
+        assert_eq!(BusState::new(0, 127, false), BusState::Warning);
+        assert_eq!(BusState::new(0, 128, false), BusState::Passive);
+        assert_eq!(BusState::new(255, 0, false), BusState::Passive);
+        assert_eq!(BusState::new(0, 0, true), BusState::Off);
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut counters = Counters::default();
+        counters.error(LastError::Stuff);
+        counters.error(LastError::Crc);
+        counters.error(LastError::Crc);
+        assert_eq!(counters.errors, [1, 0, 0, 0, 0, 2]);
+        assert_eq!(counters.total_errors(), 3);
+        counters.errors[0] = u32::MAX;
+        counters.error(LastError::Stuff);
+        assert_eq!(counters.errors[0], 0);
+    }
+
+    #[test]
+    fn test_error_interrupt() {
+        let mut counters = Counters::default();
+        assert!(!counters.error_interrupt(3, BusState::Warning));
+        assert_eq!(counters.errors[LastError::Acknowledgement as usize], 1);
+        assert_eq!(counters.state, BusState::Warning);
+        // 7 is set by software after reading the code, no new error
+        assert!(counters.error_interrupt(7, BusState::Off));
+        assert!(counters.error_interrupt(0, BusState::Off));
+        assert_eq!(counters.total_errors(), 1);
+        assert_eq!(counters.bus_off, 2);
+        assert!(!counters.error_interrupt(0, BusState::Active));
+        assert_eq!(counters.state, BusState::Active);
+    }
+
+    #[test]
+    fn test_counters_bytes() {
+        let counters = Counters {
+            rx_frames: 1,
+            tx_frames: 0x01020304,
+            rx_overruns: 3,
+            tx_dropped: 4,
+            errors: [5, 6, 7, 8, 9, 10],
+            bus_off: u32::MAX,
+            state: BusState::Active,
+        };
+        let bytes = counters.to_le_bytes();
+        assert_eq!(&bytes[..8], &[1, 0, 0, 0, 4, 3, 2, 1]);
+        assert_eq!(&bytes[40..], &[0xFF; 4]);
+        assert_eq!(Counters::from_le_bytes(&bytes), Some(counters));
+        assert_eq!(Counters::from_le_bytes(&bytes[1..]), None);
+    }
+}
@@ -0,0 +1,285 @@
+//! telemetry
+//!
+//! This module encodes and decodes the frames broadcast by the aerotemp gauges, see README.md for
+//! the layout
+//!
+
+use crate::frame::{Frame, MAX_ID};
+use aero_core::alarm::{Kind, State};
+use aero_core::icing::Risk;
+use aero_core::Temp;
+
+/// Identifier of the `Message::Temps` frame, the extremes follow with the next identifiers
+pub const DEFAULT_BASE_ID: u16 = 0x100;
+
+/// Offset from the base identifier of the temperatures frame
+const TEMPS: u16 = 0;
+/// Offset from the base identifier of the OAT extremes frame, CAT is the next one
+const EXTREMES: u16 = 1;
+const CAT_EXTREMES: u16 = EXTREMES + 1;
+
+const TEMPS_LEN: usize = 8;
+const EXTREMES_LEN: usize = 6;
+
+/// What is published by a gauge, OAT first then CAT
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Readings {
+    pub temps: [Temp; 2],
+    pub extremes: [Option<(Temp, Temp)>; 2],
+    pub alarms: [State; 2],
+    pub icing: Risk,
+    /// Consecutive failed reads of the sensors, 0 when they are healthy
+    pub read_errors: u8,
+}
+
+/// The content of a telemetry frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    Temps {
+        temps: [Temp; 2],
+        alarms: [State; 2],
+        icing: Risk,
+        read_errors: u8,
+    },
+    Extremes {
+        /// 0 is OAT, 1 is CAT
+        channel: u8,
+        /// `None` if no temperature has been read yet
+        min_max: Option<(Temp, Temp)>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The identifier is not one of the telemetry frames
+    UnknownId,
+    /// The frame doesn't have the length of its identifier
+    WrongLength,
+    /// A field has a value not defined by the layout
+    InvalidValue,
+}
+
+/// Encodes the `Readings` into frames, incrementing the rolling counter at every call so that
+/// receivers detect lost or stale frames
+#[derive(Debug)]
+pub struct Telemetry {
+    base_id: u16,
+    counter: u8,
+}
+
+impl Telemetry {
+    /// `base_id` identifies the gauge on the bus, it must leave room for the 3 frames
+    pub const fn new(base_id: u16) -> Self {
+        assert!(base_id < MAX_ID - EXTREMES);
+        Telemetry {
+            base_id,
+            counter: 0,
+        }
+    }
+
+    /// The frames to transmit, in priority order
+    pub fn encode(&mut self, readings: &Readings) -> [Frame; 3] {
+        let counter = self.counter;
+        self.counter = self.counter.wrapping_add(1);
+
+        let mut temps = [0u8; TEMPS_LEN];
+        temps[0..2].copy_from_slice(&readings.temps[0].0.to_le_bytes());
+        temps[2..4].copy_from_slice(&readings.temps[1].0.to_le_bytes());
+        temps[4] = encode_alarm(readings.alarms[0]) | encode_alarm(readings.alarms[1]) << 4;
+        temps[5] = readings.icing as u8;
+        temps[6] = readings.read_errors;
+        temps[7] = counter;
+
+        let extremes = |channel: usize| {
+            let mut data = [0u8; EXTREMES_LEN];
+            if let Some((min, max)) = readings.extremes[channel] {
+                data[0..2].copy_from_slice(&min.0.to_le_bytes());
+                data[2..4].copy_from_slice(&max.0.to_le_bytes());
+                data[4] = 1;
+            }
+            data[5] = counter;
+            data
+        };
+
+        [
+            Frame::new(self.base_id + TEMPS, &temps).unwrap(),
+            Frame::new(self.base_id + EXTREMES, &extremes(0)).unwrap(),
+            Frame::new(self.base_id + CAT_EXTREMES, &extremes(1)).unwrap(),
+        ]
+    }
+}
+
+/// Decode a frame sent by the gauge with `base_id`, returning the message and the rolling counter
+pub fn decode(base_id: u16, frame: &Frame) -> Result<(Message, u8), Error> {
+    let data = frame.data();
+    let offset = frame.id().checked_sub(base_id).ok_or(Error::UnknownId)?;
+    let i16_at = |i: usize| Temp(i16::from_le_bytes([data[i], data[i + 1]]));
+    match offset {
+        TEMPS => {
+            if data.len() != TEMPS_LEN {
+                return Err(Error::WrongLength);
+            }
+            let message = Message::Temps {
+                temps: [i16_at(0), i16_at(2)],
+                alarms: [decode_alarm(data[4] & 0x0F)?, decode_alarm(data[4] >> 4)?],
+                icing: decode_risk(data[5])?,
+                read_errors: data[6],
+            };
+            Ok((message, data[7]))
+        }
+        EXTREMES | CAT_EXTREMES => {
+            if data.len() != EXTREMES_LEN {
+                return Err(Error::WrongLength);
+            }
+            let min_max = match data[4] {
+                0 => None,
+                1 => Some((i16_at(0), i16_at(2))),
+                _ => return Err(Error::InvalidValue),
+            };
+            let message = Message::Extremes {
+                channel: (offset - EXTREMES) as u8,
+                min_max,
+            };
+            Ok((message, data[5]))
+        }
+        _ => Err(Error::UnknownId),
+    }
+}
+
+/// Bits 0-1 are the state: 0 cleared, 1 active, 2 acknowledged. Bit 2 is the kind: 0 low, 1 high
+fn encode_alarm(state: State) -> u8 {
+    let kind = |kind: Kind| match kind {
+        Kind::Low => 0,
+        Kind::High => 1 << 2,
+    };
+    match state {
+        State::Cleared => 0,
+        State::Active(k) => 1 | kind(k),
+        State::Acknowledged(k) => 2 | kind(k),
+    }
+}
+
+fn decode_alarm(nibble: u8) -> Result<State, Error> {
+    let kind = if nibble & 1 << 2 == 0 {
+        Kind::Low
+    } else {
+        Kind::High
+    };
+    match nibble {
+        0 => Ok(State::Cleared),
+        1 | 5 => Ok(State::Active(kind)),
+        2 | 6 => Ok(State::Acknowledged(kind)),
+        _ => Err(Error::InvalidValue),
+    }
+}
+
+fn decode_risk(byte: u8) -> Result<Risk, Error> {
+    match byte {
+        0 => Ok(Risk::Low),
+        1 => Ok(Risk::Light),
+        2 => Ok(Risk::Moderate),
+        3 => Ok(Risk::Serious),
+        _ => Err(Error::InvalidValue),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings() -> Readings {
+        Readings {
+            temps: [Temp(-1234), Temp(567)],
+            extremes: [Some((Temp(-2000), Temp(100))), None],
+            alarms: [State::Active(Kind::Low), State::Acknowledged(Kind::High)],
+            icing: Risk::Moderate,
+            read_errors: 3,
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut telemetry = Telemetry::new(DEFAULT_BASE_ID);
+        let [temps, oat, cat] = telemetry.encode(&readings());
+
+        assert_eq!(temps.id(), 0x100);
+        assert_eq!(temps.data(), &[0x2E, 0xFB, 0x37, 0x02, 0x61, 2, 3, 0]);
+        assert_eq!(oat.id(), 0x101);
+        assert_eq!(oat.data(), &[0x30, 0xF8, 0x64, 0x00, 1, 0]);
+        assert_eq!(cat.id(), 0x102);
+        assert_eq!(cat.data(), &[0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut telemetry = Telemetry::new(0x7FD);
+        for counter in 0..=300u32 {
+            let mut readings = readings();
+            readings.temps[1] = Temp(counter as i16);
+            let [temps, oat, cat] = telemetry.encode(&readings);
+
+            let expected = Message::Temps {
+                temps: readings.temps,
+                alarms: readings.alarms,
+                icing: readings.icing,
+                read_errors: readings.read_errors,
+            };
+            assert_eq!(decode(0x7FD, &temps), Ok((expected, counter as u8)));
+            let expected = Message::Extremes {
+                channel: 0,
+                min_max: readings.extremes[0],
+            };
+            assert_eq!(decode(0x7FD, &oat), Ok((expected, counter as u8)));
+            let expected = Message::Extremes {
+                channel: 1,
+                min_max: None,
+            };
+            assert_eq!(decode(0x7FD, &cat), Ok((expected, counter as u8)));
+        }
+    }
+
+    #[test]
+    fn test_alarms() {
+        for kind in [Kind::Low, Kind::High] {
+            for state in [
+                State::Cleared,
+                State::Active(kind),
+                State::Acknowledged(kind),
+            ] {
+                assert_eq!(decode_alarm(encode_alarm(state)), Ok(state));
+            }
+        }
+        for nibble in [3, 4, 7, 8, 15] {
+            assert_eq!(decode_alarm(nibble), Err(Error::InvalidValue));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let frame = |id, data: &[u8]| Frame::new(id, data).unwrap();
+        let base = DEFAULT_BASE_ID;
+        assert_eq!(decode(base, &frame(0x0FF, &[0; 8])), Err(Error::UnknownId));
+        assert_eq!(decode(base, &frame(0x103, &[0; 8])), Err(Error::UnknownId));
+        assert_eq!(
+            decode(base, &frame(0x100, &[0; 7])),
+            Err(Error::WrongLength)
+        );
+        assert_eq!(
+            decode(base, &frame(0x101, &[0; 8])),
+            Err(Error::WrongLength)
+        );
+        let mut data = [0u8; 8];
+        data[5] = 4;
+        assert_eq!(decode(base, &frame(0x100, &data)), Err(Error::InvalidValue));
+        assert_eq!(
+            decode(base, &frame(0x102, &[0, 0, 0, 0, 2, 0])),
+            Err(Error::InvalidValue)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_base_id_too_high() {
+        Telemetry::new(0x7FE);
+    }
+}
@@ -0,0 +1,220 @@
+//! timing
+//!
+//! This module computes the bit timing register (BTR) of the bxCAN peripheral from the clock, the
+//! bitrate and the sample point. The functions are `const` so that the firmwares compute the
+//! register at build time, all the nodes of the bus at `BITRATE`:
+//!
+//! ```
+//! use can_core::timing::{bit_timing, BITRATE, SAMPLE_POINT};
+//!
+//! const BTR: u32 = match bit_timing(8_000_000, BITRATE, SAMPLE_POINT) {
+//!     Ok(timing) => timing.btr(),
+//!     Err(_) => panic!("no bit timing for the bus bitrate"),
+//! };
+//! assert_eq!(BTR, 0x001c_0001);
+//! ```
+//!
+
+/// Bitrate of the bus of the project, in bit/s: every node must use it to share the bus
+pub const BITRATE: u32 = 250_000;
+
+/// Sample point of the nodes of the bus, in per mille
+pub const SAMPLE_POINT: u32 = 875;
+
+/// Lowest supported bitrate, in bit/s
+pub const MIN_BITRATE: u32 = 10_000;
+
+/// Highest supported bitrate, in bit/s
+pub const MAX_BITRATE: u32 = 1_000_000;
+
+/// A bit is made of a sync segment of one time quantum, then `ts1` quanta and `ts2` quanta. The
+/// sample is taken between `ts1` and `ts2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitTiming {
+    /// Prescaler of PCLK1, 1 to 1024
+    pub brp: u16,
+    /// Time segment 1, 1 to 16 time quanta
+    pub ts1: u8,
+    /// Time segment 2, 1 to 8 time quanta
+    pub ts2: u8,
+    /// Resynchronization jump width, 1 to 4 time quanta
+    pub sjw: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The bitrate is outside `MIN_BITRATE..=MAX_BITRATE`
+    InvalidBitrate,
+    /// The sample point is outside 50.0% to 90.0%
+    InvalidSamplePoint,
+    /// PCLK1 can't be divided exactly to obtain the bitrate
+    NoExactSolution,
+}
+
+impl BitTiming {
+    /// The value of the BTR register, with normal mode (no loopback or silent)
+    pub const fn btr(&self) -> u32 {
+        (self.sjw as u32 - 1) << 24
+            | (self.ts2 as u32 - 1) << 20
+            | (self.ts1 as u32 - 1) << 16
+            | (self.brp as u32 - 1)
+    }
+
+    /// Time quanta in a bit
+    pub const fn quanta(&self) -> u32 {
+        1 + self.ts1 as u32 + self.ts2 as u32
+    }
+
+    /// The sample point in per mille of the bit, rounded down
+    pub const fn sample_point(&self) -> u32 {
+        (1 + self.ts1 as u32) * 1000 / self.quanta()
+    }
+}
+
+/// The timing for `bitrate` bit/s with a PCLK1 of `pclk1` Hz and the sample point nearest to
+/// `sample_point` per mille (eg. 875 is 87.5%).
+///
+/// The bitrate must be exact. Among the exact solutions the one with the sample point nearest to
+/// the requested one is chosen, and with the same distance the one with more time quanta, which
+/// resynchronize with finer steps. The jump width is one time quantum.
+pub const fn bit_timing(pclk1: u32, bitrate: u32, sample_point: u32) -> Result<BitTiming, Error> {
+    if bitrate < MIN_BITRATE || bitrate > MAX_BITRATE {
+        return Err(Error::InvalidBitrate);
+    }
+    if sample_point < 500 || sample_point > 900 {
+        return Err(Error::InvalidSamplePoint);
+    }
+    if !pclk1.is_multiple_of(bitrate) {
+        return Err(Error::NoExactSolution);
+    }
+    let quanta_per_bitrate = pclk1 / bitrate;
+
+    // (timing, distance from the requested sample point multiplied by the quanta)
+    let mut best: Option<(BitTiming, u32)> = None;
+    // ISO 11898 requires from 8 to 25 time quanta in a bit, 25 is also 1 + 16 + 8
+    let mut quanta = 25;
+    while quanta >= 8 {
+        let brp = quanta_per_bitrate / quanta;
+        if quanta_per_bitrate.is_multiple_of(quanta) && brp >= 1 && brp <= 1024 {
+            let mut ts2 = 1;
+            while ts2 <= 8 && ts2 + 1 < quanta {
+                let ts1 = quanta - 1 - ts2;
+                if ts1 >= 1 && ts1 <= 16 {
+                    let actual = (quanta - ts2) * 1000;
+                    let target = sample_point * quanta;
+                    let distance = actual.abs_diff(target);
+                    let timing = BitTiming {
+                        brp: brp as u16,
+                        ts1: ts1 as u8,
+                        ts2: ts2 as u8,
+                        sjw: 1,
+                    };
+                    best = match best {
+                        // compare distance / quanta without divisions
+                        Some((b, d)) if d * quanta <= distance * b.quanta() => Some((b, d)),
+                        _ => Some((timing, distance)),
+                    };
+                }
+                ts2 += 1;
+            }
+        }
+        quanta -= 1;
+    }
+    match best {
+        Some((timing, _)) => Ok(timing),
+        None => Err(Error::NoExactSolution),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn btr(pclk1: u32, bitrate: u32, sample_point: u32) -> u32 {
+        bit_timing(pclk1, bitrate, sample_point).unwrap().btr()
+    }
+
+    #[test]
+    fn test_reference() {
+        // reference values for the bxCAN with SJW 1, 16 time quanta when possible, the first was
+        // hard-coded in the `can` node
+        assert_eq!(btr(8_000_000, 125_000, 875), 0x001c_0003);
+        assert_eq!(btr(8_000_000, 250_000, 875), 0x001c_0001);
+        assert_eq!(btr(8_000_000, 500_000, 875), 0x001c_0000);
+        assert_eq!(btr(8_000_000, 1_000_000, 875), 0x0005_0000);
+        assert_eq!(btr(36_000_000, 1_000_000, 875), 0x001e_0001);
+        assert_eq!(btr(36_000_000, 250_000, 875), 0x001c_0008);
+        assert_eq!(btr(36_000_000, 125_000, 875), 0x001c_0011);
+        assert_eq!(btr(36_000_000, 10_000, 875), 0x001c_00e0);
+        assert_eq!(btr(8_000_000, 10_000, 875), 0x001c_0031);
+    }
+
+    #[test]
+    fn test_sample_point() {
+        let timing = bit_timing(36_000_000, 500_000, 875).unwrap();
+        // 8 quanta give exactly 87.5%, 18 quanta 88.8%
+        assert_eq!(timing.quanta(), 8);
+        assert_eq!(timing.sample_point(), 875);
+        assert_eq!(timing.btr(), 0x0005_0008);
+
+        let timing = bit_timing(8_000_000, 125_000, 750).unwrap();
+        assert_eq!(timing.quanta(), 16);
+        assert_eq!(timing.sample_point(), 750);
+
+        let timing = bit_timing(8_000_000, 125_000, 500).unwrap();
+        assert_eq!(timing.sample_point(), 500);
+    }
+
+    #[test]
+    fn test_common_bitrates() {
+        for pclk1 in [8_000_000, 16_000_000, 24_000_000, 36_000_000] {
+            for bitrate in [
+                10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 1_000_000,
+            ] {
+                for sample_point in [750, 800, 875] {
+                    let timing = bit_timing(pclk1, bitrate, sample_point).unwrap();
+                    assert_eq!(pclk1 / (timing.brp as u32 * timing.quanta()), bitrate);
+                    assert!(timing.sample_point().abs_diff(sample_point) <= 63);
+                    assert!((1..=16).contains(&timing.ts1));
+                    assert!((1..=8).contains(&timing.ts2));
+                    assert!((1..=1024).contains(&timing.brp));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            bit_timing(8_000_000, 9_999, 875),
+            Err(Error::InvalidBitrate)
+        );
+        assert_eq!(
+            bit_timing(8_000_000, 1_000_001, 875),
+            Err(Error::InvalidBitrate)
+        );
+        assert_eq!(
+            bit_timing(8_000_000, 125_000, 950),
+            Err(Error::InvalidSamplePoint)
+        );
+        assert_eq!(
+            bit_timing(8_000_000, 125_000, 499),
+            Err(Error::InvalidSamplePoint)
+        );
+        // 8MHz / 33.333 kbit/s is not an integer
+        assert_eq!(
+            bit_timing(8_000_000, 33_333, 875),
+            Err(Error::NoExactSolution)
+        );
+        // 6 time quanta are too few
+        assert_eq!(
+            bit_timing(6_000_000, 1_000_000, 875),
+            Err(Error::NoExactSolution)
+        );
+        // 4124 = 4 * 1031 clocks per bit, 4 quanta are too few and 1031 is over the prescaler
+        assert_eq!(
+            bit_timing(41_240_000, 10_000, 875),
+            Err(Error::NoExactSolution)
+        );
+    }
+}
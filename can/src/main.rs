@@ -1,87 +1,478 @@
-//! Simple CAN example.
-//! Requires a transceiver connected to PA11, PA12 (CAN1) or PB5 PB6 (CAN2).
+//! CAN node
+//!
+//! Interrupt driven CAN node, the base of the boards connected to the bus. Requires a transceiver
+//! connected to PA11, PA12 (CAN1).
+//!
+//! Received frames are read from the RX FIFO interrupts, frames to send wait in a `TxQueue` until
+//! a mailbox is free, bus errors are counted and a node gone bus-off joins the bus again after a
+//! backoff. The node answers the parameter requests of `can_core::protocol` and sends heartbeats,
+//! with the `client` feature it also queries the parameters of the `PEER` node.
+//!
 
 #![no_main]
 #![no_std]
 
-use panic_halt as _;
+use defmt_rtt as _;
+use panic_rtt_target as _;
 
-use bxcan::filter::Mask32;
-use cortex_m_rt::entry;
-use nb::block;
-use stm32f1xx_hal::{can::Can, pac, prelude::*};
+mod node;
 
-#[cfg(feature = "sender")]
-use cortex_m_semihosting::hprintln;
+#[rtic::app(device = stm32f1xx_hal::pac, peripherals = true, dispatchers = [TAMPER, PVD])]
+mod app {
+    use bxcan::filter::{BankConfig, ListEntry16, Mask16};
+    use bxcan::{Data, Id, Interrupts, StandardId};
+    use can_core::filter::{banks, Bank, Banks, Fifo, Filter, BANKS};
+    use can_core::protocol::client::{Client, Outcome, Step};
+    use can_core::protocol::server::Server;
+    use can_core::protocol::{Heartbeat, NodeState, HEARTBEAT, MAX_NODE, REQUEST};
+    #[cfg(feature = "client")]
+    use can_core::protocol::{index, RESPONSE};
+    use can_core::recovery::Recovery;
+    use can_core::stats::{BusState, Counters};
+    use can_core::timing::{bit_timing, BITRATE, SAMPLE_POINT};
+    use can_core::{Frame, TxQueue};
+    use stm32f1xx_hal::can::Can;
+    use stm32f1xx_hal::pac;
+    use stm32f1xx_hal::prelude::*;
+    use systick_monotonic::{fugit::ExtU64, Systick};
 
-#[entry]
-fn main() -> ! {
-    let dp = pac::Peripherals::take().unwrap();
+    use crate::node::{Params, Settings};
 
-    let mut flash = dp.FLASH.constrain();
-    let mut rcc = dp.RCC.constrain();
+    /// Frames waiting for a free mailbox
+    const QUEUE_LEN: usize = 16;
 
-    // To meet CAN clock accuracy requirements an external crystal or ceramic
-    // resonator must be used. The blue pill has a 8MHz external crystal.
-    // Other boards might have a crystal with another frequency or none at all.
-    rcc.cfgr.use_hse(8.mhz()).freeze(&mut flash.acr);
+    /// How often the counters are logged, in seconds
+    const STATS_PERIOD: u64 = 10;
 
-    let mut afio = dp.AFIO.constrain(&mut rcc.apb2);
+    /// APB1 (PCLK1): 8MHz, the bitrate and the sample point of the bus
+    const BTR: u32 = match bit_timing(8_000_000, BITRATE, SAMPLE_POINT) {
+        Ok(timing) => timing.btr(),
+        Err(_) => panic!("no bit timing for the bus bitrate"),
+    };
+
+    /// Node id of this node
+    #[cfg(not(feature = "client"))]
+    const NODE: u8 = 1;
+    #[cfg(feature = "client")]
+    const NODE: u8 = 2;
+
+    /// The node queried with the `client` feature
+    const PEER: u8 = 1;
+
+    /// How often the client reads a parameter of `PEER`, in seconds, a transfer not done by then
+    /// is cancelled
+    #[cfg(feature = "client")]
+    const QUERY_PERIOD: u64 = 2;
+
+    /// The frames received by the node, the others are dropped by the peripheral. The protocol in
+    /// FIFO 0, the broadcasts in FIFO 1
+    const FILTERS: &[Filter] = &[
+        Filter::id(REQUEST + NODE as u16, Fifo::Fifo0),
+        #[cfg(feature = "client")]
+        Filter::id(RESPONSE + PEER as u16, Fifo::Fifo0),
+        Filter::range(HEARTBEAT + 1, HEARTBEAT + MAX_NODE as u16, Fifo::Fifo1),
+        // the telemetry of an aerotemp gauge
+        Filter::range(0x100, 0x102, Fifo::Fifo1),
+    ];
 
-    let mut can1 = {
-        let can = Can::new(dp.CAN1, &mut rcc.apb1, dp.USB);
+    /// The filter banks of `FILTERS`, computed at build time
+    const FILTER_BANKS: Banks<BANKS> = match banks(FILTERS) {
+        Ok(banks) => banks,
+        Err(_) => panic!("the filters don't fit in the filter banks"),
+    };
+
+    type Can1 = bxcan::Can<Can<pac::CAN1>>;
+
+    #[shared]
+    struct Shared {
+        can: Can1,
+        queue: TxQueue<QUEUE_LEN>,
+        counters: Counters,
+        recovery: Recovery,
+        settings: Settings,
+        client: Client,
+    }
 
-        let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
+    #[local]
+    struct Local {
+        server: Server,
+    }
+
+    #[monotonic(binds = SysTick, default = true)]
+    type MonoTimer = Systick<1000>;
+
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        defmt::debug!("init");
+
+        let mut flash = cx.device.FLASH.constrain();
+        let rcc = cx.device.RCC.constrain();
+        let mut afio = cx.device.AFIO.constrain();
+
+        // To meet CAN clock accuracy requirements an external crystal or ceramic
+        // resonator must be used. The blue pill has a 8MHz external crystal.
+        // Other boards might have a crystal with another frequency or none at all.
+        rcc.cfgr.use_hse(8.MHz()).freeze(&mut flash.acr);
+
+        let mono = Systick::new(cx.core.SYST, 8_000_000);
+
+        let can = Can::new(cx.device.CAN1, cx.device.USB);
+        let mut gpioa = cx.device.GPIOA.split();
         let rx = gpioa.pa11.into_floating_input(&mut gpioa.crh);
         let tx = gpioa.pa12.into_alternate_push_pull(&mut gpioa.crh);
         can.assign_pins((tx, rx), &mut afio.mapr);
 
-        bxcan::Can::new(can)
-    };
+        let mut can = bxcan::Can::builder(can)
+            .set_bit_timing(BTR)
+            .leave_disabled();
 
-    // APB1 (PCLK1): 8MHz, Bit rate: 125kBit/s, Sample Point 87.5%
-    // Value was calculated with http://www.bittiming.can-wiki.info/
-    can1.modify_config().set_bit_timing(0x001c_0003);
+        // the filters are applied when `filters` is dropped
+        {
+            let mut filters = can.modify_filters();
+            filters.clear();
+            for (i, (fifo, bank)) in FILTER_BANKS.iter().enumerate() {
+                filters.enable_bank(i as u8, to_bxcan_fifo(*fifo), to_bxcan_bank(bank));
+            }
+        }
+
+        // no interrupt on the last error code, it would fire at every failed transmission, eg. for
+        // the missing ACK of a node alone on the bus, the errors are counted at the state changes
+        // and by `stats`
+        can.enable_interrupts(
+            Interrupts::TRANSMIT_MAILBOX_EMPTY
+                | Interrupts::FIFO0_MESSAGE_PENDING
+                | Interrupts::FIFO1_MESSAGE_PENDING
+                | Interrupts::ERROR_WARNING
+                | Interrupts::ERROR_PASSIVE
+                | Interrupts::BUS_OFF
+                | Interrupts::ERROR,
+        );
+        nb::block!(can.enable_non_blocking()).unwrap();
+
+        let heartbeat = Heartbeat {
+            node: NODE,
+            state: NodeState::Starting,
+            uptime: 0,
+        };
+        send::spawn(heartbeat.encode()).unwrap();
+        heartbeat::spawn().unwrap();
+        stats::spawn_after(STATS_PERIOD.secs()).unwrap();
+        #[cfg(feature = "client")]
+        query::spawn_after(QUERY_PERIOD.secs()).unwrap();
+
+        (
+            Shared {
+                can,
+                queue: TxQueue::new(),
+                counters: Counters::default(),
+                recovery: Recovery::default(),
+                settings: Settings::new(),
+                client: Client::new(PEER),
+            },
+            Local {
+                server: Server::new(NODE),
+            },
+            init::Monotonics(mono),
+        )
+    }
+
+    #[idle]
+    fn idle(_cx: idle::Context) -> ! {
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
 
-    // Configure filters so that can frames can be received.
-    let mut filters = can1.modify_filters();
-    filters.enable_bank(0, Mask32::accept_all());
+    /// Queue a frame for transmission
+    #[task(capacity = 4, shared = [can, queue, counters])]
+    fn send(cx: send::Context, frame: Frame) {
+        (cx.shared.can, cx.shared.queue, cx.shared.counters).lock(|can, queue, counters| {
+            enqueue(frame, queue, counters);
+            pump(can, queue, counters);
+        });
+    }
 
-    // Drop filters to leave filter configuraiton mode.
-    drop(filters);
+    /// A mailbox has been emptied, the bus works so a following bus-off starts a new backoff
+    #[task(binds = USB_HP_CAN_TX, shared = [can, queue, counters, recovery])]
+    fn can_tx(cx: can_tx::Context) {
+        let shared = (cx.shared.can, cx.shared.queue, cx.shared.counters);
+        let mut recovery = cx.shared.recovery;
+        shared.lock(|can, queue, counters| {
+            can.clear_tx_interrupt();
+            pump(can, queue, counters);
+        });
+        recovery.lock(|recovery| recovery.recovered());
+    }
 
-    // Select the interface.
-    let mut can = can1;
-    //let mut can = _can2;
+    /// The requests to this node and the responses of `PEER`
+    #[task(binds = USB_LP_CAN_RX0, local = [server], shared = [can, queue, counters, settings, client])]
+    fn can_rx0(cx: can_rx0::Context) {
+        let server = cx.local.server;
+        let uptime = uptime();
+        let shared = (cx.shared.can, cx.shared.queue, cx.shared.counters);
+        let (mut settings, mut client) = (cx.shared.settings, cx.shared.client);
+        shared.lock(|can, queue, counters| {
+            receive(can, queue, counters, Fifo::Fifo0, |frame, queue, counters| {
+                let response = settings.lock(|settings| {
+                    let mut params = Params {
+                        settings,
+                        counters,
+                        uptime,
+                    };
+                    server.handle(&frame, &mut params)
+                });
+                let response = response.or_else(|| {
+                    client.lock(|client| match client.handle(&frame)? {
+                        Step::Send(request) => Some(request),
+                        Step::Done(outcome) => {
+                            log_outcome(&outcome);
+                            None
+                        }
+                    })
+                });
+                if let Some(response) = response {
+                    enqueue(response, queue, counters);
+                }
+            });
+        });
+    }
 
-    // Split the peripheral into transmitter and receiver parts.
-    block!(can.enable()).unwrap();
+    /// The broadcasts of the other nodes, they are only logged
+    #[task(binds = CAN_RX1, shared = [can, queue, counters])]
+    fn can_rx1(cx: can_rx1::Context) {
+        (cx.shared.can, cx.shared.queue, cx.shared.counters).lock(|can, queue, counters| {
+            receive(can, queue, counters, Fifo::Fifo1, |frame, _, _| {
+                match Heartbeat::decode(&frame) {
+                    Ok(heartbeat) => defmt::debug!("{}", heartbeat),
+                    Err(_) => {
+                        defmt::info!("received id:{=u16} data:{=[u8]}", frame.id(), frame.data())
+                    }
+                }
+            });
+        });
+    }
 
-    #[cfg(feature = "sender")]
-    {
-        for _i in 0..5 {
-            //delaying
-            hprintln!("prepare to send").unwrap();
+    /// Broadcast the heartbeat every `Settings::heartbeat_period`, checking every second when they
+    /// are disabled
+    #[task(shared = [settings, counters])]
+    fn heartbeat(mut cx: heartbeat::Context) {
+        let period = cx.shared.settings.lock(|s| s.heartbeat_period);
+        if period == 0 {
+            heartbeat::spawn_after(1.secs()).unwrap();
+            return;
+        }
+        heartbeat::spawn_after((period as u64).millis()).unwrap();
+        let heartbeat = Heartbeat {
+            node: NODE,
+            state: NodeState::Operational,
+            uptime: uptime(),
+        };
+        if send::spawn(heartbeat.encode()).is_err() {
+            defmt::debug!("send busy, heartbeat skipped");
+            cx.shared.counters.lock(dropped);
         }
-        let frame = bxcan::Frame::new_data(bxcan::StandardId::new(0).unwrap(), [0u8]);
-        block!(can.transmit(&frame)).unwrap();
-        hprintln!("1 sent").unwrap();
     }
 
-    // Echo back received packages in sequence.
-    // See the `can-rtfm` example for an echo implementation that adheres to
-    // correct frame ordering based on the transfer id.
-    loop {
-        if let Ok(frame) = block!(can.receive()) {
+    /// Read in turn the parameters of `PEER`
+    #[cfg(feature = "client")]
+    #[task(local = [queried: usize = 0], shared = [client, counters])]
+    fn query(mut cx: query::Context) {
+        const QUERIES: [u16; 3] = [index::NAME, index::UPTIME, index::COUNTERS];
+        query::spawn_after(QUERY_PERIOD.secs()).unwrap();
 
-            #[cfg(feature = "sender")]
-            hprintln!("received {:?}", frame).unwrap();
+        let i = *cx.local.queried % QUERIES.len();
+        *cx.local.queried += 1;
+        let frames = cx.shared.client.lock(|client| {
+            let abort = client.cancel();
+            if abort.is_some() {
+                defmt::warn!("no response from node {=u8}", PEER);
+            }
+            (abort, client.read(QUERIES[i]).unwrap())
+        });
+        // a request not sent is cancelled at the next query, as if `PEER` didn't respond
+        for frame in frames.0.into_iter().chain(Some(frames.1)) {
+            if send::spawn(frame).is_err() {
+                defmt::debug!("send busy, query frame dropped");
+                cx.shared.counters.lock(dropped);
+            }
+        }
+    }
 
-            let data = frame.data().unwrap();
-            let value = data.as_ref()[0];
-            let frame = bxcan::Frame::new_data(bxcan::StandardId::new(0).unwrap(), [value+1]);
+    /// Error and status change interrupt
+    #[task(binds = CAN_SCE, shared = [can, counters, recovery])]
+    fn can_sce(cx: can_sce::Context) {
+        // the error registers are read and cleared only here, holding the peripheral
+        let (lec, state) = cx.shared.can.lock(|can| {
+            let status = error_status(can);
+            clear_errors(can);
+            status
+        });
+        let shared = (cx.shared.counters, cx.shared.recovery);
+        shared.lock(|counters, recovery| {
+            if counters.error_interrupt(lec, state) {
+                let delay = recovery.bus_off();
+                defmt::warn!("bus-off, recovering in {=u32}ms", delay);
+                if recover::spawn_after((delay as u64).millis()).is_err() {
+                    defmt::debug!("recovery already scheduled");
+                }
+            }
+        });
+    }
 
-            block!(can.transmit(&frame)).unwrap();
+    /// Leave bus-off going through the initialization mode, the peripheral joins the bus after
+    /// 128 sequences of 11 recessive bits
+    #[task(shared = [can, queue, counters])]
+    fn recover(cx: recover::Context) {
+        (cx.shared.can, cx.shared.queue, cx.shared.counters).lock(|can, queue, counters| {
+            can.modify_config().leave_disabled();
+            // the request to leave the initialization mode is made at the first call, WouldBlock
+            // only tells the bus is not synchronized yet
+            let _ = can.enable_non_blocking();
+            pump(can, queue, counters);
+        });
+    }
+
+    /// Log the counters, the errors are counted by `can_sce`
+    #[task(shared = [counters, queue])]
+    fn stats(cx: stats::Context) {
+        stats::spawn_after(STATS_PERIOD.secs()).unwrap();
+        (cx.shared.counters, cx.shared.queue).lock(|c, queue| {
+            defmt::info!(
+                "rx:{=u32} tx:{=u32} overruns:{=u32} dropped:{=u32} errors:{=u32} bus-off:{=u32} queued:{=usize} {}",
+                c.rx_frames,
+                c.tx_frames,
+                c.rx_overruns,
+                c.tx_dropped,
+                c.total_errors(),
+                c.bus_off,
+                queue.len(),
+                c.state,
+            );
+        });
+    }
+
+    /// Read the frames of `fifo`, passing them to `on_frame`, then transmit what has been queued
+    fn receive(
+        can: &mut Can1,
+        queue: &mut TxQueue<QUEUE_LEN>,
+        counters: &mut Counters,
+        fifo: Fifo,
+        mut on_frame: impl FnMut(Frame, &mut TxQueue<QUEUE_LEN>, &mut Counters),
+    ) {
+        loop {
+            let received = match fifo {
+                Fifo::Fifo0 => can.rx0().receive(),
+                Fifo::Fifo1 => can.rx1().receive(),
+            };
+            match received {
+                Ok(frame) => {
+                    counters.rx_frames = counters.rx_frames.wrapping_add(1);
+                    if let Some(frame) = from_bxcan(&frame) {
+                        on_frame(frame, queue, counters);
+                    }
+                }
+                Err(nb::Error::Other(_overrun)) => {
+                    counters.rx_overruns = counters.rx_overruns.wrapping_add(1);
+                }
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+        pump(can, queue, counters);
+    }
+
+    fn enqueue(frame: Frame, queue: &mut TxQueue<QUEUE_LEN>, counters: &mut Counters) {
+        if queue.push(frame).is_err() {
+            dropped(counters);
+        }
+    }
+
+    /// Count a frame not sent because the queue of `send` or the `TxQueue` is full
+    fn dropped(counters: &mut Counters) {
+        counters.tx_dropped = counters.tx_dropped.wrapping_add(1);
+    }
+
+    fn log_outcome(outcome: &Outcome) {
+        match outcome {
+            Outcome::Read(value) => defmt::info!("node {=u8} value:{=[u8]}", PEER, value),
+            Outcome::Written => defmt::info!("node {=u8} written", PEER),
+            Outcome::Aborted(abort) => defmt::warn!("node {=u8} aborted: {}", PEER, abort),
+        }
+    }
+
+    /// Seconds since the start
+    fn uptime() -> u32 {
+        (monotonics::now().ticks() / 1_000) as u32
+    }
+
+    /// Move the queued frames to the free mailboxes. When all the mailboxes are pending, a frame
+    /// with higher priority replaces the lowest priority pending one, which goes back in the queue
+    fn pump(can: &mut Can1, queue: &mut TxQueue<QUEUE_LEN>, counters: &mut Counters) {
+        while let Some(frame) = queue.peek() {
+            match can.transmit(&to_bxcan(frame)) {
+                Ok(status) => {
+                    queue.pop();
+                    counters.tx_frames = counters.tx_frames.wrapping_add(1);
+                    if let Some(replaced) = status.dequeued_frame().and_then(from_bxcan) {
+                        counters.tx_frames = counters.tx_frames.wrapping_sub(1);
+                        // there is room, the frame just transmitted was in the queue
+                        queue.push(replaced).unwrap();
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn to_bxcan(frame: &Frame) -> bxcan::Frame {
+        let id = StandardId::new(frame.id()).unwrap();
+        bxcan::Frame::new_data(id, Data::new(frame.data()).unwrap())
+    }
+
+    fn to_bxcan_fifo(fifo: Fifo) -> bxcan::Fifo {
+        match fifo {
+            Fifo::Fifo0 => bxcan::Fifo::Fifo0,
+            Fifo::Fifo1 => bxcan::Fifo::Fifo1,
+        }
+    }
+
+    fn to_bxcan_bank(bank: &Bank) -> BankConfig {
+        let id = |id: u16| StandardId::new(id).unwrap();
+        match *bank {
+            Bank::List16(ids) => {
+                BankConfig::List16(ids.map(|i| ListEntry16::data_frames_with_id(id(i))))
+            }
+            Bank::Mask16(masks) => {
+                BankConfig::Mask16(masks.map(|(i, m)| Mask16::frames_with_std_id(id(i), id(m))))
+            }
+        }
+    }
+
+    /// `None` for remote frames and extended identifiers, not used by our boards
+    fn from_bxcan(frame: &bxcan::Frame) -> Option<Frame> {
+        match (frame.id(), frame.data()) {
+            (Id::Standard(id), Some(data)) => Frame::new(id.as_raw(), data),
+            _ => None,
         }
     }
+
+    /// The last error code and the bus state from the error status register, bxcan doesn't
+    /// expose it. Taking `Can1` makes sure that bxcan isn't using the peripheral meanwhile
+    fn error_status(_can: &mut Can1) -> (u8, BusState) {
+        // SAFETY: read only, ESR isn't written by bxcan
+        let can = unsafe { &*pac::CAN1::ptr() };
+        let esr = can.esr.read();
+        let state = BusState::new(esr.tec().bits(), esr.rec().bits(), esr.boff().bit_is_set());
+        (esr.lec().bits(), state)
+    }
+
+    /// Clear the error interrupt flag and set the last error code to 7, so that the next
+    /// interrupt sees only new errors. Taking `Can1` makes sure that bxcan isn't using the
+    /// peripheral meanwhile
+    fn clear_errors(_can: &mut Can1) {
+        // SAFETY: bxcan doesn't use LEC, and MSR bits are cleared by writing 1, so the other flags
+        // are left as they are
+        let can = unsafe { &*pac::CAN1::ptr() };
+        can.esr.modify(|_, w| unsafe { w.lec().bits(7) });
+        can.msr.write(|w| w.erri().set_bit());
+    }
 }
@@ -0,0 +1,69 @@
+//! node
+//!
+//! The parameters of the node, read and written from the bus through `can_core::protocol`
+//!
+
+use can_core::protocol::server::Parameters;
+use can_core::protocol::{index, Abort, Value};
+use can_core::stats::Counters;
+
+/// Longest name of the node
+const MAX_NAME: usize = 32;
+
+/// The writable parameters
+pub struct Settings {
+    pub name: Value,
+    /// Milliseconds between heartbeats, 0 disables them
+    pub heartbeat_period: u16,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Settings {
+            name: Value::from_slice(b"can node").unwrap(),
+            heartbeat_period: 1_000,
+        }
+    }
+}
+
+/// The parameters at the time of a request
+pub struct Params<'a> {
+    pub settings: &'a mut Settings,
+    pub counters: &'a Counters,
+    /// Seconds since the node started
+    pub uptime: u32,
+}
+
+impl Parameters for Params<'_> {
+    fn read(&mut self, index: u16) -> Result<Value, Abort> {
+        let value = match index {
+            index::NAME => return Ok(self.settings.name.clone()),
+            index::UPTIME => Value::from_slice(&self.uptime.to_le_bytes()),
+            index::COUNTERS => Value::from_slice(&self.counters.to_le_bytes()),
+            index::HEARTBEAT_PERIOD => {
+                Value::from_slice(&self.settings.heartbeat_period.to_le_bytes())
+            }
+            _ => return Err(Abort::UnknownParameter),
+        };
+        // all the values fit
+        Ok(value.unwrap())
+    }
+
+    fn write(&mut self, index: u16, value: &[u8]) -> Result<(), Abort> {
+        match index {
+            index::NAME if value.len() > MAX_NAME => Err(Abort::TooLong),
+            index::NAME if core::str::from_utf8(value).is_err() => Err(Abort::InvalidValue),
+            index::NAME => {
+                self.settings.name = Value::from_slice(value).unwrap();
+                Ok(())
+            }
+            index::HEARTBEAT_PERIOD => {
+                let bytes = value.try_into().map_err(|_| Abort::InvalidValue)?;
+                self.settings.heartbeat_period = u16::from_le_bytes(bytes);
+                Ok(())
+            }
+            index::UPTIME | index::COUNTERS => Err(Abort::ReadOnly),
+            _ => Err(Abort::UnknownParameter),
+        }
+    }
+}
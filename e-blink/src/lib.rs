@@ -0,0 +1,13 @@
+//! e-blink
+//!
+//! Blink patterns for status leds, see README.md
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+mod morse;
+mod pattern;
+mod sequencer;
+
+pub use pattern::Pattern;
+pub use sequencer::{Full, Priority, Repeat, Sequencer};
@@ -0,0 +1,67 @@
+//! morse
+//!
+//! This module contains the International Morse code of letters and digits
+//!
+
+/// The dots and dashes of `c`, case insensitive, `None` if it has no code
+pub fn code(c: char) -> Option<&'static str> {
+    let code = match c.to_ascii_uppercase() {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code() {
+        assert_eq!(code('s'), Some("..."));
+        assert_eq!(code('O'), Some("---"));
+        assert_eq!(code('7'), Some("--..."));
+        assert_eq!(code('?'), None);
+        // every code is unique
+        let chars = ('A'..='Z').chain('0'..='9');
+        let mut codes: Vec<_> = chars.map(|c| code(c).unwrap()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), 36);
+    }
+}
@@ -0,0 +1,163 @@
+//! pattern
+//!
+//! This module describes the blink patterns as a sequence of segments, the led on or off for some
+//! milliseconds. The level of the led is a pure function of the time since the pattern started
+//!
+
+use crate::morse;
+
+/// Milliseconds of the Morse dot, a dash is 3 dots, the gap between letters 3 and between words 7
+pub const MORSE_UNIT: u32 = 120;
+
+/// Milliseconds of every blink of `Pattern::Blinks`, the led is on as long as off
+pub const BLINK: u32 = 300;
+
+/// Milliseconds of the pause after the blinks of `Pattern::Blinks`, so that the codes are counted
+pub const PAUSE: u32 = 1_500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// Two short pulses every second, the board is alive
+    Heartbeat,
+    /// Fast even blinking, something is wrong
+    Error,
+    /// `n` blinks and a pause, like the error codes of a boiler
+    Blinks(u8),
+    /// A message in Morse code, characters without a code are skipped, space separates words
+    Morse(&'static str),
+}
+
+impl Pattern {
+    /// Call `f` with every segment of the pattern, whether the led is on and for how many
+    /// milliseconds. Stops when `f` returns false. Adjacent segments never have the same level
+    fn segments(&self, mut f: impl FnMut(bool, u32) -> bool) {
+        match *self {
+            Pattern::Heartbeat => {
+                for (on, ms) in [(true, 100), (false, 100), (true, 100), (false, 700)] {
+                    if !f(on, ms) {
+                        return;
+                    }
+                }
+            }
+            Pattern::Error => {
+                let _ = f(true, 100) && f(false, 100);
+            }
+            Pattern::Blinks(n) => {
+                for i in 0..n {
+                    let off = if i + 1 == n { BLINK + PAUSE } else { BLINK };
+                    if !(f(true, BLINK) && f(false, off)) {
+                        return;
+                    }
+                }
+                if n == 0 {
+                    f(false, PAUSE);
+                }
+            }
+            Pattern::Morse(text) => {
+                // the gap before the next dot or dash
+                let mut gap = 0;
+                let mut started = false;
+                for c in text.chars() {
+                    if c == ' ' {
+                        if started {
+                            gap = 7 * MORSE_UNIT;
+                        }
+                        continue;
+                    }
+                    let code = match morse::code(c) {
+                        Some(code) => code,
+                        None => continue,
+                    };
+                    if started {
+                        gap = gap.max(3 * MORSE_UNIT);
+                    }
+                    for symbol in code.chars() {
+                        if gap > 0 && !f(false, gap) {
+                            return;
+                        }
+                        let on = if symbol == '-' { 3 } else { 1 };
+                        if !f(true, on * MORSE_UNIT) {
+                            return;
+                        }
+                        gap = MORSE_UNIT;
+                    }
+                    started = true;
+                }
+                // a repeated message is separated as a word
+                f(false, 7 * MORSE_UNIT);
+            }
+        }
+    }
+
+    /// Milliseconds of a cycle of the pattern
+    pub fn duration(&self) -> u32 {
+        let mut total = 0u32;
+        self.segments(|_, ms| {
+            total = total.saturating_add(ms);
+            true
+        });
+        total
+    }
+
+    /// Whether the led is on `t` milliseconds after the start of a cycle, and the milliseconds
+    /// after the start when it changes. Off after the end of the cycle
+    pub fn level(&self, t: u32) -> (bool, u32) {
+        let mut start = 0u32;
+        let mut level = (false, u32::MAX);
+        self.segments(|on, ms| {
+            let end = start.saturating_add(ms);
+            if t < end {
+                level = (on, end);
+                return false;
+            }
+            start = end;
+            true
+        });
+        level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pattern sampled every `step` milliseconds as a string of `#` and `_`
+    fn draw(pattern: Pattern, step: u32) -> String {
+        (0..pattern.duration() / step)
+            .map(|i| match pattern.level(i * step).0 {
+                true => '#',
+                false => '_',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(Pattern::Heartbeat.duration(), 1_000);
+        assert_eq!(Pattern::Error.duration(), 200);
+        assert_eq!(Pattern::Blinks(3).duration(), 6 * BLINK + PAUSE);
+        assert_eq!(Pattern::Blinks(0).duration(), PAUSE);
+        // S is 3 dots and 2 gaps, O 3 dashes and 2 gaps, 2 letter gaps and the final word gap
+        let units = (3 + 2) + (9 + 2) + (3 + 2) + 2 * 3 + 7;
+        assert_eq!(Pattern::Morse("SOS").duration(), units * MORSE_UNIT);
+        assert_eq!(Pattern::Morse("").duration(), 7 * MORSE_UNIT);
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(draw(Pattern::Heartbeat, 100), "#_#_______");
+        assert_eq!(draw(Pattern::Error, 100), "#_");
+        assert_eq!(draw(Pattern::Blinks(2), BLINK), "#_#______");
+        assert_eq!(
+            draw(Pattern::Morse("sos"), MORSE_UNIT),
+            "#_#_#___###_###_###___#_#_#_______"
+        );
+        // words, skipping what has no code
+        assert_eq!(
+            draw(Pattern::Morse(" e? t "), MORSE_UNIT),
+            "#_______###_______"
+        );
+        assert_eq!(Pattern::Heartbeat.level(150), (false, 200));
+        assert_eq!(Pattern::Heartbeat.level(5_000), (false, u32::MAX));
+    }
+}
@@ -0,0 +1,329 @@
+//! sequencer
+//!
+//! This module plays the patterns requested on a single led: the pattern with the highest
+//! priority plays, the others wait in a queue. A pattern preempted by a higher priority one
+//! restarts from the beginning when it's its turn again.
+//!
+//! The sequencer doesn't read a clock, the time is passed to `update` in milliseconds, so it runs
+//! on the host and on any timer: a periodic tick or a one shot timer set to `next_change`
+//!
+
+use crate::Pattern;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Like the heartbeat, shown when nothing else is
+    Low,
+    Normal,
+    /// Like a fault, shown before everything else
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    /// Play the pattern this many cycles, then remove it
+    Times(u16),
+    /// Play the pattern until it's cancelled
+    Forever,
+}
+
+/// The queue is full, the pattern hasn't been added
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Full;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    pattern: Pattern,
+    priority: Priority,
+    repeat: Repeat,
+    /// Milliseconds when it started playing, `None` while waiting
+    started: Option<u32>,
+}
+
+/// The patterns of a led, at most `N` queued including the one playing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequencer<const N: usize> {
+    /// Sorted by decreasing priority, in order of arrival for the same priority
+    queue: [Option<Entry>; N],
+}
+
+impl<const N: usize> Default for Sequencer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Sequencer<N> {
+    pub const fn new() -> Self {
+        Sequencer { queue: [None; N] }
+    }
+
+    /// Queue `pattern`, it plays immediately if it has a higher priority than the one playing,
+    /// which restarts later
+    pub fn play(
+        &mut self,
+        pattern: Pattern,
+        priority: Priority,
+        repeat: Repeat,
+    ) -> Result<(), Full> {
+        let len = self.len();
+        if len == N {
+            return Err(Full);
+        }
+        let position = self.queue[..len]
+            .iter()
+            .position(|e| e.is_some_and(|e| e.priority < priority))
+            .unwrap_or(len);
+        if position == 0 {
+            if let Some(current) = self.queue[0].as_mut() {
+                current.started = None;
+            }
+        }
+        self.queue[position..=len].rotate_right(1);
+        self.queue[position] = Some(Entry {
+            pattern,
+            priority,
+            repeat,
+            started: None,
+        });
+        Ok(())
+    }
+
+    /// Remove every queued `pattern`, like a heartbeat or an error fixed
+    pub fn cancel(&mut self, pattern: Pattern) {
+        let playing = self.queue[0].map(|e| e.pattern);
+        let mut kept = 0;
+        for i in 0..N {
+            if let Some(entry) = self.queue[i].take() {
+                if entry.pattern != pattern {
+                    self.queue[kept] = Some(entry);
+                    kept += 1;
+                }
+            }
+        }
+        // a pattern resumed after the one playing is cancelled starts from the beginning
+        if playing == Some(pattern) {
+            if let Some(entry) = self.queue[0].as_mut() {
+                entry.started = None;
+            }
+        }
+    }
+
+    /// Remove every pattern
+    pub fn clear(&mut self) {
+        self.queue = [None; N];
+    }
+
+    /// Advance to `now` milliseconds and return whether the led is on. A pattern ending before
+    /// `now` is followed by the next one from its end, so the timing doesn't depend on how often
+    /// it's updated. The milliseconds wrap around
+    pub fn update(&mut self, now: u32) -> bool {
+        self.advance(now).0
+    }
+
+    /// Milliseconds when the led may change after `now`, `None` if nothing is playing. Calls
+    /// `update(now)` first
+    pub fn next_change(&mut self, now: u32) -> Option<u32> {
+        let (_, next) = self.advance(now);
+        next
+    }
+
+    fn advance(&mut self, now: u32) -> (bool, Option<u32>) {
+        loop {
+            let entry = match self.queue[0].as_mut() {
+                Some(entry) => entry,
+                None => return (false, None),
+            };
+            let started = *entry.started.get_or_insert(now);
+            let elapsed = now.wrapping_sub(started);
+            let cycle = entry.pattern.duration().max(1);
+            if let Repeat::Times(times) = entry.repeat {
+                let total = cycle.saturating_mul(times as u32);
+                if elapsed >= total {
+                    self.remove_first();
+                    if let Some(next) = self.queue[0].as_mut() {
+                        next.started = Some(started.wrapping_add(total));
+                    }
+                    continue;
+                }
+            }
+            let offset = elapsed % cycle;
+            let (on, change) = entry.pattern.level(offset);
+            let next = now.wrapping_add(change.min(cycle) - offset);
+            return (on, Some(next));
+        }
+    }
+
+    fn remove_first(&mut self) {
+        self.queue[0] = None;
+        self.queue.rotate_left(1);
+    }
+
+    /// The pattern playing
+    pub fn playing(&self) -> Option<Pattern> {
+        self.queue[0].map(|e| e.pattern)
+    }
+
+    /// Patterns queued including the one playing
+    pub fn len(&self) -> usize {
+        self.queue.iter().take_while(|e| e.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue[0].is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::{BLINK, PAUSE};
+    use e_rng::Rng;
+
+    /// The led sampled every `step` milliseconds from `from` as a string of `#` and `_`
+    fn draw<const N: usize>(sequencer: &mut Sequencer<N>, from: u32, to: u32, step: u32) -> String {
+        (from..to)
+            .step_by(step as usize)
+            .map(|now| match sequencer.update(now) {
+                true => '#',
+                false => '_',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut sequencer: Sequencer<4> = Sequencer::new();
+        assert!(sequencer.is_empty());
+        assert!(!sequencer.update(0));
+        assert_eq!(sequencer.next_change(0), None);
+    }
+
+    #[test]
+    fn test_queue_and_priorities() {
+        let mut sequencer: Sequencer<4> = Sequencer::new();
+        sequencer
+            .play(Pattern::Heartbeat, Priority::Low, Repeat::Forever)
+            .unwrap();
+        assert_eq!(draw(&mut sequencer, 0, 2_000, 100), "#_#_______#_#_______");
+
+        // an error preempts the heartbeat for 3 cycles, then the heartbeat restarts
+        sequencer
+            .play(Pattern::Error, Priority::High, Repeat::Times(3))
+            .unwrap();
+        assert_eq!(sequencer.playing(), Some(Pattern::Error));
+        assert_eq!(draw(&mut sequencer, 2_050, 3_650, 100), "#_#_#_#_#_______");
+
+        // normal patterns wait in order
+        sequencer
+            .play(Pattern::Blinks(1), Priority::Normal, Repeat::Times(1))
+            .unwrap();
+        sequencer
+            .play(Pattern::Blinks(2), Priority::Normal, Repeat::Times(1))
+            .unwrap();
+        assert_eq!(sequencer.len(), 3);
+        let from = 10_000;
+        let blinks = 2 * BLINK + PAUSE + 4 * BLINK + PAUSE;
+        let drawn = draw(&mut sequencer, from, from + blinks + 1_000, BLINK);
+        assert_eq!(&drawn[..2], "#_");
+        assert_eq!(&drawn[7..11], "#_#_");
+        assert_eq!(sequencer.playing(), Some(Pattern::Heartbeat));
+        assert_eq!(sequencer.len(), 1);
+    }
+
+    #[test]
+    fn test_timing_independent_of_updates() {
+        // updated rarely, the patterns still follow each other from their end
+        let mut sequencer: Sequencer<4> = Sequencer::new();
+        sequencer
+            .play(Pattern::Error, Priority::Normal, Repeat::Times(2))
+            .unwrap();
+        sequencer
+            .play(Pattern::Heartbeat, Priority::Low, Repeat::Forever)
+            .unwrap();
+        assert!(sequencer.update(1_000));
+        // the heartbeat started at 1_400, its second pulse is at 1_600
+        assert!(!sequencer.update(2_550));
+        assert!(sequencer.update(2_600));
+        assert_eq!(sequencer.next_change(2_650), Some(2_700));
+    }
+
+    #[test]
+    fn test_cancel_and_full() {
+        let mut sequencer: Sequencer<2> = Sequencer::new();
+        sequencer
+            .play(Pattern::Heartbeat, Priority::Low, Repeat::Forever)
+            .unwrap();
+        sequencer
+            .play(Pattern::Error, Priority::High, Repeat::Forever)
+            .unwrap();
+        assert_eq!(
+            sequencer.play(Pattern::Blinks(1), Priority::High, Repeat::Forever),
+            Err(Full)
+        );
+        assert!(sequencer.update(0));
+        assert!(!sequencer.update(150));
+        sequencer.cancel(Pattern::Error);
+        assert_eq!(sequencer.playing(), Some(Pattern::Heartbeat));
+        // the heartbeat restarts when resumed
+        assert!(sequencer.update(160));
+        assert!(!sequencer.update(260));
+        sequencer.clear();
+        assert!(sequencer.is_empty());
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let mut sequencer: Sequencer<2> = Sequencer::new();
+        sequencer
+            .play(Pattern::Blinks(1), Priority::Normal, Repeat::Times(1))
+            .unwrap();
+        let start = u32::MAX - 100;
+        assert!(sequencer.update(start));
+        assert!(sequencer.update(start.wrapping_add(BLINK - 1)));
+        assert!(!sequencer.update(start.wrapping_add(BLINK)));
+        assert_eq!(
+            sequencer.next_change(start.wrapping_add(BLINK)),
+            Some(start.wrapping_add(2 * BLINK + PAUSE))
+        );
+        assert!(!sequencer.update(start.wrapping_add(2 * BLINK + PAUSE)));
+        assert!(sequencer.is_empty());
+    }
+
+    /// Any sequence of requests, the led is on only while a pattern is playing and the pattern
+    /// playing has the highest priority
+    #[test]
+    fn test_property_highest_priority() {
+        let mut rng = Rng::new(11);
+        let patterns = [
+            Pattern::Heartbeat,
+            Pattern::Error,
+            Pattern::Blinks(2),
+            Pattern::Morse("SOS"),
+        ];
+        let priorities = [Priority::Low, Priority::Normal, Priority::High];
+        let mut sequencer: Sequencer<8> = Sequencer::new();
+        let mut now = 0u32;
+        for _ in 0..5_000 {
+            match rng.below(10) {
+                0 => {
+                    let pattern = patterns[rng.below(4) as usize];
+                    let priority = priorities[rng.below(3) as usize];
+                    let repeat = match rng.below(3) {
+                        0 => Repeat::Forever,
+                        n => Repeat::Times(n as u16),
+                    };
+                    let _ = sequencer.play(pattern, priority, repeat);
+                }
+                1 => sequencer.cancel(patterns[rng.below(4) as usize]),
+                _ => (),
+            }
+            now += rng.below(500);
+            let on = sequencer.update(now);
+            assert!(!on || !sequencer.is_empty());
+            let entries: Vec<_> = sequencer.queue.iter().flatten().collect();
+            assert!(entries.windows(2).all(|w| w[0].priority >= w[1].priority));
+            assert_eq!(entries.len(), sequencer.len());
+        }
+    }
+}
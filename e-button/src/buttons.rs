@@ -0,0 +1,556 @@
+//! buttons
+//!
+//! This module turns the edges of the pins of some buttons into gestures: click, double click,
+//! long press, repeat while held and chords of buttons pressed together.
+//!
+//! The state machine doesn't read pins or clocks, it's fed with the level of a pin when it changes
+//! and with the time in ticks, which wrap around, so it runs on the host and with any timer. The
+//! gestures depending only on time, like the long press or the end of the wait for a double click,
+//! are reported by `poll`. A button with a `Debouncer` is fed with the samples of its pin instead
+//!
+
+use crate::Debouncer;
+
+/// Times of the gestures in ticks, `Default` is in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Edges closer than this to the last accepted one are bounces
+    pub debounce: u32,
+    /// A button held this long is a long press
+    pub long: u32,
+    /// Period of the repeats while a button is held after the long press, 0 for no repeats
+    pub repeat: u32,
+    /// A press within this from the release of a click makes a double click. With 0 there are no
+    /// double clicks and the click is reported on the release, without waiting
+    pub double: u32,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Timings {
+            debounce: 20,
+            long: 800,
+            repeat: 200,
+            double: 300,
+        }
+    }
+}
+
+impl Timings {
+    /// These timings in milliseconds converted to ticks of `ticks_per_ms`, like the cycles of a
+    /// `MonoTimer`
+    pub const fn scaled(self, ticks_per_ms: u32) -> Self {
+        Timings {
+            debounce: self.debounce.saturating_mul(ticks_per_ms),
+            long: self.long.saturating_mul(ticks_per_ms),
+            repeat: self.repeat.saturating_mul(ticks_per_ms),
+            double: self.double.saturating_mul(ticks_per_ms),
+        }
+    }
+}
+
+/// A gesture, with the index of the button or a bit for every button of a chord
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Click(usize),
+    DoubleClick(usize),
+    /// Held for `Timings::long`, reported while the button is still down
+    Long(usize),
+    /// Still held after the long press, every `Timings::repeat`
+    Repeat(usize),
+    /// Buttons pressed together before any of them made a gesture, reported at the first release
+    Chord(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// Pressed at `since`, `pending` if after a click waiting for the double click
+    Down {
+        since: u32,
+        pending: bool,
+    },
+    /// Held past the long press, the next repeat is at `next`
+    Held {
+        next: u32,
+    },
+    /// Released after a click at `since`, waiting for the double click
+    Released {
+        since: u32,
+    },
+    /// Pressed together with other buttons
+    Chord,
+    /// The gesture has already been reported, ignored until released
+    Consumed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Button {
+    /// Debounced level
+    down: bool,
+    /// Level of the last edge
+    raw: bool,
+    /// When `down` changed
+    changed: u32,
+    /// Whether `debounce` elapsed since `changed`
+    settled: bool,
+    /// Debounces the samples of a sampled button, its edges are ignored
+    debouncer: Option<Debouncer>,
+    state: State,
+}
+
+/// The gestures of `N` buttons, at most 32 so that a chord fits a bit each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buttons<const N: usize> {
+    timings: Timings,
+    buttons: [Button; N],
+    /// The buttons of the chord not reported yet
+    chord: u32,
+}
+
+impl<const N: usize> Buttons<N> {
+    /// All the buttons released
+    pub const fn new(timings: Timings) -> Self {
+        assert!(N <= 32, "a chord has a bit for every button");
+        Buttons {
+            timings,
+            buttons: [Button {
+                down: false,
+                raw: false,
+                changed: 0,
+                settled: true,
+                debouncer: None,
+                state: State::Idle,
+            }; N],
+            chord: 0,
+        }
+    }
+
+    /// Sample `button` with `debouncer` instead of taking its edges, for noisy switches or pins
+    /// without an interrupt
+    pub fn set_debouncer(&mut self, button: usize, debouncer: Debouncer) {
+        self.buttons[button].debouncer = Some(debouncer);
+    }
+
+    /// The pin of `button` changed to `down` at `now`, call `f` with the gestures completed. An
+    /// edge within `Timings::debounce` from the last accepted one is a bounce, the level it leaves
+    /// is accepted by `poll` at the end of the debounce. Ignored if `button` is sampled
+    pub fn edge(&mut self, button: usize, down: bool, now: u32, mut f: impl FnMut(Event)) {
+        let b = &mut self.buttons[button];
+        if b.debouncer.is_some() {
+            return;
+        }
+        b.raw = down;
+        if down == b.down {
+            return;
+        }
+        if !b.settled && now.wrapping_sub(b.changed) < self.timings.debounce {
+            return;
+        }
+        self.accept(button, down, now, &mut f);
+    }
+
+    /// The pin of `button` read `down` at `now` from a periodic timer, call `f` with the gestures
+    /// completed. Without a debouncer the sample is taken as an edge
+    pub fn sample(&mut self, button: usize, down: bool, now: u32, mut f: impl FnMut(Event)) {
+        let b = &mut self.buttons[button];
+        let debouncer = match b.debouncer.as_mut() {
+            Some(debouncer) => debouncer,
+            None => return self.edge(button, down, now, f),
+        };
+        if let Some(down) = debouncer.sample(down) {
+            b.raw = down;
+            self.accept(button, down, now, &mut f);
+            // already debounced
+            self.buttons[button].settled = true;
+        }
+    }
+
+    /// Call `f` with the gestures completed at `now`, like the long presses. Must be called at
+    /// least at the times returned by `next_poll`, calling it more often is harmless
+    pub fn poll(&mut self, now: u32, mut f: impl FnMut(Event)) {
+        let timings = self.timings;
+        for i in 0..N {
+            let b = &mut self.buttons[i];
+            if !b.settled && now.wrapping_sub(b.changed) >= timings.debounce {
+                b.settled = true;
+                let raw = b.raw;
+                if raw != b.down {
+                    self.accept(i, raw, now, &mut f);
+                }
+            }
+            let b = &mut self.buttons[i];
+            match b.state {
+                State::Down { since, pending } if now.wrapping_sub(since) >= timings.long => {
+                    if pending {
+                        f(Event::Click(i));
+                    }
+                    b.state = State::Held {
+                        next: since
+                            .wrapping_add(timings.long)
+                            .wrapping_add(timings.repeat),
+                    };
+                    f(Event::Long(i));
+                }
+                State::Held { next } if timings.repeat > 0 && reached(now, next) => {
+                    b.state = State::Held {
+                        next: next.wrapping_add(timings.repeat),
+                    };
+                    f(Event::Repeat(i));
+                }
+                State::Released { since } if now.wrapping_sub(since) >= timings.double => {
+                    b.state = State::Idle;
+                    f(Event::Click(i));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Ticks when `poll` has something to do, `None` if it's not needed until the next edge. For a
+    /// one shot timer, a periodic one can ignore it
+    pub fn next_poll(&self, now: u32) -> Option<u32> {
+        let timings = self.timings;
+        self.buttons
+            .iter()
+            .flat_map(|b| {
+                let settle = (!b.settled && b.raw != b.down)
+                    .then(|| b.changed.wrapping_add(timings.debounce));
+                let gesture = match b.state {
+                    State::Down { since, .. } => Some(since.wrapping_add(timings.long)),
+                    State::Held { next } if timings.repeat > 0 => Some(next),
+                    State::Released { since } => Some(since.wrapping_add(timings.double)),
+                    _ => None,
+                };
+                settle.into_iter().chain(gesture)
+            })
+            .map(|at| match reached(now, at) {
+                true => 0,
+                false => at.wrapping_sub(now),
+            })
+            .min()
+            .map(|delay| now.wrapping_add(delay))
+    }
+
+    /// Whether `button` is down, after the debounce
+    pub fn is_down(&self, button: usize) -> bool {
+        self.buttons[button].down
+    }
+
+    fn accept(&mut self, i: usize, down: bool, now: u32, f: &mut impl FnMut(Event)) {
+        let b = &mut self.buttons[i];
+        b.down = down;
+        b.changed = now;
+        b.settled = false;
+        if down {
+            self.press(i, now, f);
+        } else {
+            self.release(i, now, f);
+        }
+    }
+
+    fn press(&mut self, i: usize, now: u32, f: &mut impl FnMut(Event)) {
+        // the press of another button ends the wait for a double click
+        for (j, b) in self.buttons.iter_mut().enumerate() {
+            if j != i && matches!(b.state, State::Released { .. }) {
+                b.state = State::Idle;
+                f(Event::Click(j));
+            }
+        }
+        let pending = matches!(self.buttons[i].state, State::Released { .. });
+        let together = self
+            .buttons
+            .iter()
+            .enumerate()
+            .filter(|(j, b)| *j != i && matches!(b.state, State::Down { .. } | State::Chord))
+            .fold(0, |mask, (j, _)| mask | 1 << j);
+        if together == 0 {
+            self.buttons[i].state = State::Down {
+                since: now,
+                pending,
+            };
+            return;
+        }
+        if pending {
+            f(Event::Click(i));
+        }
+        for (j, b) in self.buttons.iter_mut().enumerate() {
+            if let State::Down { pending: true, .. } = b.state {
+                f(Event::Click(j));
+            }
+            if together & 1 << j != 0 {
+                b.state = State::Chord;
+            }
+        }
+        self.buttons[i].state = State::Chord;
+        self.chord |= together | 1 << i;
+    }
+
+    fn release(&mut self, i: usize, now: u32, f: &mut impl FnMut(Event)) {
+        let state = core::mem::replace(&mut self.buttons[i].state, State::Idle);
+        match state {
+            State::Down { pending: true, .. } => f(Event::DoubleClick(i)),
+            State::Down { pending: false, .. } => {
+                if self.timings.double == 0 {
+                    f(Event::Click(i));
+                } else {
+                    self.buttons[i].state = State::Released { since: now };
+                }
+            }
+            State::Chord => {
+                for b in self.buttons.iter_mut() {
+                    if b.state == State::Chord {
+                        b.state = State::Consumed;
+                    }
+                }
+                f(Event::Chord(core::mem::take(&mut self.chord)));
+            }
+            State::Idle | State::Held { .. } | State::Released { .. } | State::Consumed => (),
+        }
+    }
+}
+
+/// Whether `now` is at or after `at`, on wrapping ticks less than half the range apart
+fn reached(now: u32, at: u32) -> bool {
+    (now.wrapping_sub(at) as i32) >= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use e_rng::Rng;
+
+    /// Feed `edges` of (button, down, ticks) polling every tick up to `until`, return the events
+    /// with the tick they happened
+    fn run<const N: usize>(
+        buttons: &mut Buttons<N>,
+        edges: &[(usize, bool, u32)],
+        until: u32,
+    ) -> Vec<(u32, Event)> {
+        let mut events = vec![];
+        let mut edges = edges.iter().peekable();
+        for now in 0..=until {
+            while let Some((button, down, _)) = edges.next_if(|e| e.2 == now) {
+                buttons.edge(*button, *down, now, |e| events.push((now, e)));
+            }
+            buttons.poll(now, |e| events.push((now, e)));
+        }
+        events
+    }
+
+    fn buttons<const N: usize>() -> Buttons<N> {
+        Buttons::new(Timings::default())
+    }
+
+    #[test]
+    fn test_click() {
+        // bounces on press and release
+        let edges = [
+            (0, true, 10),
+            (0, false, 12),
+            (0, true, 15),
+            (0, false, 100),
+            (0, true, 101),
+            (0, false, 105),
+        ];
+        let events = run(&mut buttons::<1>(), &edges, 1_000);
+        assert_eq!(events, [(400, Event::Click(0))]);
+
+        // without double clicks the click is reported on the release
+        let timings = Timings {
+            double: 0,
+            ..Default::default()
+        };
+        let events = run(&mut Buttons::<1>::new(timings), &edges, 1_000);
+        assert_eq!(events, [(100, Event::Click(0))]);
+    }
+
+    #[test]
+    fn test_short_tap() {
+        // released within the debounce, the release is accepted at its end
+        let mut buttons = buttons::<1>();
+        let events = run(&mut buttons, &[(0, true, 10), (0, false, 15)], 1_000);
+        assert_eq!(events, [(330, Event::Click(0))]);
+        assert!(!buttons.is_down(0));
+    }
+
+    #[test]
+    fn test_double_click() {
+        let edges = [
+            (0, true, 0),
+            (0, false, 100),
+            (0, true, 300),
+            (0, false, 400),
+            // too late for a double
+            (0, true, 1_000),
+            (0, false, 1_100),
+            (0, true, 1_401),
+            (0, false, 1_500),
+        ];
+        let events = run(&mut buttons::<1>(), &edges, 2_000);
+        assert_eq!(
+            events,
+            [
+                (400, Event::DoubleClick(0)),
+                (1_400, Event::Click(0)),
+                (1_800, Event::Click(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_and_repeat() {
+        let mut buttons = buttons::<1>();
+        let events = run(&mut buttons, &[(0, true, 0), (0, false, 1_300)], 2_000);
+        assert_eq!(
+            events,
+            [
+                (800, Event::Long(0)),
+                (1_000, Event::Repeat(0)),
+                (1_200, Event::Repeat(0)),
+            ]
+        );
+
+        // a click then a long press, the click isn't a double
+        let edges = [(0, true, 0), (0, false, 100), (0, true, 200)];
+        let events = run(&mut buttons, &edges, 1_000);
+        assert_eq!(events, [(1_000, Event::Click(0)), (1_000, Event::Long(0))]);
+    }
+
+    #[test]
+    fn test_chord() {
+        let edges = [
+            (0, true, 0),
+            (1, true, 50),
+            (0, false, 200),
+            (1, false, 250),
+        ];
+        let events = run(&mut buttons::<3>(), &edges, 2_000);
+        assert_eq!(events, [(200, Event::Chord(0b11))]);
+
+        // a button already held long doesn't make a chord
+        let edges = [
+            (0, true, 0),
+            (2, true, 900),
+            (2, false, 950),
+            (0, false, 990),
+        ];
+        let events = run(&mut buttons::<3>(), &edges, 2_000);
+        assert_eq!(events, [(800, Event::Long(0)), (1_250, Event::Click(2))]);
+
+        // a click waiting for the double is reported when another button is pressed
+        let edges = [
+            (0, true, 0),
+            (0, false, 100),
+            (1, true, 150),
+            (1, false, 250),
+        ];
+        let events = run(&mut buttons::<3>(), &edges, 2_000);
+        assert_eq!(events, [(150, Event::Click(0)), (550, Event::Click(1))]);
+    }
+
+    #[test]
+    fn test_sampled() {
+        let mut buttons = buttons::<2>();
+        buttons.set_debouncer(1, Debouncer::integrator(5));
+        let mut events = vec![];
+        // a noisy press of the sampled button, sampled every tick, is down from the 5th sample
+        // down at 10 and up at 36, after 5 more samples up than down
+        let samples = "__#_##_#####################_#__#______________";
+        for (now, sample) in samples.chars().enumerate() {
+            let now = now as u32;
+            buttons.sample(1, sample == '#', now, |e| events.push((now, e)));
+            // its edges are ignored
+            buttons.edge(1, true, now, |e| events.push((now, e)));
+            buttons.poll(now, |e| events.push((now, e)));
+            assert_eq!(buttons.is_down(1), (10..36).contains(&now));
+        }
+        buttons.poll(1_000, |e| events.push((1_000, e)));
+        assert_eq!(events, [(1_000, Event::Click(1))]);
+        assert_eq!(buttons.next_poll(1_000), None);
+
+        // a button without debouncer takes the samples as edges
+        for now in 2_000..2_100 {
+            buttons.sample(0, now < 2_050, now, |e| events.push((now, e)));
+        }
+        buttons.poll(3_000, |e| events.push((3_000, e)));
+        assert_eq!(events[1..], [(3_000, Event::Click(0))]);
+    }
+
+    #[test]
+    fn test_next_poll() {
+        let mut buttons = buttons::<2>();
+        assert_eq!(buttons.next_poll(0), None);
+        buttons.edge(0, true, 100, |_| panic!());
+        assert_eq!(buttons.next_poll(100), Some(900));
+        // a bounce is settled at the end of the debounce
+        buttons.edge(0, false, 105, |_| panic!());
+        assert_eq!(buttons.next_poll(105), Some(120));
+        buttons.edge(0, true, 110, |_| panic!());
+        assert_eq!(buttons.next_poll(110), Some(900));
+        assert_eq!(buttons.next_poll(1_000), Some(1_000));
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let mut buttons = buttons::<1>();
+        let start = u32::MAX - 50;
+        let mut events = vec![];
+        buttons.edge(0, true, start, |e| events.push(e));
+        buttons.poll(start.wrapping_add(799), |e| events.push(e));
+        assert!(events.is_empty());
+        buttons.poll(start.wrapping_add(800), |e| events.push(e));
+        assert_eq!(events, [Event::Long(0)]);
+    }
+
+    #[test]
+    fn test_scaled() {
+        let timings = Timings::default().scaled(8_000);
+        assert_eq!(timings.debounce, 160_000);
+        assert_eq!(timings.long, 6_400_000);
+        assert_eq!(Timings::default().scaled(u32::MAX).long, u32::MAX);
+    }
+
+    /// Presses short or long separated by more than the double click wait, with bounces on every
+    /// edge, make exactly one click or long press each
+    #[test]
+    fn test_property_bounces() {
+        let mut rng = Rng::new(5);
+        let timings = Timings {
+            repeat: 0,
+            ..Default::default()
+        };
+        let mut edges = vec![];
+        let mut expected = vec![];
+        let mut now = 100;
+        for _ in 0..200 {
+            let long = rng.below(2) == 0;
+            let held = match long {
+                true => timings.long + rng.below(500),
+                false => timings.debounce + rng.below(timings.long - timings.debounce),
+            };
+            for (down, at) in [(true, now), (false, now + held)] {
+                edges.push((0, down, at));
+                // bounces within the debounce, ending on the level
+                let mut bounce = at;
+                for _ in 0..rng.below(4) {
+                    bounce += 1 + rng.below(2);
+                    edges.push((0, !down, bounce));
+                    bounce += 1 + rng.below(2);
+                    edges.push((0, down, bounce));
+                }
+            }
+            expected.push(match long {
+                true => Event::Long(0),
+                false => Event::Click(0),
+            });
+            now += held + timings.double + 50 + rng.below(1_000);
+        }
+        let mut buttons = Buttons::<1>::new(timings);
+        let events: Vec<_> = run(&mut buttons, &edges, now)
+            .into_iter()
+            .map(|(_, e)| e)
+            .collect();
+        assert_eq!(events, expected);
+    }
+}
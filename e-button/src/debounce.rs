@@ -0,0 +1,198 @@
+//! debounce
+//!
+//! This module debounces a pin sampled by a periodic timer instead of interrupting on its edges:
+//! a noisy switch bouncing for some milliseconds costs no more than a clean one, and its level
+//! changes once
+//!
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A counter going up on the samples down and down on the samples up, the level changes when
+    /// it reaches `max` or 0. Isolated glitches only delay the change
+    Integrator { count: u8, max: u8 },
+    /// The last samples a bit each, the level changes when the ones in `mask` agree. Any glitch
+    /// restarts the count
+    Shift { history: u32, mask: u32 },
+}
+
+/// The debounced level of a pin from its samples, released at the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Debouncer {
+    kind: Kind,
+    down: bool,
+}
+
+impl Debouncer {
+    /// Changes level after `samples` more samples of the new level than of the old
+    pub const fn integrator(samples: u8) -> Self {
+        let max = if samples == 0 { 1 } else { samples };
+        Debouncer {
+            kind: Kind::Integrator { count: 0, max },
+            down: false,
+        }
+    }
+
+    /// Changes level after `samples` consecutive samples of the new level, from 1 to 32
+    pub const fn shift_register(samples: u8) -> Self {
+        let len = if samples == 0 {
+            1
+        } else if samples > 32 {
+            32
+        } else {
+            samples
+        };
+        Debouncer {
+            kind: Kind::Shift {
+                history: 0,
+                mask: u32::MAX >> (32 - len as u32),
+            },
+            down: false,
+        }
+    }
+
+    /// Feed a sample of the pin, return the new level when it changes
+    pub fn sample(&mut self, sample: bool) -> Option<bool> {
+        let settled = match &mut self.kind {
+            Kind::Integrator { count, max } => {
+                *count = match sample {
+                    true => (*count + 1).min(*max),
+                    false => count.saturating_sub(1),
+                };
+                match *count {
+                    0 => Some(false),
+                    c if c == *max => Some(true),
+                    _ => None,
+                }
+            }
+            Kind::Shift { history, mask } => {
+                *history = *history << 1 | sample as u32;
+                match *history & *mask {
+                    0 => Some(false),
+                    h if h == *mask => Some(true),
+                    _ => None,
+                }
+            }
+        };
+        match settled {
+            Some(level) if level != self.down => {
+                self.down = level;
+                Some(level)
+            }
+            _ => None,
+        }
+    }
+
+    /// The debounced level
+    pub fn is_down(&self) -> bool {
+        self.down
+    }
+
+    /// Take `down` as the debounced level without reporting it, like a pin known to be steady when
+    /// the sampling starts
+    pub fn settle(&mut self, down: bool) {
+        match &mut self.kind {
+            Kind::Integrator { count, max } => *count = if down { *max } else { 0 },
+            Kind::Shift { history, mask } => *history = if down { *mask } else { 0 },
+        }
+        self.down = down;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use e_rng::Rng;
+
+    /// The samples of `pattern`, `#` down and `_` up, through `debouncer` as the debounced levels
+    fn run(mut debouncer: Debouncer, pattern: &str) -> String {
+        pattern
+            .chars()
+            .map(|c| {
+                debouncer.sample(c == '#');
+                match debouncer.is_down() {
+                    true => '#',
+                    false => '_',
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_integrator() {
+        let debouncer = Debouncer::integrator(3);
+        assert_eq!(run(debouncer, "___###___"), "_____###_");
+        // glitches delay the change without restarting it
+        assert_eq!(run(debouncer, "#_#_##_##__"), "________###");
+        assert_eq!(run(debouncer, "#_#_#_#_#_"), "__________");
+    }
+
+    #[test]
+    fn test_shift_register() {
+        let debouncer = Debouncer::shift_register(3);
+        assert_eq!(run(debouncer, "___###___"), "_____###_");
+        // a glitch restarts the count
+        assert_eq!(run(debouncer, "##_###_##_"), "_____#####");
+        assert_eq!(
+            run(Debouncer::shift_register(40), &"#".repeat(40)).find('#'),
+            Some(31)
+        );
+        assert_eq!(run(Debouncer::shift_register(0), "#_"), "#_");
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut debouncer = Debouncer::integrator(2);
+        let changes: Vec<_> = [true, true, true, false, true, false, false, false]
+            .iter()
+            .map(|s| debouncer.sample(*s))
+            .collect();
+        assert_eq!(
+            changes,
+            [None, Some(true), None, None, None, None, Some(false), None]
+        );
+    }
+
+    #[test]
+    fn test_settle() {
+        for mut debouncer in [Debouncer::integrator(3), Debouncer::shift_register(3)] {
+            debouncer.settle(true);
+            assert!(debouncer.is_down());
+            assert_eq!(debouncer.sample(true), None);
+            assert_eq!(run(debouncer, "_#___#"), "####__");
+            debouncer.settle(false);
+            assert_eq!(run(debouncer, "#_###_"), "____##");
+        }
+    }
+
+    /// A press with bounces shorter than the debouncer, sampled at random phases, changes the
+    /// level exactly twice
+    #[test]
+    fn test_property_one_press() {
+        let mut rng = Rng::new(3);
+        for _ in 0..500 {
+            let samples = 2 + rng.below(8) as u8;
+            let mut debouncer = match rng.below(2) {
+                0 => Debouncer::integrator(samples),
+                _ => Debouncer::shift_register(samples),
+            };
+            let mut pattern = vec![false; rng.below(20) as usize];
+            for level in [true, false] {
+                for _ in 0..rng.below(4) {
+                    // bounces shorter than the debouncer
+                    let run = 1 + rng.below(samples as u32 - 1) as usize;
+                    pattern.extend(std::iter::repeat_n(level, run));
+                    pattern.extend(std::iter::repeat_n(!level, 1));
+                }
+                pattern.extend(std::iter::repeat_n(
+                    level,
+                    2 * samples as usize + rng.below(30) as usize,
+                ));
+            }
+            let changes: Vec<_> = pattern
+                .iter()
+                .filter_map(|s| debouncer.sample(*s))
+                .collect();
+            assert_eq!(changes, [true, false], "{:?}", debouncer);
+        }
+    }
+}
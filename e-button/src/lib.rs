@@ -0,0 +1,12 @@
+//! e-button
+//!
+//! Gestures of push buttons from the edges or the samples of their pins, see README.md
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+mod buttons;
+mod debounce;
+
+pub use buttons::{Buttons, Event, Timings};
+pub use debounce::Debouncer;
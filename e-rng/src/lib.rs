@@ -0,0 +1,66 @@
+//! e-rng
+//!
+//! Deterministic pseudo random numbers for the property tests, see README.md
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+/// Linear congruential generator with the constants of Knuth's MMIX, returning the 31 high bits
+/// of its state which are the most random ones
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    /// A number in `0..2^31`
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+
+    /// A number in `0..n`, slightly biased towards the low ones unless `n` is a power of two.
+    ///
+    /// Panics if `n` is 0
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let mut a = Rng::new(7);
+        let mut b = a.clone();
+        let first: Vec<u32> = (0..100).map(|_| a.next_u32()).collect();
+        assert!(first.iter().all(|n| *n < 1 << 31));
+        assert!(first.iter().all(|n| *n == b.next_u32()));
+        assert_ne!(Rng::new(8).next_u32(), first[0]);
+    }
+
+    #[test]
+    fn test_below() {
+        let mut rng = Rng::new(42);
+        let mut seen = [0u32; 10];
+        for _ in 0..10_000 {
+            seen[rng.below(10) as usize] += 1;
+        }
+        // every value comes out, roughly as often as the others
+        assert!(seen.iter().all(|n| (800..1200).contains(n)), "{:?}", seen);
+        assert_eq!(rng.below(1), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_below_zero() {
+        Rng::new(1).below(0);
+    }
+}
@@ -0,0 +1,20 @@
+//! e-series
+//!
+//! Time series at multiple resolutions, see README.md
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+mod series;
+mod timeline;
+mod window;
+
+pub use series::Series;
+pub use timeline::Timeline;
+pub use window::{Reducer, Window};
+
+/// Where the values of a level are appended, usually a ring buffer keeping the latest ones
+pub trait Store {
+    /// Append the `value` of the window starting at `start` seconds
+    fn append(&mut self, start: u32, value: i16);
+}
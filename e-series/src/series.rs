@@ -0,0 +1,254 @@
+//! series
+//!
+//! This module contains the multi-resolution time series: every level reduces the samples of the
+//! windows of its interval and appends the result to its store, with the start of the window
+//!
+
+use crate::window::{Reducer, Window};
+use crate::Store;
+
+pub struct Series<S, const L: usize> {
+    levels: [S; L],
+    /// Seconds of the windows of every level
+    intervals: [u32; L],
+    reducer: Reducer,
+    /// The window being filled of every level and its index, `seconds / interval`
+    windows: [(u32, Window); L],
+}
+
+impl<S: Store, const L: usize> Series<S, L> {
+    /// A series appending to `levels` the samples reduced over windows of `intervals` seconds,
+    /// usually increasing like `[1, 60, 3600]`.
+    ///
+    /// Panics if an interval is 0
+    pub fn new(levels: [S; L], intervals: [u32; L], reducer: Reducer) -> Self {
+        assert!(
+            intervals.iter().all(|i| *i > 0),
+            "interval must be positive"
+        );
+        Series {
+            levels,
+            intervals,
+            reducer,
+            windows: [(0, Window::new()); L],
+        }
+    }
+
+    /// Push the sample taken at `seconds`, `None` if the reading failed. The windows of the
+    /// previous instants are reduced and appended to the levels.
+    ///
+    /// `seconds` is expected not to decrease, a sample older than the current window closes it as
+    /// a newer one would
+    pub fn push(&mut self, seconds: u32, value: Option<i16>) {
+        for ((level, interval), (index, window)) in self
+            .levels
+            .iter_mut()
+            .zip(self.intervals.iter())
+            .zip(self.windows.iter_mut())
+        {
+            let current = seconds / interval;
+            if current != *index {
+                if let Some(reduced) = window.reduce(self.reducer) {
+                    level.append(*index * interval, reduced);
+                }
+                *index = current;
+                *window = Window::new();
+            }
+            if let Some(value) = value {
+                window.add(value);
+            }
+        }
+    }
+
+    /// The seconds of the start of the window being filled at `level`
+    pub fn partial_start(&self, level: usize) -> u32 {
+        self.windows[level].0 * self.intervals[level]
+    }
+
+    /// The value of the window being filled at `level`, `None` if it has no samples yet
+    pub fn partial(&self, level: usize) -> Option<i16> {
+        self.windows[level].1.reduce(self.reducer)
+    }
+
+    pub fn level(&self, level: usize) -> &S {
+        &self.levels[level]
+    }
+
+    pub fn levels(&self) -> &[S; L] {
+        &self.levels
+    }
+
+    pub fn intervals(&self) -> &[u32; L] {
+        &self.intervals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use e_rng::Rng;
+
+    impl Store for Vec<i16> {
+        fn append(&mut self, _start: u32, value: i16) {
+            self.push(value);
+        }
+    }
+
+    impl Store for Vec<(u32, i16)> {
+        fn append(&mut self, start: u32, value: i16) {
+            self.push((start, value));
+        }
+    }
+
+    fn series<const L: usize>(intervals: [u32; L], reducer: Reducer) -> Series<Vec<i16>, L> {
+        Series::new(core::array::from_fn(|_| vec![]), intervals, reducer)
+    }
+
+    fn reduce(values: &[i16], reducer: Reducer) -> i16 {
+        match reducer {
+            Reducer::Mean => {
+                let sum: i64 = values.iter().map(|v| *v as i64).sum();
+                (sum / values.len() as i64) as i16
+            }
+            Reducer::Min => *values.iter().min().unwrap(),
+            Reducer::Max => *values.iter().max().unwrap(),
+            Reducer::Last => *values.last().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut series = series([1, 60, 3600], Reducer::Mean);
+        for seconds in 0..=2 * 3600 {
+            series.push(seconds, Some((seconds % 120) as i16));
+        }
+        assert_eq!(series.level(0).len(), 2 * 3600);
+        assert_eq!(series.level(0)[..3], [0, 1, 2]);
+        assert_eq!(series.level(1).len(), 120);
+        assert_eq!(series.level(1)[..3], [29, 89, 29]);
+        assert_eq!(series.level(2), &vec![59, 59]);
+        assert_eq!(series.partial(0), Some(0));
+        assert_eq!(series.partial(1), Some(0));
+        assert_eq!(series.partial(2), Some(0));
+    }
+
+    #[test]
+    fn test_partial_windows() {
+        // fewer samples than an interval don't underflow and are reduced once the window ends
+        let mut series = series([1, 60], Reducer::Mean);
+        series.push(58, Some(10));
+        series.push(59, Some(20));
+        assert_eq!(series.level(1), &Vec::<i16>::new());
+        assert_eq!(series.partial(1), Some(15));
+        series.push(60, Some(100));
+        assert_eq!(series.level(1), &vec![15]);
+        assert_eq!(series.partial(1), Some(100));
+    }
+
+    #[test]
+    fn test_gaps() {
+        let mut series = series([1, 10], Reducer::Max);
+        series.push(1, Some(5));
+        series.push(2, None);
+        series.push(3, Some(7));
+        // a window of 10 seconds without samples
+        for seconds in 4..25 {
+            series.push(seconds, None);
+        }
+        series.push(25, Some(1));
+        series.push(30, None);
+        assert_eq!(series.level(0), &vec![5, 7, 1]);
+        assert_eq!(series.level(1), &vec![7, 1]);
+        assert_eq!(series.partial(1), None);
+    }
+
+    #[test]
+    fn test_irregular_spacing() {
+        // samples at every wake up, not every second
+        let mut series = series([1, 60], Reducer::Last);
+        for (seconds, value) in [(0, 1), (17, 2), (59, 3), (61, 4), (200, 5)] {
+            series.push(seconds, Some(value));
+        }
+        assert_eq!(series.level(0), &vec![1, 2, 3, 4]);
+        assert_eq!(series.level(1), &vec![3, 4]);
+        assert_eq!(series.partial(1), Some(5));
+    }
+
+    #[test]
+    fn test_starts() {
+        // seconds of a wall clock, the windows are aligned to its minutes and hours
+        let mut series: Series<Vec<(u32, i16)>, 2> =
+            Series::new([vec![], vec![]], [60, 3600], Reducer::Max);
+        let three = 3 * 3600;
+        series.push(three - 30, Some(1));
+        series.push(three + 10, Some(2));
+        series.push(three + 50, Some(3));
+        assert_eq!(series.partial_start(1), three);
+        // a gap of two minutes
+        series.push(three + 200, Some(4));
+        series.push(three + 3600, None);
+        assert_eq!(
+            series.level(0),
+            &vec![(three - 60, 1), (three, 3), (three + 180, 4)]
+        );
+        assert_eq!(series.level(1), &vec![(three - 3600, 1), (three, 4)]);
+        assert_eq!(series.partial_start(0), three + 3600);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_interval() {
+        series([1, 0], Reducer::Mean);
+    }
+
+    /// The series against a straightforward implementation keeping all the samples, on random
+    /// intervals, reducers, gaps and spacing
+    #[test]
+    fn test_property_matches_reference() {
+        let mut rng = Rng::new(42);
+        let reducers = [Reducer::Mean, Reducer::Min, Reducer::Max, Reducer::Last];
+        for _ in 0..300 {
+            let reducer = reducers[rng.below(4) as usize];
+            let intervals = [1 + rng.below(3), 1 + rng.below(30), 1 + rng.below(400)];
+            let mut series = series(intervals, reducer);
+            let mut samples: Vec<(u32, i16)> = vec![];
+            let mut seconds = rng.below(1000);
+            let max_step = 1 + rng.below(20);
+            for _ in 0..rng.below(2000) {
+                let value = match rng.below(4) {
+                    0 => None,
+                    _ => Some(rng.next_u32() as i16),
+                };
+                series.push(seconds, value);
+                if let Some(value) = value {
+                    samples.push((seconds, value));
+                }
+                seconds += rng.below(max_step);
+            }
+
+            for (level, interval) in intervals.iter().enumerate() {
+                let mut windows: Vec<(u32, Vec<i16>)> = vec![];
+                for (seconds, value) in samples.iter() {
+                    let index = seconds / interval;
+                    match windows.last_mut() {
+                        Some((i, values)) if *i == index => values.push(*value),
+                        _ => windows.push((index, vec![*value])),
+                    }
+                }
+                let reduced: Vec<i16> = windows
+                    .iter()
+                    .map(|(_, values)| reduce(values, reducer))
+                    .collect();
+                // the window being filled is not appended yet
+                let (expected, partial) = match windows.last() {
+                    Some((i, _)) if *i == series.windows[level].0 => {
+                        (&reduced[..reduced.len() - 1], reduced.last().copied())
+                    }
+                    _ => (&reduced[..], None),
+                };
+                assert_eq!(series.level(level).as_slice(), expected);
+                assert_eq!(series.partial(level), partial);
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! timeline
+//!
+//! This module keeps the start of the windows appended to a level alongside the values, in a byte
+//! per value: the windows are aligned to the interval, so only the windows skipped between two
+//! values and the start of the newest are needed
+//!
+
+/// The starts of the last `N` windows of a level of `interval` seconds, the newest has age 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline<const N: usize> {
+    interval: u32,
+    newest: Option<u32>,
+    /// Windows skipped before every value, `u8::MAX` if unknown or too many to count
+    skipped: [u8; N],
+    /// Index of the newest value in `skipped`
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Timeline<N> {
+    pub const fn new(interval: u32) -> Self {
+        Timeline {
+            interval,
+            newest: None,
+            skipped: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Record the `start` of the window of a value just appended, as passed to `Store::append`
+    pub fn push(&mut self, start: u32) {
+        if N == 0 {
+            return;
+        }
+        let skipped = match self.newest {
+            Some(newest) if start > newest => {
+                let windows = (start - newest) / self.interval.max(1);
+                windows.saturating_sub(1).min(u8::MAX as u32) as u8
+            }
+            _ => u8::MAX,
+        };
+        self.head = (self.head + 1) % N;
+        self.skipped[self.head] = skipped;
+        self.len = (self.len + 1).min(N);
+        self.newest = Some(start);
+    }
+
+    /// The start of the value appended `age` values before the newest, `None` if it's not kept or
+    /// too many windows have been skipped since
+    pub fn start(&self, age: usize) -> Option<u32> {
+        if age >= self.len {
+            return None;
+        }
+        let mut windows = 0u32;
+        for i in 0..age {
+            let skipped = self.skipped[(self.head + N - i) % N];
+            if skipped == u8::MAX {
+                return None;
+            }
+            windows += 1 + skipped as u32;
+        }
+        self.newest?
+            .checked_sub(windows.checked_mul(self.interval)?)
+    }
+
+    /// The start of the newest value
+    pub fn newest(&self) -> Option<u32> {
+        self.newest
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use e_rng::Rng;
+
+    #[test]
+    fn test_starts() {
+        let mut timeline: Timeline<4> = Timeline::new(60);
+        assert!(timeline.is_empty());
+        assert_eq!(timeline.start(0), None);
+        for start in [600, 660, 900, 960] {
+            timeline.push(start);
+        }
+        assert_eq!(timeline.newest(), Some(960));
+        let starts: Vec<_> = (0..5).map(|age| timeline.start(age)).collect();
+        assert_eq!(starts, [Some(960), Some(900), Some(660), Some(600), None]);
+
+        // the oldest is dropped
+        timeline.push(1_020);
+        assert_eq!(timeline.len(), 4);
+        assert_eq!(timeline.start(3), Some(660));
+        assert_eq!(timeline.start(4), None);
+    }
+
+    #[test]
+    fn test_long_gap() {
+        let mut timeline: Timeline<4> = Timeline::new(1);
+        timeline.push(10);
+        timeline.push(10 + 255);
+        timeline.push(10 + 255 + 300);
+        timeline.push(10 + 255 + 300 + 1);
+        // 254 skipped fit a byte, 299 don't
+        assert_eq!(timeline.start(1), Some(10 + 255 + 300));
+        assert_eq!(timeline.start(2), None);
+        assert_eq!(timeline.start(3), None);
+    }
+
+    /// The timeline against all the starts, on random gaps
+    #[test]
+    fn test_matches_all_starts() {
+        let mut rng = Rng::new(7);
+        let mut timeline: Timeline<16> = Timeline::new(60);
+        let mut starts = vec![];
+        let mut start = 3_600;
+        for _ in 0..200 {
+            start += 60 * (1 + rng.below(4));
+            timeline.push(start);
+            starts.push(start);
+            for age in 0..20 {
+                let expected = (age < 16).then(|| starts.iter().rev().nth(age).copied());
+                assert_eq!(timeline.start(age), expected.flatten());
+            }
+        }
+    }
+}
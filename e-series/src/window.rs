@@ -0,0 +1,116 @@
+//! window
+//!
+//! This module accumulates the samples of a time window and reduces them to a single value
+//!
+
+/// How the samples of a window are reduced to a single value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reducer {
+    /// The mean truncated toward zero
+    Mean,
+    Min,
+    Max,
+    /// The sample pushed last
+    Last,
+}
+
+/// The samples of a window, it doesn't keep them but only what is needed by every reducer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    sum: i64,
+    count: u32,
+    min: i16,
+    max: i16,
+    last: i16,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Window {
+    pub const fn new() -> Self {
+        Window {
+            sum: 0,
+            count: 0,
+            min: i16::MAX,
+            max: i16::MIN,
+            last: 0,
+        }
+    }
+
+    pub fn add(&mut self, value: i16) {
+        self.sum += value as i64;
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.last = value;
+    }
+
+    /// The samples reduced with `reducer`, `None` if the window is empty
+    pub fn reduce(&self, reducer: Reducer) -> Option<i16> {
+        if self.count == 0 {
+            return None;
+        }
+        let value = match reducer {
+            Reducer::Mean => (self.sum / self.count as i64) as i16,
+            Reducer::Min => self.min,
+            Reducer::Max => self.max,
+            Reducer::Last => self.last,
+        };
+        Some(value)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduce() {
+        let mut window = Window::new();
+        assert!(window.is_empty());
+        for reducer in [Reducer::Mean, Reducer::Min, Reducer::Max, Reducer::Last] {
+            assert_eq!(window.reduce(reducer), None);
+        }
+        for value in [10, -20, 45, 3] {
+            window.add(value);
+        }
+        assert_eq!(window.len(), 4);
+        assert_eq!(window.reduce(Reducer::Mean), Some(9));
+        assert_eq!(window.reduce(Reducer::Min), Some(-20));
+        assert_eq!(window.reduce(Reducer::Max), Some(45));
+        assert_eq!(window.reduce(Reducer::Last), Some(3));
+
+        let mut window = Window::new();
+        window.add(-5);
+        window.add(-10);
+        // toward zero
+        assert_eq!(window.reduce(Reducer::Mean), Some(-7));
+    }
+
+    #[test]
+    fn test_extremes() {
+        let mut window = Window::new();
+        for _ in 0..100_000 {
+            window.add(i16::MAX);
+        }
+        assert_eq!(window.reduce(Reducer::Mean), Some(i16::MAX));
+        let mut window = Window::new();
+        for _ in 0..100_000 {
+            window.add(i16::MIN);
+        }
+        assert_eq!(window.reduce(Reducer::Mean), Some(i16::MIN));
+        assert_eq!(window.reduce(Reducer::Max), Some(i16::MIN));
+    }
+}
@@ -0,0 +1,27 @@
+/// CRC-16/CCITT-FALSE, polynomial `0x1021` with initial value `0xFFFF`
+pub fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+pub const CRC_INIT: u16 = 0xFFFF;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(CRC_INIT, b"123456789"), 0x29B1);
+        assert_eq!(crc16(CRC_INIT, b""), CRC_INIT);
+    }
+}
@@ -0,0 +1,37 @@
+//! e-store
+//!
+//! A wear levelled key/value store for the last pages of the flash, see README.md for the layout
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+mod crc;
+pub mod ram;
+#[cfg(any(test, feature = "stm32f1"))]
+pub mod stm32f1;
+mod store;
+
+pub use ram::RamFlash;
+pub use store::{Error, Store, MAX_LEN};
+
+/// The flash area reserved to the store, made of `pages()` pages of `page_size()` bytes.
+/// Offsets are relative to the start of the area.
+pub trait Flash {
+    type Error;
+
+    /// Size in bytes of an erasable page
+    fn page_size(&self) -> usize;
+
+    /// Number of pages reserved to the store, at least 2
+    fn pages(&self) -> usize;
+
+    /// Read `buf.len()` bytes starting at `offset`
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write `data` starting at `offset`, both `offset` and the length of `data` are even and the
+    /// area is erased
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Erase the page with index `page`, setting all its bytes to `0xFF`
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error>;
+}
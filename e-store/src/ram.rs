@@ -0,0 +1,94 @@
+//! ram
+//!
+//! A `Flash` in memory, with the constraints of a real one, to test the store on the host
+//!
+
+use crate::Flash;
+
+/// Errors of the `RamFlash`, they are bugs of the store
+#[derive(Debug, PartialEq, Eq)]
+pub enum RamError {
+    /// Access beyond the end of the area
+    OutOfBounds,
+    /// Write at an odd offset or with an odd length
+    Unaligned,
+    /// Write on bytes not erased
+    NotErased,
+}
+
+/// `PAGES` pages of `PAGE` bytes kept in memory
+pub struct RamFlash<const PAGE: usize, const PAGES: usize> {
+    pub pages: [[u8; PAGE]; PAGES],
+    /// Number of erases done on every page
+    pub erases: [u32; PAGES],
+    /// If `Some(n)`, the writes after the next `n` bytes are dropped, like at a power loss
+    pub power_loss_after: Option<usize>,
+}
+
+impl<const PAGE: usize, const PAGES: usize> Default for RamFlash<PAGE, PAGES> {
+    fn default() -> Self {
+        RamFlash {
+            pages: [[0xFF; PAGE]; PAGES],
+            erases: [0; PAGES],
+            power_loss_after: None,
+        }
+    }
+}
+
+impl<const PAGE: usize, const PAGES: usize> Flash for RamFlash<PAGE, PAGES> {
+    type Error = RamError;
+
+    fn page_size(&self) -> usize {
+        PAGE
+    }
+
+    fn pages(&self) -> usize {
+        PAGES
+    }
+
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error> {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let pos = offset + i;
+            if pos >= PAGE * PAGES {
+                return Err(RamError::OutOfBounds);
+            }
+            *byte = self.pages[pos / PAGE][pos % PAGE];
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        if !offset.is_multiple_of(2) || !data.len().is_multiple_of(2) {
+            return Err(RamError::Unaligned);
+        }
+        if offset + data.len() > PAGE * PAGES {
+            return Err(RamError::OutOfBounds);
+        }
+        for (i, byte) in data.iter().enumerate() {
+            match self.power_loss_after.as_mut() {
+                Some(0) => return Ok(()),
+                Some(n) => *n -= 1,
+                None => (),
+            }
+            let pos = offset + i;
+            let current = &mut self.pages[pos / PAGE][pos % PAGE];
+            if *current != 0xFF {
+                return Err(RamError::NotErased);
+            }
+            *current = *byte;
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error> {
+        if page >= PAGES {
+            return Err(RamError::OutOfBounds);
+        }
+        if self.power_loss_after == Some(0) {
+            return Ok(());
+        }
+        self.pages[page] = [0xFF; PAGE];
+        self.erases[page] += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! stm32f1
+//!
+//! This module gives the last pages of the STM32F103 flash to the `Store`, the pages are excluded
+//! from the program in the `memory.x` of the firmwares
+//!
+
+/// Offset from the start of the flash of the pages reserved to the store
+pub const STORE_START: u32 = 62 * 1024;
+
+/// Size of a page of the STM32F103C8
+pub const PAGE_SIZE: usize = 1024;
+
+/// Number of pages reserved to the store
+pub const PAGES: usize = 2;
+
+/// Define `Storage`, the flash area at `STORE_START`, implementing `e_store::Flash` over the
+/// `flash::Parts` of the `stm32f1xx_hal` of the calling crate.
+///
+/// A macro rather than a type since the firmwares use different versions of `stm32f1xx-hal`, whose
+/// `flash::Parts::writer` and `FlashWriter` have the same signatures.
+#[macro_export]
+macro_rules! stm32f1_storage {
+    () => {
+        /// The flash area at `e_store::stm32f1::STORE_START`
+        pub struct Storage {
+            flash: stm32f1xx_hal::flash::Parts,
+        }
+
+        impl Storage {
+            /// Use the flash `Parts`, after the clocks are freezed
+            pub fn new(flash: stm32f1xx_hal::flash::Parts) -> Self {
+                Storage { flash }
+            }
+        }
+
+        impl $crate::Flash for Storage {
+            type Error = stm32f1xx_hal::flash::Error;
+
+            fn page_size(&self) -> usize {
+                $crate::stm32f1::PAGE_SIZE
+            }
+
+            fn pages(&self) -> usize {
+                $crate::stm32f1::PAGES
+            }
+
+            fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error> {
+                use stm32f1xx_hal::flash::{FlashSize, SectorSize};
+                let writer = self.flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+                let start = $crate::stm32f1::STORE_START + offset as u32;
+                let data = writer.read(start, buf.len())?;
+                buf.copy_from_slice(data);
+                Ok(())
+            }
+
+            fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+                use stm32f1xx_hal::flash::{FlashSize, SectorSize};
+                let mut writer = self.flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+                writer.write($crate::stm32f1::STORE_START + offset as u32, data)
+            }
+
+            fn erase(&mut self, page: usize) -> Result<(), Self::Error> {
+                use stm32f1xx_hal::flash::{FlashSize, SectorSize};
+                let mut writer = self.flash.writer(SectorSize::Sz1K, FlashSize::Sz64K);
+                let page = page * $crate::stm32f1::PAGE_SIZE;
+                writer.page_erase($crate::stm32f1::STORE_START + page as u32)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Store;
+
+    /// The flash of the hal, as seen by `stm32f1_storage!`
+    mod stm32f1xx_hal {
+        pub mod flash {
+            pub enum SectorSize {
+                Sz1K,
+            }
+
+            pub enum FlashSize {
+                Sz64K,
+            }
+
+            #[derive(Debug, PartialEq, Eq)]
+            pub enum Error {
+                AddressLargerThanFlash,
+            }
+
+            /// The 64K of the STM32F103C8
+            pub struct Parts(pub Vec<u8>);
+
+            impl Parts {
+                pub fn writer(&mut self, _: SectorSize, _: FlashSize) -> FlashWriter<'_> {
+                    FlashWriter(&mut self.0)
+                }
+            }
+
+            pub struct FlashWriter<'a>(&'a mut Vec<u8>);
+
+            impl FlashWriter<'_> {
+                fn range(&self, offset: u32, len: usize) -> Result<core::ops::Range<usize>, Error> {
+                    let start = offset as usize;
+                    if start + len > self.0.len() {
+                        return Err(Error::AddressLargerThanFlash);
+                    }
+                    Ok(start..start + len)
+                }
+
+                pub fn read(&self, offset: u32, len: usize) -> Result<&[u8], Error> {
+                    Ok(&self.0[self.range(offset, len)?])
+                }
+
+                pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+                    let range = self.range(offset, data.len())?;
+                    self.0[range].copy_from_slice(data);
+                    Ok(())
+                }
+
+                pub fn page_erase(&mut self, offset: u32) -> Result<(), Error> {
+                    let range = self.range(offset, 1024)?;
+                    self.0[range].fill(0xFF);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    stm32f1_storage!();
+
+    #[test]
+    fn test_storage() {
+        let flash = stm32f1xx_hal::flash::Parts(vec![0xFF; 64 * 1024]);
+        let mut store = Store::new(Storage::new(flash)).unwrap();
+        store.write(3, 0, b"moisture").unwrap();
+
+        let flash = store.release().flash.0;
+        let start = STORE_START as usize;
+        // the program before the store is left as it was
+        assert!(flash[..start].iter().all(|b| *b == 0xFF));
+        assert_eq!(&flash[start..start + 4], b"eSt0");
+
+        let flash = stm32f1xx_hal::flash::Parts(flash);
+        let mut store = Store::new(Storage::new(flash)).unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(store.read(3, 0, &mut buf).unwrap(), Some(8));
+        assert_eq!(&buf, b"moisture");
+    }
+}
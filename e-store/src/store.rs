@@ -0,0 +1,409 @@
+use crate::crc::{crc16, CRC_INIT};
+use crate::Flash;
+
+/// Maximum length of a value
+pub const MAX_LEN: usize = 64;
+
+const MAGIC: [u8; 4] = *b"eSt0";
+const PAGE_HEADER: usize = 8;
+const RECORD_HEADER: usize = 4;
+const CRC: usize = 2;
+const ERASED: u8 = 0xFF;
+const WRITTEN: u8 = 0x00;
+
+/// Errors of the store
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// Error of the underlying flash
+    Flash(E),
+    /// The key `0xFF` is reserved
+    InvalidKey,
+    /// The value is longer than `MAX_LEN`
+    TooLong,
+    /// The buffer given to `read` is shorter than the value
+    BufferTooSmall,
+    /// The latest values don't fit in a page
+    Full,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::Flash(e)
+    }
+}
+
+/// A record found while scanning a page
+#[derive(Clone, Copy)]
+struct Record {
+    offset: usize,
+    key: u8,
+    version: u8,
+    len: usize,
+}
+
+impl Record {
+    fn size(&self) -> usize {
+        record_size(self.len)
+    }
+}
+
+fn record_size(len: usize) -> usize {
+    RECORD_HEADER + len + len % 2 + CRC
+}
+
+/// The key/value store, values are written in the active page and moved to the next one when it
+/// is full
+pub struct Store<F: Flash> {
+    flash: F,
+    /// index of the active page
+    page: usize,
+    /// sequence number of the active page
+    seq: u32,
+    /// offset of the next record in the active page
+    end: usize,
+    /// false if the active page contains a record half written, new records can't be appended
+    clean: bool,
+}
+
+impl<F: Flash> Store<F> {
+    /// Open the store, if no page is valid (eg. first boot) the store is initialized empty
+    pub fn new(mut flash: F) -> Result<Self, Error<F::Error>> {
+        let mut active: Option<(usize, u32)> = None;
+        for page in 0..flash.pages() {
+            let mut header = [0u8; PAGE_HEADER];
+            flash.read(page * flash.page_size(), &mut header)?;
+            if header[..4] == MAGIC {
+                let seq = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+                if active.map(|(_, s)| seq > s).unwrap_or(true) {
+                    active = Some((page, seq));
+                }
+            }
+        }
+
+        let mut store = Store {
+            flash,
+            page: 0,
+            seq: 0,
+            end: PAGE_HEADER,
+            clean: true,
+        };
+        match active {
+            Some((page, seq)) => {
+                store.page = page;
+                store.seq = seq;
+                let (end, clean) = store.scan(page, |_| ())?;
+                store.end = end;
+                store.clean = clean;
+            }
+            None => {
+                store.flash.erase(0)?;
+                store.write_page_header(0, 0)?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// Read the latest value of `key` in `buf`, returning its length.
+    /// Return `None` if the key was never written or if it was written with another `version`
+    pub fn read(
+        &mut self,
+        key: u8,
+        version: u8,
+        buf: &mut [u8],
+    ) -> Result<Option<usize>, Error<F::Error>> {
+        match self.find(self.page, key)? {
+            Some(record) if record.version == version => {
+                if buf.len() < record.len {
+                    return Err(Error::BufferTooSmall);
+                }
+                let offset = self.page_offset(self.page) + record.offset + RECORD_HEADER;
+                self.flash.read(offset, &mut buf[..record.len])?;
+                Ok(Some(record.len))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Write `data` as the value of `key`, nothing is written if the value is unchanged
+    pub fn write(&mut self, key: u8, version: u8, data: &[u8]) -> Result<(), Error<F::Error>> {
+        if key == ERASED {
+            return Err(Error::InvalidKey);
+        }
+        if data.len() > MAX_LEN {
+            return Err(Error::TooLong);
+        }
+        if let Some(record) = self.find(self.page, key)? {
+            if record.version == version && record.len == data.len() {
+                let mut current = [0u8; MAX_LEN];
+                let offset = self.page_offset(self.page) + record.offset + RECORD_HEADER;
+                self.flash.read(offset, &mut current[..data.len()])?;
+                if current[..data.len()] == *data {
+                    return Ok(());
+                }
+            }
+        }
+
+        let size = record_size(data.len());
+        if !self.clean || self.end + size > self.flash.page_size() {
+            self.compact()?;
+            if self.end + size > self.flash.page_size() {
+                return Err(Error::Full);
+            }
+        }
+
+        let mut record = [0u8; RECORD_HEADER + MAX_LEN + CRC];
+        record[..RECORD_HEADER].copy_from_slice(&[key, version, data.len() as u8, WRITTEN]);
+        record[RECORD_HEADER..RECORD_HEADER + data.len()].copy_from_slice(data);
+        let crc_start = size - CRC;
+        let crc = crc16(crc16(CRC_INIT, &record[..RECORD_HEADER]), data);
+        record[crc_start..size].copy_from_slice(&crc.to_le_bytes());
+
+        let offset = self.page_offset(self.page) + self.end;
+        self.end += size;
+        self.flash.write(offset, &record[..size])?;
+        Ok(())
+    }
+
+    /// Give back the flash
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    fn page_offset(&self, page: usize) -> usize {
+        page * self.flash.page_size()
+    }
+
+    fn write_page_header(&mut self, page: usize, seq: u32) -> Result<(), Error<F::Error>> {
+        let mut header = [0u8; PAGE_HEADER];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..].copy_from_slice(&seq.to_le_bytes());
+        let offset = self.page_offset(page);
+        self.flash.write(offset, &header)?;
+        self.page = page;
+        self.seq = seq;
+        Ok(())
+    }
+
+    /// Call `f` with every valid record of `page`, return the offset after the last one and false
+    /// if the scan stopped because of a record half written
+    fn scan(
+        &mut self,
+        page: usize,
+        mut f: impl FnMut(Record),
+    ) -> Result<(usize, bool), Error<F::Error>> {
+        let page_size = self.flash.page_size();
+        let start = self.page_offset(page);
+        let mut offset = PAGE_HEADER;
+        while offset + RECORD_HEADER <= page_size {
+            let mut header = [0u8; RECORD_HEADER];
+            self.flash.read(start + offset, &mut header)?;
+            let [key, version, len, written] = header;
+            if key == ERASED {
+                return Ok((offset, true));
+            }
+            let record = Record {
+                offset,
+                key,
+                version,
+                len: len as usize,
+            };
+            if written != WRITTEN || record.len > MAX_LEN || offset + record.size() > page_size {
+                return Ok((offset, false));
+            }
+            let mut data = [0u8; MAX_LEN + 1 + CRC];
+            let data = &mut data[..record.size() - RECORD_HEADER];
+            self.flash.read(start + offset + RECORD_HEADER, data)?;
+            let (value, crc) = data.split_at(data.len() - CRC);
+            let expected = crc16(crc16(CRC_INIT, &header), &value[..record.len]);
+            if crc != expected.to_le_bytes() {
+                return Ok((offset, false));
+            }
+            f(record);
+            offset += record.size();
+        }
+        Ok((offset, true))
+    }
+
+    /// The latest valid record of `key` in `page`
+    fn find(&mut self, page: usize, key: u8) -> Result<Option<Record>, Error<F::Error>> {
+        let mut found = None;
+        self.scan(page, |record| {
+            if record.key == key {
+                found = Some(record)
+            }
+        })?;
+        Ok(found)
+    }
+
+    /// Copy the latest value of every key to the next page, which becomes the active one.
+    /// The key about to be written is copied too, so it isn't lost if power goes down before the
+    /// new value is written
+    fn compact(&mut self) -> Result<(), Error<F::Error>> {
+        let old = self.page;
+        let new = (old + 1) % self.flash.pages();
+        let page_size = self.flash.page_size();
+
+        let mut keys = [0u32; 8];
+        self.scan(old, |record| {
+            keys[record.key as usize / 32] |= 1 << (record.key % 32)
+        })?;
+
+        self.flash.erase(new)?;
+        let mut end = PAGE_HEADER;
+        for key in 0..ERASED {
+            if keys[key as usize / 32] & (1 << (key % 32)) == 0 {
+                continue;
+            }
+            if let Some(record) = self.find(old, key)? {
+                if end + record.size() > page_size {
+                    return Err(Error::Full);
+                }
+                let mut buf = [0u8; RECORD_HEADER + MAX_LEN + 1 + CRC];
+                let buf = &mut buf[..record.size()];
+                self.flash
+                    .read(self.page_offset(old) + record.offset, buf)?;
+                self.flash.write(self.page_offset(new) + end, buf)?;
+                end += record.size();
+            }
+        }
+        // written last, so that the new page is considered only when complete
+        self.write_page_header(new, self.seq.wrapping_add(1))?;
+        self.end = end;
+        self.clean = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ram::RamFlash;
+
+    type Ram = RamFlash<128, 2>;
+
+    fn read(store: &mut Store<Ram>, key: u8, version: u8) -> Option<Vec<u8>> {
+        let mut buf = [0u8; MAX_LEN];
+        store
+            .read(key, version, &mut buf)
+            .unwrap()
+            .map(|len| buf[..len].to_vec())
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut store = Store::new(Ram::default()).unwrap();
+        assert_eq!(read(&mut store, 0, 0), None);
+        let flash = store.release();
+        assert_eq!(flash.pages[0][..4], MAGIC);
+    }
+
+    #[test]
+    fn test_write_read_reopen() {
+        let mut store = Store::new(Ram::default()).unwrap();
+        store.write(1, 0, b"hello").unwrap();
+        store.write(2, 0, &[]).unwrap();
+        store.write(1, 0, b"world!").unwrap();
+        assert_eq!(read(&mut store, 1, 0), Some(b"world!".to_vec()));
+        assert_eq!(read(&mut store, 2, 0), Some(vec![]));
+        assert_eq!(read(&mut store, 3, 0), None);
+
+        let mut store = Store::new(store.release()).unwrap();
+        assert_eq!(read(&mut store, 1, 0), Some(b"world!".to_vec()));
+        assert_eq!(read(&mut store, 2, 0), Some(vec![]));
+    }
+
+    #[test]
+    fn test_version() {
+        let mut store = Store::new(Ram::default()).unwrap();
+        store.write(1, 1, b"v1").unwrap();
+        assert_eq!(read(&mut store, 1, 2), None);
+        assert_eq!(read(&mut store, 1, 1), Some(b"v1".to_vec()));
+        store.write(1, 2, b"v2").unwrap();
+        assert_eq!(read(&mut store, 1, 1), None);
+        assert_eq!(read(&mut store, 1, 2), Some(b"v2".to_vec()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut store = Store::new(Ram::default()).unwrap();
+        assert_eq!(store.write(0xFF, 0, b""), Err(Error::InvalidKey));
+        assert_eq!(store.write(0, 0, &[0; MAX_LEN + 1]), Err(Error::TooLong));
+        store.write(0, 0, b"abc").unwrap();
+        assert_eq!(store.read(0, 0, &mut [0u8; 2]), Err(Error::BufferTooSmall));
+        // a page of 128 bytes holds a single value of 64 bytes
+        store.write(1, 0, &[1; MAX_LEN]).unwrap();
+        assert_eq!(store.write(2, 0, &[2; MAX_LEN]), Err(Error::Full));
+    }
+
+    #[test]
+    fn test_unchanged_not_written() {
+        let mut store = Store::new(Ram::default()).unwrap();
+        store.write(1, 0, b"same").unwrap();
+        let end = store.end;
+        store.write(1, 0, b"same").unwrap();
+        assert_eq!(store.end, end);
+    }
+
+    #[test]
+    fn test_wear_levelling() {
+        let mut store = Store::new(RamFlash::<128, 4>::default()).unwrap();
+        for i in 0..1000u32 {
+            store.write(1, 0, &i.to_le_bytes()).unwrap();
+            store.write(2, 0, &(i * 2).to_le_bytes()).unwrap();
+        }
+        let mut buf = [0u8; 4];
+        assert_eq!(store.read(1, 0, &mut buf).unwrap(), Some(4));
+        assert_eq!(u32::from_le_bytes(buf), 999);
+        assert_eq!(store.read(2, 0, &mut buf).unwrap(), Some(4));
+        assert_eq!(u32::from_le_bytes(buf), 1998);
+
+        let flash = store.release();
+        let min = *flash.erases.iter().min().unwrap();
+        let max = *flash.erases.iter().max().unwrap();
+        assert!(min > 40, "{:?}", flash.erases);
+        assert!(max - min <= 1, "{:?}", flash.erases);
+    }
+
+    #[test]
+    fn test_power_loss() {
+        // interrupt the writes at every possible byte, the store must always return either the
+        // previous or the new value of the key being written, and the other keys intact
+        for lost_after in 0..300 {
+            let mut store = Store::new(Ram::default()).unwrap();
+            store.write(1, 0, b"other").unwrap();
+            for i in 0..10u8 {
+                store.write(2, 0, &[i; 20]).unwrap();
+            }
+            let mut flash = store.release();
+            flash.power_loss_after = Some(lost_after);
+            let mut store = Store::new(flash).unwrap();
+            let _ = store.write(2, 0, &[10; 20]);
+            for i in 11..20u8 {
+                let _ = store.write(2, 0, &[i; 20]);
+            }
+            let mut flash = store.release();
+            let written = flash.power_loss_after != Some(0);
+            flash.power_loss_after = None;
+
+            let mut store = Store::new(flash).unwrap();
+            assert_eq!(
+                read(&mut store, 1, 0),
+                Some(b"other".to_vec()),
+                "{}",
+                lost_after
+            );
+            let value = read(&mut store, 2, 0).unwrap();
+            assert!(value.iter().all(|b| *b == value[0]), "{}", lost_after);
+            if written {
+                assert_eq!(value, vec![19; 20]);
+            }
+
+            // the store keeps working after the power loss
+            store.write(2, 0, &[42; 20]).unwrap();
+            store.write(3, 0, b"new").unwrap();
+            assert_eq!(read(&mut store, 2, 0), Some(vec![42; 20]));
+            assert_eq!(read(&mut store, 3, 0), Some(b"new".to_vec()));
+            assert_eq!(read(&mut store, 1, 0), Some(b"other".to_vec()));
+        }
+    }
+}
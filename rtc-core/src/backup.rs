@@ -0,0 +1,109 @@
+//! backup
+//!
+//! This module sets the clock when the backup domain lost its power. A backup data register marks
+//! the clock as set, it's cleared with the rest of the backup domain when VBAT is lost too, and
+//! the RTC counter is then set to `INITIAL_TIME`
+//!
+
+use crate::DateTime;
+
+/// Time set when the backup domain lost its power
+pub const INITIAL_TIME: DateTime = match DateTime::new(2024, 1, 1, 0, 0, 0) {
+    Ok(time) => time,
+    Err(_) => panic!("invalid initial time"),
+};
+
+/// Backup data register marking the clock as set, the following ones are free for the firmware
+pub const CLOCK_SET_REGISTER: usize = 0;
+
+/// Value of `CLOCK_SET_REGISTER` once the clock has been set
+pub const CLOCK_SET: u16 = 0xC10C;
+
+/// Whether `CLOCK_SET_REGISTER`, reading `register`, marks the clock as set since the backup
+/// domain was powered
+pub fn is_set(register: u16) -> bool {
+    register == CLOCK_SET
+}
+
+/// `INITIAL_TIME` as seconds of the RTC counter
+pub fn initial_seconds() -> u32 {
+    // every valid DateTime fits the counter up to the end of MAX_YEAR
+    INITIAL_TIME.to_seconds().unwrap_or(u32::MAX)
+}
+
+/// Set the RTC `$rtc` to `INITIAL_TIME` and mark the clock as set in `$backup_domain`, unless it
+/// has been set since the backup domain was powered. Evaluates to `true` if the clock was set.
+///
+/// A macro rather than a function since the firmwares use different versions of
+/// `stm32f1xx-hal`, whose `Rtc::set_time` and `BackupDomain::read_data_register_low` and
+/// `write_data_register_low` have the same signatures.
+#[macro_export]
+macro_rules! set_clock_if_lost {
+    ($rtc:expr, $backup_domain:expr) => {{
+        let backup_domain = &mut $backup_domain;
+        let lost = !$crate::backup::is_set(
+            backup_domain.read_data_register_low($crate::backup::CLOCK_SET_REGISTER),
+        );
+        if lost {
+            $rtc.set_time($crate::backup::initial_seconds());
+            backup_domain.write_data_register_low(
+                $crate::backup::CLOCK_SET_REGISTER,
+                $crate::backup::CLOCK_SET,
+            );
+        }
+        lost
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rtc(u32);
+
+    impl Rtc {
+        fn set_time(&mut self, seconds: u32) {
+            self.0 = seconds;
+        }
+    }
+
+    struct BackupDomain([u16; 10]);
+
+    impl BackupDomain {
+        fn read_data_register_low(&self, register: usize) -> u16 {
+            self.0[register]
+        }
+
+        fn write_data_register_low(&mut self, register: usize, value: u16) {
+            self.0[register] = value;
+        }
+    }
+
+    #[test]
+    fn test_initial_seconds() {
+        assert_eq!(INITIAL_TIME.to_string(), "2024-01-01 00:00:00");
+        assert_eq!(DateTime::from_seconds(initial_seconds()), INITIAL_TIME);
+    }
+
+    #[test]
+    fn test_set_clock_if_lost() {
+        let mut rtc = Rtc(1_234);
+        let mut backup_domain = BackupDomain([0; 10]);
+        assert!(!is_set(backup_domain.0[CLOCK_SET_REGISTER]));
+
+        assert!(set_clock_if_lost!(rtc, backup_domain));
+        assert_eq!(rtc.0, initial_seconds());
+        assert!(is_set(backup_domain.0[CLOCK_SET_REGISTER]));
+        assert_eq!(backup_domain.0[CLOCK_SET_REGISTER + 1..], [0; 9]);
+
+        // the clock keeps counting across resets
+        rtc.0 += 60;
+        assert!(!set_clock_if_lost!(rtc, backup_domain));
+        assert_eq!(rtc.0, initial_seconds() + 60);
+
+        // VBAT lost too
+        backup_domain.0 = [0; 10];
+        assert!(set_clock_if_lost!(rtc, backup_domain));
+        assert_eq!(rtc.0, initial_seconds());
+    }
+}
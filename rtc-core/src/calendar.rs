@@ -0,0 +1,291 @@
+//! calendar
+//!
+//! This module converts the seconds counted by the RTC to calendar date and time and back. The
+//! counter holds the seconds since `EPOCH_YEAR`-01-01 00:00:00, so a `u32` lasts until 2136
+//!
+
+use core::fmt;
+
+/// Year of the counter 0
+pub const EPOCH_YEAR: u16 = 2000;
+
+/// Last year fully representable by a `u32` counter
+pub const MAX_YEAR: u16 = 2135;
+
+const SECONDS_PER_DAY: u32 = 86_400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A field is out of its range, like the month 13 or the 30th of February
+    InvalidField,
+    /// The year is before `EPOCH_YEAR` or after `MAX_YEAR`
+    OutOfRange,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// A date and time, without time zone: the RTC is set to the local time or to UTC at the user's
+/// choice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    year: u16,
+    /// 1 to 12
+    month: u8,
+    /// 1 to 31
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+pub const fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+/// Days in `month` of `year`, 0 if `month` is not 1 to 12
+pub const fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+const fn days_in_year(year: u16) -> u32 {
+    if is_leap_year(year) {
+        366
+    } else {
+        365
+    }
+}
+
+impl DateTime {
+    pub const fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, Error> {
+        if year < EPOCH_YEAR || year > MAX_YEAR {
+            return Err(Error::OutOfRange);
+        }
+        if month == 0
+            || month > 12
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return Err(Error::InvalidField);
+        }
+        Ok(DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// The date and time `seconds` after the epoch
+    pub fn from_seconds(seconds: u32) -> Self {
+        let mut days = seconds / SECONDS_PER_DAY;
+        let time = seconds % SECONDS_PER_DAY;
+
+        let mut year = EPOCH_YEAR;
+        while days >= days_in_year(year) {
+            days -= days_in_year(year);
+            year += 1;
+        }
+        let mut month = 1;
+        while days >= days_in_month(year, month) as u32 {
+            days -= days_in_month(year, month) as u32;
+            month += 1;
+        }
+        DateTime {
+            year,
+            month,
+            day: days as u8 + 1,
+            hour: (time / 3_600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    /// Seconds since the epoch, `None` after the end of the `u32` counter in 2136
+    pub fn to_seconds(&self) -> Option<u32> {
+        let days = self.days_since_epoch();
+        let time = self.hour as u32 * 3_600 + self.minute as u32 * 60 + self.second as u32;
+        days.checked_mul(SECONDS_PER_DAY)?.checked_add(time)
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        // the epoch was a Saturday
+        let days = self.days_since_epoch();
+        match (days + 5) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+
+    fn days_since_epoch(&self) -> u32 {
+        (EPOCH_YEAR..self.year).map(days_in_year).sum::<u32>()
+            + (1..self.month)
+                .map(|m| days_in_month(self.year, m) as u32)
+                .sum::<u32>()
+            + self.day as u32
+            - 1
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime::new(year, month, day, hour, minute, second).unwrap()
+    }
+
+    #[test]
+    fn test_leap_years() {
+        assert!(is_leap_year(2000));
+        assert!(is_leap_year(2024));
+        assert!(!is_leap_year(2023));
+        assert!(!is_leap_year(2100));
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2100, 2), 28);
+        assert_eq!(days_in_month(2023, 13), 0);
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(DateTime::new(2024, 2, 29, 0, 0, 0).is_ok());
+        assert_eq!(
+            DateTime::new(2023, 2, 29, 0, 0, 0),
+            Err(Error::InvalidField)
+        );
+        assert_eq!(
+            DateTime::new(2023, 4, 31, 0, 0, 0),
+            Err(Error::InvalidField)
+        );
+        assert_eq!(DateTime::new(2023, 0, 1, 0, 0, 0), Err(Error::InvalidField));
+        assert_eq!(
+            DateTime::new(2023, 1, 1, 24, 0, 0),
+            Err(Error::InvalidField)
+        );
+        assert_eq!(DateTime::new(1999, 1, 1, 0, 0, 0), Err(Error::OutOfRange));
+        assert_eq!(DateTime::new(2136, 1, 1, 0, 0, 0), Err(Error::OutOfRange));
+    }
+
+    #[test]
+    fn test_known_dates() {
+        assert_eq!(DateTime::from_seconds(0), date(2000, 1, 1, 0, 0, 0));
+        // 2000 is a leap year
+        assert_eq!(
+            DateTime::from_seconds(59 * 86_400),
+            date(2000, 2, 29, 0, 0, 0)
+        );
+        assert_eq!(
+            DateTime::from_seconds(60 * 86_400),
+            date(2000, 3, 1, 0, 0, 0)
+        );
+        // unix time 1709210096 minus the 946684800 seconds from 1970 to 2000
+        let leap_day = date(2024, 2, 29, 12, 34, 56);
+        assert_eq!(leap_day.to_seconds(), Some(1_709_210_096 - 946_684_800));
+        assert_eq!(leap_day.to_string(), "2024-02-29 12:34:56");
+        assert_eq!(
+            DateTime::from_seconds(u32::MAX).to_string(),
+            "2136-02-07 06:28:15"
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        // every day of the range, at a different time of the day
+        let mut seconds = 0u32;
+        let mut expected = date(2000, 1, 1, 0, 0, 0);
+        while let Some(next) = seconds.checked_add(86_400 + 7) {
+            let datetime = DateTime::from_seconds(seconds);
+            assert_eq!(datetime.to_seconds(), Some(seconds));
+            assert!(datetime >= expected);
+            expected = datetime;
+            seconds = next;
+        }
+        for seconds in [0, 1, 59, 60, 3_599, 3_600, 86_399, 86_400, u32::MAX] {
+            assert_eq!(DateTime::from_seconds(seconds).to_seconds(), Some(seconds));
+        }
+        let end = DateTime {
+            year: 2136,
+            month: 2,
+            day: 7,
+            hour: 6,
+            minute: 28,
+            second: 16,
+        };
+        assert_eq!(end.to_seconds(), None);
+    }
+
+    #[test]
+    fn test_weekday() {
+        assert_eq!(date(2000, 1, 1, 0, 0, 0).weekday(), Weekday::Saturday);
+        assert_eq!(date(2024, 2, 29, 0, 0, 0).weekday(), Weekday::Thursday);
+        assert_eq!(date(2100, 3, 1, 0, 0, 0).weekday(), Weekday::Monday);
+    }
+}
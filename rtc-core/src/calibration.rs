@@ -0,0 +1,140 @@
+//! calibration
+//!
+//! This module compensates the drift of the 32.768kHz crystal. The STM32F1 RTC can only slow down
+//! the clock, skipping `cal` pulses every 2^20 (BKP_RTCCR, about 0.954 ppm per step up to 121
+//! ppm), so the prescaler is chosen to make the tick a bit fast, 30.5 ppm per step, and then
+//! slowed down to the right rate
+//!
+
+/// Frequency of the LSE crystal
+pub const LSE_HZ: u32 = 32_768;
+
+/// Maximum value of the calibration register
+pub const MAX_CAL: u8 = 127;
+
+/// Maximum drift compensated, well beyond the tolerance of any watch crystal
+pub const MAX_DRIFT_PPM: i32 = 500;
+
+const CAL_PERIOD: i64 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The drift is beyond `MAX_DRIFT_PPM`
+    OutOfRange,
+}
+
+/// The values of the RTC registers giving one tick per second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    /// The RTC prescaler reload value, RTC_PRL, the crystal pulses in a tick are one more
+    pub prescaler: u32,
+    /// Pulses skipped every 2^20, BKP_RTCCR CAL
+    pub cal: u8,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            prescaler: LSE_HZ - 1,
+            cal: 0,
+        }
+    }
+}
+
+impl Calibration {
+    /// The calibration for a crystal running `ppm` parts per million fast, negative if it's slow.
+    /// A clock gaining 1 second a day is about 11.6 ppm fast
+    pub fn for_drift(ppm: i32) -> Result<Self, Error> {
+        if ppm.abs() > MAX_DRIFT_PPM {
+            return Err(Error::OutOfRange);
+        }
+        let crystal = LSE_HZ as i64 * (1_000_000 + ppm as i64);
+        // the largest divider with a tick a bit fast, less than a crystal pulse per second
+        let divider = crystal / 1_000_000;
+        // cal such that crystal / divider * (1 - cal / 2^20) = 1_000_000
+        let fast = crystal - divider * 1_000_000;
+        let cal = (fast * CAL_PERIOD + crystal / 2) / crystal;
+        Ok(Calibration {
+            prescaler: divider as u32 - 1,
+            cal: cal as u8,
+        })
+    }
+
+    /// The drift in tenths of ppm left after the calibration of a crystal `ppm` fast, due to the
+    /// resolution of the registers
+    pub fn residual(&self, ppm: i32) -> i64 {
+        let crystal = LSE_HZ as i64 * (1_000_000 + ppm as i64);
+        let divider = self.prescaler as i64 + 1;
+        // ticks per 10^7 seconds minus 10^7
+        let ticks = crystal * (CAL_PERIOD - self.cal as i64) * 10 / (divider * CAL_PERIOD);
+        ticks - 10_000_000
+    }
+}
+
+/// The drift in ppm of a clock that gained `gained_ms` milliseconds, negative if lost, in
+/// `elapsed` seconds
+pub fn drift_ppm(gained_ms: i32, elapsed: u32) -> i32 {
+    if elapsed == 0 {
+        return 0;
+    }
+    let ppm = gained_ms as i64 * 1_000 / elapsed as i64;
+    ppm.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact() {
+        assert_eq!(Calibration::for_drift(0), Ok(Calibration::default()));
+        assert_eq!(
+            Calibration::default(),
+            Calibration {
+                prescaler: 32_767,
+                cal: 0
+            }
+        );
+        let fast = Calibration::for_drift(10).unwrap();
+        assert_eq!(fast.prescaler, 32_767);
+        assert_eq!(fast.cal, 10);
+        // beyond the range of cal the prescaler grows
+        let faster = Calibration::for_drift(200).unwrap();
+        assert_eq!(faster.prescaler, 32_773);
+        // a slow crystal reduces the prescaler
+        let slow = Calibration::for_drift(-10).unwrap();
+        assert_eq!(slow.prescaler, 32_766);
+        assert_eq!(slow.cal, 22);
+        assert_eq!(
+            Calibration::for_drift(MAX_DRIFT_PPM + 1),
+            Err(Error::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_residual() {
+        // every drift is compensated within the resolution of a step of cal
+        for ppm in -MAX_DRIFT_PPM..=MAX_DRIFT_PPM {
+            let calibration = Calibration::for_drift(ppm).unwrap();
+            assert!(calibration.cal <= MAX_CAL);
+            let residual = calibration.residual(ppm);
+            assert!(
+                residual.abs() <= 5,
+                "{} {:?} {}",
+                ppm,
+                calibration,
+                residual
+            );
+        }
+    }
+
+    #[test]
+    fn test_drift_ppm() {
+        // 1 second a day
+        assert_eq!(drift_ppm(1_000, 86_400), 11);
+        assert_eq!(drift_ppm(-2_000, 86_400), -23);
+        assert_eq!(drift_ppm(1_000, 0), 0);
+    }
+}
@@ -0,0 +1,17 @@
+//! rtc-core
+//!
+//! Hardware independent logic of the real-time clock: the conversion between the seconds counted
+//! by the RTC and the calendar, the drift calibration, the wall-clock spans of the windows of a
+//! time series and the setting of the clock when the backup domain lost its power, testable on the
+//! host
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod backup;
+pub mod calendar;
+pub mod calibration;
+pub mod span;
+
+pub use calendar::DateTime;
+pub use span::Span;
@@ -0,0 +1,129 @@
+//! span
+//!
+//! This module labels the windows of a time series with the wall-clock time they cover, like
+//! "03:00-04:00" for the average of an hour. The windows are aligned to multiples of their interval
+//! since the epoch, a midnight, so windows of a minute or an hour start on the minute or the hour
+//!
+
+use crate::DateTime;
+use core::fmt;
+
+const MINUTE: u32 = 60;
+const DAY: u32 = 86_400;
+
+/// The window of `interval` seconds starting at `start` seconds since the epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Span {
+    pub start: u32,
+    pub interval: u32,
+}
+
+impl Span {
+    /// The window of `interval` seconds containing `seconds`
+    pub fn containing(seconds: u32, interval: u32) -> Self {
+        let interval = interval.max(1);
+        Span {
+            start: seconds / interval * interval,
+            interval,
+        }
+    }
+
+    pub fn start(&self) -> DateTime {
+        DateTime::from_seconds(self.start)
+    }
+
+    /// The first second after the window
+    pub fn end(&self) -> DateTime {
+        DateTime::from_seconds(self.start.saturating_add(self.interval))
+    }
+}
+
+/// Like "12:34:56" for a second, "12:30:00-12:30:10" for windows of seconds, "03:00-04:00" for
+/// windows of minutes and the date for a day
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, end) = (self.start(), self.end());
+        let aligned = |multiple: u32| {
+            self.interval.is_multiple_of(multiple) && self.start.is_multiple_of(multiple)
+        };
+        if self.interval == DAY && aligned(DAY) {
+            write!(
+                f,
+                "{}-{:02}-{:02}",
+                start.year(),
+                start.month(),
+                start.day()
+            )
+        } else if aligned(MINUTE) {
+            write!(
+                f,
+                "{:02}:{:02}-{:02}:{:02}",
+                start.hour(),
+                start.minute(),
+                end.hour(),
+                end.minute()
+            )
+        } else {
+            write!(
+                f,
+                "{:02}:{:02}:{:02}",
+                start.hour(),
+                start.minute(),
+                start.second()
+            )?;
+            if self.interval > 1 {
+                write!(
+                    f,
+                    "-{:02}:{:02}:{:02}",
+                    end.hour(),
+                    end.minute(),
+                    end.second()
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_containing() {
+        let three = DateTime::new(2024, 2, 29, 3, 0, 0)
+            .unwrap()
+            .to_seconds()
+            .unwrap();
+        let span = Span::containing(three + 1_234, 3_600);
+        assert_eq!(span.start, three);
+        assert_eq!(span.start().to_string(), "2024-02-29 03:00:00");
+        assert_eq!(span.end().to_string(), "2024-02-29 04:00:00");
+        assert_eq!(Span::containing(three + 59, 60).start, three);
+        assert_eq!(Span::containing(three + 60, 60).start, three + 60);
+        assert_eq!(
+            Span::containing(7, 0),
+            Span {
+                start: 7,
+                interval: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let midnight = DateTime::new(2024, 2, 29, 0, 0, 0)
+            .unwrap()
+            .to_seconds()
+            .unwrap();
+        let three = midnight + 3 * 3_600;
+        let label = |seconds, interval| Span::containing(seconds, interval).to_string();
+        assert_eq!(label(three + 1_234, 3_600), "03:00-04:00");
+        assert_eq!(label(three + 1_234, 60), "03:20-03:21");
+        assert_eq!(label(three + 1_234, 10), "03:20:30-03:20:40");
+        assert_eq!(label(three + 1_234, 1), "03:20:34");
+        assert_eq!(label(midnight - 1, 3_600), "23:00-00:00");
+        assert_eq!(label(three, 86_400), "2024-02-29");
+    }
+}
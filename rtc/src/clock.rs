@@ -0,0 +1,69 @@
+//! clock
+//!
+//! This module keeps the calendar date and time in the backup domain: the RTC counts the seconds
+//! from the LSE crystal, and with a battery on VBAT it keeps counting across resets and with the
+//! main supply off. The backup data register `rtc_core::backup::CLOCK_SET_REGISTER` marks the
+//! clock as set, it's cleared with the rest of the backup domain when VBAT is lost too
+//!
+
+use rtc_core::calibration::Calibration;
+use rtc_core::DateTime;
+use stm32f1xx_hal::backup_domain::BackupDomain;
+use stm32f1xx_hal::pac;
+use stm32f1xx_hal::rtc::Rtc;
+
+pub struct Clock {
+    rtc: Rtc,
+    backup_domain: BackupDomain,
+}
+
+impl Clock {
+    /// Start the RTC on the LSE with the `calibration` of the crystal, the time is kept if it was
+    /// already running
+    pub fn new(rtc: pac::RTC, mut backup_domain: BackupDomain, calibration: Calibration) -> Self {
+        let rtc = Rtc::rtc(rtc, &mut backup_domain);
+        let mut clock = Clock { rtc, backup_domain };
+        clock.set_calibration(calibration);
+        clock
+    }
+
+    /// Set the time to `rtc_core::backup::INITIAL_TIME` unless it has been set since the backup
+    /// domain was powered, returns whether it was set
+    pub fn set_if_lost(&mut self) -> bool {
+        rtc_core::set_clock_if_lost!(self.rtc, self.backup_domain)
+    }
+
+    pub fn now(&self) -> DateTime {
+        DateTime::from_seconds(self.rtc.current_time())
+    }
+
+    /// Fire the alarm interrupt at `at`
+    pub fn set_alarm(&mut self, at: DateTime) {
+        self.rtc.set_alarm(at.to_seconds().unwrap_or(u32::MAX));
+    }
+
+    /// Write the prescaler and the calibration register, the prescaler is reloaded at the next
+    /// second so the current one isn't cut short
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        // SAFETY: the RTC and BKP registers are owned by `rtc` and `backup_domain`, the writes are
+        // enabled by `BackupDomain`
+        unsafe {
+            let rtc = &*pac::RTC::ptr();
+            while rtc.crl.read().rtoff().bit_is_clear() {}
+            rtc.crl.modify(|_, w| w.cnf().set_bit());
+            rtc.prlh
+                .write(|w| w.bits((calibration.prescaler >> 16) & 0xf));
+            rtc.prll.write(|w| w.bits(calibration.prescaler & 0xffff));
+            rtc.crl.modify(|_, w| w.cnf().clear_bit());
+            while rtc.crl.read().rtoff().bit_is_clear() {}
+
+            let bkp = &*pac::BKP::ptr();
+            bkp.rtccr.modify(|_, w| w.cal().bits(calibration.cal));
+        }
+    }
+
+    /// The RTC, to enable and clear its interrupts
+    pub fn rtc(&mut self) -> &mut Rtc {
+        &mut self.rtc
+    }
+}
@@ -1,4 +1,13 @@
-//! Uses the timer interrupt to blink a led with different frequencies.
+//! Keeps the calendar date and time in the RTC of the backup domain, printing it every second over
+//! semihosting with the `semihosting` feature and blinking the led at the start of every minute
+//! with the RTC alarm.
+//!
+//! The led shows a heartbeat, three blinks for a while after the clock has been reset to
+//! `INITIAL_TIME` and a blink every minute, the patterns are played by `e_blink::Sequencer` on a
+//! 100Hz timer.
+//!
+//! The RTC counts from the 32.768kHz LSE crystal: with a coin cell on VBAT the time survives resets
+//! and power losses, it's set to `INITIAL_TIME` only when the backup domain lost its power too.
 //!
 //! This assumes that a LED is connected to pc13 as is the case on the blue pill board.
 //!
@@ -8,12 +17,18 @@
 #![no_std]
 #![no_main]
 
+mod clock;
+
 // you can put a breakpoint on `rust_begin_unwind` to catch panics
 use panic_halt as _;
 
 use rtic::app;
 
-use embedded_hal::digital::v2::OutputPin;
+use crate::clock::Clock;
+use e_blink::{Pattern, Priority, Repeat, Sequencer};
+use rtc_core::backup::INITIAL_TIME;
+use rtc_core::calibration::Calibration;
+use rtc_core::DateTime;
 use stm32f1xx_hal::{
     gpio::{gpioc::PC13, Output, PushPull, State},
     pac,
@@ -21,52 +36,88 @@ use stm32f1xx_hal::{
     timer::{CountDownTimer, Event, Timer},
 };
 
+#[cfg(feature = "semihosting")]
+macro_rules! hprintln {
+    ($s:expr, $($tt:tt)*) => {
+        cortex_m_semihosting::export::hstdout_fmt(format_args!(concat!($s, "\n"), $($tt)*)).unwrap()
+    };
+}
+#[cfg(not(feature = "semihosting"))]
+macro_rules! hprintln {
+    ($s:expr, $($tt:tt)*) => {};
+}
+
+/// Measured drift of the crystal in ppm, positive if the clock gains time. Compare the clock with
+/// a reference after some days and use `rtc_core::calibration::drift_ppm`
+const DRIFT_PPM: i32 = 0;
+
+/// EXTI line of the RTC alarm
+const RTC_ALARM_LINE: u32 = 1 << 17;
+
+/// Milliseconds between the updates of the led
+const BLINK_TICK_MS: u32 = 10;
+
 #[app(device = stm32f1xx_hal::pac, peripherals = true)]
 const APP: () = {
     struct Resources {
         led: PC13<Output<PushPull>>,
-        timer_handler: CountDownTimer<pac::TIM1>,
-
-        #[init(false)]
-        led_state: bool,
+        clock: Clock,
+        timer: CountDownTimer<pac::TIM2>,
+        blink: Sequencer<4>,
+        #[init(0)]
+        millis: u32,
     }
 
     #[init]
     fn init(cx: init::Context) -> init::LateResources {
-        // Take ownership over the raw flash and rcc devices and convert them into the corresponding
-        // HAL structs
         let mut flash = cx.device.FLASH.constrain();
         let mut rcc = cx.device.RCC.constrain();
-
-        // Freeze the configuration of all the clocks in the system and store the frozen frequencies
-        // in `clocks`
         let clocks = rcc.cfgr.freeze(&mut flash.acr);
 
-        // Acquire the GPIOC peripheral
         let mut gpioc = cx.device.GPIOC.split(&mut rcc.apb2);
-
-        // Configure gpio C pin 13 as a push-pull output. The `crh` register is passed to the
-        // function in order to configure the port. For pins 0-7, crl should be passed instead
         let led = gpioc
             .pc13
             .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
-        // Configure the syst timer to trigger an update every second and enables interrupt
-        let mut timer =
-            Timer::tim1(cx.device.TIM1, &clocks, &mut rcc.apb2).start_count_down(1.hz());
+
+        // Enables the writes to the backup domain
+        let mut pwr = cx.device.PWR;
+        let backup_domain = rcc.bkp.constrain(cx.device.BKP, &mut rcc.apb1, &mut pwr);
+        let calibration = Calibration::for_drift(DRIFT_PPM).unwrap_or_default();
+        let mut clock = Clock::new(cx.device.RTC, backup_domain, calibration);
+        let mut blink = Sequencer::new();
+        blink
+            .play(Pattern::Heartbeat, Priority::Low, Repeat::Forever)
+            .unwrap();
+        if clock.set_if_lost() {
+            hprintln!("backup domain reset, set {}", INITIAL_TIME);
+            blink
+                .play(Pattern::Blinks(3), Priority::Normal, Repeat::Times(5))
+                .unwrap();
+        }
+
+        let now = clock.now();
+        clock.set_alarm(next_minute(now));
+        clock.rtc().listen_alarm();
+        clock.rtc().listen_seconds();
+        // The alarm reaches the RTCALARM interrupt through the EXTI
+        let exti = &cx.device.EXTI;
+        exti.imr
+            .modify(|r, w| unsafe { w.bits(r.bits() | RTC_ALARM_LINE) });
+        exti.rtsr
+            .modify(|r, w| unsafe { w.bits(r.bits() | RTC_ALARM_LINE) });
+
+        let mut timer = Timer::tim2(cx.device.TIM2, &clocks, &mut rcc.apb1)
+            .start_count_down((1_000 / BLINK_TICK_MS).hz());
         timer.listen(Event::Update);
 
-        // Init the static resources to use them later through RTIC
         init::LateResources {
             led,
-            timer_handler: timer,
+            clock,
+            timer,
+            blink,
         }
     }
 
-    // Optional.
-    //
-    // https://rtic.rs/0.5/book/en/by-example/app.html#idle
-    // > When no idle function is declared, the runtime sets the SLEEPONEXIT bit and then
-    // > sends the microcontroller to sleep after running init.
     #[idle]
     fn idle(_cx: idle::Context) -> ! {
         loop {
@@ -74,34 +125,46 @@ const APP: () = {
         }
     }
 
-    #[task(binds = TIM1_UP, priority = 1, resources = [led, timer_handler, led_state])]
-    fn tick(cx: tick::Context) {
-        // Depending on the application, you could want to delegate some of the work done here to
-        // the idle task if you want to minimize the latency of interrupts with same priority (if
-        // you have any). That could be done with some kind of machine state, etc.
+    #[task(binds = RTC, priority = 1, resources = [clock])]
+    fn second(cx: second::Context) {
+        #[cfg(feature = "semihosting")]
+        {
+            let now = cx.resources.clock.now();
+            hprintln!("{} {:?}", now, now.weekday());
+        }
+        cx.resources.clock.rtc().clear_second_flag();
+    }
 
-        // Count used to change the timer update frequency
-        static mut COUNT: u8 = 0;
+    #[task(binds = RTCALARM, priority = 1, resources = [clock, blink])]
+    fn alarm(cx: alarm::Context) {
+        // the heartbeat resumes after the blink, a full queue only misses a minute
+        let _ = cx
+            .resources
+            .blink
+            .play(Pattern::Blinks(1), Priority::High, Repeat::Times(1));
+        let clock = cx.resources.clock;
+        let now = clock.now();
+        clock.set_alarm(next_minute(now));
+        clock.rtc().clear_alarm_flag();
+        unsafe { (*pac::EXTI::ptr()).pr.write(|w| w.bits(RTC_ALARM_LINE)) };
+    }
 
-        if *cx.resources.led_state {
-            // Uses resources managed by rtic to turn led off (on bluepill)
-            cx.resources.led.set_high().unwrap();
-            *cx.resources.led_state = false;
-        } else {
+    #[task(binds = TIM2, priority = 1, resources = [timer, blink, led, millis])]
+    fn tick(cx: tick::Context) {
+        let millis = cx.resources.millis;
+        *millis = millis.wrapping_add(BLINK_TICK_MS);
+        // the led on PC13 lights up when the pin is low
+        if cx.resources.blink.update(*millis) {
             cx.resources.led.set_low().unwrap();
-            *cx.resources.led_state = true;
-        }
-        *COUNT += 1;
-
-        if *COUNT == 4 {
-            // Changes timer update frequency
-            cx.resources.timer_handler.start(2.hz());
-        } else if *COUNT == 12 {
-            cx.resources.timer_handler.start(1.hz());
-            *COUNT = 0;
+        } else {
+            cx.resources.led.set_high().unwrap();
         }
-
-        // Clears the update flag
-        cx.resources.timer_handler.clear_update_interrupt_flag();
+        cx.resources.timer.clear_update_interrupt_flag();
     }
-};
\ No newline at end of file
+};
+
+/// The start of the minute after `now`
+fn next_minute(now: DateTime) -> DateTime {
+    let seconds = now.to_seconds().unwrap_or(u32::MAX);
+    DateTime::from_seconds((seconds / 60).saturating_add(1).saturating_mul(60))
+}
@@ -0,0 +1,201 @@
+//! battery
+//!
+//! This module converts the ADC readings of the battery to millivolts and estimates the state of
+//! charge from the discharge curve of the chemistry.
+//!
+//! The supply of the ADC isn't known precisely, so it's measured reading the internal reference
+//! VREFINT, and the battery is read through a resistor divider to stay below the supply
+//!
+
+/// Typical voltage of VREFINT on the STM32F1, it has no factory calibration
+pub const VREFINT_MV: u32 = 1_200;
+
+/// Reading of the 12 bits ADC at the supply voltage
+pub const ADC_MAX: u32 = 4_095;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chemistry {
+    LiIon,
+    NiMh,
+    Alkaline,
+}
+
+impl Chemistry {
+    /// Millivolts of a cell and the state of charge in percent, from full to empty
+    fn curve(&self) -> &'static [(u16, u8)] {
+        match self {
+            Chemistry::LiIon => &[
+                (4_200, 100),
+                (4_060, 90),
+                (3_980, 80),
+                (3_920, 70),
+                (3_870, 60),
+                (3_820, 50),
+                (3_790, 40),
+                (3_770, 30),
+                (3_740, 20),
+                (3_680, 10),
+                (3_450, 5),
+                (3_000, 0),
+            ],
+            Chemistry::NiMh => &[
+                (1_400, 100),
+                (1_300, 90),
+                (1_270, 80),
+                (1_250, 70),
+                (1_230, 60),
+                (1_220, 50),
+                (1_200, 40),
+                (1_180, 30),
+                (1_160, 20),
+                (1_130, 10),
+                (1_000, 0),
+            ],
+            Chemistry::Alkaline => &[
+                (1_600, 100),
+                (1_500, 80),
+                (1_400, 60),
+                (1_300, 40),
+                (1_200, 20),
+                (1_100, 10),
+                (900, 0),
+            ],
+        }
+    }
+}
+
+/// The battery and how it's connected to the ADC pin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub chemistry: Chemistry,
+    /// Cells in series
+    pub cells: u8,
+    /// Ohms of the divider resistor between the battery and the pin, 0 if there is no divider
+    pub divider_top: u32,
+    /// Ohms of the divider resistor between the pin and ground
+    pub divider_bottom: u32,
+    /// Percent at or below which the charge is low
+    pub low: u8,
+}
+
+impl Config {
+    /// Millivolts of the battery given the `raw` reading of the pin and the `vrefint` reading of
+    /// the internal reference, `None` if `vrefint` is 0 which happens only if the ADC is broken
+    pub fn millivolts(&self, raw: u16, vrefint: u16) -> Option<u16> {
+        if vrefint == 0 {
+            return None;
+        }
+        // raw / ADC_MAX * vdda where vdda = VREFINT_MV * ADC_MAX / vrefint
+        let pin = raw as u64 * VREFINT_MV as u64 / vrefint as u64;
+        let bottom = self.divider_bottom.max(1) as u64;
+        let battery = pin * (self.divider_top as u64 + bottom) / bottom;
+        Some(battery.min(u16::MAX as u64) as u16)
+    }
+
+    /// State of charge in percent interpolating the discharge curve
+    pub fn percent(&self, millivolts: u16) -> u8 {
+        let cell = millivolts / self.cells.max(1) as u16;
+        let curve = self.chemistry.curve();
+        let (full, empty) = (curve[0], curve[curve.len() - 1]);
+        if cell >= full.0 {
+            return full.1;
+        }
+        if cell <= empty.0 {
+            return empty.1;
+        }
+        let upper = curve.windows(2).find(|w| cell >= w[1].0).unwrap();
+        let ((high_mv, high), (low_mv, low)) = (upper[0], upper[1]);
+        let percent =
+            low as u32 + (cell - low_mv) as u32 * (high - low) as u32 / (high_mv - low_mv) as u32;
+        percent as u8
+    }
+
+    pub fn is_low(&self, millivolts: u16) -> bool {
+        self.percent(millivolts) <= self.low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIION: Config = Config {
+        chemistry: Chemistry::LiIon,
+        cells: 1,
+        divider_top: 100_000,
+        divider_bottom: 100_000,
+        low: 20,
+    };
+
+    #[test]
+    fn test_millivolts() {
+        // 3.3V supply, VREFINT reads 1200 / 3300 * 4095
+        let vrefint = 1_489;
+        // half of 3.7V on the pin
+        let raw = (1_850 * 4_095 / 3_300) as u16;
+        let mv = LIION.millivolts(raw, vrefint).unwrap();
+        assert!((3_695..=3_705).contains(&mv), "{}", mv);
+
+        // same pin voltage with a 3.0V supply
+        let vrefint = 1_638;
+        let raw = (1_850 * 4_095 / 3_000) as u16;
+        let mv = LIION.millivolts(raw, vrefint).unwrap();
+        assert!((3_695..=3_705).contains(&mv), "{}", mv);
+
+        let direct = Config {
+            divider_top: 0,
+            ..LIION
+        };
+        assert_eq!(direct.millivolts(4_095, 4_095), Some(1_200));
+        assert_eq!(direct.millivolts(4_095, 0), None);
+        // saturates instead of wrapping
+        let high = Config {
+            divider_top: 1_000_000,
+            divider_bottom: 1,
+            ..LIION
+        };
+        assert_eq!(high.millivolts(4_095, 1), Some(u16::MAX));
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(LIION.percent(4_300), 100);
+        assert_eq!(LIION.percent(4_200), 100);
+        assert_eq!(LIION.percent(3_820), 50);
+        assert_eq!(LIION.percent(3_845), 55);
+        assert_eq!(LIION.percent(3_000), 0);
+        assert_eq!(LIION.percent(0), 0);
+        assert!(LIION.is_low(3_740));
+        assert!(!LIION.is_low(3_760));
+
+        let nimh = Config {
+            chemistry: Chemistry::NiMh,
+            cells: 4,
+            ..LIION
+        };
+        assert_eq!(nimh.percent(4 * 1_220), 50);
+        assert_eq!(nimh.percent(4 * 1_400), 100);
+
+        let alkaline = Config {
+            chemistry: Chemistry::Alkaline,
+            cells: 2,
+            ..LIION
+        };
+        assert_eq!(alkaline.percent(2 * 1_450), 70);
+        assert_eq!(alkaline.percent(2 * 900), 0);
+    }
+
+    #[test]
+    fn test_percent_monotonic() {
+        for chemistry in [Chemistry::LiIon, Chemistry::NiMh, Chemistry::Alkaline] {
+            let config = Config { chemistry, ..LIION };
+            let mut last = 0;
+            for mv in 0..5_000 {
+                let percent = config.percent(mv);
+                assert!(percent >= last && percent <= 100);
+                last = percent;
+            }
+            assert_eq!(last, 100);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! health
+//!
+//! This module tracks the health of a sensor which may fail to read, like the DHT22: consecutive
+//! failures, age of the last good reading and errors by kind. After a failure the next read is
+//! retried with an exponential backoff, so a sensor unplugged doesn't keep the cpu busy
+//!
+
+/// Minimum seconds between reads, the DHT22 sampling period is 2 seconds
+pub const MIN_INTERVAL: u32 = 2;
+
+/// Maximum seconds between retries of a failing sensor
+pub const MAX_BACKOFF: u32 = 64;
+
+/// Consecutive failures after which the sensor is considered faulty
+pub const FAULT_AFTER: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The sensor didn't answer in time
+    Timeout,
+    /// The data received is corrupted
+    Checksum,
+    /// The pin couldn't be driven or read
+    Pin,
+}
+
+/// Errors since boot by kind
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCounts {
+    pub timeout: u32,
+    pub checksum: u32,
+    pub pin: u32,
+}
+
+impl ErrorCounts {
+    pub fn total(&self) -> u32 {
+        self.timeout
+            .saturating_add(self.checksum)
+            .saturating_add(self.pin)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    consecutive_failures: u32,
+    /// Seconds of the last good reading
+    last_good: Option<u32>,
+    errors: ErrorCounts,
+    /// Seconds of the next read
+    next_read: u32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    pub const fn new() -> Self {
+        Health {
+            consecutive_failures: 0,
+            last_good: None,
+            errors: ErrorCounts {
+                timeout: 0,
+                checksum: 0,
+                pin: 0,
+            },
+            next_read: 0,
+        }
+    }
+
+    /// Whether the sensor should be read at `now` seconds
+    pub fn should_read(&self, now: u32) -> bool {
+        now >= self.next_read
+    }
+
+    /// Record a good reading at `now` seconds
+    pub fn success(&mut self, now: u32) {
+        self.consecutive_failures = 0;
+        self.last_good = Some(now);
+        self.next_read = now.saturating_add(MIN_INTERVAL);
+    }
+
+    /// Record a failed read at `now` seconds, the next read is delayed doubling the interval at
+    /// every consecutive failure up to `MAX_BACKOFF`
+    pub fn failure(&mut self, kind: ErrorKind, now: u32) {
+        let count = match kind {
+            ErrorKind::Timeout => &mut self.errors.timeout,
+            ErrorKind::Checksum => &mut self.errors.checksum,
+            ErrorKind::Pin => &mut self.errors.pin,
+        };
+        *count = count.saturating_add(1);
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        // MAX_BACKOFF is reached well before the shift overflows
+        let shift = (self.consecutive_failures - 1).min(16);
+        let backoff = (MIN_INTERVAL << shift).min(MAX_BACKOFF);
+        self.next_read = now.saturating_add(backoff);
+    }
+
+    /// The sensor failed too many times in a row, its last reading shouldn't be trusted
+    pub fn is_faulty(&self) -> bool {
+        self.consecutive_failures >= FAULT_AFTER
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Seconds since the last good reading, `None` if the sensor never worked
+    pub fn age(&self, now: u32) -> Option<u32> {
+        self.last_good.map(|last| now.saturating_sub(last))
+    }
+
+    pub fn errors(&self) -> &ErrorCounts {
+        &self.errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_success() {
+        let mut health = Health::new();
+        assert!(health.should_read(0));
+        assert_eq!(health.age(0), None);
+        health.success(1);
+        assert!(!health.should_read(2));
+        assert!(health.should_read(3));
+        assert_eq!(health.age(10), Some(9));
+        assert!(!health.is_faulty());
+        assert_eq!(health.errors().total(), 0);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut health = Health::new();
+        let mut now = 10;
+        let mut intervals = vec![];
+        for _ in 0..8 {
+            health.failure(ErrorKind::Timeout, now);
+            let next = (now..).find(|s| health.should_read(*s)).unwrap();
+            intervals.push(next - now);
+            now = next;
+        }
+        assert_eq!(intervals, vec![2, 4, 8, 16, 32, 64, 64, 64]);
+        assert_eq!(health.consecutive_failures(), 8);
+
+        // a good reading restores the normal interval
+        health.success(now);
+        assert!(health.should_read(now + MIN_INTERVAL));
+        assert_eq!(health.consecutive_failures(), 0);
+
+        // no overflow after a long time failing
+        for _ in 0..100 {
+            health.failure(ErrorKind::Pin, u32::MAX - 1);
+        }
+        assert!(!health.should_read(u32::MAX - 1));
+        assert!(health.should_read(u32::MAX));
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut health = Health::new();
+        health.success(0);
+        health.failure(ErrorKind::Checksum, 2);
+        health.failure(ErrorKind::Timeout, 4);
+        assert!(!health.is_faulty());
+        health.failure(ErrorKind::Checksum, 8);
+        assert!(health.is_faulty());
+        assert_eq!(
+            health.errors(),
+            &ErrorCounts {
+                timeout: 1,
+                checksum: 2,
+                pin: 0
+            }
+        );
+        assert_eq!(health.errors().total(), 3);
+        assert_eq!(health.age(8), Some(8));
+
+        health.success(16);
+        assert!(!health.is_faulty());
+        // the counts are kept
+        assert_eq!(health.errors().total(), 3);
+    }
+}
@@ -0,0 +1,14 @@
+//! thirsty-core
+//!
+//! Hardware independent logic of the `thirsty` plant monitor, see README.md
+//!
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod battery;
+pub mod health;
+pub mod moisture;
+pub mod power;
+pub mod pump;
+
+pub use health::{ErrorKind, Health};
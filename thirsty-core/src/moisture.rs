@@ -0,0 +1,234 @@
+//! moisture
+//!
+//! This module converts the readings of the soil moisture sensor to percent through a two point
+//! calibration, captured with a button sequence, and decides when the plant is thirsty
+//!
+
+/// Minimum difference between the dry and the wet readings, closer points are likely a mistake
+pub const MIN_SPAN: u16 = 200;
+
+/// The readings of the sensor in dry air and in water. Capacitive sensors read lower when wet,
+/// resistive ones higher, both work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    pub dry: u16,
+    pub wet: u16,
+}
+
+/// Typical readings of a capacitive sensor with the 12 bits ADC at 3.3V, used until calibrated
+pub const DEFAULT_CALIBRATION: Calibration = Calibration {
+    dry: 3_000,
+    wet: 1_400,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The dry and the wet readings are closer than `MIN_SPAN`
+    TooClose,
+}
+
+impl Calibration {
+    pub fn new(dry: u16, wet: u16) -> Result<Self, Error> {
+        if dry.abs_diff(wet) < MIN_SPAN {
+            return Err(Error::TooClose);
+        }
+        Ok(Calibration { dry, wet })
+    }
+
+    /// Moisture in percent of the `raw` reading, 0 is dry and 100 is wet, clamped outside the
+    /// calibration points
+    pub fn percent(&self, raw: u16) -> u8 {
+        let (dry, wet, raw) = (self.dry as i32, self.wet as i32, raw as i32);
+        if dry == wet {
+            return 0;
+        }
+        let percent = (raw - dry) * 100 / (wet - dry);
+        percent.clamp(0, 100) as u8
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let mut bytes = [0u8; 4];
+        bytes[..2].copy_from_slice(&self.dry.to_le_bytes());
+        bytes[2..].copy_from_slice(&self.wet.to_le_bytes());
+        bytes
+    }
+
+    /// The calibration saved with `to_bytes`, `None` if the bytes are not a valid calibration
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; 4] = bytes.try_into().ok()?;
+        let dry = u16::from_le_bytes([bytes[0], bytes[1]]);
+        let wet = u16::from_le_bytes([bytes[2], bytes[3]]);
+        Calibration::new(dry, wet).ok()
+    }
+}
+
+/// The steps of the calibration: the sensor is put in dry air and a reading captured, then in
+/// water and another reading captured
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Wizard {
+    #[default]
+    Idle,
+    Dry,
+    Wet {
+        dry: u16,
+    },
+}
+
+impl Wizard {
+    pub fn start(&mut self) {
+        *self = Wizard::Dry;
+    }
+
+    pub fn cancel(&mut self) {
+        *self = Wizard::Idle;
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self != Wizard::Idle
+    }
+
+    /// Capture the `raw` reading for the current step. Returns the calibration after the wet
+    /// reading, the wizard is idle again in any case
+    pub fn capture(&mut self, raw: u16) -> Option<Result<Calibration, Error>> {
+        match *self {
+            Wizard::Idle => None,
+            Wizard::Dry => {
+                *self = Wizard::Wet { dry: raw };
+                None
+            }
+            Wizard::Wet { dry } => {
+                *self = Wizard::Idle;
+                Some(Calibration::new(dry, raw))
+            }
+        }
+    }
+}
+
+/// When the plant needs water
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    /// Percent below which the plant becomes thirsty
+    pub thirsty: u8,
+    /// Percent above `thirsty` the moisture has to reach to quench the thirst, so that readings
+    /// oscillating around the threshold don't toggle the state
+    pub hysteresis: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thirst {
+    thresholds: Thresholds,
+    thirsty: bool,
+}
+
+impl Thirst {
+    pub const fn new(thresholds: Thresholds) -> Self {
+        Thirst {
+            thresholds,
+            thirsty: false,
+        }
+    }
+
+    /// Update the state with the moisture `percent`, returns whether the plant is thirsty
+    pub fn update(&mut self, percent: u8) -> bool {
+        let Thresholds {
+            thirsty,
+            hysteresis,
+        } = self.thresholds;
+        if percent < thirsty {
+            self.thirsty = true;
+        } else if percent >= thirsty.saturating_add(hysteresis).min(100) {
+            self.thirsty = false;
+        }
+        self.thirsty
+    }
+
+    pub fn is_thirsty(&self) -> bool {
+        self.thirsty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent() {
+        let capacitive = Calibration::new(3_000, 1_400).unwrap();
+        assert_eq!(capacitive.percent(3_000), 0);
+        assert_eq!(capacitive.percent(3_500), 0);
+        assert_eq!(capacitive.percent(2_200), 50);
+        assert_eq!(capacitive.percent(1_400), 100);
+        assert_eq!(capacitive.percent(0), 100);
+
+        let resistive = Calibration::new(100, 3_100).unwrap();
+        assert_eq!(resistive.percent(0), 0);
+        assert_eq!(resistive.percent(1_600), 50);
+        assert_eq!(resistive.percent(4_095), 100);
+
+        assert_eq!(Calibration::new(1_000, 1_199), Err(Error::TooClose));
+        assert_eq!(Calibration::new(1_199, 1_000), Err(Error::TooClose));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let calibration = Calibration::new(3_000, 1_400).unwrap();
+        let bytes = calibration.to_bytes();
+        assert_eq!(Calibration::from_bytes(&bytes), Some(calibration));
+        assert_eq!(Calibration::from_bytes(&bytes[..3]), None);
+        assert_eq!(Calibration::from_bytes(&[0, 1, 0, 1]), None);
+    }
+
+    #[test]
+    fn test_wizard() {
+        let mut wizard = Wizard::default();
+        assert_eq!(wizard.capture(3_000), None);
+        assert!(!wizard.is_active());
+
+        wizard.start();
+        assert_eq!(wizard.capture(3_000), None);
+        assert_eq!(wizard, Wizard::Wet { dry: 3_000 });
+        assert_eq!(
+            wizard.capture(1_400),
+            Some(Ok(Calibration {
+                dry: 3_000,
+                wet: 1_400
+            }))
+        );
+        assert!(!wizard.is_active());
+
+        wizard.start();
+        wizard.capture(3_000);
+        assert_eq!(wizard.capture(2_900), Some(Err(Error::TooClose)));
+        assert!(!wizard.is_active());
+
+        wizard.start();
+        wizard.cancel();
+        assert_eq!(wizard.capture(3_000), None);
+    }
+
+    #[test]
+    fn test_thirst() {
+        let mut thirst = Thirst::new(Thresholds {
+            thirsty: 30,
+            hysteresis: 5,
+        });
+        let states: Vec<bool> = [40, 31, 30, 29, 30, 33, 34, 35, 31, 29]
+            .iter()
+            .map(|p| thirst.update(*p))
+            .collect();
+        assert_eq!(
+            states,
+            [false, false, false, true, true, true, true, false, false, true]
+        );
+        assert!(thirst.is_thirsty());
+
+        // no overflow with a large hysteresis, the thirst is quenched by a full 100%
+        let mut thirst = Thirst::new(Thresholds {
+            thirsty: 50,
+            hysteresis: 250,
+        });
+        assert!(thirst.update(10));
+        assert!(thirst.update(99));
+        assert!(!thirst.update(100));
+    }
+}
@@ -0,0 +1,138 @@
+//! power
+//!
+//! This module decides when the plant monitor wakes up and how deep it sleeps: the sensors are
+//! sampled every `sample_interval` seconds and the display turns off after `display_timeout`
+//! seconds without pressing a button. With the display off and nothing to do the MCU can enter
+//! STOP, the deepest sleep keeping the RAM
+//!
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Seconds between samples while nobody is looking, the samples are aligned to multiples
+    pub sample_interval: u32,
+    /// Seconds the display stays on after the last button press
+    pub display_timeout: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Power {
+    config: Config,
+    /// Seconds of the last button press
+    last_activity: u32,
+    display_on: bool,
+}
+
+impl Power {
+    /// The display starts on at `now` seconds
+    pub const fn new(config: Config, now: u32) -> Self {
+        Power {
+            config,
+            last_activity: now,
+            display_on: true,
+        }
+    }
+
+    /// A button has been pressed at `now` seconds. Returns `false` if the display was off, in this
+    /// case the press only turns it on and shouldn't do anything else
+    pub fn activity(&mut self, now: u32) -> bool {
+        self.last_activity = now;
+        let was_on = self.display_on;
+        self.display_on = true;
+        was_on
+    }
+
+    /// Update the state at `now` seconds, returns whether the display must be on
+    pub fn update(&mut self, now: u32) -> bool {
+        if now.saturating_sub(self.last_activity) >= self.config.display_timeout {
+            self.display_on = false;
+        }
+        self.display_on
+    }
+
+    pub fn is_display_on(&self) -> bool {
+        self.display_on
+    }
+
+    /// Seconds of the next wake up after `now`: the next second while the display is on or
+    /// something is `busy`, like the pump running, otherwise the next sample
+    pub fn next_wakeup(&self, now: u32, busy: bool) -> u32 {
+        if self.display_on || busy {
+            return now.saturating_add(1);
+        }
+        let interval = self.config.sample_interval.max(1);
+        (now / interval).saturating_add(1).saturating_mul(interval)
+    }
+
+    /// Whether the MCU can enter STOP until the next wake up. The cycle counter used to debounce
+    /// the buttons doesn't run in STOP, so it's used only with the display off
+    pub fn can_stop(&self, busy: bool) -> bool {
+        !self.display_on && !busy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: Config = Config {
+        sample_interval: 60,
+        display_timeout: 30,
+    };
+
+    #[test]
+    fn test_display_timeout() {
+        let mut power = Power::new(CONFIG, 100);
+        assert!(power.update(129));
+        assert!(!power.update(130));
+        assert!(!power.is_display_on());
+
+        // the press turning on the display is consumed
+        assert!(!power.activity(200));
+        assert!(power.update(200));
+        assert!(power.activity(210));
+        assert!(power.update(239));
+        assert!(!power.update(240));
+    }
+
+    #[test]
+    fn test_wakeups() {
+        let mut power = Power::new(CONFIG, 0);
+        assert_eq!(power.next_wakeup(10, false), 11);
+        assert!(!power.can_stop(false));
+
+        power.update(30);
+        assert_eq!(power.next_wakeup(30, false), 60);
+        assert_eq!(power.next_wakeup(60, false), 120);
+        assert_eq!(power.next_wakeup(119, false), 120);
+        assert!(power.can_stop(false));
+
+        // the pump runs
+        assert_eq!(power.next_wakeup(61, true), 62);
+        assert!(!power.can_stop(true));
+
+        // no overflow at the end of time
+        assert_eq!(power.next_wakeup(u32::MAX, false), u32::MAX);
+    }
+
+    #[test]
+    fn test_sample_spacing() {
+        // the samples are taken at irregular intervals when the display goes on and off, they
+        // stay on whole seconds and never go back in time
+        let mut power = Power::new(CONFIG, 0);
+        let mut now = 0;
+        let mut samples = vec![];
+        while now < 600 {
+            if now == 300 {
+                power.activity(now);
+            }
+            power.update(now);
+            samples.push(now);
+            now = power.next_wakeup(now, false);
+        }
+        assert_eq!(samples[..3], [0, 1, 2]);
+        assert!(samples.contains(&60) && samples.contains(&240));
+        assert!(samples.contains(&301) && samples.contains(&329));
+        assert!(!samples.contains(&331));
+        assert!(samples.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,453 @@
+//! pump
+//!
+//! This module decides when to run the watering pump, it's a state machine driven only by the time
+//! and the moisture readings so that it runs on the host against simulated soil.
+//!
+//! The interlocks keep a fault from flooding the plant: a minimum interval between waterings, a
+//! maximum run time per cycle, a daily volume cap and a fault when the moisture doesn't rise after
+//! watering, like with a dry tank or a sensor out of the soil
+//!
+
+/// Seconds in a day, the daily cap resets at multiples of it
+pub const DAY: u32 = 86_400;
+
+/// 16-bit words of a `Saved`, like the backup data registers of the STM32F103
+pub const WORDS: usize = 6;
+
+/// Marks the words written by `Saved::to_words`, registers cleared after losing power read 0
+const MAGIC: u16 = 0xA500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Seconds between the start of two waterings
+    pub min_interval: u32,
+    /// Maximum seconds the pump runs in a cycle
+    pub max_run: u32,
+    /// Millilitres per second of the pump
+    pub flow: u32,
+    /// Maximum millilitres per day
+    pub daily_cap: u32,
+    /// Percent at which the pump stops before `max_run`
+    pub target: u8,
+    /// Seconds waited after watering for the water to reach the sensor
+    pub settle: u32,
+    /// Minimum percent the moisture has to rise after watering, otherwise it's a fault
+    pub min_rise: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    /// The pump is on since `since` seconds, `before` is the moisture before watering
+    Watering {
+        since: u32,
+        before: u8,
+    },
+    /// Waiting until `until` seconds to check the moisture rose from `before`
+    Settling {
+        until: u32,
+        before: u8,
+    },
+    /// The moisture didn't rise after watering, the pump is kept off until `reset`
+    Fault,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Controller {
+    config: Config,
+    state: State,
+    last_start: Option<u32>,
+    /// `seconds / DAY` of `used_today`
+    day: u32,
+    /// Millilitres pumped today
+    used_today: u32,
+    /// Millilitres of the current cycle already added to `used_today`
+    used_cycle: u32,
+}
+
+impl Controller {
+    pub const fn new(config: Config) -> Self {
+        Controller {
+            config,
+            state: State::Idle,
+            last_start: None,
+            day: 0,
+            used_today: 0,
+            used_cycle: 0,
+        }
+    }
+
+    /// Update the controller at `now` seconds, `moisture` is the percent just read, `None` if the
+    /// reading isn't available. Returns whether the pump must be on
+    pub fn update(&mut self, now: u32, moisture: Option<u8>, thirsty: bool) -> bool {
+        if now / DAY != self.day {
+            self.day = now / DAY;
+            self.used_today = 0;
+        }
+        let config = self.config;
+        self.state = match self.state {
+            State::Idle => {
+                let rested = self
+                    .last_start
+                    .is_none_or(|last| now.saturating_sub(last) >= config.min_interval);
+                match moisture {
+                    Some(before) if thirsty && rested && self.used_today < config.daily_cap => {
+                        self.last_start = Some(now);
+                        self.used_cycle = 0;
+                        State::Watering { since: now, before }
+                    }
+                    _ => State::Idle,
+                }
+            }
+            State::Watering { since, before } => {
+                let run = now.saturating_sub(since);
+                let used = run.saturating_mul(config.flow);
+                self.used_today = self.used_today.saturating_add(used - self.used_cycle);
+                self.used_cycle = used;
+                let done = run >= config.max_run
+                    || self.used_today >= config.daily_cap
+                    || moisture.is_none_or(|m| m >= config.target);
+                if done {
+                    State::Settling {
+                        until: now.saturating_add(config.settle),
+                        before,
+                    }
+                } else {
+                    State::Watering { since, before }
+                }
+            }
+            State::Settling { until, before } if now >= until => match moisture {
+                Some(after) if after >= before.saturating_add(config.min_rise) => State::Idle,
+                _ => State::Fault,
+            },
+            state => state,
+        };
+        self.is_on()
+    }
+
+    pub fn is_on(&self) -> bool {
+        matches!(self.state, State::Watering { .. })
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn is_fault(&self) -> bool {
+        self.state == State::Fault
+    }
+
+    /// Clear the fault once the cause is fixed, like after refilling the tank
+    pub fn reset(&mut self) {
+        if self.is_fault() {
+            self.state = State::Idle;
+        }
+    }
+
+    /// Millilitres pumped since the start of the day
+    pub fn used_today(&self) -> u32 {
+        self.used_today
+    }
+
+    /// The interlocks to keep across a reset
+    pub fn save(&self) -> Saved {
+        Saved {
+            cycle: match self.state {
+                State::Watering { before, .. } | State::Settling { before, .. } => Some(before),
+                _ => None,
+            },
+            fault: self.is_fault(),
+            last_start: self.last_start,
+            day: self.day,
+            used_today: self.used_today,
+        }
+    }
+
+    /// A controller going on at `now` seconds with the interlocks `saved` before a reset, like a
+    /// brownout at the start of the pump. A cycle interrupted by the reset isn't resumed, it
+    /// settles from `now` and the rise of the moisture is checked as usual
+    pub fn restore(config: Config, saved: Saved, now: u32) -> Self {
+        let state = match saved.cycle {
+            _ if saved.fault => State::Fault,
+            Some(before) => State::Settling {
+                until: now.saturating_add(config.settle),
+                before,
+            },
+            None => State::Idle,
+        };
+        Controller {
+            config,
+            state,
+            last_start: saved.last_start,
+            day: saved.day,
+            used_today: saved.used_today,
+            used_cycle: 0,
+        }
+    }
+}
+
+/// The part of a `Controller` to keep across resets, otherwise a reset would clear the minimum
+/// interval, the daily cap and the fault, and the pump would start again at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Saved {
+    /// The moisture before the cycle in progress, watering or settling
+    cycle: Option<u8>,
+    fault: bool,
+    last_start: Option<u32>,
+    day: u32,
+    used_today: u32,
+}
+
+impl Saved {
+    /// The day and the millilitres of the day saturate at `u16::MAX`, more than 179 years since
+    /// the epoch of the clock and 65 litres
+    pub fn to_words(&self) -> [u16; WORDS] {
+        let flags = self.fault as u16
+            | (self.cycle.is_some() as u16) << 1
+            | (self.last_start.is_some() as u16) << 2;
+        let last_start = self.last_start.unwrap_or(0);
+        [
+            MAGIC | flags,
+            self.cycle.unwrap_or(0) as u16,
+            (last_start >> 16) as u16,
+            last_start as u16,
+            self.day.min(u16::MAX as u32) as u16,
+            self.used_today.min(u16::MAX as u32) as u16,
+        ]
+    }
+
+    /// `None` if the words weren't written by `to_words`
+    pub fn from_words(words: [u16; WORDS]) -> Option<Self> {
+        if words[0] & 0xFF00 != MAGIC {
+            return None;
+        }
+        let flag = |bit: u16| words[0] & 1 << bit != 0;
+        Some(Saved {
+            cycle: flag(1).then_some(words[1] as u8),
+            fault: flag(0),
+            last_start: flag(2).then_some((words[2] as u32) << 16 | words[3] as u32),
+            day: words[4] as u32,
+            used_today: words[5] as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: Config = Config {
+        min_interval: 3_600,
+        max_run: 20,
+        flow: 10,
+        daily_cap: 1_000,
+        target: 60,
+        settle: 600,
+        min_rise: 5,
+    };
+
+    /// Soil drying at a constant rate, the water pumped raises the moisture after reaching the
+    /// sensor. `tank` is the water left, a tank empty pumps nothing
+    struct Soil {
+        /// Thousandths of percent, to dry slowly
+        moisture: u32,
+        /// Thousandths of percent lost per second
+        drying: u32,
+        /// Thousandths of percent per millilitre
+        absorption: u32,
+        /// Millilitres on their way to the sensor, with the second they arrive
+        in_flight: Vec<(u32, u32)>,
+        tank: u32,
+        pumped: u32,
+    }
+
+    impl Soil {
+        fn new(percent: u32, drying: u32, absorption: u32, tank: u32) -> Self {
+            Soil {
+                moisture: percent * 1_000,
+                drying,
+                absorption,
+                in_flight: vec![],
+                tank,
+                pumped: 0,
+            }
+        }
+
+        fn step(&mut self, now: u32, pump: bool) {
+            if pump && self.tank > 0 {
+                let ml = CONFIG.flow.min(self.tank);
+                self.tank -= ml;
+                self.pumped += ml;
+                self.in_flight.push((now + 60, ml));
+            }
+            let arrived: u32 = self
+                .in_flight
+                .iter()
+                .filter(|(at, _)| *at <= now)
+                .map(|(_, ml)| ml)
+                .sum();
+            self.in_flight.retain(|(at, _)| *at > now);
+            self.moisture = (self.moisture + arrived * self.absorption)
+                .saturating_sub(self.drying)
+                .min(100_000);
+        }
+
+        fn percent(&self) -> u8 {
+            (self.moisture / 1_000) as u8
+        }
+    }
+
+    /// Run the controller on `soil` for `seconds`, returns the seconds the pump started
+    fn simulate(controller: &mut Controller, soil: &mut Soil, from: u32, seconds: u32) -> Vec<u32> {
+        let mut starts = vec![];
+        let mut was_on = false;
+        for now in from..from + seconds {
+            let percent = soil.percent();
+            let on = controller.update(now, Some(percent), percent < 30);
+            if on && !was_on {
+                starts.push(now);
+            }
+            assert!(!on || !controller.is_fault());
+            was_on = on;
+            soil.step(now, on);
+        }
+        starts
+    }
+
+    #[test]
+    fn test_waters_thirsty_soil() {
+        let mut controller = Controller::new(CONFIG);
+        // dries 1% every 10 minutes, 10ml raise 1%
+        let mut soil = Soil::new(35, 1_000 / 600, 100, 100_000);
+        let starts = simulate(&mut controller, &mut soil, 0, DAY);
+        assert!(!starts.is_empty());
+        assert!(!controller.is_fault());
+        for pair in starts.windows(2) {
+            assert!(pair[1] - pair[0] >= CONFIG.min_interval);
+        }
+        // a cycle is at most max_run seconds
+        assert!(soil.pumped <= starts.len() as u32 * CONFIG.max_run * CONFIG.flow);
+        assert!(controller.used_today() <= CONFIG.daily_cap);
+        assert!(soil.percent() >= 20, "{}", soil.percent());
+    }
+
+    #[test]
+    fn test_dry_tank_fault() {
+        let mut controller = Controller::new(CONFIG);
+        let mut soil = Soil::new(25, 1, 100, 0);
+        let starts = simulate(&mut controller, &mut soil, 0, 3 * CONFIG.min_interval);
+        // one cycle, then the fault keeps the pump off
+        assert_eq!(starts.len(), 1);
+        assert!(controller.is_fault());
+        assert!(!controller.update(4 * CONFIG.min_interval, Some(20), true));
+
+        // refilled
+        soil.tank = 100_000;
+        controller.reset();
+        let from = 4 * CONFIG.min_interval;
+        let starts = simulate(&mut controller, &mut soil, from, 2 * CONFIG.settle);
+        assert_eq!(starts, vec![from]);
+        assert!(!controller.is_fault());
+    }
+
+    #[test]
+    fn test_sensor_disconnected_fault() {
+        let mut controller = Controller::new(CONFIG);
+        assert!(controller.update(0, Some(20), true));
+        // the reading is lost while watering
+        assert!(!controller.update(1, None, true));
+        assert_eq!(
+            controller.state(),
+            State::Settling {
+                until: 1 + CONFIG.settle,
+                before: 20
+            }
+        );
+        assert!(!controller.update(1 + CONFIG.settle, None, true));
+        assert!(controller.is_fault());
+    }
+
+    #[test]
+    fn test_daily_cap() {
+        let config = Config {
+            min_interval: 60,
+            min_rise: 0,
+            ..CONFIG
+        };
+        let mut controller = Controller::new(config);
+        // always thirsty, like soil draining faster than it's watered
+        let mut pumped = 0;
+        for now in 0..DAY {
+            if controller.update(now, Some(20), true) {
+                pumped += config.flow;
+            }
+        }
+        assert!(!controller.is_fault());
+        assert_eq!(controller.used_today(), config.daily_cap);
+        assert_eq!(pumped, config.daily_cap);
+
+        // the next day starts again
+        assert!(controller.update(DAY, Some(20), true));
+        assert_eq!(controller.used_today(), 0);
+    }
+
+    #[test]
+    fn test_saved_across_resets() {
+        let mut controller = Controller::new(CONFIG);
+        assert!(controller.update(DAY + 10, Some(20), true));
+        assert!(controller.update(DAY + 15, Some(20), true));
+
+        // a brownout while watering
+        let words = controller.save().to_words();
+        let saved = Saved::from_words(words).unwrap();
+        assert_eq!(saved, controller.save());
+        let now = DAY + 20;
+        let mut controller = Controller::restore(CONFIG, saved, now);
+        assert_eq!(controller.used_today(), 5 * CONFIG.flow);
+        assert!(!controller.update(now, Some(20), true));
+        // the moisture didn't rise, like with a dry tank
+        assert!(!controller.update(now + CONFIG.settle, Some(20), true));
+        assert!(controller.is_fault());
+
+        // the fault survives the next reset, then the minimum interval
+        let saved = Saved::from_words(controller.save().to_words()).unwrap();
+        let mut controller = Controller::restore(CONFIG, saved, now + 2 * CONFIG.settle);
+        assert!(controller.is_fault());
+        controller.reset();
+        let saved = Saved::from_words(controller.save().to_words()).unwrap();
+        let mut controller = Controller::restore(CONFIG, saved, now);
+        assert!(!controller.update(DAY + CONFIG.min_interval, Some(20), true));
+        assert!(controller.update(DAY + 10 + CONFIG.min_interval, Some(20), true));
+    }
+
+    #[test]
+    fn test_saved_words() {
+        // the registers after losing power
+        assert_eq!(Saved::from_words([0; WORDS]), None);
+        let saved = Controller::new(CONFIG).save();
+        assert_eq!(Saved::from_words(saved.to_words()), Some(saved));
+        assert_eq!(
+            Controller::restore(CONFIG, saved, 0),
+            Controller::new(CONFIG)
+        );
+    }
+
+    #[test]
+    fn test_max_run_and_target() {
+        let mut controller = Controller::new(CONFIG);
+        assert!(controller.update(0, Some(20), true));
+        assert!(controller.update(CONFIG.max_run - 1, Some(25), true));
+        assert!(!controller.update(CONFIG.max_run, Some(25), true));
+        assert_eq!(controller.used_today(), CONFIG.max_run * CONFIG.flow);
+
+        // the target stops the pump early
+        let mut controller = Controller::new(CONFIG);
+        assert!(controller.update(0, Some(20), true));
+        assert!(!controller.update(5, Some(CONFIG.target), true));
+        assert_eq!(controller.used_today(), 5 * CONFIG.flow);
+        // not thirsty, nothing happens
+        let mut controller = Controller::new(CONFIG);
+        assert!(!controller.update(0, Some(20), false));
+        assert_eq!(controller.state(), State::Idle);
+    }
+}
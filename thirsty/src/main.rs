@@ -1,7 +1,9 @@
 #![no_std]
 #![no_main]
 
+mod power;
 mod sensors;
+mod storage;
 mod types;
 
 // you can put a breakpoint on `rust_begin_unwind` to catch panics
@@ -9,11 +11,15 @@ use panic_halt as _;
 
 use rtic::app;
 
-use crate::sensors::{Battery, ButtonA, ButtonB, Moisture, TempHumidity};
+use crate::sensors::{
+    values, Battery, ButtonA, ButtonB, Moisture, TempHumidity, BATTERY, THRESHOLDS,
+};
+use crate::storage::{Storage, KEY_CALIBRATION, RECORDS_VERSION};
 use crate::types::{OnScreen, TimeSlice};
 use core::fmt::Write;
+use e_button::Debouncer;
 use e_ring::hist::Hist;
-use e_ring::Ring;
+use e_store::Store;
 use e_write_buffer::WriteBuffer;
 use embedded_graphics::drawable::Drawable;
 use embedded_graphics::fonts::{Font6x8, Text};
@@ -21,22 +27,64 @@ use embedded_graphics::geometry::{Point, Size};
 use embedded_graphics::pixelcolor::BinaryColor;
 use embedded_graphics::style::TextStyleBuilder;
 use embedded_hal::digital::v2::{InputPin, OutputPin};
+use rtc_core::Span;
 use ssd1306::displaysize::DisplaySize128x64;
 use ssd1306::mode::GraphicsMode;
 use ssd1306::prelude::I2CInterface;
 use ssd1306::{Builder, I2CDIBuilder};
 use stm32f1xx_hal::adc::Adc;
+use stm32f1xx_hal::backup_domain::BackupDomain;
+use stm32f1xx_hal::gpio::gpioa::PA1;
 use stm32f1xx_hal::gpio::gpiob::{PB10, PB11};
-use stm32f1xx_hal::gpio::{Alternate, Edge, ExtiPin, OpenDrain};
+use stm32f1xx_hal::gpio::gpioc::PC13;
+use stm32f1xx_hal::gpio::{Alternate, Edge, ExtiPin, OpenDrain, Output, PushPull, State};
 use stm32f1xx_hal::i2c::BlockingI2c;
 use stm32f1xx_hal::i2c::{DutyCycle, Mode};
 use stm32f1xx_hal::pac::I2C2;
 use stm32f1xx_hal::prelude::*;
+use stm32f1xx_hal::rtc::Rtc;
 use stm32f1xx_hal::time::MonoTimer;
-use stm32f1xx_hal::timer::{CountDownTimer, Event, Timer};
+use stm32f1xx_hal::timer::{CountDownTimer, Event as TimerEvent, Timer};
 use stm32f1xx_hal::{delay, pac};
+use thirsty_core::moisture::{Calibration, Thirst, Wizard, DEFAULT_CALIBRATION};
+use thirsty_core::power::{self as policy, Power};
+use thirsty_core::pump::{self, Controller, Saved};
+use thirsty_core::Health;
+
+/// How the buttons are debounced: `None` on their falling edges, or sampled by TIM2 while the
+/// display is on, for noisy switches that would interrupt on every bounce, here down or up for 10
+/// more milliseconds than the opposite. With the display off all of them wake up the MCU on their
+/// edges
+const BUTTON_A_DEBOUNCER: Option<Debouncer> = None;
+const BUTTON_B_DEBOUNCER: Option<Debouncer> = Some(Debouncer::integrator(10));
+const SAMPLED: bool = BUTTON_A_DEBOUNCER.is_some() || BUTTON_B_DEBOUNCER.is_some();
+
+/// Frequency of the samples of the sampled buttons, the debouncers count milliseconds
+const SAMPLE_HZ: u32 = 1_000;
+
+/// Sample every minute while nobody is looking, the display turns off after 30 seconds
+const POWER: policy::Config = policy::Config {
+    sample_interval: 60,
+    display_timeout: 30,
+};
 
-const RECENTLY: u32 = 2_000_000;
+/// EXTI line of the RTC alarm, the only way it wakes up the MCU from STOP
+const RTC_ALARM_LINE: u32 = 1 << 17;
+
+/// The first of the `pump::WORDS` backup data registers keeping the interlocks of the pump across
+/// resets, like a brownout when it starts, after the one marking the clock as set
+const PUMP_REGISTER: usize = rtc_core::backup::CLOCK_SET_REGISTER + 1;
+
+/// A 10 ml/s pump watering at most 500 ml a day, and at most once an hour
+const PUMP: pump::Config = pump::Config {
+    min_interval: 3_600,
+    max_run: 15,
+    flow: 10,
+    daily_cap: 500,
+    target: 60,
+    settle: 900,
+    min_rise: 5,
+};
 
 #[cfg(feature = "semihosting")]
 macro_rules! hprintln {
@@ -52,19 +100,35 @@ macro_rules! hprintln {
 #[app(device = stm32f1xx_hal::pac, peripherals = true)]
 const APP: () = {
     struct Resources {
-        timer_handler: CountDownTimer<pac::TIM1>,
+        rtc: Rtc,
         mono_timer: MonoTimer,
+        /// Seconds counted by the RTC at the last wake up
         seconds: u32,
+        power: Power,
+        /// The idle task can enter STOP
+        stop: bool,
+        scb: cortex_m::peripheral::SCB,
 
         battery: Battery,
         moisture: Moisture,
         temp_humidity: TempHumidity,
         button_a: ButtonA,
         button_b: ButtonB,
+        sample_timer: CountDownTimer<pac::TIM2>,
+        exti: pac::EXTI,
 
         on_screen: OnScreen,
         time_slice: TimeSlice,
 
+        store: Store<Storage>,
+        wizard: Wizard,
+        /// On while the plant is thirsty
+        thirsty_led: PC13<Output<PushPull>>,
+        pump: Controller,
+        backup_domain: BackupDomain,
+        /// Drives the relay of the pump, on when high
+        pump_pin: PA1<Output<PushPull>>,
+
         display: GraphicsMode<
             I2CInterface<
                 BlockingI2c<I2C2, (PB10<Alternate<OpenDrain>>, PB11<Alternate<OpenDrain>>)>,
@@ -86,9 +150,26 @@ const APP: () = {
             .use_hse(8.mhz())
             .freeze(&mut flash.acr);
 
+        // Restore the moisture calibration saved in the last pages of the flash
+        let mut store = Store::new(Storage::new(flash)).unwrap();
+        let mut buf = [0u8; 4];
+        let saved = match store.read(KEY_CALIBRATION, RECORDS_VERSION, &mut buf) {
+            Ok(Some(4)) => Calibration::from_bytes(&buf),
+            _ => None,
+        };
+
         // Acquire the GPIO peripherals
         let mut gpioa = cx.device.GPIOA.split(&mut rcc.apb2);
         let mut gpiob = cx.device.GPIOB.split(&mut rcc.apb2);
+        let mut gpioc = cx.device.GPIOC.split(&mut rcc.apb2);
+
+        // The led of the blue pill, on when low
+        let thirsty_led = gpioc
+            .pc13
+            .into_push_pull_output_with_state(&mut gpioc.crh, State::High);
+        let pump_pin = gpioa
+            .pa1
+            .into_push_pull_output_with_state(&mut gpioa.crl, State::Low);
 
         // Setup Moisture
         let moisture_adc = Adc::adc1(cx.device.ADC1, &mut rcc.apb2, clocks);
@@ -96,7 +177,10 @@ const APP: () = {
         let moisture = Moisture {
             adc: moisture_adc,
             channel: ch0,
-            values: [Ring::new(), Ring::new(), Ring::new()],
+            calibration: saved.unwrap_or(DEFAULT_CALIBRATION),
+            calibrated: saved.is_some(),
+            thirst: Thirst::new(THRESHOLDS),
+            values: values(),
         };
 
         // Setup Battery
@@ -105,7 +189,7 @@ const APP: () = {
         let battery = Battery {
             adc: battery_adc,
             channel: ch1,
-            values: [Ring::new(), Ring::new(), Ring::new()],
+            values: values(),
         };
 
         // Setup Temp and Humidity
@@ -115,8 +199,9 @@ const APP: () = {
         let temp_humidity = TempHumidity {
             delay,
             dht_pin,
-            temp_values: [Ring::new(), Ring::new(), Ring::new()],
-            humidity_values: [Ring::new(), Ring::new(), Ring::new()],
+            temp_values: values(),
+            humidity_values: values(),
+            health: Health::new(),
         };
 
         // Setup button
@@ -130,16 +215,31 @@ const APP: () = {
         button_b_pin.trigger_on_edge(&cx.device.EXTI, Edge::FALLING);
         button_b_pin.enable_interrupt(&cx.device.EXTI);
 
-        let button_a = ButtonA {
+        let mut button_a = ButtonA {
             pin: button_a_pin,
             last: mono_timer.now(),
+            debouncer: BUTTON_A_DEBOUNCER,
+            sampling: false,
+            pressed: false,
         };
 
-        let button_b = ButtonB {
+        let mut button_b = ButtonB {
             pin: button_b_pin,
             last: mono_timer.now(),
+            debouncer: BUTTON_B_DEBOUNCER,
+            sampling: false,
+            pressed: false,
         };
 
+        // The display starts on, the sampled buttons are sampled since now
+        button_a.set_sampling(true, &cx.device.EXTI);
+        button_b.set_sampling(true, &cx.device.EXTI);
+        let mut sample_timer =
+            Timer::tim2(cx.device.TIM2, &clocks, &mut rcc.apb1).start_count_down(SAMPLE_HZ.hz());
+        if SAMPLED {
+            sample_timer.listen(TimerEvent::Update);
+        }
+
         // Setup display
         let scl = gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
         let sda = gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
@@ -161,16 +261,39 @@ const APP: () = {
         let mut display: GraphicsMode<_, _> = Builder::new().connect(interface).into();
         display.init().unwrap();
 
-        // Configure the syst timer to trigger an update every second and enables interrupt
-        let mut timer =
-            Timer::tim1(cx.device.TIM1, &clocks, &mut rcc.apb2).start_count_down(1.hz());
-        timer.listen(Event::Update);
+        // The RTC alarm wakes up the MCU to sample, counting the seconds in STOP too
+        let mut pwr = cx.device.PWR;
+        power::configure_stop(&pwr);
+        let mut backup_domain = rcc.bkp.constrain(cx.device.BKP, &mut rcc.apb1, &mut pwr);
+        let mut rtc = Rtc::rtc(cx.device.RTC, &mut backup_domain);
+        rtc_core::set_clock_if_lost!(rtc, backup_domain);
+        let seconds = rtc.current_time();
+        let words =
+            core::array::from_fn(|i| backup_domain.read_data_register_low(PUMP_REGISTER + i));
+        let pump = match Saved::from_words(words) {
+            Some(saved) => Controller::restore(PUMP, saved, seconds),
+            None => Controller::new(PUMP),
+        };
+        rtc.set_alarm(seconds + 1);
+        rtc.listen_alarm();
+        let exti = &cx.device.EXTI;
+        exti.imr
+            .modify(|r, w| unsafe { w.bits(r.bits() | RTC_ALARM_LINE) });
+        exti.rtsr
+            .modify(|r, w| unsafe { w.bits(r.bits() | RTC_ALARM_LINE) });
+
+        // Keep the debugger connected in STOP
+        #[cfg(feature = "semihosting")]
+        cx.device.DBGMCU.cr.modify(|_, w| w.dbg_stop().set_bit());
 
         // Init the static resources to use them later through RTIC
         init::LateResources {
-            timer_handler: timer,
+            rtc,
             mono_timer,
-            seconds: 1,
+            seconds,
+            power: Power::new(POWER, seconds),
+            stop: false,
+            scb: cx.core.SCB,
             time_slice: TimeSlice::Second,
             battery,
             moisture,
@@ -178,82 +301,271 @@ const APP: () = {
             on_screen: OnScreen::Battery,
             button_a,
             button_b,
+            sample_timer,
+            exti: cx.device.EXTI,
             display,
+            store,
+            wizard: Wizard::Idle,
+            thirsty_led,
+            pump,
+            backup_domain,
+            pump_pin,
         }
     }
 
-    #[idle]
-    fn idle(_cx: idle::Context) -> ! {
+    #[idle(resources = [stop, scb])]
+    fn idle(mut cx: idle::Context) -> ! {
         loop {
-            cortex_m::asm::wfi();
+            let stop = cx.resources.stop.lock(|stop| *stop);
+            power::sleep(cx.resources.scb, stop);
         }
     }
 
-    #[task(binds = TIM1_UP, priority = 1, spawn = [screen], resources = [timer_handler, battery, moisture, temp_humidity, seconds])]
+    #[task(binds = RTCALARM, priority = 1, spawn = [screen], resources = [rtc, power, stop, battery, moisture, temp_humidity, seconds, thirsty_led, pump, backup_domain, pump_pin, button_a, button_b, sample_timer, exti])]
     fn tick(cx: tick::Context) {
-        cx.resources.battery.read_and_store(*cx.resources.seconds);
-        cx.resources.moisture.read_and_store(*cx.resources.seconds);
+        // the samples are irregularly spaced, the aggregation is on the RTC seconds
+        *cx.resources.seconds = cx.resources.rtc.current_time();
+        let vrefint = cx.resources.moisture.adc.read_vref();
+        cx.resources
+            .battery
+            .read_and_store(*cx.resources.seconds, vrefint);
+        let moisture = cx.resources.moisture;
+        let percent = moisture.read_and_store(*cx.resources.seconds);
+        let thirsty = moisture.thirst.is_thirsty();
+        if thirsty {
+            cx.resources.thirsty_led.set_low().unwrap();
+        } else {
+            cx.resources.thirsty_led.set_high().unwrap();
+        }
+        let seconds = *cx.resources.seconds;
+        // the percent of a sensor not calibrated yet is a guess, it never starts the pump
+        let water = thirsty && moisture.calibrated;
+        if cx.resources.pump.update(seconds, Some(percent), water) {
+            cx.resources.pump_pin.set_high().unwrap();
+        } else {
+            cx.resources.pump_pin.set_low().unwrap();
+        }
+        save_pump(cx.resources.pump, cx.resources.backup_domain);
         cx.resources
             .temp_humidity
             .read_and_store(*cx.resources.seconds);
-        *cx.resources.seconds += 1;
 
-        cx.spawn.screen().unwrap();
+        // the screen turns off the display too
+        let power = cx.resources.power;
+        power.update(seconds);
+        let _ = cx.spawn.screen();
+
+        let busy = cx.resources.pump.is_on();
+        cx.resources.rtc.set_alarm(power.next_wakeup(seconds, busy));
+        *cx.resources.stop = power.can_stop(busy);
+        if !power.is_display_on() {
+            // the timer doesn't run in STOP, the buttons wake up the MCU on their edges
+            cx.resources.sample_timer.unlisten(TimerEvent::Update);
+            let exti = cx.resources.exti;
+            cx.resources.button_a.set_sampling(false, exti);
+            cx.resources.button_b.set_sampling(false, exti);
+        }
 
-        // Clears the update flag
-        cx.resources.timer_handler.clear_update_interrupt_flag();
+        // Clears the alarm flags
+        cx.resources.rtc.clear_alarm_flag();
+        unsafe { (*pac::EXTI::ptr()).pr.write(|w| w.bits(RTC_ALARM_LINE)) };
     }
 
-    #[task(binds = EXTI9_5, priority = 1, spawn = [screen], resources = [on_screen, button_a, button_b, mono_timer, time_slice])]
+    #[task(binds = EXTI9_5, priority = 1, spawn = [screen], resources = [on_screen, button_a, button_b, sample_timer, exti, mono_timer, time_slice, moisture, wizard, store, pump, backup_domain, rtc, power, stop])]
     fn button(cx: button::Context) {
-        if cx.resources.button_a.pin.is_low().unwrap() {
-            if cx.resources.button_a.last.elapsed() > RECENTLY {
-                cx.resources.button_a.last = cx.resources.mono_timer.now();
-                cx.resources.on_screen.next();
-                cx.spawn.screen().unwrap();
+        let now = cx.resources.mono_timer.now();
+        let seconds = cx.resources.rtc.current_time();
+        let button_a = cx.resources.button_a;
+        let button_b = cx.resources.button_b;
+
+        if !cx.resources.power.is_display_on() {
+            // the MCU may have been in STOP where the cycle counter doesn't run, a press only
+            // turns on the display without debouncing
+            if button_a.pin.is_low().unwrap() || button_b.pin.is_low().unwrap() {
+                cx.resources.power.activity(seconds);
+                button_a.last = now;
+                button_b.last = now;
+                button_a.set_sampling(true, cx.resources.exti);
+                button_b.set_sampling(true, cx.resources.exti);
+                if SAMPLED {
+                    cx.resources.sample_timer.listen(TimerEvent::Update);
+                }
+                *cx.resources.stop = false;
+                cx.resources.rtc.set_alarm(seconds + 1);
+                let _ = cx.spawn.screen();
             }
+            button_a.pin.clear_interrupt_pending_bit();
+            button_b.pin.clear_interrupt_pending_bit();
+            return;
         }
 
-        if cx.resources.button_b.pin.is_low().unwrap() {
-            if cx.resources.button_b.last.elapsed() > RECENTLY {
-                cx.resources.button_a.last = cx.resources.mono_timer.now();
+        let a_pressed = button_a.pressed(now);
+        let b_pressed = button_b.pressed(now);
+        let a_low = button_a.is_down();
+        let b_low = button_b.is_down();
+
+        let wizard = cx.resources.wizard;
+        if a_low && b_low && (a_pressed || b_pressed) && !wizard.is_active() {
+            // both buttons down start the calibration of the moisture sensor
+            wizard.start();
+            *cx.resources.on_screen = OnScreen::Moisture;
+        } else if wizard.is_active() {
+            if a_pressed {
+                let moisture = cx.resources.moisture;
+                let raw = moisture.read_raw();
+                match wizard.capture(raw) {
+                    Some(Ok(calibration)) => {
+                        moisture.calibration = calibration;
+                        moisture.calibrated = true;
+                        let bytes = calibration.to_bytes();
+                        if cx
+                            .resources
+                            .store
+                            .write(KEY_CALIBRATION, RECORDS_VERSION, &bytes)
+                            .is_err()
+                        {
+                            hprintln!("{}", "error saving the calibration");
+                        }
+                    }
+                    Some(Err(e)) => hprintln!("calibration {:?}", e),
+                    None => (),
+                }
+            } else if b_pressed {
+                wizard.cancel();
+            }
+        } else {
+            if a_pressed {
+                cx.resources.on_screen.next();
+            }
+            let on_moisture = matches!(cx.resources.on_screen, OnScreen::Moisture);
+            if b_pressed && on_moisture && cx.resources.pump.is_fault() {
+                // the tank has been refilled or the sensor put back in the soil
+                cx.resources.pump.reset();
+                save_pump(cx.resources.pump, cx.resources.backup_domain);
+            } else if b_pressed {
                 cx.resources.time_slice.next();
-                cx.spawn.screen().unwrap();
             }
         }
 
+        if a_pressed || b_pressed {
+            cx.resources.power.activity(seconds);
+            // fails only if already spawned, then it draws the new state anyway
+            let _ = cx.spawn.screen();
+        }
+
         // Clears the update flag
-        cx.resources.button_a.pin.clear_interrupt_pending_bit();
-        cx.resources.button_b.pin.clear_interrupt_pending_bit();
+        button_a.pin.clear_interrupt_pending_bit();
+        button_b.pin.clear_interrupt_pending_bit();
+    }
+
+    /// Samples the buttons with a debouncer while the display is on, their presses are handled by
+    /// the task of the edges
+    #[task(binds = TIM2, priority = 1, resources = [button_a, button_b, sample_timer])]
+    fn sample(cx: sample::Context) {
+        let a_pressed = cx.resources.button_a.sample();
+        let b_pressed = cx.resources.button_b.sample();
+        if a_pressed || b_pressed {
+            rtic::pend(pac::Interrupt::EXTI9_5);
+        }
+        cx.resources.sample_timer.clear_update_interrupt_flag();
     }
 
-    #[task(resources = [battery, moisture, temp_humidity, display, on_screen, time_slice])]
+    #[task(resources = [battery, moisture, temp_humidity, display, on_screen, time_slice, seconds, wizard, pump, power])]
     fn screen(cx: screen::Context) {
         let mut title: WriteBuffer<20> = WriteBuffer::new();
         let mut buffer: WriteBuffer<20> = WriteBuffer::new();
         let time_slice = *cx.resources.time_slice;
         let display = cx.resources.display;
+        if !cx.resources.power.is_display_on() {
+            display.display_on(false).unwrap();
+            return;
+        }
+        display.display_on(true).unwrap();
         display.clear();
 
         write!(title, "{:?}", cx.resources.on_screen).unwrap();
-        let ring = match cx.resources.on_screen {
+        let values = match cx.resources.on_screen {
             OnScreen::Battery => &cx.resources.battery.values,
             OnScreen::Moisture => &cx.resources.moisture.values,
             OnScreen::Humidity => &cx.resources.temp_humidity.humidity_values,
             OnScreen::Temperature => &cx.resources.temp_humidity.temp_values,
         };
+        let history = values.level(time_slice as usize);
+        let ring = &history.values;
 
         let text_style = TextStyleBuilder::new(Font6x8)
             .text_color(BinaryColor::On)
             .background_color(BinaryColor::Off)
             .build();
 
-        if let Some(last) = ring[time_slice as usize].last() {
+        if cx.resources.wizard.is_active() {
+            let step = match *cx.resources.wizard {
+                Wizard::Dry => "1/2 sensor in air",
+                _ => "2/2 sensor in water",
+            };
+            for (i, line) in ["Calibration", step, "A capture B cancel"]
+                .iter()
+                .enumerate()
+            {
+                Text::new(line, Point::new(0, i as i32 * 12))
+                    .into_styled(text_style)
+                    .draw(display)
+                    .unwrap();
+            }
+            display.flush().unwrap();
+            return;
+        }
+
+        let health = match cx.resources.on_screen {
+            OnScreen::Humidity | OnScreen::Temperature => Some(&cx.resources.temp_humidity.health),
+            OnScreen::Battery | OnScreen::Moisture => None,
+        };
+        if let Some(health) = health.filter(|h| h.is_faulty()) {
+            Text::new(&title.as_str().unwrap(), Point::zero())
+                .into_styled(text_style)
+                .draw(display)
+                .unwrap();
+            Text::new("sensor fault", Point::new(0, 12))
+                .into_styled(text_style)
+                .draw(display)
+                .unwrap();
+
+            write!(buffer, "errors {}", health.errors().total()).unwrap();
+            Text::new(&buffer.as_str().unwrap(), Point::new(0, 28))
+                .into_styled(text_style)
+                .draw(display)
+                .unwrap();
+            buffer.reset();
+
+            match health.age(*cx.resources.seconds) {
+                // fits the 20 chars of the buffer for any age
+                Some(age) => write!(buffer, "last ok {}s", age).unwrap(),
+                None => write!(buffer, "never read").unwrap(),
+            }
+            Text::new(&buffer.as_str().unwrap(), Point::new(0, 40))
+                .into_styled(text_style)
+                .draw(display)
+                .unwrap();
+            display.flush().unwrap();
+            return;
+        }
+
+        if let Some(last) = ring.last() {
+            let mut value: WriteBuffer<20> = WriteBuffer::new();
+            match cx.resources.on_screen {
+                OnScreen::Battery => {
+                    let mv = last.max(0) as u16;
+                    let percent = BATTERY.percent(mv);
+                    write!(value, "{}.{:02}V {:>3}%", mv / 1000, mv % 1000 / 10, percent).unwrap()
+                }
+                OnScreen::Moisture => write!(value, "{}%", last).unwrap(),
+                _ => write!(value, "{}", last as u32).unwrap(),
+            }
             write!(
                 buffer,
                 "{} {:>width$}",
                 title,
-                last as u32,
+                value.as_str().unwrap(),
                 width = 19 - title.len()
             )
             .unwrap();
@@ -264,15 +576,53 @@ const APP: () = {
             buffer.reset();
 
             write!(buffer, "{:?}", cx.resources.time_slice).unwrap();
+            if let OnScreen::Battery = cx.resources.on_screen {
+                // on the latest reading, whatever the time slice shown, still in the window of its
+                // second until the next reading
+                let values = &cx.resources.battery.values;
+                let latest = values.partial(0).or_else(|| values.level(0).values.last());
+                if latest.map_or(false, |mv| BATTERY.is_low(mv.max(0) as u16)) {
+                    write!(buffer, " LOW BATTERY").unwrap();
+                }
+            }
+            if let OnScreen::Moisture = cx.resources.on_screen {
+                let moisture = &cx.resources.moisture;
+                if cx.resources.pump.is_fault() {
+                    write!(buffer, " PUMP FAULT").unwrap();
+                } else if cx.resources.pump.is_on() {
+                    write!(buffer, " WATERING").unwrap();
+                } else if moisture.thirst.is_thirsty() {
+                    write!(buffer, " THIRSTY").unwrap();
+                } else if !moisture.calibrated {
+                    write!(buffer, " uncalibrated").unwrap();
+                }
+            }
             Text::new(&buffer.as_str().unwrap(), Point::new(0, 12))
                 .into_styled(text_style)
                 .draw(display)
                 .unwrap();
             buffer.reset();
 
+            // the clock time of the value shown, like "03:00-04:00 avg" for an hour
+            if let Some(start) = history.starts.newest() {
+                let span = Span {
+                    start,
+                    interval: history.starts.interval(),
+                };
+                write!(buffer, "{}", span).unwrap();
+                if span.interval > 1 {
+                    write!(buffer, " avg").unwrap();
+                }
+                Text::new(&buffer.as_str().unwrap(), Point::new(0, 20))
+                    .into_styled(text_style)
+                    .draw(display)
+                    .unwrap();
+                buffer.reset();
+            }
+
             let hist = Hist::new(Point::new(0, 28), Size::new(128, 36));
             hist.draw(
-                &ring[time_slice as usize],
+                ring,
                 display,
                 BinaryColor::On,
                 BinaryColor::Off,
@@ -298,3 +648,10 @@ const APP: () = {
         fn TAMPER();
     }
 };
+
+/// Keep the interlocks of the pump in the backup data registers, powered as long as the RTC
+fn save_pump(pump: &Controller, backup_domain: &mut BackupDomain) {
+    for (i, word) in pump.save().to_words().iter().enumerate() {
+        backup_domain.write_data_register_low(PUMP_REGISTER + i, *word);
+    }
+}
@@ -0,0 +1,41 @@
+//! power
+//!
+//! This module puts the STM32F103 in SLEEP or STOP until the next interrupt, when to use which is
+//! decided by `thirsty_core::power`
+//!
+
+use cortex_m::peripheral::SCB;
+use stm32f1xx_hal::pac;
+
+/// Make the deep sleep STOP, not STANDBY which loses the RAM, with the voltage regulator in low
+/// power mode
+pub fn configure_stop(pwr: &pac::PWR) {
+    pwr.cr.modify(|_, w| w.pdds().clear_bit().lpds().set_bit());
+}
+
+/// Sleep until the next interrupt, in STOP if `stop`, otherwise in SLEEP keeping the clocks on.
+///
+/// Only the EXTI lines wake up from STOP: the buttons and the RTC alarm on line 17
+pub fn sleep(scb: &mut SCB, stop: bool) {
+    if stop {
+        scb.set_sleepdeep();
+    } else {
+        scb.clear_sleepdeep();
+    }
+    cortex_m::asm::wfi();
+    if stop {
+        restore_clocks();
+    }
+}
+
+/// The MCU wakes up from STOP running on the 8MHz HSI, switch back to the HSE crystal used since
+/// `init`. The frequency is the same, so the interrupt served before this doesn't notice and the
+/// peripherals don't need to be configured again
+fn restore_clocks() {
+    // only the clock source is changed, the prescalers frozen in `init` are kept
+    let rcc = unsafe { &*pac::RCC::ptr() };
+    rcc.cr.modify(|_, w| w.hseon().set_bit());
+    while rcc.cr.read().hserdy().bit_is_clear() {}
+    rcc.cfgr.modify(|_, w| w.sw().hse());
+    while !rcc.cfgr.read().sws().is_hse() {}
+}
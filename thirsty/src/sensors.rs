@@ -1,112 +1,223 @@
-use dht_sensor::{dht22, DhtReading};
+use dht_sensor::{dht22, DhtError, DhtReading};
+use e_button::Debouncer;
 use e_ring::Ring;
+use e_series::{Reducer, Series, Store, Timeline};
 use embedded_hal::adc::OneShot;
+use embedded_hal::digital::v2::InputPin;
 use stm32f1xx_hal::adc::Adc;
 use stm32f1xx_hal::delay::Delay;
 use stm32f1xx_hal::gpio::gpioa::{PA6, PA7};
 use stm32f1xx_hal::gpio::gpiob::{PB0, PB1, PB5};
-use stm32f1xx_hal::gpio::{Analog, Floating, Input, OpenDrain, Output};
-use stm32f1xx_hal::pac::{ADC1, ADC2};
+use stm32f1xx_hal::gpio::{Analog, ExtiPin, Floating, Input, OpenDrain, Output};
+use stm32f1xx_hal::pac::{ADC1, ADC2, EXTI};
 use stm32f1xx_hal::time::Instant;
+use thirsty_core::battery::{self, Chemistry};
+use thirsty_core::moisture::{Calibration, Thirst, Thresholds};
+use thirsty_core::{ErrorKind, Health};
 
-// seconds in a minute, seconds in an hour
-const INTERVALS: [u32; 2] = [60, 3600];
+/// The values of a resolution, drawn on the screen, and the wall-clock starts of their windows
+pub struct History {
+    pub values: Ring<i16, 128>,
+    pub starts: Timeline<128>,
+}
+
+impl History {
+    fn new(interval: u32) -> Self {
+        History {
+            values: Ring::new(),
+            starts: Timeline::new(interval),
+        }
+    }
+}
+
+impl Store for History {
+    fn append(&mut self, start: u32, value: i16) {
+        self.values.append(value);
+        self.starts.push(start);
+    }
+}
+
+/// The readings averaged by second, minute and hour, indexed by `TimeSlice`. The seconds are the
+/// ones of the RTC, so the minutes and hours are the ones of the clock
+pub type Values = Series<History, 3>;
+
+pub fn values() -> Values {
+    let intervals = [1, 60, 3600];
+    Series::new(intervals.map(History::new), intervals, Reducer::Mean)
+}
+
+/// A Li-ion cell read through two 100k resistors, halving it below the 3.3V supply
+pub const BATTERY: battery::Config = battery::Config {
+    chemistry: Chemistry::LiIon,
+    cells: 1,
+    divider_top: 100_000,
+    divider_bottom: 100_000,
+    low: 20,
+};
 
 pub struct Battery {
     pub adc: Adc<ADC2>,
     pub channel: PB1<Analog>,
-    pub values: [Ring<i16, 128>; 3],
+    /// Millivolts
+    pub values: Values,
 }
 
 impl Battery {
-    pub fn read_and_store(&mut self, seconds: u32) {
-        let value: u16 = self.adc.read(&mut self.channel).unwrap();
-        self.values[0].append(value as i16);
-        for (i, interval) in INTERVALS.iter().enumerate() {
-            if seconds % interval == 0 {
-                let len = self.values[i].len();
-                let value = self.values[i]
-                    .iter()
-                    .skip(len - 60)
-                    .map(|e| e as f32)
-                    .sum::<f32>()
-                    / 60.0;
-                self.values[i + 1].append(value as i16);
-            }
-        }
+    /// `vrefint` is the reading of the internal reference, only ADC1 is connected to it
+    pub fn read_and_store(&mut self, seconds: u32, vrefint: u16) {
+        let raw: u16 = self.adc.read(&mut self.channel).unwrap();
+        let millivolts = BATTERY
+            .millivolts(raw, vrefint)
+            .map(|mv| mv.min(i16::MAX as u16) as i16);
+        self.values.push(seconds, millivolts);
     }
 }
 
+/// The plant is thirsty below 30% and satisfied again at 40%
+pub const THRESHOLDS: Thresholds = Thresholds {
+    thirsty: 30,
+    hysteresis: 10,
+};
+
 pub struct Moisture {
     pub adc: Adc<ADC1>,
     pub channel: PB0<Analog>,
-    pub values: [Ring<i16, 128>; 3],
+    pub calibration: Calibration,
+    /// The calibration has been captured, not the default one
+    pub calibrated: bool,
+    pub thirst: Thirst,
+    /// Percent
+    pub values: Values,
 }
 
 impl Moisture {
-    pub fn read_and_store(&mut self, seconds: u32) {
-        let value: u16 = self.adc.read(&mut self.channel).unwrap();
-        self.values[0].append(value as i16);
-        for (i, interval) in INTERVALS.iter().enumerate() {
-            if seconds % interval == 0 {
-                let len = self.values[i].len();
-                let value = self.values[i]
-                    .iter()
-                    .skip(len - 60)
-                    .map(|e| e as f32)
-                    .sum::<f32>()
-                    / 60.0;
-                self.values[i + 1].append(value as i16);
-            }
-        }
+    pub fn read_raw(&mut self) -> u16 {
+        self.adc.read(&mut self.channel).unwrap()
+    }
+
+    /// Returns the moisture in percent, `thirst` is updated with it
+    pub fn read_and_store(&mut self, seconds: u32) -> u8 {
+        let raw = self.read_raw();
+        let percent = self.calibration.percent(raw);
+        self.values.push(seconds, Some(percent as i16));
+        self.thirst.update(percent);
+        percent
     }
 }
 
 pub struct TempHumidity {
     pub delay: Delay,
     pub dht_pin: PB5<Output<OpenDrain>>,
-    pub temp_values: [Ring<i16, 128>; 3],
-    pub humidity_values: [Ring<i16, 128>; 3],
+    /// The sensor is read at most every 2 seconds, less when failing, the values have gaps and the
+    /// averages are over the readings available
+    pub temp_values: Values,
+    pub humidity_values: Values,
+    pub health: Health,
 }
 
 impl TempHumidity {
     pub fn read_and_store(&mut self, seconds: u32) {
-        let dht22::Reading {
-            temperature,
-            relative_humidity,
-        } = dht22::Reading::read(&mut self.delay, &mut self.dht_pin).unwrap();
-        self.temp_values[0].append((temperature * 10.0) as i16);
-        self.humidity_values[0].append((relative_humidity * 10.0) as i16);
-
-        for (i, interval) in INTERVALS.iter().enumerate() {
-            if seconds % interval == 0 {
-                let len = self.temp_values[i].len();
-                let value = self.temp_values[i]
-                    .iter()
-                    .skip(len - 60)
-                    .map(|e| e as f32)
-                    .sum::<f32>()
-                    / 60.0;
-                self.temp_values[i + 1].append(value as i16);
-
-                let len = self.humidity_values[i].len();
-                let value = self.humidity_values[i]
-                    .iter()
-                    .skip(len - 60)
-                    .map(|e| e as f32)
-                    .sum::<f32>()
-                    / 60.0;
-                self.humidity_values[i + 1].append(value as i16);
+        let reading = if self.health.should_read(seconds) {
+            match dht22::Reading::read(&mut self.delay, &mut self.dht_pin) {
+                Ok(reading) => {
+                    self.health.success(seconds);
+                    Some(reading)
+                }
+                Err(e) => {
+                    self.health.failure(error_kind(&e), seconds);
+                    None
+                }
             }
-        }
+        } else {
+            None
+        };
+
+        let temperature = reading.as_ref().map(|r| (r.temperature * 10.0) as i16);
+        let humidity = reading.map(|r| (r.relative_humidity * 10.0) as i16);
+        self.temp_values.push(seconds, temperature);
+        self.humidity_values.push(seconds, humidity);
     }
 }
 
+fn error_kind<E>(error: &DhtError<E>) -> ErrorKind {
+    match error {
+        DhtError::PinError(_) => ErrorKind::Pin,
+        DhtError::ChecksumMismatch => ErrorKind::Checksum,
+        DhtError::Timeout => ErrorKind::Timeout,
+    }
+}
+
+/// Falling edges of a button closer than this number of cycles are bounces
+pub const RECENTLY: u32 = 2_000_000;
+
 macro_rules! impl_button {
     ( $button_struct:ident, $pin_type:ty ) => {
+        /// A button pulling its pin low, debounced on its falling edges or, with a `debouncer`,
+        /// sampled by a timer while the display is on
         pub struct $button_struct {
             pub pin: $pin_type,
             pub last: Instant,
+            pub debouncer: Option<Debouncer>,
+            /// Sampled by the timer, the EXTI interrupt is enabled only while it isn't
+            pub sampling: bool,
+            /// Pressed according to the samples, not handled yet
+            pub pressed: bool,
+        }
+
+        impl $button_struct {
+            /// With a debouncer, sample the button if `sampling`, otherwise take its edges to wake
+            /// up the MCU from STOP where the timer doesn't run
+            pub fn set_sampling(&mut self, sampling: bool, exti: &EXTI) {
+                if self.debouncer.is_none() || self.sampling == sampling {
+                    return;
+                }
+                self.sampling = sampling;
+                if sampling {
+                    self.pin.disable_interrupt(exti);
+                    // the press waking up the MCU only turns on the display
+                    let low = self.pin.is_low().unwrap_or(false);
+                    if let Some(debouncer) = self.debouncer.as_mut() {
+                        debouncer.settle(low);
+                    }
+                    self.pressed = false;
+                } else {
+                    self.pin.clear_interrupt_pending_bit();
+                    self.pin.enable_interrupt(exti);
+                }
+            }
+
+            /// Feed a sample of the pin to the debouncer, return true when the button is pressed
+            pub fn sample(&mut self) -> bool {
+                let low = self.pin.is_low().unwrap_or(false);
+                match self.debouncer.as_mut() {
+                    Some(debouncer) if self.sampling => {
+                        self.pressed |= debouncer.sample(low) == Some(true);
+                        self.pressed
+                    }
+                    _ => false,
+                }
+            }
+
+            /// The debounced level if sampled, otherwise the one of the pin
+            pub fn is_down(&self) -> bool {
+                match self.debouncer.as_ref() {
+                    Some(debouncer) if self.sampling => debouncer.is_down(),
+                    _ => self.pin.is_low().unwrap_or(false),
+                }
+            }
+
+            /// Whether the button has been pressed since the last call: according to the samples,
+            /// or down on an edge farther than `RECENTLY` from the last press
+            pub fn pressed(&mut self, now: Instant) -> bool {
+                if self.sampling {
+                    return core::mem::take(&mut self.pressed);
+                }
+                let pressed = self.is_down() && self.last.elapsed() > RECENTLY;
+                if pressed {
+                    self.last = now;
+                }
+                pressed
+            }
         }
     };
 }
@@ -0,0 +1,13 @@
+//! storage
+//!
+//! This module gives the last pages of the STM32F103 flash to the `e_store::Store`, through the
+//! backend shared with the other firmwares in `e_store::stm32f1`, and holds the keys of the records
+//!
+
+e_store::stm32f1_storage!();
+
+/// Key of the moisture calibration in the store
+pub const KEY_CALIBRATION: u8 = 0;
+
+/// Version of the layout of the records, bumped when one changes
+pub const RECORDS_VERSION: u8 = 0;